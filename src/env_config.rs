@@ -0,0 +1,172 @@
+use serde::de::{self, Deserialize, Deserializer, Visitor};
+
+use crate::error::{AppError, AppResult};
+use crate::models::Config;
+
+const ENV_PREFIX: &str = "MIAO_";
+
+/// route_mode 只在当前会话有效，与配置文件中的同名字段一样不从环境变量读取
+const SESSION_ONLY_FIELDS: &[&str] = &["route_mode"];
+
+/// 只用来取得 serde 派生代码传给 deserialize_struct 的字段列表，不读取任何数据
+struct FieldNames<'a>(&'a mut &'static [&'static str]);
+
+impl<'de> Deserializer<'de> for FieldNames<'_> {
+    type Error = de::value::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Self::Error> {
+        Err(de::Error::custom("field introspection only"))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        _visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        *self.0 = fields;
+        Err(de::Error::custom("field introspection only"))
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map enum identifier ignored_any
+    }
+}
+
+/// Config 的全部字段名，新增字段无需在这里登记
+fn config_fields() -> &'static [&'static str] {
+    let mut fields: &'static [&'static str] = &[];
+    let _ = Config::deserialize(FieldNames(&mut fields));
+    fields
+}
+
+fn env_var_name(field: &str) -> String {
+    format!("{ENV_PREFIX}{}", field.to_ascii_uppercase())
+}
+
+/// 环境变量的值按 YAML 解析，因此 `8080`、`true`、`[a, b]`、`{up_mbps: 30}` 都可以直接使用
+fn parse_env_value(raw: &str) -> serde_json::Value {
+    serde_yaml::from_str::<serde_json::Value>(raw)
+        .ok()
+        .filter(|value| !value.is_null() || raw.trim().is_empty())
+        .unwrap_or_else(|| serde_json::Value::String(raw.to_string()))
+}
+
+fn comma_list(raw: &str) -> serde_json::Value {
+    raw.split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(|item| serde_json::Value::String(item.to_string()))
+        .collect()
+}
+
+/// 用 `MIAO_<字段名大写>` 环境变量覆盖配置，如 `MIAO_PORT=8080`、`MIAO_SUBS=url1,url2`。
+/// 列表字段的值不是 YAML 列表时按逗号拆分。返回覆盖后的配置和生效的变量名
+pub fn apply_env_overrides(
+    config: Config,
+    vars: impl IntoIterator<Item = (String, String)>,
+) -> AppResult<(Config, Vec<String>)> {
+    let vars: std::collections::HashMap<String, String> = vars
+        .into_iter()
+        .filter(|(name, _)| name.starts_with(ENV_PREFIX))
+        .collect();
+    let mut merged = serde_json::to_value(&config)?;
+    let mut applied = Vec::new();
+
+    for field in config_fields() {
+        let name = env_var_name(field);
+        let Some(raw) = vars.get(&name) else {
+            continue;
+        };
+        if SESSION_ONLY_FIELDS.contains(field) {
+            tracing::info!(var = %name, "Ignoring session-only setting from environment");
+            continue;
+        }
+
+        let mut candidate = merged.clone();
+        candidate[*field] = parse_env_value(raw);
+        if let Err(e) = serde_json::from_value::<Config>(candidate.clone()) {
+            candidate[*field] = comma_list(raw);
+            if serde_json::from_value::<Config>(candidate.clone()).is_err() {
+                return Err(AppError::message(format!(
+                    "Invalid value for {}: {}",
+                    name, e
+                )));
+            }
+        }
+        merged = candidate;
+        applied.push(name);
+    }
+
+    Ok((serde_json::from_value(merged)?, applied))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{apply_env_overrides, config_fields};
+    use crate::models::{Config, RouteMode};
+
+    fn vars(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn config_fields_lists_every_field() {
+        let fields = config_fields();
+
+        assert!(fields.contains(&"port"));
+        assert!(fields.contains(&"subs"));
+        assert!(fields.contains(&"max_sub_bytes"));
+    }
+
+    #[test]
+    fn apply_env_overrides_parses_scalars_lists_and_maps() {
+        let base = Config {
+            port: Some(6161),
+            sniff: false,
+            ..Default::default()
+        };
+
+        let (config, applied) = apply_env_overrides(
+            base,
+            vars(&[
+                ("MIAO_PORT", "8080"),
+                (
+                    "MIAO_SUBS",
+                    "https://a.example.com/sub, https://b.example.com/sub",
+                ),
+                ("MIAO_SNIFF", "true"),
+                ("MIAO_HYSTERIA2", "{up_mbps: 30}"),
+                ("MIAO_ROUTE_MODE", "global"),
+                ("MIAO_UNKNOWN", "1"),
+                ("PATH", "/usr/bin"),
+            ]),
+        )
+        .unwrap();
+
+        assert_eq!(config.port, Some(8080));
+        let urls: Vec<_> = config.subs.iter().map(|sub| sub.url.as_str()).collect();
+        assert_eq!(
+            urls,
+            vec!["https://a.example.com/sub", "https://b.example.com/sub"]
+        );
+        assert!(config.sniff);
+        assert_eq!(config.hysteria2.unwrap().up_mbps, Some(30));
+        assert_eq!(config.route_mode, RouteMode::default());
+        assert_eq!(applied.len(), 4);
+    }
+
+    #[test]
+    fn apply_env_overrides_rejects_invalid_values() {
+        let err = apply_env_overrides(Config::default(), vars(&[("MIAO_PORT", "not-a-port")]))
+            .err()
+            .unwrap();
+
+        assert!(err.to_string().contains("Invalid value for MIAO_PORT"));
+    }
+}
@@ -10,6 +10,8 @@ pub enum AppError {
     Io(std::io::Error),
     Json(serde_json::Error),
     Yaml(serde_yaml::Error),
+    TomlDe(toml::de::Error),
+    TomlSer(toml::ser::Error),
     Http(reqwest::Error),
     Context {
         context: String,
@@ -38,6 +40,8 @@ impl Display for AppError {
             Self::Io(err) => write!(f, "{err}"),
             Self::Json(err) => write!(f, "{err}"),
             Self::Yaml(err) => write!(f, "{err}"),
+            Self::TomlDe(err) => write!(f, "{err}"),
+            Self::TomlSer(err) => write!(f, "{err}"),
             Self::Http(err) => write!(f, "{err}"),
             Self::Context { context, source } => write!(f, "{context}: {source}"),
         }
@@ -52,6 +56,8 @@ impl Error for AppError {
             Self::Io(err) => Some(err),
             Self::Json(err) => Some(err),
             Self::Yaml(err) => Some(err),
+            Self::TomlDe(err) => Some(err),
+            Self::TomlSer(err) => Some(err),
             Self::Http(err) => Some(err),
             Self::Context { source, .. } => Some(source.as_ref()),
         }
@@ -88,6 +94,18 @@ impl From<serde_yaml::Error> for AppError {
     }
 }
 
+impl From<toml::de::Error> for AppError {
+    fn from(value: toml::de::Error) -> Self {
+        Self::TomlDe(value)
+    }
+}
+
+impl From<toml::ser::Error> for AppError {
+    fn from(value: toml::ser::Error) -> Self {
+        Self::TomlSer(value)
+    }
+}
+
 impl From<reqwest::Error> for AppError {
     fn from(value: reqwest::Error) -> Self {
         Self::Http(value)
@@ -14,18 +14,16 @@ use tokio::time::Duration;
 use tokio_tungstenite::{connect_async, tungstenite::Message as TungsteniteMessage};
 use tracing::warn;
 
+use crate::services::config::{clash_api_url, CLASH_API_ADDR};
 use crate::state::AppState;
 
-const CLASH_API_BASE: &str = "http://127.0.0.1:6262";
-const CLASH_TRAFFIC_WS: &str = "ws://127.0.0.1:6262/traffic";
-
 fn clash_target_url(uri: &axum::http::Uri) -> String {
     let path_and_query = uri.path_and_query().map(|pq| pq.as_str()).unwrap_or("/");
     let suffix = path_and_query
         .strip_prefix("/api/clash")
         .unwrap_or(path_and_query);
     let suffix = if suffix.is_empty() { "/" } else { suffix };
-    format!("{CLASH_API_BASE}{suffix}")
+    clash_api_url(suffix)
 }
 
 pub async fn proxy_clash_http(
@@ -86,7 +84,7 @@ pub async fn proxy_clash_traffic(ws: WebSocketUpgrade) -> impl IntoResponse {
 }
 
 async fn bridge_traffic_socket(socket: WebSocket) {
-    let upstream = match connect_async(CLASH_TRAFFIC_WS).await {
+    let upstream = match connect_async(format!("ws://{CLASH_API_ADDR}/traffic")).await {
         Ok((socket, _)) => socket,
         Err(err) => {
             warn!(error = %err, "Failed to connect to Clash traffic WebSocket");
@@ -0,0 +1,185 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::{header::CONTENT_TYPE, StatusCode},
+    response::{IntoResponse, Json, Response},
+};
+use std::sync::Arc;
+
+use crate::models::{
+    ApiResponse, ConfigBackup, ConfigDiff, ExportFormat, ReloadSummary, SubscriptionExportQuery,
+    TestRunResult,
+};
+use crate::responses::{status_error, success, success_no_data, HandlerResult};
+use crate::services::backup::{list_backups, load_backup};
+use crate::services::clash_export::build_clash_config;
+use crate::services::config::{
+    apply_config_change, apply_reloaded_config, build_config, config_with_route_override,
+    load_generated_config, node_outbounds, prepare_loaded_config, read_config_file,
+};
+use crate::services::config_diff::diff_configs;
+use crate::services::share_link::build_v2ray_subscription;
+use crate::services::testrun::test_run_config;
+use crate::state::AppState;
+
+pub async fn get_generated_config(
+    State(state): State<Arc<AppState>>,
+) -> HandlerResult<serde_json::Value> {
+    let generated = load_generated_config(&state.sing_box_paths)
+        .await
+        .map_err(|e| status_error(StatusCode::INTERNAL_SERVER_ERROR, e))?
+        .ok_or_else(|| status_error(StatusCode::NOT_FOUND, "Config has not been generated yet"))?;
+
+    Ok(success("Generated config loaded", generated))
+}
+
+pub async fn list_config_backups(
+    State(state): State<Arc<AppState>>,
+) -> HandlerResult<Vec<ConfigBackup>> {
+    let backups = list_backups(&state.config_path)
+        .await
+        .map_err(|e| status_error(StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    Ok(success("Config backups loaded", backups))
+}
+
+/// 恢复指定备份并重新加载；当前配置会先被备份，回滚本身也可以撤销
+pub async fn rollback_config(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> HandlerResult {
+    let restored = load_backup(&state.config_path, &id)
+        .await
+        .map_err(|e| status_error(StatusCode::BAD_REQUEST, e))?;
+
+    let _config_update = state.config_update.lock().await;
+    let old_config = state.config.read().await.clone();
+    apply_config_change(&state, &old_config, &restored)
+        .await
+        .map_err(|e| status_error(StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    Ok(success_no_data(format!(
+        "Config restored from backup {} and sing-box restarted",
+        id
+    )))
+}
+
+/// 重新读取配置文件并应用；文件无法解析或校验失败时保留当前配置
+pub async fn reload_config_file(
+    State(state): State<Arc<AppState>>,
+) -> HandlerResult<ReloadSummary> {
+    let _config_update = state.config_update.lock().await;
+    let file_config = read_config_file(&state.config_path)
+        .await
+        .map_err(|e| status_error(StatusCode::BAD_REQUEST, e))?;
+    let new_config = prepare_loaded_config(file_config.clone())
+        .map_err(|e| status_error(StatusCode::BAD_REQUEST, e))?;
+    let summary = ReloadSummary {
+        subscriptions: new_config.subs.len(),
+        manual_nodes: new_config.nodes.len(),
+        nodes: Vec::new(),
+        warning: None,
+    };
+
+    let old_config = state.config.read().await.clone();
+    apply_reloaded_config(&state, &old_config, file_config, new_config)
+        .await
+        .map_err(|e| status_error(StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    let nodes = load_generated_config(&state.sing_box_paths)
+        .await
+        .ok()
+        .flatten()
+        .map(|generated| {
+            node_outbounds(&generated)
+                .iter()
+                .filter_map(|outbound| outbound["tag"].as_str().map(str::to_string))
+                .collect()
+        })
+        .unwrap_or_default();
+    Ok(success(
+        "Config file reloaded and sing-box restarted",
+        ReloadSummary {
+            nodes,
+            warning: state.config_warning.lock().await.clone(),
+            ..summary
+        },
+    ))
+}
+
+pub async fn get_clash_config(
+    State(state): State<Arc<AppState>>,
+) -> Result<Response, (StatusCode, Json<ApiResponse<()>>)> {
+    let generated = load_generated_config(&state.sing_box_paths)
+        .await
+        .map_err(|e| status_error(StatusCode::INTERNAL_SERVER_ERROR, e))?
+        .ok_or_else(|| status_error(StatusCode::NOT_FOUND, "Config has not been generated yet"))?;
+
+    let yaml = build_clash_config(&node_outbounds(&generated))
+        .map_err(|e| status_error(StatusCode::UNPROCESSABLE_ENTITY, e))?;
+
+    Ok(([(CONTENT_TYPE, "text/yaml; charset=utf-8")], yaml).into_response())
+}
+
+/// 把合并、筛选后的节点重新发布为订阅，供其他设备使用；subscription_token 由路由层校验
+pub async fn export_subscription(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<SubscriptionExportQuery>,
+) -> Result<Response, (StatusCode, Json<ApiResponse<()>>)> {
+    let generated = load_generated_config(&state.sing_box_paths)
+        .await
+        .map_err(|e| status_error(StatusCode::INTERNAL_SERVER_ERROR, e))?
+        .ok_or_else(|| status_error(StatusCode::NOT_FOUND, "Config has not been generated yet"))?;
+    let outbounds = node_outbounds(&generated);
+
+    let (content_type, body) = match query.format {
+        ExportFormat::V2ray => (
+            "text/plain; charset=utf-8",
+            build_v2ray_subscription(&outbounds),
+        ),
+        ExportFormat::Clash => ("text/yaml; charset=utf-8", build_clash_config(&outbounds)),
+    };
+    let body = body.map_err(|e| status_error(StatusCode::UNPROCESSABLE_ENTITY, e))?;
+    Ok(([(CONTENT_TYPE, content_type)], body).into_response())
+}
+
+pub async fn get_config_diff(State(state): State<Arc<AppState>>) -> HandlerResult<ConfigDiff> {
+    let config = state.config.read().await.clone();
+    let route_override = *state.route_mode_override.read().await;
+    let runtime_config = config_with_route_override(&config, route_override);
+
+    let fresh = build_config(&runtime_config, &state)
+        .await
+        .map_err(|e| status_error(StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    let current = load_generated_config(&state.sing_box_paths)
+        .await
+        .map_err(|e| status_error(StatusCode::INTERNAL_SERVER_ERROR, e))?
+        .unwrap_or_default();
+
+    Ok(success(
+        "Config diff computed",
+        diff_configs(&current, &fresh),
+    ))
+}
+
+pub async fn test_run(State(state): State<Arc<AppState>>) -> HandlerResult<TestRunResult> {
+    let config = state.config.read().await.clone();
+    let route_override = *state.route_mode_override.read().await;
+    let runtime_config = config_with_route_override(&config, route_override);
+
+    let candidate = build_config(&runtime_config, &state)
+        .await
+        .map_err(|e| status_error(StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    let result = test_run_config(
+        &candidate,
+        &state.sing_box_paths,
+        runtime_config.sing_box_channel,
+    )
+    .await
+    .map_err(|e| status_error(StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    let message = if result.success {
+        "sing-box stayed up during test run"
+    } else {
+        "sing-box exited during test run"
+    };
+    Ok(success(message, result))
+}
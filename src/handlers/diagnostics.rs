@@ -0,0 +1,17 @@
+use axum::{extract::State, http::StatusCode};
+use std::sync::Arc;
+
+use crate::models::DiagnosticsBundle;
+use crate::responses::{status_error, success, HandlerResult};
+use crate::services::diagnostics::collect_diagnostics;
+use crate::state::AppState;
+
+/// 一次性导出提交问题报告所需的信息，订阅地址和密钥已脱敏
+pub async fn get_diagnostics(
+    State(state): State<Arc<AppState>>,
+) -> HandlerResult<DiagnosticsBundle> {
+    let bundle = collect_diagnostics(&state)
+        .await
+        .map_err(|e| status_error(StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    Ok(success("Diagnostics collected", bundle))
+}
@@ -0,0 +1,93 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        State,
+    },
+    response::IntoResponse,
+};
+use futures::{SinkExt, StreamExt};
+use tokio::sync::broadcast::error::RecvError;
+use tracing::warn;
+
+use crate::state::AppState;
+
+/// 实时推送 sing-box 日志：连接后先发送缓冲中的历史日志，之后逐行推送新日志
+pub async fn stream_sing_logs(
+    State(state): State<Arc<AppState>>,
+    ws: WebSocketUpgrade,
+) -> impl IntoResponse {
+    ws.on_upgrade(move |socket| send_logs(socket, state))
+}
+
+async fn send_logs(socket: WebSocket, state: Arc<AppState>) {
+    let (history, mut live) = state.sing_box_logs.subscribe();
+    let (mut client_tx, mut client_rx) = socket.split();
+
+    for line in history {
+        if client_tx.send(Message::Text(line.into())).await.is_err() {
+            return;
+        }
+    }
+
+    loop {
+        tokio::select! {
+            line = live.recv() => {
+                let line = match line {
+                    Ok(line) => line,
+                    Err(RecvError::Lagged(skipped)) => {
+                        warn!(skipped, "Log WebSocket client fell behind, skipping lines");
+                        continue;
+                    }
+                    Err(RecvError::Closed) => break,
+                };
+                if client_tx.send(Message::Text(line.into())).await.is_err() {
+                    break;
+                }
+            }
+            client_msg = client_rx.next() => {
+                match client_msg {
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                    Some(Ok(_)) => {}
+                }
+            }
+        }
+    }
+
+    let _ = client_tx.close().await;
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::StreamExt;
+    use tokio_tungstenite::{connect_async, tungstenite::Message};
+
+    use crate::models::Config;
+    use crate::router::build_router;
+    use crate::services::singbox::push_log_line;
+    use crate::test_support::app_state;
+
+    #[tokio::test]
+    async fn log_websocket_sends_history_then_live_lines() {
+        let state = app_state(Config::default());
+        push_log_line(&state.sing_box_logs, "old line".to_string());
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = build_router(state.clone());
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+
+        let (mut socket, _) = connect_async(format!("ws://{addr}/api/sing/logs/ws"))
+            .await
+            .unwrap();
+        let first = socket.next().await.unwrap().unwrap();
+        push_log_line(&state.sing_box_logs, "new line".to_string());
+        let second = socket.next().await.unwrap().unwrap();
+
+        assert_eq!(first, Message::text("old line"));
+        assert_eq!(second, Message::text("new line"));
+    }
+}
@@ -1,7 +1,11 @@
 pub mod clash;
+pub mod config;
+pub mod diagnostics;
+pub mod logs;
 pub mod nodes;
 pub mod proxy;
 pub mod service;
+pub mod setup;
 pub mod static_assets;
 pub mod subs;
 pub mod version;
@@ -1,12 +1,21 @@
 use axum::{extract::State, http::StatusCode, response::Json};
 use serde_json::{json, Map, Value as JsonValue};
+use std::collections::HashMap;
 use std::sync::Arc;
 use tracing::warn;
 
-use crate::models::{ApiResponse, DeleteNodeRequest, NodeInfo, NodeRequest};
+use crate::models::{
+    ApiResponse, DeleteNodeRequest, LatencyReport, NodeHealth, NodeInfo, NodeRequest, NodeTraffic,
+    Transport,
+};
 use crate::responses::{status_error, success, success_no_data, HandlerResult};
-use crate::services::config::apply_config_change;
+use crate::services::config::{
+    apply_config_change, load_generated_config, node_outbounds, LOCAL_DNS_SERVER,
+};
+use crate::services::health::{node_health, record_latencies};
+use crate::services::latency::{aggregate_region_latency, probe_latencies};
 use crate::services::node_parser::parse_node_json;
+use crate::services::traffic::node_traffic;
 use crate::state::AppState;
 use crate::validation::Validator;
 
@@ -39,6 +48,12 @@ fn base_outbound(typ: &str, req: &NodeRequest) -> Map<String, JsonValue> {
     obj.insert("tag".to_string(), json!(req.tag.trim()));
     obj.insert("server".to_string(), json!(req.server.trim()));
     obj.insert("server_port".to_string(), json!(req.server_port));
+    if let Some(strategy) = non_empty(&req.domain_strategy) {
+        obj.insert(
+            "domain_resolver".to_string(),
+            json!({"server": LOCAL_DNS_SERVER, "strategy": strategy}),
+        );
+    }
     obj
 }
 
@@ -67,6 +82,9 @@ fn build_tls(req: &NodeRequest, default_enabled: bool, force_enabled: bool) -> O
         }
     }
 
+    insert_optional_string(&mut tls, "min_version", non_empty(&req.tls_min_version));
+    insert_optional_string(&mut tls, "max_version", non_empty(&req.tls_max_version));
+
     if let Some(fingerprint) = non_empty(&req.client_fingerprint) {
         let fingerprint = fingerprint.to_ascii_lowercase();
         if fingerprint != "none" {
@@ -93,38 +111,28 @@ fn build_tls(req: &NodeRequest, default_enabled: bool, force_enabled: bool) -> O
 
 fn build_transport(req: &NodeRequest) -> Option<JsonValue> {
     let transport_type = non_empty(&req.transport_type).unwrap_or("tcp");
-    match transport_type {
-        "tcp" => None,
-        "ws" => {
-            let mut transport = Map::new();
-            transport.insert("type".to_string(), json!("ws"));
-            insert_optional_string(&mut transport, "path", non_empty(&req.transport_path));
-            if let Some(host) = non_empty(&req.transport_host) {
-                transport.insert("headers".to_string(), json!({ "Host": host }));
-            }
-            Some(JsonValue::Object(transport))
-        }
-        "grpc" => {
-            let mut transport = Map::new();
-            transport.insert("type".to_string(), json!("grpc"));
-            insert_optional_string(
-                &mut transport,
-                "service_name",
-                non_empty(&req.grpc_service_name),
-            );
-            Some(JsonValue::Object(transport))
-        }
-        "http" | "h2" => {
-            let mut transport = Map::new();
-            transport.insert("type".to_string(), json!("http"));
-            insert_optional_string(&mut transport, "path", non_empty(&req.transport_path));
-            if let Some(host) = non_empty(&req.transport_host) {
-                transport.insert("host".to_string(), json!([host]));
-            }
-            Some(JsonValue::Object(transport))
-        }
-        _ => None,
-    }
+    let path = non_empty(&req.transport_path).map(str::to_string);
+    let host = non_empty(&req.transport_host);
+    let transport = match transport_type {
+        "tcp" => return None,
+        "ws" => Transport {
+            path,
+            headers: host.map(|host| json!({ "Host": host })),
+            ..Transport::new("ws")
+        },
+        "grpc" => Transport {
+            service_name: non_empty(&req.grpc_service_name).map(str::to_string),
+            ..Transport::new("grpc")
+        },
+        "http" | "h2" => Transport {
+            path,
+            host: host.map(str::to_string).into_iter().collect(),
+            ..Transport::new("http")
+        },
+        "quic" => Transport::new("quic"),
+        _ => return None,
+    };
+    Some(json!(transport))
 }
 
 fn build_node_value(req: &NodeRequest, node_type: &str) -> JsonValue {
@@ -234,12 +242,58 @@ fn build_node_value(req: &NodeRequest, node_type: &str) -> JsonValue {
                     }),
                 );
             }
+            if req.ignore_bandwidth {
+                obj.insert("ignore_bandwidth".to_string(), json!(true));
+            }
             JsonValue::Object(obj)
         }
     }
 }
 
+/// 通过 Clash API 测量当前配置中所有节点的延迟，并按地区汇总。
+/// 地区中位延迟会被记录下来，下次生成配置时用于排列地区分组
+pub async fn get_node_latency(State(state): State<Arc<AppState>>) -> HandlerResult<LatencyReport> {
+    let generated = load_generated_config(&state.sing_box_paths)
+        .await
+        .map_err(|e| status_error(StatusCode::INTERNAL_SERVER_ERROR, e))?
+        .ok_or_else(|| status_error(StatusCode::NOT_FOUND, "Config has not been generated yet"))?;
+    let tags: Vec<String> = node_outbounds(&generated)
+        .iter()
+        .filter_map(|outbound| outbound["tag"].as_str().map(str::to_string))
+        .collect();
+
+    let nodes = probe_latencies(&state.http_client, tags).await;
+    record_latencies(&state, &nodes).await;
+    let regions = aggregate_region_latency(&nodes);
+    *state.region_latency.lock().await = regions
+        .iter()
+        .filter_map(|region| Some((region.region.clone(), region.median_ms?)))
+        .collect();
+
+    Ok(success("Latency probed", LatencyReport { nodes, regions }))
+}
+
+/// 各节点最近的延迟测试结果与连续失败次数，由 /api/nodes/latency 更新
+pub async fn get_node_health(State(state): State<Arc<AppState>>) -> HandlerResult<Vec<NodeHealth>> {
+    Ok(success("Node health loaded", node_health(&state).await))
+}
+
+/// 按节点汇总经 Clash API 观察到的流量，并给出与上一次查询相比的速率
+pub async fn get_node_traffic(
+    State(state): State<Arc<AppState>>,
+) -> HandlerResult<Vec<NodeTraffic>> {
+    let traffic = node_traffic(&state)
+        .await
+        .map_err(|e| status_error(StatusCode::BAD_GATEWAY, e))?;
+    Ok(success("Node traffic loaded", traffic))
+}
+
 pub async fn get_nodes(State(state): State<Arc<AppState>>) -> Json<ApiResponse<Vec<NodeInfo>>> {
+    let health: HashMap<String, NodeHealth> = node_health(&state)
+        .await
+        .into_iter()
+        .map(|node| (node.tag.clone(), node))
+        .collect();
     let config = state.config.read().await;
 
     let mut nodes = Vec::new();
@@ -249,6 +303,7 @@ pub async fn get_nodes(State(state): State<Arc<AppState>>) -> Json<ApiResponse<V
         match parse_node_json(node_str) {
             Ok((display_info, _)) => {
                 nodes.push(NodeInfo {
+                    health: health.get(&display_info.tag).cloned(),
                     tag: display_info.tag,
                     server: display_info.server,
                     server_port: display_info.server_port,
@@ -294,7 +349,7 @@ pub async fn add_node(
 
     let _config_update = state.config_update.lock().await;
     let old_config = state.config.read().await.clone();
-    let mut new_config = old_config.clone();
+    let mut new_config = state.file_config.read().await.clone();
 
     // 检查标签唯一性（大小写不敏感）
     let req_tag_lower = req.tag.to_lowercase();
@@ -336,7 +391,7 @@ pub async fn delete_node(
 ) -> HandlerResult {
     let _config_update = state.config_update.lock().await;
     let old_config = state.config.read().await.clone();
-    let mut new_config = old_config.clone();
+    let mut new_config = state.file_config.read().await.clone();
 
     let original_len = new_config.nodes.len();
     new_config.nodes.retain(|node_str| {
@@ -379,6 +434,7 @@ mod tests {
             custom_rules: vec![],
             route_mode: Default::default(),
             vps_ip: None,
+            ..Default::default()
         });
 
         let Json(response) = get_nodes(State(state)).await;
@@ -426,6 +482,24 @@ mod tests {
         assert_eq!(value["transport"]["headers"]["Host"], "cdn.example.com");
     }
 
+    #[test]
+    fn build_node_value_sets_per_node_domain_strategy() {
+        let req = NodeRequest {
+            node_type: Some("ss".to_string()),
+            tag: "ss".to_string(),
+            server: "ss.example.com".to_string(),
+            server_port: 8388,
+            password: "password123".to_string(),
+            domain_strategy: Some("ipv4_only".to_string()),
+            ..NodeRequest::default()
+        };
+
+        let value = build_node_value(&req, "ss");
+
+        assert_eq!(value["domain_resolver"]["server"], "local");
+        assert_eq!(value["domain_resolver"]["strategy"], "ipv4_only");
+    }
+
     #[test]
     fn build_node_value_maps_manual_tuic_defaults() {
         let req = NodeRequest {
@@ -459,6 +533,7 @@ mod tests {
             custom_rules: vec![],
             route_mode: Default::default(),
             vps_ip: None,
+            ..Default::default()
         });
 
         let Json(response) = get_nodes(State(state)).await;
@@ -489,6 +564,7 @@ mod tests {
             custom_rules: vec![],
             route_mode: Default::default(),
             vps_ip: None,
+            ..Default::default()
         });
 
         let Json(response) = get_nodes(State(state)).await;
@@ -508,6 +584,7 @@ mod tests {
             custom_rules: vec![],
             route_mode: Default::default(),
             vps_ip: None,
+            ..Default::default()
         });
 
         let Json(response) = get_nodes(State(state)).await;
@@ -531,6 +608,7 @@ mod tests {
             custom_rules: vec![],
             route_mode: Default::default(),
             vps_ip: None,
+            ..Default::default()
         });
 
         let Json(response) = get_nodes(State(state)).await;
@@ -553,6 +631,7 @@ mod tests {
             custom_rules: vec![],
             route_mode: Default::default(),
             vps_ip: None,
+            ..Default::default()
         });
 
         let Json(response) = get_nodes(State(state)).await;
@@ -580,6 +659,7 @@ mod tests {
             custom_rules: vec![],
             route_mode: Default::default(),
             vps_ip: None,
+            ..Default::default()
         });
 
         let Json(response) = get_nodes(State(state)).await;
@@ -604,6 +684,7 @@ mod tests {
             custom_rules: vec![],
             route_mode: Default::default(),
             vps_ip: None,
+            ..Default::default()
         });
 
         let Json(response) = get_nodes(State(state)).await;
@@ -627,6 +708,7 @@ mod tests {
             custom_rules: vec![],
             route_mode: Default::default(),
             vps_ip: None,
+            ..Default::default()
         });
 
         let Json(response) = get_nodes(State(state)).await;
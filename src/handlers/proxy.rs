@@ -1,12 +1,33 @@
-use axum::response::Json;
+use axum::{extract::State, http::StatusCode, response::Json};
+use std::sync::Arc;
 
-use crate::models::{ApiResponse, LastProxy};
-use crate::responses::{error, success_no_data};
-use crate::services::proxy::save_last_proxy;
+use crate::models::{ApiResponse, LastProxy, SelectNodeRequest};
+use crate::responses::{error, status_error, success_no_data, HandlerResult};
+use crate::services::proxy::{save_last_proxy, select_proxy};
+use crate::state::AppState;
 
-pub async fn set_last_proxy(Json(req): Json<LastProxy>) -> Json<ApiResponse<()>> {
-    match save_last_proxy(&req).await {
+pub async fn set_last_proxy(
+    State(state): State<Arc<AppState>>,
+    Json(req): Json<LastProxy>,
+) -> Json<ApiResponse<()>> {
+    match save_last_proxy(&state, &req).await {
         Ok(_) => success_no_data("Last proxy saved"),
         Err(e) => error(format!("Failed to save: {}", e)),
     }
 }
+
+/// 切换节点并记住选择，之后重新生成配置、重启或开机后都会恢复
+pub async fn select_node(
+    State(state): State<Arc<AppState>>,
+    Json(req): Json<SelectNodeRequest>,
+) -> HandlerResult {
+    let proxy = LastProxy {
+        group: req.group,
+        name: req.name,
+        identity: None,
+    };
+    select_proxy(&state, &proxy)
+        .await
+        .map_err(|e| status_error(StatusCode::BAD_GATEWAY, e))?;
+    Ok(success_no_data(format!("Switched to {}", proxy.name)))
+}
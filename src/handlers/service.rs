@@ -7,9 +7,9 @@ use crate::error::AppError;
 use crate::models::{ApiResponse, ConnectivityResult, RouteMode, RouteModeRequest, StatusData};
 use crate::responses::{status_error, success, success_no_data, HandlerResult};
 use crate::services::{
-    config::apply_runtime_config_change,
+    config::{apply_runtime_config_change, load_generated_config, node_outbounds},
     proxy::restore_last_proxy,
-    singbox::{start_sing_internal, stop_sing_internal},
+    singbox::{record_exit, restart_sing_internal, start_sing_internal, stop_sing_internal},
 };
 use crate::state::AppState;
 
@@ -20,8 +20,9 @@ pub async fn get_status(State(state): State<Arc<AppState>>) -> Json<ApiResponse<
 
         if let Some(ref mut proc) = *lock {
             match proc.child.try_wait() {
-                Ok(Some(_)) => {
+                Ok(Some(exit_status)) => {
                     *lock = None;
+                    record_exit(&state, exit_status).await;
                     (false, None, None)
                 }
                 Ok(None) => {
@@ -39,11 +40,17 @@ pub async fn get_status(State(state): State<Arc<AppState>>) -> Json<ApiResponse<
         .initializing
         .load(std::sync::atomic::Ordering::Relaxed);
     let warning = state.config_warning.lock().await.clone();
+    let last_gen_ms = state
+        .last_gen_duration
+        .lock()
+        .await
+        .map(|elapsed| elapsed.as_millis() as u64);
     let route_mode = state
         .route_mode_override
         .read()
         .await
         .unwrap_or(RouteMode::default());
+    let last_exit = state.last_exit.lock().await.clone();
 
     success(
         if running { "running" } else { "stopped" },
@@ -54,10 +61,45 @@ pub async fn get_status(State(state): State<Arc<AppState>>) -> Json<ApiResponse<
             pid,
             uptime_secs,
             warning,
+            last_gen_ms,
+            last_exit,
         },
     )
 }
 
+/// 存活探针：只要面板服务能响应即返回 200
+pub async fn get_health() -> Json<ApiResponse<()>> {
+    success_no_data("ok")
+}
+
+/// 就绪探针：sing-box 正在运行、配置已生成且至少有一个节点时才返回 200
+pub async fn get_ready(State(state): State<Arc<AppState>>) -> HandlerResult {
+    if !sing_box_is_running(&state).await {
+        return Err(status_error(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "sing-box is not running",
+        ));
+    }
+
+    let generated = load_generated_config(&state.sing_box_paths)
+        .await
+        .map_err(|e| status_error(StatusCode::SERVICE_UNAVAILABLE, e))?
+        .ok_or_else(|| {
+            status_error(
+                StatusCode::SERVICE_UNAVAILABLE,
+                "Config has not been generated yet",
+            )
+        })?;
+    if node_outbounds(&generated).is_empty() {
+        return Err(status_error(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "No nodes available in generated config",
+        ));
+    }
+
+    Ok(success_no_data("ready"))
+}
+
 pub async fn start_service(State(state): State<Arc<AppState>>) -> HandlerResult {
     match start_sing_internal(&state).await {
         Ok(_) => {
@@ -83,13 +125,32 @@ pub async fn stop_service(State(state): State<Arc<AppState>>) -> Json<ApiRespons
     success_no_data("sing-box stopped")
 }
 
+pub async fn restart_service(State(state): State<Arc<AppState>>) -> HandlerResult {
+    let _config_update = state.config_update.lock().await;
+
+    match restart_sing_internal(&state).await {
+        Ok(_) => {
+            let state_for_proxy = state.clone();
+            tokio::spawn(async move {
+                restore_last_proxy(&state_for_proxy).await;
+            });
+            Ok(success_no_data("sing-box restarted successfully"))
+        }
+        Err(e) => Err(status_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to restart: {}", e),
+        )),
+    }
+}
+
 async fn sing_box_is_running(state: &Arc<AppState>) -> bool {
     let mut lock = state.sing_process.lock().await;
 
     match &mut *lock {
         Some(proc) => match proc.child.try_wait() {
-            Ok(Some(_)) => {
+            Ok(Some(exit_status)) => {
                 *lock = None;
+                record_exit(state, exit_status).await;
                 false
             }
             Ok(None) => true,
@@ -176,7 +237,7 @@ pub async fn test_connectivity(
 mod tests {
     use axum::extract::State;
 
-    use super::get_status;
+    use super::{get_ready, get_status};
     use crate::models::{Config, RouteMode};
     use crate::test_support::app_state;
 
@@ -189,6 +250,7 @@ mod tests {
             custom_rules: vec![],
             route_mode: Default::default(),
             vps_ip: None,
+            ..Default::default()
         });
 
         let axum::response::Json(response) = get_status(State(state)).await;
@@ -201,6 +263,19 @@ mod tests {
         assert!(data.uptime_secs.is_none());
     }
 
+    #[tokio::test]
+    async fn get_ready_reports_unavailable_when_sing_box_is_not_running() {
+        let state = app_state(Config::default());
+
+        let Err((status, axum::response::Json(response))) = get_ready(State(state)).await else {
+            panic!("expected readiness probe to fail");
+        };
+
+        assert_eq!(status, axum::http::StatusCode::SERVICE_UNAVAILABLE);
+        assert!(!response.success);
+        assert_eq!(response.message, "sing-box is not running");
+    }
+
     #[tokio::test]
     async fn get_status_reports_route_mode_override_without_mutating_config() {
         let state = app_state(Config {
@@ -210,6 +285,7 @@ mod tests {
             custom_rules: vec![],
             route_mode: RouteMode::Rule,
             vps_ip: None,
+            ..Default::default()
         });
         *state.route_mode_override.write().await = Some(RouteMode::Global);
 
@@ -229,6 +305,7 @@ mod tests {
             custom_rules: vec![],
             route_mode: RouteMode::Global,
             vps_ip: None,
+            ..Default::default()
         });
 
         let axum::response::Json(response) = get_status(State(state)).await;
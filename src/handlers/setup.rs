@@ -0,0 +1,142 @@
+use axum::{extract::State, http::StatusCode, response::Json};
+use std::sync::Arc;
+
+use crate::models::{Config, NodeFilter, SetupRequest, Subscription, DEFAULT_PORT};
+use crate::responses::{status_error, success_no_data, HandlerResult};
+use crate::services::config::{apply_config_change, preview_subscription};
+use crate::state::AppState;
+use crate::validation::Validator;
+
+/// 首次使用引导：校验订阅可用后写入最小配置，生成 sing-box 配置并启动。
+/// 配置文件已存在时需显式传入 force
+pub async fn setup(
+    State(state): State<Arc<AppState>>,
+    Json(req): Json<SetupRequest>,
+) -> HandlerResult {
+    Validator::subscription_url(&req.sub_url)
+        .and_then(|_| req.port.map_or(Ok(()), Validator::port))
+        .and_then(|_| Validator::regions(&req.regions))
+        .map_err(|e| status_error(StatusCode::BAD_REQUEST, e))?;
+
+    let _config_update = state.config_update.lock().await;
+    if !req.force
+        && tokio::fs::try_exists(&state.config_path)
+            .await
+            .unwrap_or(true)
+    {
+        return Err(status_error(
+            StatusCode::CONFLICT,
+            "Config file already exists; set force to overwrite it",
+        ));
+    }
+
+    let new_config = Config {
+        port: req.port,
+        subs: vec![Subscription {
+            url: req.sub_url,
+            filter: NodeFilter {
+                regions: req.regions,
+                ..Default::default()
+            },
+            ..Default::default()
+        }],
+        ..Default::default()
+    };
+    let preview = preview_subscription(&new_config, &state.http_client, &new_config.subs[0])
+        .await
+        .map_err(|e| status_error(StatusCode::BAD_REQUEST, e))?;
+    if preview.node_names.is_empty() {
+        return Err(status_error(
+            StatusCode::BAD_REQUEST,
+            "Subscription contains no usable nodes",
+        ));
+    }
+
+    let old_config = state.config.read().await.clone();
+    apply_config_change(&state, &old_config, &new_config)
+        .await
+        .map_err(|e| status_error(StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    let mut message = format!(
+        "Config created with {} nodes and sing-box started",
+        preview.node_names.len()
+    );
+    if new_config.port.unwrap_or(DEFAULT_PORT) != old_config.port.unwrap_or(DEFAULT_PORT) {
+        message.push_str("; restart miao to listen on the new port");
+    }
+    Ok(success_no_data(message))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{extract::State, http::StatusCode, response::Json};
+
+    use super::setup;
+    use crate::{
+        models::{Config, SetupRequest},
+        test_support::app_state,
+    };
+
+    #[tokio::test]
+    async fn setup_refuses_to_overwrite_existing_config_without_force() {
+        let state = app_state(Config::default());
+        tokio::fs::write(&state.config_path, "port: 6161\n")
+            .await
+            .unwrap();
+
+        let result = setup(
+            State(state.clone()),
+            Json(SetupRequest {
+                port: None,
+                sub_url: "https://example.com/sub".to_string(),
+                regions: vec![],
+                force: false,
+            }),
+        )
+        .await;
+        let _ = tokio::fs::remove_file(&state.config_path).await;
+
+        let Err((status, Json(response))) = result else {
+            panic!("setup should refuse to overwrite");
+        };
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert!(!response.success);
+    }
+
+    #[tokio::test]
+    async fn setup_rejects_unknown_region_and_unusable_subscription() {
+        use axum::{routing::get, Router};
+
+        let app = Router::new().route("/sub", get(|| async { "proxies: []\n" }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        let state = app_state(Config::default());
+        let _ = tokio::fs::remove_file(&state.config_path).await;
+        let request = |regions: Vec<String>| SetupRequest {
+            port: Some(7070),
+            sub_url: format!("http://{addr}/sub"),
+            regions,
+            force: false,
+        };
+
+        let Err((status, Json(response))) =
+            setup(State(state.clone()), Json(request(vec!["XX".into()]))).await
+        else {
+            panic!("unknown region should be rejected");
+        };
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(response.message.contains("XX"));
+
+        let Err((status, Json(response))) =
+            setup(State(state.clone()), Json(request(vec![]))).await
+        else {
+            panic!("subscription without nodes should be rejected");
+        };
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(response.message, "Subscription contains no usable nodes");
+        assert!(state.config.read().await.subs.is_empty());
+    }
+}
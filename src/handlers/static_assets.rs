@@ -1,7 +1,44 @@
-use axum::response::Html;
+use axum::{extract::State, response::Html};
+use std::path::Path;
+use std::sync::Arc;
+use tracing::warn;
 
-pub async fn serve_index() -> Html<&'static str> {
-    Html(include_str!("../../public/index.html"))
+use crate::state::{AppState, IndexCache};
+
+const EMBEDDED_INDEX: &str = include_str!("../../public/index.html");
+
+/// 读取自定义首页，修改时间未变时直接返回缓存内容
+async fn load_custom_index(state: &AppState, path: &Path) -> std::io::Result<String> {
+    let modified = tokio::fs::metadata(path).await?.modified()?;
+    let mut cache = state.index_cache.lock().await;
+    if let Some(cached) = cache
+        .as_ref()
+        .filter(|cached| cached.path == path && cached.modified == modified)
+    {
+        return Ok(cached.html.clone());
+    }
+
+    let html = tokio::fs::read_to_string(path).await?;
+    *cache = Some(IndexCache {
+        path: path.to_path_buf(),
+        modified,
+        html: html.clone(),
+    });
+    Ok(html)
+}
+
+pub async fn serve_index(State(state): State<Arc<AppState>>) -> Html<String> {
+    let index_path = state.config.read().await.index_path.clone();
+    if let Some(path) = index_path {
+        match load_custom_index(&state, Path::new(&path)).await {
+            Ok(html) => return Html(html),
+            Err(e) => warn!(
+                "Failed to read index_path {}, serving built-in page: {}",
+                path, e
+            ),
+        }
+    }
+    Html(EMBEDDED_INDEX.to_string())
 }
 
 pub async fn serve_favicon() -> (
@@ -16,16 +53,36 @@ pub async fn serve_favicon() -> (
 
 #[cfg(test)]
 mod tests {
+    use axum::extract::State;
+
     use super::{serve_favicon, serve_index};
+    use crate::{models::Config, test_support::app_state};
 
     #[tokio::test]
     async fn serve_index_returns_html_document() {
-        let axum::response::Html(html) = serve_index().await;
+        let axum::response::Html(html) = serve_index(State(app_state(Config::default()))).await;
 
         assert!(html.to_lowercase().contains("<!doctype html>"));
         assert!(html.contains("Miao 控制面板"));
     }
 
+    #[tokio::test]
+    async fn serve_index_prefers_custom_file_and_falls_back_when_missing() {
+        let path = std::env::temp_dir().join(format!("miao-index-{}.html", std::process::id()));
+        std::fs::write(&path, "<html>custom landing</html>").unwrap();
+        let state = app_state(Config {
+            index_path: Some(path.to_string_lossy().into_owned()),
+            ..Default::default()
+        });
+
+        let axum::response::Html(html) = serve_index(State(state.clone())).await;
+        assert_eq!(html, "<html>custom landing</html>");
+
+        std::fs::remove_file(&path).unwrap();
+        let axum::response::Html(html) = serve_index(State(state)).await;
+        assert!(html.contains("Miao 控制面板"));
+    }
+
     #[tokio::test]
     async fn serve_favicon_returns_svg_content_type_and_body() {
         let (headers, body) = serve_favicon().await;
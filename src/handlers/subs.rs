@@ -1,30 +1,19 @@
 use axum::{extract::State, http::StatusCode, response::Json};
-use std::sync::Arc;
+use std::{collections::BTreeMap, sync::Arc};
 
-use crate::models::{ApiResponse, SubRequest, SubStatus};
+use crate::models::{
+    ApiResponse, SubPreview, SubPreviewRequest, SubRequest, SubStatus, Subscription,
+};
 use crate::responses::{status_error, success, success_no_data, HandlerResult};
-use crate::services::config::{apply_config_change, regenerate_and_restart};
+use crate::services::config::{
+    apply_config_change, preview_subscription, regenerate_and_restart, subscription_statuses,
+};
+use crate::services::subscription::normalize_sub_url;
 use crate::state::AppState;
 use crate::validation::Validator;
 
 pub async fn get_subs(State(state): State<Arc<AppState>>) -> Json<ApiResponse<Vec<SubStatus>>> {
-    let config = state.config.read().await;
-    let status_map = state.sub_status.lock().await;
-
-    let subs_with_status: Vec<SubStatus> = config
-        .subs
-        .iter()
-        .map(|url| {
-            status_map.get(url).cloned().unwrap_or(SubStatus {
-                url: url.clone(),
-                success: true,
-                node_count: 0,
-                error: None,
-            })
-        })
-        .collect();
-
-    success("Subscriptions loaded", subs_with_status)
+    success("Subscriptions loaded", subscription_statuses(&state).await)
 }
 
 pub async fn add_sub(
@@ -37,16 +26,27 @@ pub async fn add_sub(
 
     let _config_update = state.config_update.lock().await;
     let old_config = state.config.read().await.clone();
-    let mut new_config = old_config.clone();
+    let mut new_config = state.file_config.read().await.clone();
 
-    if new_config.subs.contains(&req.url) {
+    let normalized = normalize_sub_url(&req.url);
+    if new_config
+        .subs
+        .iter()
+        .any(|sub| normalize_sub_url(&sub.url) == normalized)
+    {
         return Err(status_error(
             StatusCode::BAD_REQUEST,
             "Subscription already exists",
         ));
     }
 
-    new_config.subs.push(req.url);
+    new_config.subs.push(Subscription {
+        url: req.url,
+        limit: req.limit,
+        priority: req.priority,
+        refresh_interval_secs: req.refresh_interval_secs,
+        ..Default::default()
+    });
 
     match apply_config_change(&state, &old_config, &new_config).await {
         Ok(_) => Ok(success_no_data("Subscription added and sing-box restarted")),
@@ -60,10 +60,10 @@ pub async fn delete_sub(
 ) -> HandlerResult {
     let _config_update = state.config_update.lock().await;
     let old_config = state.config.read().await.clone();
-    let mut new_config = old_config.clone();
+    let mut new_config = state.file_config.read().await.clone();
 
     let original_len = new_config.subs.len();
-    new_config.subs.retain(|s| s != &req.url);
+    new_config.subs.retain(|sub| sub.url != req.url);
 
     if new_config.subs.len() == original_len {
         return Err(status_error(
@@ -94,12 +94,63 @@ pub async fn refresh_subs(State(state): State<Arc<AppState>>) -> HandlerResult {
     }
 }
 
+/// 单独获取一个订阅并返回解析结果。已在配置中的订阅沿用其 limit、证书等设置
+pub async fn preview_sub(
+    State(state): State<Arc<AppState>>,
+    Json(req): Json<SubPreviewRequest>,
+) -> HandlerResult<SubPreview> {
+    if let Err(e) = Validator::subscription_url(&req.url) {
+        return Err(status_error(StatusCode::BAD_REQUEST, e));
+    }
+    let config = state.config.read().await.clone();
+    let mut sub = config
+        .subs
+        .iter()
+        .find(|sub| sub.url == req.url)
+        .cloned()
+        .unwrap_or_else(|| Subscription::from(req.url.as_str()));
+    if req.user_agent.is_some() {
+        sub.user_agent = req.user_agent;
+    }
+    if let Some(format) = req.format {
+        sub.format = format;
+    }
+
+    let result = preview_subscription(&config, &state.http_client, &sub)
+        .await
+        .map_err(|e| status_error(StatusCode::BAD_GATEWAY, e))?;
+
+    let mut by_type = BTreeMap::new();
+    for outbound in &result.outbounds {
+        let node_type = outbound["type"].as_str().unwrap_or("unknown").to_string();
+        *by_type.entry(node_type).or_insert(0) += 1;
+    }
+
+    Ok(success(
+        "Subscription parsed",
+        SubPreview {
+            url: sub.url,
+            total_count: result.total_count,
+            node_names: result.node_names,
+            outbounds: result.outbounds,
+            by_type,
+            dropped: result.dropped,
+            parse_errors: result.parse_errors,
+            userinfo: result.userinfo,
+        },
+    ))
+}
+
 #[cfg(test)]
 mod tests {
     use axum::{extract::State, response::Json};
 
-    use super::get_subs;
-    use crate::{error::AppError, models::Config, test_support::app_state};
+    use super::{get_subs, preview_sub};
+    use crate::{
+        error::AppError,
+        models::{Config, SubPreviewRequest},
+        test_support::app_state,
+    };
 
     #[test]
     fn app_error_context_message_stays_user_visible() {
@@ -118,11 +169,12 @@ mod tests {
     async fn get_subs_returns_default_pending_status_when_status_missing() {
         let state = app_state(Config {
             port: None,
-            subs: vec!["https://example.com/sub".to_string()],
+            subs: vec!["https://example.com/sub".into()],
             nodes: vec![],
             custom_rules: vec![],
             route_mode: Default::default(),
             vps_ip: None,
+            ..Default::default()
         });
 
         let Json(response) = get_subs(State(state)).await;
@@ -136,4 +188,41 @@ mod tests {
         assert_eq!(subs[0].node_count, 0);
         assert!(subs[0].error.is_none());
     }
+
+    #[tokio::test]
+    async fn preview_sub_reports_counts_with_custom_user_agent() {
+        use axum::{http::HeaderMap, routing::get, Router};
+
+        let app = Router::new().route(
+            "/sub",
+            get(|headers: HeaderMap| async move {
+                assert_eq!(headers["user-agent"], "preview-agent");
+                "proxies:\n  - {name: ss-a, type: ss, server: a.example.com, port: 8388, cipher: aes-128-gcm, password: p}\n  - {name: ssr-b, type: ssr, server: b.example.com, port: 8388}\n"
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        let state = app_state(Config::default());
+
+        let Json(response) = preview_sub(
+            State(state.clone()),
+            Json(SubPreviewRequest {
+                url: format!("http://{addr}/sub"),
+                user_agent: Some("preview-agent".to_string()),
+                format: None,
+            }),
+        )
+        .await
+        .unwrap_or_else(|_| panic!("preview should succeed"));
+
+        let preview = response.data.unwrap();
+        assert_eq!(preview.total_count, 2);
+        assert_eq!(preview.node_names, vec!["ss-a"]);
+        assert_eq!(preview.by_type.get("shadowsocks"), Some(&1));
+        assert_eq!(preview.dropped.values().sum::<usize>(), 1);
+        assert!(state.config.read().await.subs.is_empty());
+    }
 }
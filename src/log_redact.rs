@@ -0,0 +1,160 @@
+use regex::Regex;
+use std::borrow::Cow;
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::LazyLock;
+use tracing_subscriber::fmt::format::{DefaultFields, Format};
+use tracing_subscriber::fmt::{MakeWriter, SubscriberBuilder};
+use tracing_subscriber::EnvFilter;
+
+/// 默认开启；配置 log_secrets: true 时关闭，便于调试
+static ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// `password=...`、`"uuid": "..."` 等形式的敏感字段；彩色输出时 fmt 层会在字段名前后
+/// 插入 ANSI 转义序列（如 `\x1b[3mpassword\x1b[0m\x1b[2m=\x1b[0m`），一并跳过
+static SECRET_FIELD_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r#"(?i)("?(?:\x1b\[[0-9;]*m|\b)(?:password|passwd|uuid|private[-_]key|pre[-_]shared[-_]key|auth[-_]str|token|secret)"?(?:\x1b\[[0-9;]*m)*\s*[:=](?:\x1b\[[0-9;]*m)*\s*)("[^"]*"|[^\s,;&}\]\x1b]+)"#,
+    )
+    .unwrap()
+});
+/// 订阅地址的查询参数里通常带着 token
+static URL_QUERY_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"(https?://[^\s?"'<>]+)\?[^\s"'<>]+"#).unwrap());
+
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// 替换一行日志中的敏感字段值与 URL 查询参数
+pub fn redact(line: &str) -> Cow<'_, str> {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return Cow::Borrowed(line);
+    }
+    // 带引号的值保留引号，JSON 仍然可读
+    let mask_field = |caps: &regex::Captures| {
+        let quote = if caps[2].starts_with('"') { "\"" } else { "" };
+        format!("{}{quote}***{quote}", &caps[1])
+    };
+    match URL_QUERY_REGEX.replace_all(line, "$1?***") {
+        Cow::Borrowed(_) => SECRET_FIELD_REGEX.replace_all(line, mask_field),
+        Cow::Owned(masked) => Cow::Owned(
+            SECRET_FIELD_REGEX
+                .replace_all(&masked, mask_field)
+                .into_owned(),
+        ),
+    }
+}
+
+/// 写入前脱敏的 tracing 输出；fmt 层每条日志只调用一次 write
+pub struct Redacting<M>(M);
+
+pub struct RedactingWriter<W>(W);
+
+impl<'a, M: MakeWriter<'a>> MakeWriter<'a> for Redacting<M> {
+    type Writer = RedactingWriter<M::Writer>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        RedactingWriter(self.0.make_writer())
+    }
+}
+
+impl<W: Write> Write for RedactingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let text = String::from_utf8_lossy(buf);
+        self.0.write_all(redact(&text).as_bytes())?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.0.flush()
+    }
+}
+
+/// 日志输出统一经过脱敏写入 `make_writer`
+pub fn subscriber_builder<M>(
+    filter: EnvFilter,
+    make_writer: M,
+) -> SubscriberBuilder<DefaultFields, Format, EnvFilter, Redacting<M>>
+where
+    M: for<'a> MakeWriter<'a> + Send + Sync + 'static,
+{
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(Redacting(make_writer))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{redact, subscriber_builder};
+    use std::io::Write;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn redact_masks_secret_fields_and_url_queries() {
+        assert_eq!(
+            redact(
+                "url=https://sub.example.com/api/v1/client/subscribe?token=abc&flag=clash error"
+            ),
+            "url=https://sub.example.com/api/v1/client/subscribe?*** error"
+        );
+        assert_eq!(
+            redact(
+                r#"invalid node {"type":"vless","uuid":"b831381d-6324","password": "p@ss","server":"a.example.com"}"#
+            ),
+            r#"invalid node {"type":"vless","uuid":"***","password": "***","server":"a.example.com"}"#
+        );
+        assert_eq!(
+            redact("private-key: abc123, sni=example.com"),
+            "private-key: ***, sni=example.com"
+        );
+        assert_eq!(redact("Fetching subscription"), "Fetching subscription");
+        assert_eq!(
+            redact(
+                "\x1b[3mpassword\x1b[0m\x1b[2m=\x1b[0m\"p@ss\" \x1b[3mtag\x1b[0m\x1b[2m=\x1b[0mhk"
+            ),
+            "\x1b[3mpassword\x1b[0m\x1b[2m=\x1b[0m\"***\" \x1b[3mtag\x1b[0m\x1b[2m=\x1b[0mhk"
+        );
+    }
+
+    struct Captured(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn subscriber_redacts_structured_fields_end_to_end() {
+        let output = Arc::new(Mutex::new(Vec::new()));
+        let sink = output.clone();
+        let subscriber =
+            subscriber_builder(tracing_subscriber::EnvFilter::new("info"), move || {
+                Captured(sink.clone())
+            })
+            .with_ansi(true)
+            .finish();
+
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!(
+                password = "hunter2",
+                url = %"https://sub.example.com/api?token=abc123",
+                "Fetching subscription"
+            );
+        });
+        let logged = String::from_utf8(output.lock().unwrap().clone()).unwrap();
+
+        assert!(logged.contains('\x1b'));
+        assert!(logged.contains("Fetching subscription"));
+        assert!(logged.contains(r#""***""#));
+        assert!(logged.contains("https://sub.example.com/api?***"));
+        assert!(!logged.contains("hunter2"));
+        assert!(!logged.contains("abc123"));
+    }
+}
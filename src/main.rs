@@ -1,5 +1,7 @@
+mod env_config;
 mod error;
 mod handlers;
+mod log_redact;
 mod models;
 mod paths;
 mod responses;
@@ -8,6 +10,7 @@ mod services;
 mod state;
 #[cfg(test)]
 mod test_support;
+mod tui;
 mod validation;
 
 use crate::error::{AppError, AppResult};
@@ -16,11 +19,24 @@ use std::{fs, sync::Arc};
 use tracing::{error, info, warn};
 
 use models::{Config, DEFAULT_PORT};
+use paths::ConfigFormat;
 use services::{
-    config::{gen_config, restore_config_from_cache, save_config_cache},
+    bandwidth::probe_unmeasured_nodes,
+    compat::warn_on_version_skew,
+    config::{
+        gen_config, get_config_template, parse_config, persist_file_config_change,
+        prepare_loaded_config, regenerate_if_corrupt, restore_config_from_cache, save_config_cache,
+        write_direct_fallback_config,
+    },
+    housekeeping::run_housekeeping,
+    network_wait::wait_for_network,
     openwrt::check_and_install_openwrt_dependencies,
     proxy::restore_last_proxy,
-    singbox::{extract_sing_box, start_sing_internal, stop_sing_internal},
+    scheduler::{run_health_probe, run_refresh_scheduler},
+    singbox::{
+        extract_sing_box, start_sing_internal, stop_orphaned_sing_box, stop_sing_internal,
+        SingBoxPaths,
+    },
     vps::ensure_vps_hysteria_node,
 };
 use state::AppState;
@@ -102,31 +118,105 @@ async fn open_onboarding_browser(url: String) {
     }
 }
 
-fn config_declares_route_mode(content: &str) -> bool {
-    let Ok(value) = serde_yaml::from_str::<serde_yaml::Value>(content) else {
-        return false;
-    };
+async fn bind_panel_listener(host: &str, port: u16) -> AppResult<tokio::net::TcpListener> {
+    tokio::net::TcpListener::bind(format!("{}:{}", host, port))
+        .await
+        .map_err(|e| {
+            if e.kind() == std::io::ErrorKind::AddrInUse {
+                AppError::message(format!(
+                    "Port {} is already in use; stop the other process or set a different `port` in the config",
+                    port
+                ))
+            } else {
+                AppError::context(format!("Failed to bind panel port {}", port), e)
+            }
+        })
+}
 
-    value
-        .as_mapping()
-        .is_some_and(|mapping| mapping.contains_key("route_mode"))
+fn config_declares_route_mode(content: &str, format: ConfigFormat) -> bool {
+    match format {
+        ConfigFormat::Yaml => serde_yaml::from_str::<serde_yaml::Value>(content)
+            .ok()
+            .and_then(|value| value.as_mapping().map(|m| m.contains_key("route_mode")))
+            .unwrap_or(false),
+        ConfigFormat::Toml => toml::from_str::<toml::Table>(content)
+            .is_ok_and(|table| table.contains_key("route_mode")),
+    }
+}
+
+const STARTER_YAML: &str = r#"# Miao 配置文件，完整说明见 README
+# 面板端口
+port: 6161
+
+# 订阅地址，可写成纯 URL，也可写成带选项的对象
+subs: []
+#  - https://example.com/subscription
+#  - url: https://example.com/another
+#    regions: [HK, JP]
+
+# 手动节点（sing-box outbound JSON）
+nodes: []
+"#;
+
+const STARTER_TOML: &str = r#"# Miao 配置文件，完整说明见 README
+# 面板端口
+port = 6161
+
+# 订阅地址，可写成纯 URL，也可写成带选项的对象
+subs = []
+# subs = ["https://example.com/subscription"]
+
+# 手动节点（sing-box outbound JSON）
+nodes = []
+"#;
+
+/// `--init`：在配置路径写入带注释的初始配置，已有配置文件时不覆盖
+fn write_starter_config(path: &std::path::Path) -> AppResult<()> {
+    if path.exists() {
+        return Err(AppError::message(format!(
+            "Config file already exists at {}; remove it first to regenerate",
+            path.display()
+        )));
+    }
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)
+            .map_err(|e| AppError::context(format!("Failed to create {}", dir.display()), e))?;
+    }
+    let starter = match ConfigFormat::from_path(path) {
+        ConfigFormat::Yaml => STARTER_YAML,
+        ConfigFormat::Toml => STARTER_TOML,
+    };
+    fs::write(path, starter)
+        .map_err(|e| AppError::context(format!("Failed to write {}", path.display()), e))
 }
 
 #[tokio::main]
 async fn main() -> AppResult<()> {
     // 初始化结构化日志
-    tracing_subscriber::fmt()
-        .with_env_filter(
-            tracing_subscriber::EnvFilter::from_default_env()
-                .add_directive(tracing::Level::INFO.into()),
-        )
-        .init();
+    log_redact::subscriber_builder(
+        tracing_subscriber::EnvFilter::from_default_env()
+            .add_directive(tracing::Level::INFO.into()),
+        std::io::stdout,
+    )
+    .init();
 
     if std::env::args().any(|a| a == "--version" || a == "-V") {
         println!("miao v{}", VERSION);
         return Ok(());
     }
 
+    // `miao template`：打印内置的 sing-box 配置模板，作为编写 template_patch 的参考
+    if std::env::args().nth(1).as_deref() == Some("template") {
+        println!("{}", serde_json::to_string_pretty(&get_config_template())?);
+        return Ok(());
+    }
+
+    // `miao tui`：连接运行中的实例，在终端里查看节点延迟并切换节点，无需 root
+    if std::env::args().nth(1).as_deref() == Some("tui") {
+        let args: Vec<String> = std::env::args().skip(2).collect();
+        return tui::run(&args).await;
+    }
+
     if !Uid::effective().is_root() {
         error!("This application must be run as root");
         std::process::exit(1);
@@ -148,10 +238,17 @@ async fn main() -> AppResult<()> {
         "Resolved configuration path"
     );
 
+    if std::env::args().any(|a| a == "--init") {
+        write_starter_config(&config_path)?;
+        println!("Starter config written to {}", config_path.display());
+        return Ok(());
+    }
+
     let config: Config = match tokio::fs::read_to_string(&config_path).await {
         Ok(content) => {
-            let route_mode_declared = config_declares_route_mode(&content);
-            let mut config: Config = serde_yaml::from_str(&content)?;
+            let route_mode_declared =
+                config_declares_route_mode(&content, ConfigFormat::from_path(&config_path));
+            let mut config = parse_config(&content, &config_path)?;
             if route_mode_declared {
                 info!(
                     config_path = ?config_path,
@@ -164,12 +261,16 @@ async fn main() -> AppResult<()> {
         Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
             info!(
                 config_path = ?config_path,
-                "No config file found, using in-memory default configuration"
+                "No config file found, using in-memory default configuration; run with --init to create a starter config"
             );
             Config::default()
         }
         Err(e) => return Err(e.into()),
     };
+    let file_config = config.clone();
+    let config = prepare_loaded_config(config)?;
+    log_redact::set_enabled(!config.log_secrets);
+
     let port = config.port.unwrap_or(DEFAULT_PORT);
     let subs_count = config.subs.len();
     let nodes_count = config.nodes.len();
@@ -181,18 +282,25 @@ async fn main() -> AppResult<()> {
         "Configuration loaded"
     );
 
-    let _ = extract_sing_box()?;
+    // 先绑定面板端口，端口被占用时在启动 sing-box 接管网络之前直接退出
+    let listener = bind_panel_listener("0.0.0.0", port).await?;
+
+    let sing_box_paths = SingBoxPaths::from_config(&config);
+    extract_sing_box(&sing_box_paths, config.sing_box_channel)?;
+    stop_orphaned_sing_box(&sing_box_paths, config.sing_box_stop_grace_ms).await;
 
     // 初始化应用状态
     let app_state = Arc::new(
-        AppState::with_config_path(config.clone(), config_path)
+        AppState::with_file_config(config.clone(), file_config, config_path)
             .map_err(|e| AppError::context("Failed to create HTTP client", e))?,
     );
     let state_for_init = app_state.clone();
+    tokio::spawn(run_refresh_scheduler(app_state.clone()));
+    tokio::spawn(run_health_probe(app_state.clone()));
+    tokio::spawn(run_housekeeping(app_state.clone()));
 
     // Start web server immediately so the panel is accessible during initialization
     let app = router::build_router(app_state.clone());
-    let listener = tokio::net::TcpListener::bind(format!("0.0.0.0:{}", port)).await?;
     info!(port = port, url = %format!("http://localhost:{}", port), "Miao panel started");
 
     // Auto-open browser for onboarding when no subs/nodes configured
@@ -207,10 +315,35 @@ async fn main() -> AppResult<()> {
     tokio::spawn(async move {
         let mut config = config;
 
-        match ensure_vps_hysteria_node(&mut config, &state_for_init.config_path).await {
-            Ok(_) => {
+        if let Some(delay) = config.startup_delay_secs.filter(|secs| *secs > 0) {
+            info!(delay_secs = delay, "Delaying startup");
+            tokio::time::sleep(std::time::Duration::from_secs(delay)).await;
+        }
+        if let Some(timeout) = config.wait_for_network_secs.filter(|secs| *secs > 0) {
+            let probe_host = config
+                .subs
+                .first()
+                .and_then(|sub| url::Url::parse(sub.url.trim()).ok())
+                .and_then(|url| url.host_str().map(str::to_string));
+            wait_for_network(
+                probe_host.as_deref(),
+                std::time::Duration::from_secs(timeout),
+            )
+            .await;
+        }
+
+        match ensure_vps_hysteria_node(&mut config).await {
+            Ok(Some(node)) => {
                 *state_for_init.config.write().await = config.clone();
+                if let Err(e) =
+                    persist_file_config_change(&state_for_init, |file| file.nodes.push(node)).await
+                {
+                    error!(error = %e, "Failed to save provisioned VPS node to config");
+                    *state_for_init.config_warning.lock().await =
+                        Some(format!("VPS 节点未能写入配置文件: {}", e));
+                }
             }
+            Ok(None) => {}
             Err(e) => {
                 error!(error = %e, "Failed to provision VPS from vps_ip");
                 *state_for_init.config_warning.lock().await =
@@ -234,13 +367,40 @@ async fn main() -> AppResult<()> {
                     all_subs_failed = true;
                 }
             }
+            Err(e) if config.fail_fast => {
+                error!(error = %e, "Failed to generate config, exiting (fail_fast)");
+                std::process::exit(1);
+            }
             Err(e) => {
                 error!(error = %e, "Failed to generate config");
-                match restore_config_from_cache().await {
+                match restore_config_from_cache(
+                    &state_for_init.sing_box_paths,
+                    config.overwrite_foreign_config,
+                )
+                .await
+                {
                     Ok(_) => {
                         warn!("Using cached config as fallback");
                         all_subs_failed = true;
                     }
+                    Err(cache_err) if config.direct_fallback => {
+                        error!(
+                            error = %cache_err,
+                            "No cached config available, entering DIRECT-ONLY fallback mode: traffic will NOT be proxied"
+                        );
+                        if let Err(e) = write_direct_fallback_config(&config, &state_for_init).await
+                        {
+                            error!(error = %e, "Failed to write direct fallback config");
+                            state_for_init
+                                .initializing
+                                .store(false, std::sync::atomic::Ordering::Relaxed);
+                            return;
+                        }
+                        *state_for_init.config_warning.lock().await = Some(
+                            "所有订阅获取失败且无可用缓存，已进入直连兜底模式，流量未经代理"
+                                .to_string(),
+                        );
+                    }
                     Err(cache_err) => {
                         error!(error = %cache_err, "No cached config available");
                         *state_for_init.config_warning.lock().await =
@@ -254,15 +414,43 @@ async fn main() -> AppResult<()> {
             }
         }
 
+        match regenerate_if_corrupt(&config, &state_for_init).await {
+            Ok(true) => info!("Corrupt sing-box config regenerated"),
+            Ok(false) => {}
+            Err(e) => {
+                error!(error = %e, "sing-box config is corrupt and could not be regenerated");
+                *state_for_init.config_warning.lock().await =
+                    Some("sing-box 配置文件损坏且无法重新生成".to_string());
+                state_for_init
+                    .initializing
+                    .store(false, std::sync::atomic::Ordering::Relaxed);
+                return;
+            }
+        }
+
+        warn_on_version_skew(&state_for_init.sing_box_paths, config.sing_box_channel).await;
+
         info!("Checking dependencies...");
         if let Err(e) = check_and_install_openwrt_dependencies().await {
             error!("Failed to check or install OpenWrt dependencies: {}", e);
         }
 
+        if !config.autostart {
+            info!("autostart disabled, sing-box not started; use /api/service/start to launch it");
+            if all_subs_failed {
+                *state_for_init.config_warning.lock().await =
+                    Some("所有订阅获取失败，请检查当前订阅".to_string());
+            }
+            state_for_init
+                .initializing
+                .store(false, std::sync::atomic::Ordering::Relaxed);
+            return;
+        }
+
         match start_sing_internal(&state_for_init).await {
             Ok(_) => {
                 info!("sing-box started successfully");
-                save_config_cache().await;
+                save_config_cache(&state_for_init.sing_box_paths).await;
                 if all_subs_failed {
                     warn!("所有订阅获取失败，请检查当前订阅");
                     *state_for_init.config_warning.lock().await =
@@ -272,6 +460,9 @@ async fn main() -> AppResult<()> {
                 tokio::spawn(async move {
                     restore_last_proxy(&state_for_proxy).await;
                 });
+                if let Some(settings) = config.bandwidth_probe.clone() {
+                    tokio::spawn(probe_unmeasured_nodes(state_for_init.clone(), settings));
+                }
             }
             Err(e) => error!("Failed to start sing-box: {}", e),
         }
@@ -304,7 +495,39 @@ async fn shutdown_signal(state: Arc<AppState>) {
 
 #[cfg(test)]
 mod tests {
-    use super::config_declares_route_mode;
+    use super::{
+        bind_panel_listener, config_declares_route_mode, write_starter_config, ConfigFormat,
+    };
+    use crate::services::config::parse_config;
+
+    #[tokio::test]
+    async fn bind_panel_listener_reports_port_in_use() {
+        let occupied = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = occupied.local_addr().unwrap().port();
+
+        let err = bind_panel_listener("127.0.0.1", port).await.unwrap_err();
+
+        assert!(err
+            .to_string()
+            .contains(&format!("Port {} is already in use", port)));
+    }
+
+    #[test]
+    fn write_starter_config_creates_parseable_config_once() {
+        let dir = std::env::temp_dir().join(format!("miao-init-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+
+        for name in ["config.yaml", "config.toml"] {
+            let path = dir.join(name);
+            write_starter_config(&path).unwrap();
+            let config = parse_config(&std::fs::read_to_string(&path).unwrap(), &path).unwrap();
+            let again = write_starter_config(&path).unwrap_err().to_string();
+
+            assert_eq!(config.port, Some(6161));
+            assert!(again.contains("already exists"));
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
 
     #[test]
     fn config_declares_route_mode_when_top_level_key_exists() {
@@ -314,7 +537,7 @@ route_mode: global
 subs: []
 "#;
 
-        assert!(config_declares_route_mode(yaml));
+        assert!(config_declares_route_mode(yaml, ConfigFormat::Yaml));
     }
 
     #[test]
@@ -324,11 +547,31 @@ custom_rules:
   - '{"route_mode":"global"}'
 "#;
 
-        assert!(!config_declares_route_mode(yaml));
+        assert!(!config_declares_route_mode(yaml, ConfigFormat::Yaml));
     }
 
     #[test]
     fn config_declares_route_mode_handles_invalid_yaml() {
-        assert!(!config_declares_route_mode("route_mode: ["));
+        assert!(!config_declares_route_mode(
+            "route_mode: [",
+            ConfigFormat::Yaml
+        ));
+    }
+
+    #[test]
+    fn config_declares_route_mode_reads_toml_keys() {
+        let toml = r#"
+port = 6161
+route_mode = "global"
+
+[[subs]]
+url = "https://example.com/sub"
+"#;
+
+        assert!(config_declares_route_mode(toml, ConfigFormat::Toml));
+        assert!(!config_declares_route_mode(
+            "port = 6161",
+            ConfigFormat::Toml
+        ));
     }
 }
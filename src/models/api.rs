@@ -1,6 +1,7 @@
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
-use crate::models::config::RouteMode;
+use crate::models::config::{RouteMode, SubscriptionFormat};
 
 #[derive(Serialize)]
 pub struct ApiResponse<T: Serialize> {
@@ -47,6 +48,19 @@ pub struct StatusData {
     pub uptime_secs: Option<u64>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub warning: Option<String>,
+    /// 最近一次生成配置的总耗时（含全部订阅获取）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_gen_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_exit: Option<LastExit>,
+}
+
+/// sing-box 最近一次意外退出的退出码与原因（最近的日志输出）
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct LastExit {
+    /// 被信号终止时为空
+    pub code: Option<i32>,
+    pub reason: String,
 }
 
 #[derive(Serialize, Clone)]
@@ -60,6 +74,54 @@ pub struct ConnectivityResult {
 #[derive(Deserialize)]
 pub struct SubRequest {
     pub url: String,
+    #[serde(default)]
+    pub limit: Option<usize>,
+    #[serde(default)]
+    pub priority: i32,
+    #[serde(default)]
+    pub refresh_interval_secs: Option<u64>,
+}
+
+#[derive(Deserialize)]
+pub struct SubPreviewRequest {
+    pub url: String,
+    #[serde(default)]
+    pub user_agent: Option<String>,
+    /// 订阅格式，未指定时使用已配置订阅的格式，否则为 clash
+    #[serde(default)]
+    pub format: Option<SubscriptionFormat>,
+}
+
+/// 单个订阅的解析结果，不参与合并、不写入配置
+#[derive(Debug, Serialize)]
+pub struct SubPreview {
+    pub url: String,
+    /// 订阅中 proxies 的原始总数
+    pub total_count: usize,
+    pub node_names: Vec<String>,
+    pub outbounds: Vec<serde_json::Value>,
+    /// 成功转换的节点按 outbound 类型计数
+    pub by_type: BTreeMap<String, usize>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub dropped: BTreeMap<String, usize>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub parse_errors: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub userinfo: Option<SubscriptionUserInfo>,
+}
+
+/// 首次使用引导：生成只包含一个订阅的最小配置
+#[derive(Deserialize)]
+pub struct SetupRequest {
+    #[serde(default)]
+    pub port: Option<u16>,
+    pub sub_url: String,
+    /// 只保留这些地区的节点，如 ["HK", "JP"]
+    #[serde(default)]
+    pub regions: Vec<String>,
+    /// 配置文件已存在时是否覆盖（旧文件会先备份）
+    #[serde(default)]
+    pub force: bool,
 }
 
 #[derive(Deserialize)]
@@ -67,6 +129,22 @@ pub struct RouteModeRequest {
     pub route_mode: RouteMode,
 }
 
+/// 订阅导出格式：v2ray 为 base64 编码的分享链接列表，clash 为 Clash YAML
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    V2ray,
+    Clash,
+}
+
+/// GET /api/subscription 的查询参数；设备订阅时无法附加请求头，令牌放在 URL 中
+#[derive(Deserialize)]
+pub struct SubscriptionExportQuery {
+    #[serde(default)]
+    pub format: ExportFormat,
+}
+
 #[derive(Clone, Serialize)]
 pub struct SubStatus {
     pub url: String,
@@ -74,4 +152,154 @@ pub struct SubStatus {
     pub node_count: usize,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub error: Option<String>,
+    /// 因协议不受支持而被跳过的节点数，按原因统计
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub dropped: BTreeMap<String, usize>,
+    /// 来自 subscription-userinfo 响应头的流量与到期信息
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub userinfo: Option<SubscriptionUserInfo>,
+    /// 最近一次实际请求该订阅（含下载与解析）耗时；复用缓存或被跳过时为空
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fetch_ms: Option<u64>,
+}
+
+/// 订阅流量配额与到期时间，字节数与 Unix 时间戳均来自服务端
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct SubscriptionUserInfo {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub upload: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub download: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expire: Option<i64>,
+    /// expire 的可读形式，如 "2026-01-31 00:00:00 UTC"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expire_at: Option<String>,
+}
+
+/// 配置变化对运行中 sing-box 的影响：只有 outbounds 变化时可以用 SIGHUP 重载，
+/// 不必重启整个进程
+#[derive(Clone, Copy, Debug, Default, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ReloadScope {
+    #[default]
+    Unchanged,
+    OutboundsOnly,
+    Full,
+}
+
+#[derive(Debug, Default, Serialize, PartialEq, Eq)]
+pub struct ConfigDiff {
+    pub added_nodes: Vec<String>,
+    pub removed_nodes: Vec<String>,
+    /// tag 相同但内容（服务器、密码等）发生变化的节点
+    pub changed_nodes: Vec<String>,
+    /// 发生变化的顶层配置段，如 dns、route、outbounds
+    pub changed_sections: Vec<String>,
+    pub reload_scope: ReloadScope,
+}
+
+/// 提交问题报告用的诊断信息，订阅地址与密码、token 等均已脱敏
+#[derive(Serialize)]
+pub struct DiagnosticsBundle {
+    pub generated_at: String,
+    pub miao_version: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sing_box_version: Option<String>,
+    pub system: SystemInfo,
+    pub running: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub warning: Option<String>,
+    /// 当前生效的 miao 配置
+    pub config: serde_json::Value,
+    /// 最近生成的 sing-box 配置
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub generated_config: Option<serde_json::Value>,
+    pub subscriptions: Vec<SubStatus>,
+    /// sing-box 最近的输出
+    pub logs: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct SystemInfo {
+    pub os: String,
+    pub arch: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kernel: Option<String>,
+    pub openwrt: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TestRunResult {
+    pub success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+    pub duration_ms: u64,
+    pub output: String,
+}
+
+/// 单个地区的延迟汇总，只统计测速成功的节点
+#[derive(Debug, Serialize)]
+pub struct RegionLatency {
+    pub region: String,
+    /// 对应的地区分组 outbound tag
+    pub group: String,
+    pub nodes: usize,
+    pub reachable: usize,
+    pub min_ms: Option<u64>,
+    pub median_ms: Option<u64>,
+}
+
+/// 节点健康状态，由每次延迟测试累积更新
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct NodeHealth {
+    pub tag: String,
+    /// 最近一次测试的延迟，失败时为空
+    pub last_latency_ms: Option<u64>,
+    /// 最近一次测试成功的 Unix 时间戳
+    pub last_ok_at: Option<i64>,
+    pub consecutive_failures: u32,
+    /// 是否为当前记住的节点选择
+    pub selected: bool,
+}
+
+/// 经某个节点（连接链路的最后一跳）的累计流量，由 /api/nodes/traffic 汇总
+#[derive(Clone, Debug, Serialize, PartialEq, Eq)]
+pub struct NodeTraffic {
+    pub tag: String,
+    pub upload_bytes: u64,
+    pub download_bytes: u64,
+    /// 与上一次查询相比的速率（字节/秒），首次查询时为空
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub upload_rate: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub download_rate: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LatencyReport {
+    pub nodes: BTreeMap<String, Option<u64>>,
+    pub regions: Vec<RegionLatency>,
+}
+
+/// POST /api/reload 的结果：重新读取配置文件并生成后的节点概况
+#[derive(Debug, Serialize)]
+pub struct ReloadSummary {
+    pub subscriptions: usize,
+    pub manual_nodes: usize,
+    /// 生成的配置中全部节点的 tag
+    pub nodes: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub warning: Option<String>,
+}
+
+/// config.yaml 的一个备份
+#[derive(Debug, Serialize)]
+pub struct ConfigBackup {
+    /// 用于回滚的备份标识，即文件名中 .bak. 之后的部分
+    pub id: String,
+    pub created_at: String,
+    pub size: u64,
 }
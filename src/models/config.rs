@@ -1,4 +1,5 @@
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::BTreeMap;
 
 #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
@@ -8,27 +9,804 @@ pub enum RouteMode {
     Global,
 }
 
-#[derive(Clone, Default, Serialize, Deserialize)]
+/// 使用的内嵌 sing-box 版本通道
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SingBoxChannel {
+    #[default]
+    Stable,
+    Latest,
+}
+
+impl SingBoxChannel {
+    pub fn is_stable(&self) -> bool {
+        *self == SingBoxChannel::Stable
+    }
+}
+
+/// 手动节点与订阅节点同名时的处理方式
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TagCollision {
+    /// 两者都保留，订阅节点改名为 `名称 (2)` 等
+    #[default]
+    Rename,
+    /// 丢弃同名的订阅节点
+    PreferManual,
+    /// 丢弃同名的手动节点
+    PreferSub,
+}
+
+impl TagCollision {
+    fn is_rename(&self) -> bool {
+        *self == Self::Rename
+    }
+}
+
+/// 订阅条目：既可写成纯 URL 字符串，也可写成带选项的对象
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(remote = "Self")]
+pub struct Subscription {
+    pub url: String,
+    /// 最多保留的节点数，按 limit_by 选取
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<usize>,
+    #[serde(default, skip_serializing_if = "LimitBy::is_first")]
+    pub limit_by: LimitBy,
+    /// 合并优先级，越大越靠前；同名节点由优先级高的订阅保留原名
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub priority: i32,
+    /// 双向 TLS 客户端证书（PEM 路径），覆盖全局 client_cert/client_key
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_cert: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_key: Option<String>,
+    /// 该订阅的定时刷新间隔（秒），覆盖全局 refresh_interval_secs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refresh_interval_secs: Option<u64>,
+    /// 请求订阅时使用的 User-Agent，默认 clash-meta
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_agent: Option<String>,
+    /// 请求订阅时附加的请求头，如订阅在 Cloudflare 之后时带上 cf_clearance Cookie
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "SubscriptionFormat::is_clash")]
+    pub format: SubscriptionFormat,
+    /// 该订阅的节点筛选（regions/include/exclude），未设置任何一项时使用全局 node_filter
+    #[serde(flatten)]
+    pub filter: NodeFilter,
+}
+
+/// 订阅设置了 limit 时保留哪些节点
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LimitBy {
+    /// 按订阅中的顺序取前 N 个
+    #[default]
+    First,
+    /// 按最近一次测得的延迟取最快的 N 个，没有延迟记录的节点排在最后
+    Fastest,
+}
+
+impl LimitBy {
+    fn is_first(&self) -> bool {
+        *self == Self::First
+    }
+}
+
+/// 按名称筛选订阅节点，各项同时生效
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NodeFilter {
+    /// 只保留名称可识别为这些地区（如 HK、JP）的节点，为空时不过滤
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub regions: Vec<String>,
+    /// 只保留名称匹配该正则的节点
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub include: Option<String>,
+    /// 丢弃名称匹配该正则的节点
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exclude: Option<String>,
+}
+
+impl NodeFilter {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// 订阅内容格式：Clash YAML，或直接是 sing-box outbound 的 JSON 数组
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SubscriptionFormat {
+    #[default]
+    #[serde(rename = "clash")]
+    Clash,
+    #[serde(rename = "singbox-json")]
+    SingboxJson,
+}
+
+impl SubscriptionFormat {
+    fn is_clash(&self) -> bool {
+        *self == Self::Clash
+    }
+}
+
+fn is_zero(value: &i32) -> bool {
+    *value == 0
+}
+
+impl Subscription {
+    fn has_options(&self) -> bool {
+        *self != Subscription::from(self.url.as_str())
+    }
+
+    /// 订阅自身定义了筛选时使用自身的，否则使用全局筛选
+    pub fn resolved_filter<'a>(&'a self, global: Option<&'a NodeFilter>) -> &'a NodeFilter {
+        if self.filter.is_empty() {
+            global.unwrap_or(&self.filter)
+        } else {
+            &self.filter
+        }
+    }
+}
+
+impl From<&str> for Subscription {
+    fn from(url: &str) -> Self {
+        Self::from(url.to_string())
+    }
+}
+
+impl From<String> for Subscription {
+    fn from(url: String) -> Self {
+        Self {
+            url,
+            ..Default::default()
+        }
+    }
+}
+
+impl Serialize for Subscription {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if self.has_options() {
+            Subscription::serialize(self, serializer)
+        } else {
+            serializer.serialize_str(&self.url)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Subscription {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        // 只在反序列化时短暂存在，不必为体积差异装箱
+        #[allow(clippy::large_enum_variant)]
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Url(String),
+            Detailed(#[serde(with = "Subscription")] Subscription),
+        }
+
+        Ok(match Repr::deserialize(deserializer)? {
+            Repr::Url(url) => url.into(),
+            Repr::Detailed(sub) => sub,
+        })
+    }
+}
+
+/// 按进程名分流的规则，转换为 sing-box 的 process_name 路由规则
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AppRule {
+    pub process: String,
+    /// direct、proxy 或某个节点 tag
+    pub outbound: String,
+}
+
+/// 按目标端口分流的规则，ports 为逗号分隔的端口或范围，如 "3478-3480,27015"
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PortRule {
+    pub ports: String,
+    /// direct、proxy 或某个节点 tag
+    pub outbound: String,
+}
+
+/// 按嗅探到的域名（TLS SNI、HTTP Host）分流的规则，如让 netflix.com 固定走某个节点
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DomainRule {
+    /// 匹配该后缀的域名，如 "netflix.com" 同时匹配 www.netflix.com
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub domain_suffix: Vec<String>,
+    /// 完整匹配的域名
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub domain: Vec<String>,
+    /// direct、proxy 或某个节点 tag
+    pub outbound: String,
+}
+
+/// 自定义节点分组：名称匹配任一正则的节点归入该分组，一个节点可以属于多个分组
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NodeGroup {
+    /// 分组名，同时作为 selector 的 tag，如 "Streaming"
+    pub name: String,
+    /// 匹配节点名称的正则，如 "(?i)netflix|🎬"
+    pub patterns: Vec<String>,
+}
+
+/// 规则集命中后的动作：直连、走代理或拒绝连接
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RuleSetAction {
+    Direct,
+    Proxy,
+    Block,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RuleSetFormat {
+    #[default]
+    Binary,
+    Source,
+}
+
+/// 远程规则集（如 geosite-google），由 sing-box 启动时下载
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RuleSetRule {
+    pub tag: String,
+    pub url: String,
+    pub action: RuleSetAction,
+    /// .srs 为 binary，.json 为 source
+    #[serde(default)]
+    pub format: RuleSetFormat,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DnsServerType {
+    #[default]
+    Udp,
+    Tls,
+    Https,
+    Quic,
+}
+
+/// 单个 DNS 服务器。https 类型的 address 为完整 URL（如 https://1.1.1.1/dns-query），
+/// 其余类型为 host 或 host:port
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DnsServer {
+    #[serde(rename = "type", default)]
+    pub server_type: DnsServerType,
+    pub address: String,
+}
+
+/// 覆盖模板中的远程（经代理）与本地（直连）DNS 服务器
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DnsConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remote: Option<DnsServer>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub local: Option<DnsServer>,
+    /// 未命中 DNS 规则时使用的服务器 tag，默认为模板中的远程服务器 cfdns
+    #[serde(rename = "final", default, skip_serializing_if = "Option::is_none")]
+    pub final_server: Option<String>,
+    /// 去掉把国内域名分流到本地 DNS 的规则，所有查询都经 final 服务器
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub no_split: bool,
+}
+
+/// 覆盖模板中 sing-box 自身的 log 设置，未设置的字段保持模板默认
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SingLogConfig {
+    /// trace、debug、info、warn、error、fatal 或 panic
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub level: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<bool>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub disabled: bool,
+    /// 写入该文件而不是标准输出；设置后面板日志中不再有 sing-box 的输出
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<String>,
+}
+
+/// 定期清理：压缩轮转后的日志、删除多余的配置备份、淘汰过期的订阅缓存
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HousekeepingConfig {
+    #[serde(default = "default_housekeeping_interval")]
+    pub interval_secs: u64,
+    /// 订阅缓存的最长保留时间（秒），未设置时不淘汰
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sub_cache_ttl_secs: Option<u64>,
+    /// 保留的已压缩轮转日志数量（每个日志文件分别计数）
+    #[serde(default = "default_retention")]
+    pub log_retention: usize,
+    /// 保留的配置备份数量
+    #[serde(default = "default_retention")]
+    pub backup_retention: usize,
+}
+
+fn default_housekeeping_interval() -> u64 {
+    3600
+}
+
+fn default_retention() -> usize {
+    DEFAULT_RETENTION
+}
+
+/// hysteria2 拥塞控制：outbound 带有 up_mbps/down_mbps 时客户端使用 Brutal，按给定带宽
+/// 固定速率发送；不带时使用 BBR，根据网络状况自适应
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Hysteria2Tuning {
+    /// 为没有带宽提示的节点补上的默认上行带宽
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub up_mbps: Option<u32>,
+    /// 为没有带宽提示的节点补上的默认下行带宽
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub down_mbps: Option<u32>,
+    /// 去掉所有节点（包括订阅给出的）的带宽提示，统一使用 BBR
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub ignore_bandwidth: bool,
+}
+
+/// 额外输出的 sing-box 配置：复用同一次获取的订阅节点，按各自的筛选和模板补丁
+/// 写到 path（如给移动设备用的精简配置）
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ConfigOutput {
+    pub path: String,
+    /// 叠加在全局 template_patch 之上的 JSON merge patch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub template_patch: Option<serde_json::Value>,
+    /// 只筛选订阅节点，手动节点始终保留
+    #[serde(flatten)]
+    pub filter: NodeFilter,
+}
+
+/// hysteria2 节点带宽测速：sing-box 启动后经各节点下载、上传 test_bytes 字节，
+/// 测得的速率在下次生成配置时写入 up_mbps/down_mbps
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BandwidthProbeConfig {
+    #[serde(default = "default_probe_bytes")]
+    pub test_bytes: u64,
+    #[serde(default = "default_probe_download_url")]
+    pub download_url: String,
+    #[serde(default = "default_probe_upload_url")]
+    pub upload_url: String,
+}
+
+/// 单次测速传输量上限
+pub const MAX_PROBE_BYTES: u64 = 50_000_000;
+
+fn default_probe_bytes() -> u64 {
+    10_000_000
+}
+
+fn default_probe_download_url() -> String {
+    "https://speed.cloudflare.com/__down?bytes=10000000".to_string()
+}
+
+fn default_probe_upload_url() -> String {
+    "https://speed.cloudflare.com/__up".to_string()
+}
+
+/// TUN 网卡 MTU：固定数值，或 "auto" 表示使用默认路由网卡的 MTU
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TunMtu {
+    Auto,
+    Fixed(u32),
+}
+
+impl Serialize for TunMtu {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            TunMtu::Auto => serializer.serialize_str("auto"),
+            TunMtu::Fixed(mtu) => serializer.serialize_u32(*mtu),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for TunMtu {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Fixed(u32),
+            Keyword(String),
+        }
+
+        match Repr::deserialize(deserializer)? {
+            Repr::Fixed(mtu) => Ok(TunMtu::Fixed(mtu)),
+            Repr::Keyword(keyword) if keyword.eq_ignore_ascii_case("auto") => Ok(TunMtu::Auto),
+            Repr::Keyword(other) => Err(serde::de::Error::custom(format!(
+                "tun_mtu must be \"auto\" or a number, got \"{other}\""
+            ))),
+        }
+    }
+}
+
+/// 订阅节点名称的转换操作，按配置顺序依次应用
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum NameTransform {
+    /// 移除 emoji（含国旗、变体选择符等）
+    StripEmoji,
+    /// 去除首尾空白
+    Trim,
+    /// 正则替换，replacement 支持 $1 等捕获组引用
+    Replace {
+        pattern: String,
+        #[serde(default)]
+        replacement: String,
+    },
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
 pub struct Config {
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub port: Option<u16>,
     #[serde(default)]
-    pub subs: Vec<String>,
+    pub subs: Vec<Subscription>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub vps_ip: Option<String>,
     #[serde(default)]
     pub nodes: Vec<String>,
     #[serde(default)]
     pub custom_rules: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub app_rules: Vec<AppRule>,
+    /// 按目标端口分流，规则模式下位于 app_rules 之后
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub port_rules: Vec<PortRule>,
+    /// 按嗅探到的域名分流，规则模式下位于 port_rules 之后，需要开启 sniff
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub domain_rules: Vec<DomainRule>,
+    /// 按名称把节点归入自定义分组，未匹配任何分组的节点归入 Other
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub groups: Vec<NodeGroup>,
+    /// 按规则集分流，规则模式下优先于内置的国内直连规则
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rule_sets: Vec<RuleSetRule>,
+    /// raw.githubusercontent.com 的镜像地址，按顺序尝试，用于下载 GitHub 托管的规则集
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rule_set_mirrors: Vec<String>,
+    /// 未命中任何规则时使用的 outbound，默认 proxy；全局模式下始终为 proxy
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub route_final: Option<String>,
+    /// 远程 DNS 服务器经由的 outbound（通常是某个低延迟节点），默认经 proxy 选择器
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dns_outbound: Option<String>,
+    /// 订阅转换时原样复制到 outbound 的额外 Clash 字段
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub passthrough_keys: Vec<String>,
+    /// 订阅定时刷新间隔（秒），未设置时不定时刷新；单个订阅可用同名字段覆盖
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refresh_interval_secs: Option<u64>,
+    /// 订阅节点名称的转换规则，同时作用于 selector 列表和 outbound tag
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub name_transforms: Vec<NameTransform>,
+    /// 全局节点筛选，作用于没有定义自身筛选的订阅
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub node_filter: Option<NodeFilter>,
+    /// 节点重名时追加协议后缀（如 "Tokyo [hy2]"）区分，而不是追加序号
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub protocol_tag_suffix: bool,
+    /// 按协议、服务器与端口识别节点：相同标识的节点只保留第一个，记住的选择在
+    /// 节点改名（如 "JP 01" 变为 "JP 02"）后仍能恢复
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub stable_node_identity: bool,
+    /// 生成配置时解析订阅节点的 server 主机名，去掉无法解析的节点（会产生额外的 DNS 查询）
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub resolve_check: bool,
+    /// 订阅列表中有重复 URL 时拒绝启动，而不是去重并警告
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub strict_subs: bool,
+    /// 手动节点与订阅节点同名时的处理方式：rename（默认）、prefer_manual、prefer_sub
+    #[serde(default, skip_serializing_if = "TagCollision::is_rename")]
+    pub tag_collision: TagCollision,
+    /// 允许覆盖不是由 miao 生成的 config.json（默认拒绝，以免覆盖手写的配置）
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub overwrite_foreign_config: bool,
+    /// 获取订阅时使用的双向 TLS 客户端证书和私钥（PEM 路径）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_cert: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_key: Option<String>,
+    #[serde(default, skip_serializing_if = "SingBoxChannel::is_stable")]
+    pub sing_box_channel: SingBoxChannel,
+    /// 追加到 `sing-box run -c <config>` 之后的额外参数
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sing_box_args: Vec<String>,
+    /// 存放生成的 config.json 的目录，默认 /tmp/miao-sing-box
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sing_box_config_dir: Option<String>,
+    /// sing-box 数据目录（-D），存放规则集、面板和缓存，默认 /tmp/miao-sing-box
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sing_box_data_dir: Option<String>,
+    /// sing-box 二进制解压目录，默认 /tmp/miao-sing-box
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sing_box_binary_dir: Option<String>,
+    /// 启动 sing-box 后等待多久（毫秒）确认进程仍在运行，默认 2000
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sing_box_start_grace_ms: Option<u64>,
+    /// 停止 sing-box 时发送 SIGTERM 后等待其退出的时间（毫秒），超时后强制结束，默认 3000
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sing_box_stop_grace_ms: Option<u64>,
+    /// 启动 sing-box 子进程时额外设置的环境变量
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub sing_box_env: BTreeMap<String, String>,
+    /// 在注入节点前应用到内置模板上的 JSON merge patch（RFC 7386）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub template_patch: Option<serde_json::Value>,
+    /// 为未自带解析策略的节点 outbound 统一设置域名解析策略（如 ipv4_only）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub outbound_domain_strategy: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dns: Option<DnsConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sing_log: Option<SingLogConfig>,
+    /// 未设置时不做定期清理
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub housekeeping: Option<HousekeepingConfig>,
+    /// TUN 网卡 MTU，未设置时使用模板中的 9000
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tun_mtu: Option<TunMtu>,
+    /// 启动后先等待的秒数，再获取订阅并启动 sing-box
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub startup_delay_secs: Option<u64>,
+    /// 获取订阅前最多等待网络就绪（有默认路由且第一个订阅的主机名可以解析）的秒数
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wait_for_network_secs: Option<u64>,
+    /// 启动时生成配置后是否自动启动 sing-box；关闭后需通过 /api/service/start 手动启动
+    #[serde(default = "default_true", skip_serializing_if = "is_true")]
+    pub autostart: bool,
+    /// 是否启用 sing-box 的 Clash API（127.0.0.1:6262）；关闭后节点切换、延迟测试、
+    /// 流量统计、记住的节点恢复与带宽测速均不可用
+    #[serde(default = "default_true", skip_serializing_if = "is_true")]
+    pub enable_clash_api: bool,
+    /// 是否保留模板中的 sniff 路由规则（协议/域名嗅探）
+    #[serde(default = "default_true", skip_serializing_if = "is_true")]
+    pub sniff: bool,
+    /// 嗅探超时（毫秒），未设置时使用 sing-box 默认值
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sniff_timeout_ms: Option<u64>,
+    /// 只嗅探列出的协议（如 tls、http、quic），为空时嗅探全部
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sniff_protocols: Vec<String>,
+    /// 为每个节点 outbound 添加 `_source` 字段标注来源（订阅 URL 或 manual），
+    /// sing-box 校验不通过时自动去除
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub annotate_sources: bool,
+    /// 测量 hysteria2 节点的实际带宽并据此设置 up_mbps/down_mbps，开销较大，默认关闭
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bandwidth_probe: Option<BandwidthProbeConfig>,
+    /// hysteria2 节点的默认带宽或统一改用 BBR
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hysteria2: Option<Hysteria2Tuning>,
+    /// 所有启用 TLS 的节点默认的 TLS 版本范围（"1.0" 到 "1.3"），节点自带的设置优先
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls_min_version: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls_max_version: Option<String>,
+    /// 每次生成配置时额外写出的 sing-box 配置文件
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub outputs: Vec<ConfigOutput>,
+    /// 按节点名称识别地区，生成地区 urltest 分组并按测得的地区延迟排在 proxy 最前面
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub region_groups: bool,
+    /// 自定义首页 HTML 文件，每次请求时检查修改时间；文件不存在时使用内置页面
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub index_path: Option<String>,
+    /// 只读 API：拒绝所有修改类请求（非 GET/HEAD），携带 admin_token 的请求除外
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub readonly_api: bool,
+    /// 只读模式下获得完整权限的令牌，通过 `Authorization: Bearer <token>` 传递
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub admin_token: Option<String>,
+    /// 设置后 /api/subscription、/api/config、/api/config/clash 需要携带 `?token=<值>`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subscription_token: Option<String>,
+    /// 同时处理的 API 请求上限，超出的请求短暂排队，排队超时返回 503；修改后需重启生效
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_concurrent_requests: Option<usize>,
+    /// 订阅全部失败且没有缓存时生成仅直连的配置，让 TUN 以直通模式启动而不是直接失败
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub direct_fallback: bool,
+    /// 启动时生成配置失败则立即以非零状态退出，不使用缓存或直连兜底，交给外部守护进程重启
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub fail_fast: bool,
+    /// 关闭日志脱敏，密码、uuid 与订阅地址的查询参数按原样输出，仅用于调试
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub log_secrets: bool,
+    /// 主备订阅：按合并顺序逐个获取，累计可用节点达到 failover_min_nodes 后跳过其余订阅，
+    /// 而不是合并所有订阅
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub subscription_failover: bool,
+    /// subscription_failover 下停止获取所需的最少节点数，默认 1
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub failover_min_nodes: Option<usize>,
+    /// 单个订阅（或 proxy provider）响应体的最大字节数，默认 10 MiB，超出时中止下载
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_sub_bytes: Option<usize>,
     #[serde(default, skip_serializing, skip_deserializing)]
     pub route_mode: RouteMode,
 }
 
+fn default_true() -> bool {
+    true
+}
+
+fn is_true(value: &bool) -> bool {
+    *value
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            port: None,
+            subs: Vec::new(),
+            vps_ip: None,
+            nodes: Vec::new(),
+            custom_rules: Vec::new(),
+            app_rules: Vec::new(),
+            port_rules: Vec::new(),
+            domain_rules: Vec::new(),
+            groups: Vec::new(),
+            rule_sets: Vec::new(),
+            rule_set_mirrors: Vec::new(),
+            route_final: None,
+            dns_outbound: None,
+            passthrough_keys: Vec::new(),
+            refresh_interval_secs: None,
+            name_transforms: Vec::new(),
+            node_filter: None,
+            protocol_tag_suffix: false,
+            stable_node_identity: false,
+            resolve_check: false,
+            strict_subs: false,
+            tag_collision: TagCollision::default(),
+            overwrite_foreign_config: false,
+            client_cert: None,
+            client_key: None,
+            sing_box_channel: SingBoxChannel::default(),
+            sing_box_args: Vec::new(),
+            sing_box_config_dir: None,
+            sing_box_data_dir: None,
+            sing_box_binary_dir: None,
+            sing_box_start_grace_ms: None,
+            sing_box_stop_grace_ms: None,
+            sing_box_env: BTreeMap::new(),
+            template_patch: None,
+            outbound_domain_strategy: None,
+            dns: None,
+            sing_log: None,
+            tun_mtu: None,
+            housekeeping: None,
+            startup_delay_secs: None,
+            wait_for_network_secs: None,
+            autostart: true,
+            enable_clash_api: true,
+            sniff: true,
+            sniff_timeout_ms: None,
+            sniff_protocols: Vec::new(),
+            annotate_sources: false,
+            bandwidth_probe: None,
+            hysteria2: None,
+            tls_min_version: None,
+            tls_max_version: None,
+            outputs: Vec::new(),
+            region_groups: false,
+            index_path: None,
+            readonly_api: false,
+            admin_token: None,
+            subscription_token: None,
+            max_concurrent_requests: None,
+            direct_fallback: false,
+            fail_fast: false,
+            log_secrets: false,
+            subscription_failover: false,
+            failover_min_nodes: None,
+            max_sub_bytes: None,
+            route_mode: RouteMode::default(),
+        }
+    }
+}
+
 pub const DEFAULT_PORT: u16 = 6161;
+/// 未配置 housekeeping 时保留的配置备份与压缩日志数量
+const DEFAULT_RETENTION: usize = 5;
+const DEFAULT_MAX_CONCURRENT_REQUESTS: usize = 64;
+pub const DEFAULT_MAX_SUB_BYTES: usize = 10 * 1024 * 1024;
+
+impl Config {
+    /// 配置备份保留数量，未配置 housekeeping 时使用默认值
+    pub fn backup_retention(&self) -> usize {
+        self.housekeeping
+            .as_ref()
+            .map_or(DEFAULT_RETENTION, |settings| settings.backup_retention)
+    }
+
+    pub fn max_concurrent_requests(&self) -> usize {
+        self.max_concurrent_requests
+            .unwrap_or(DEFAULT_MAX_CONCURRENT_REQUESTS)
+    }
+
+    pub fn failover_min_nodes(&self) -> usize {
+        self.failover_min_nodes.unwrap_or(1)
+    }
+
+    pub fn max_sub_bytes(&self) -> usize {
+        self.max_sub_bytes.unwrap_or(DEFAULT_MAX_SUB_BYTES)
+    }
+}
 
 #[cfg(test)]
 mod tests {
-    use super::Config;
+    use super::{Config, NodeFilter, Subscription, TunMtu};
+
+    #[test]
+    fn subscription_accepts_plain_url_and_object_forms() {
+        let yaml = r#"
+subs:
+  - https://a.example.com/sub
+  - url: https://b.example.com/sub
+    limit: 20
+"#;
+
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+
+        assert_eq!(
+            config.subs[0],
+            Subscription::from("https://a.example.com/sub")
+        );
+        assert_eq!(config.subs[1].url, "https://b.example.com/sub");
+        assert_eq!(config.subs[1].limit, Some(20));
+    }
+
+    #[test]
+    fn subscription_filter_falls_back_to_global_node_filter() {
+        let yaml = r#"
+node_filter:
+  include: "Japan|日本"
+subs:
+  - https://a.example.com/sub
+  - url: https://b.example.com/sub
+    regions: [JP]
+    exclude: "0\\.1x"
+"#;
+
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+        let global = config.node_filter.as_ref();
+
+        assert_eq!(
+            config.subs[0].resolved_filter(global).include.as_deref(),
+            Some("Japan|日本")
+        );
+        assert_eq!(
+            config.subs[1].resolved_filter(global),
+            &NodeFilter {
+                regions: vec!["JP".to_string()],
+                include: None,
+                exclude: Some(r"0\.1x".to_string()),
+            }
+        );
+    }
+
+    #[test]
+    fn subscription_serializes_as_plain_url_without_options() {
+        let config = Config {
+            subs: vec![
+                "https://a.example.com/sub".into(),
+                Subscription {
+                    url: "https://b.example.com/sub".to_string(),
+                    limit: Some(20),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+
+        let yaml = serde_yaml::to_string(&config).unwrap();
+
+        assert!(yaml.contains("- https://a.example.com/sub\n"));
+        assert!(yaml.contains("- url: https://b.example.com/sub\n  limit: 20\n"));
+    }
 
     #[test]
     fn config_serializes_vps_ip_when_present() {
@@ -39,6 +817,7 @@ mod tests {
             nodes: vec![],
             custom_rules: vec![],
             route_mode: Default::default(),
+            ..Default::default()
         };
 
         let yaml = serde_yaml::to_string(&config).unwrap();
@@ -55,6 +834,7 @@ mod tests {
             nodes: vec![],
             custom_rules: vec![],
             route_mode: Default::default(),
+            ..Default::default()
         };
 
         let yaml = serde_yaml::to_string(&config).unwrap();
@@ -71,6 +851,7 @@ mod tests {
             nodes: vec![],
             custom_rules: vec![],
             route_mode: super::RouteMode::Global,
+            ..Default::default()
         };
 
         let yaml = serde_yaml::to_string(&config).unwrap();
@@ -87,6 +868,7 @@ mod tests {
             nodes: vec![],
             custom_rules: vec![],
             route_mode: Default::default(),
+            ..Default::default()
         };
 
         let yaml = serde_yaml::to_string(&config).unwrap();
@@ -108,4 +890,34 @@ custom_rules: []
 
         assert_eq!(config.route_mode, super::RouteMode::Rule);
     }
+
+    #[test]
+    fn config_autostart_defaults_to_true_and_round_trips_when_disabled() {
+        let config: Config = serde_yaml::from_str("port: 6161\n").unwrap();
+        assert!(config.autostart);
+        assert!(Config::default().autostart);
+        assert!(!serde_yaml::to_string(&config)
+            .unwrap()
+            .contains("autostart"));
+
+        let config: Config = serde_yaml::from_str("autostart: false\n").unwrap();
+        assert!(!config.autostart);
+        assert!(serde_yaml::to_string(&config)
+            .unwrap()
+            .contains("autostart: false"));
+    }
+
+    #[test]
+    fn tun_mtu_accepts_auto_or_number() {
+        let auto: Config = serde_yaml::from_str("tun_mtu: auto").unwrap();
+        assert_eq!(auto.tun_mtu, Some(TunMtu::Auto));
+
+        let fixed: Config = serde_yaml::from_str("tun_mtu: 1400").unwrap();
+        assert_eq!(fixed.tun_mtu, Some(TunMtu::Fixed(1400)));
+        assert!(serde_yaml::to_string(&fixed)
+            .unwrap()
+            .contains("tun_mtu: 1400"));
+
+        assert!(serde_yaml::from_str::<Config>("tun_mtu: big").is_err());
+    }
 }
@@ -5,9 +5,20 @@ pub mod proxy;
 pub mod version;
 
 pub use api::{
-    ApiResponse, ConnectivityResult, RouteModeRequest, StatusData, SubRequest, SubStatus,
+    ApiResponse, ConfigBackup, ConfigDiff, ConnectivityResult, DiagnosticsBundle, ExportFormat,
+    LastExit, LatencyReport, NodeHealth, NodeTraffic, RegionLatency, ReloadScope, ReloadSummary,
+    RouteModeRequest, SetupRequest, StatusData, SubPreview, SubPreviewRequest, SubRequest,
+    SubStatus, SubscriptionExportQuery, SubscriptionUserInfo, SystemInfo, TestRunResult,
 };
-pub use config::{Config, RouteMode, DEFAULT_PORT};
-pub use node::{DeleteNodeRequest, Hysteria2, Hysteria2Obfs, NodeInfo, NodeRequest, Tls};
-pub use proxy::LastProxy;
+pub use config::{
+    AppRule, BandwidthProbeConfig, Config, ConfigOutput, DnsConfig, DnsServer, DnsServerType,
+    DomainRule, HousekeepingConfig, Hysteria2Tuning, LimitBy, NameTransform, NodeFilter, NodeGroup,
+    PortRule, RouteMode, RuleSetAction, RuleSetFormat, RuleSetRule, SingBoxChannel, SingLogConfig,
+    Subscription, SubscriptionFormat, TagCollision, TunMtu, DEFAULT_MAX_SUB_BYTES, DEFAULT_PORT,
+    MAX_PROBE_BYTES,
+};
+pub use node::{
+    DeleteNodeRequest, Hysteria2, Hysteria2Obfs, NodeInfo, NodeRequest, Tls, Transport,
+};
+pub use proxy::{LastProxy, PersistedState, SelectNodeRequest};
 pub use version::{GitHubAsset, GitHubRelease, VersionInfo};
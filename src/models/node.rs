@@ -1,5 +1,7 @@
 use serde::{Deserialize, Serialize};
 
+use crate::models::api::NodeHealth;
+
 #[derive(Clone, Serialize, Deserialize)]
 pub struct Hysteria2 {
     #[serde(rename = "type")]
@@ -15,6 +17,9 @@ pub struct Hysteria2 {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub obfs: Option<Hysteria2Obfs>,
     pub tls: Tls,
+    /// 去掉该节点的带宽提示改用 BBR，不受全局 hysteria2 设置影响；生成配置时移除
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub ignore_bandwidth: bool,
 }
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -24,12 +29,69 @@ pub struct Hysteria2Obfs {
     pub password: String,
 }
 
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(Clone, Default, Serialize, Deserialize)]
 pub struct Tls {
     pub enabled: bool,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub server_name: Option<String>,
     pub insecure: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub alpn: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub utls: Option<Utls>,
+    /// 允许的 TLS 版本范围（如 "1.2"、"1.3"），未设置时使用 sing-box 默认值
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_version: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_version: Option<String>,
+}
+
+/// sing-box 的 V2Ray 传输层，vmess/vless/trojan 共用；quic 没有额外选项
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct Transport {
+    #[serde(rename = "type")]
+    pub transport_type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    /// http 传输层的 Host 列表；ws 的 Host 放在 headers 中
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub host: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub method: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub headers: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub service_name: Option<String>,
+}
+
+impl Transport {
+    pub fn new(transport_type: &str) -> Self {
+        Self {
+            transport_type: transport_type.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// sing-box 的 QUIC 传输层必须与普通 TLS 一起使用，不支持 Reality
+    pub fn check_tls(&self, tls: Option<&serde_json::Value>) -> Result<(), String> {
+        if self.transport_type != "quic" {
+            return Ok(());
+        }
+        let Some(tls) = tls.filter(|tls| tls["enabled"] == true) else {
+            return Err("QUIC transport requires TLS".to_string());
+        };
+        if tls["reality"]["enabled"] == true {
+            return Err("QUIC transport cannot be combined with Reality".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// uTLS 客户端指纹伪装，对应 Clash 的 client-fingerprint
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Utls {
+    pub enabled: bool,
+    pub fingerprint: String,
 }
 
 #[derive(Deserialize, Default)]
@@ -65,6 +127,10 @@ pub struct NodeRequest {
     #[serde(default)]
     pub client_fingerprint: Option<String>,
     #[serde(default)]
+    pub tls_min_version: Option<String>,
+    #[serde(default)]
+    pub tls_max_version: Option<String>,
+    #[serde(default)]
     pub reality_public_key: Option<String>,
     #[serde(default)]
     pub reality_short_id: Option<String>,
@@ -82,6 +148,10 @@ pub struct NodeRequest {
     pub obfs_type: Option<String>,
     #[serde(default)]
     pub obfs_password: Option<String>,
+    #[serde(default)]
+    pub ignore_bandwidth: bool,
+    #[serde(default)]
+    pub domain_strategy: Option<String>,
 }
 
 #[derive(Deserialize)]
@@ -97,11 +167,14 @@ pub struct NodeInfo {
     pub node_type: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub sni: Option<String>,
+    /// 尚未测试过延迟的节点为空
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub health: Option<NodeHealth>,
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{Hysteria2, Hysteria2Obfs, Tls};
+    use super::{Hysteria2, Hysteria2Obfs, Tls, Utls};
 
     #[test]
     fn hysteria2_serializes_obfs_when_enabled() {
@@ -119,9 +192,9 @@ mod tests {
             }),
             tls: Tls {
                 enabled: true,
-                server_name: None,
-                insecure: false,
+                ..Default::default()
             },
+            ignore_bandwidth: false,
         };
 
         let value = serde_json::to_value(node).unwrap();
@@ -143,13 +216,43 @@ mod tests {
             obfs: None,
             tls: Tls {
                 enabled: true,
-                server_name: None,
-                insecure: false,
+                ..Default::default()
             },
+            ignore_bandwidth: false,
         };
 
         let value = serde_json::to_value(node).unwrap();
 
         assert!(value.get("obfs").is_none());
     }
+
+    #[test]
+    fn tls_serializes_alpn_and_utls_only_when_set() {
+        let plain = serde_json::to_value(Tls {
+            enabled: true,
+            ..Default::default()
+        })
+        .unwrap();
+        assert!(plain.get("alpn").is_none());
+        assert!(plain.get("utls").is_none());
+        assert!(plain.get("min_version").is_none());
+
+        let hardened = serde_json::to_value(Tls {
+            enabled: true,
+            server_name: Some("example.com".to_string()),
+            insecure: false,
+            alpn: vec!["h2".to_string(), "http/1.1".to_string()],
+            utls: Some(Utls {
+                enabled: true,
+                fingerprint: "chrome".to_string(),
+            }),
+            min_version: Some("1.3".to_string()),
+            max_version: None,
+        })
+        .unwrap();
+        assert_eq!(hardened["alpn"], serde_json::json!(["h2", "http/1.1"]));
+        assert_eq!(hardened["utls"]["fingerprint"], "chrome");
+        assert_eq!(hardened["min_version"], "1.3");
+        assert!(hardened.get("max_version").is_none());
+    }
 }
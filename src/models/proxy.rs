@@ -1,7 +1,30 @@
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
 pub struct LastProxy {
     pub group: String,
     pub name: String,
+    /// 启用 stable_node_identity 时记录节点的稳定标识（协议、服务器与端口），
+    /// 节点改名后据此找回原来的选择
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub identity: Option<String>,
+}
+
+/// 持久化到 state.json 的运行状态，跨配置重新生成、进程崩溃和重启保留
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct PersistedState {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_proxy: Option<LastProxy>,
+}
+
+#[derive(Deserialize)]
+pub struct SelectNodeRequest {
+    /// selector 分组名，默认 proxy
+    #[serde(default = "default_select_group")]
+    pub group: String,
+    pub name: String,
+}
+
+fn default_select_group() -> String {
+    "proxy".to_string()
 }
@@ -1,10 +1,28 @@
 use std::ffi::OsString;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use crate::error::{AppError, AppResult};
 
 pub const CONFIG_FILENAME: &str = "config.yaml";
+pub const TOML_CONFIG_FILENAME: &str = "config.toml";
 pub const ETC_CONFIG_PATH: &str = "/etc/miao/config.yaml";
+pub const ETC_TOML_CONFIG_PATH: &str = "/etc/miao/config.toml";
+
+/// 配置文件格式，按扩展名判断：.toml 为 TOML，其余均按 YAML 处理
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConfigFormat {
+    Yaml,
+    Toml,
+}
+
+impl ConfigFormat {
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("toml") => Self::Toml,
+            _ => Self::Yaml,
+        }
+    }
+}
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum ConfigPathSource {
@@ -58,15 +76,31 @@ pub fn resolve_config_path() -> AppResult<ConfigPathResolution> {
         });
     }
 
-    let exe_dir_config = std::env::current_exe()
-        .ok()
-        .and_then(|path| path.parent().map(|parent| parent.join(CONFIG_FILENAME)));
+    let exe_dir_config = std::env::current_exe().ok().and_then(|path| {
+        path.parent()
+            .map(|parent| preferred_config_file(parent, |path| path.exists()))
+    });
     let exe_dir_config_exists = exe_dir_config.as_deref().is_some_and(|path| path.exists());
 
-    Ok(resolve_config_path_from_parts(
-        exe_dir_config_exists,
-        exe_dir_config,
-    ))
+    let mut resolution = resolve_config_path_from_parts(exe_dir_config_exists, exe_dir_config);
+    if resolution.source == ConfigPathSource::EtcDefault
+        && !Path::new(ETC_CONFIG_PATH).exists()
+        && Path::new(ETC_TOML_CONFIG_PATH).exists()
+    {
+        resolution.path = PathBuf::from(ETC_TOML_CONFIG_PATH);
+    }
+    Ok(resolution)
+}
+
+/// 目录中同时存在时优先 config.yaml；只有 config.toml 时使用它；都不存在时默认 YAML
+fn preferred_config_file(dir: &Path, exists: impl Fn(&Path) -> bool) -> PathBuf {
+    let yaml = dir.join(CONFIG_FILENAME);
+    let toml = dir.join(TOML_CONFIG_FILENAME);
+    if !exists(&yaml) && exists(&toml) {
+        toml
+    } else {
+        yaml
+    }
 }
 
 fn resolve_config_path_from_parts(
@@ -94,7 +128,8 @@ mod tests {
     use std::path::PathBuf;
 
     use super::{
-        config_arg_from, resolve_config_path_from_parts, ConfigPathSource, ETC_CONFIG_PATH,
+        config_arg_from, preferred_config_file, resolve_config_path_from_parts, ConfigFormat,
+        ConfigPathSource, ETC_CONFIG_PATH,
     };
 
     #[test]
@@ -141,4 +176,30 @@ mod tests {
         assert_eq!(resolution.path, PathBuf::from(ETC_CONFIG_PATH));
         assert_eq!(resolution.source, ConfigPathSource::EtcDefault);
     }
+
+    #[test]
+    fn toml_config_is_used_only_when_yaml_is_absent() {
+        let dir = std::path::Path::new("/opt/miao");
+
+        assert_eq!(
+            preferred_config_file(dir, |path| path.ends_with("config.toml")),
+            PathBuf::from("/opt/miao/config.toml")
+        );
+        assert_eq!(
+            preferred_config_file(dir, |_| true),
+            PathBuf::from("/opt/miao/config.yaml")
+        );
+        assert_eq!(
+            preferred_config_file(dir, |_| false),
+            PathBuf::from("/opt/miao/config.yaml")
+        );
+        assert_eq!(
+            ConfigFormat::from_path(std::path::Path::new("/etc/miao/miao.TOML")),
+            ConfigFormat::Toml
+        );
+        assert_eq!(
+            ConfigFormat::from_path(std::path::Path::new("/etc/miao/config.yml")),
+            ConfigFormat::Yaml
+        );
+    }
 }
@@ -1,54 +1,238 @@
 use std::sync::Arc;
+use std::time::Duration;
 
 use axum::{
+    extract::{Request, State},
+    http::{header::AUTHORIZATION, Method, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
     routing::{any, delete, get, post},
     Router,
 };
 
 use crate::handlers::{
     clash::{proxy_clash_http, proxy_clash_traffic},
-    nodes::{add_node, delete_node, get_nodes},
-    proxy::set_last_proxy,
-    service::{get_status, set_route_mode, start_service, stop_service, test_connectivity},
+    config::{
+        export_subscription, get_clash_config, get_config_diff, get_generated_config,
+        list_config_backups, reload_config_file, rollback_config, test_run,
+    },
+    diagnostics::get_diagnostics,
+    logs::stream_sing_logs,
+    nodes::{
+        add_node, delete_node, get_node_health, get_node_latency, get_node_traffic, get_nodes,
+    },
+    proxy::{select_node, set_last_proxy},
+    service::{
+        get_health, get_ready, get_status, restart_service, set_route_mode, start_service,
+        stop_service, test_connectivity,
+    },
+    setup::setup,
     static_assets::{serve_favicon, serve_index},
-    subs::{add_sub, delete_sub, get_subs, refresh_subs},
+    subs::{add_sub, delete_sub, get_subs, preview_sub, refresh_subs},
     version::{get_version, upgrade},
 };
+use crate::responses::status_error;
 use crate::state::AppState;
 
+fn bearer_token(request: &Request) -> Option<&str> {
+    request
+        .headers()
+        .get(AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+}
+
+/// 逐字节比较，耗时与令牌内容无关
+pub(crate) fn token_matches(provided: &str, expected: &str) -> bool {
+    provided.len() == expected.len()
+        && provided
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0u8, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
+
+/// 只读模式下拦截修改类请求（启停 sing-box、增删节点/订阅、切换代理等），
+/// 读取类接口照常开放；携带正确 admin_token 的请求不受限制
+async fn readonly_guard(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let is_read = matches!(*request.method(), Method::GET | Method::HEAD);
+    if !is_read {
+        let config = state.config.read().await;
+        let is_admin = config
+            .admin_token
+            .as_deref()
+            .zip(bearer_token(&request))
+            .is_some_and(|(expected, provided)| token_matches(provided, expected));
+        if config.readonly_api && !is_admin {
+            return status_error::<()>(StatusCode::FORBIDDEN, "API is in read-only mode")
+                .into_response();
+        }
+    }
+    next.run(request).await
+}
+
+fn query_token(request: &Request) -> Option<String> {
+    url::form_urlencoded::parse(request.uri().query()?.as_bytes())
+        .find(|(key, _)| key == "token")
+        .map(|(_, value)| value.into_owned())
+}
+
+/// 返回节点密码、UUID 等凭据的接口（生成的配置、Clash 配置、订阅导出）在设置了
+/// subscription_token 时需要携带 `?token=<值>` 或 `Authorization: Bearer <值>`；
+/// 携带正确 admin_token 的请求同样放行
+async fn subscription_token_guard(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let config = state.config.read().await;
+    if let Some(expected) = config.subscription_token.as_deref() {
+        let bearer = bearer_token(&request);
+        let authorized = query_token(&request)
+            .as_deref()
+            .or(bearer)
+            .is_some_and(|provided| token_matches(provided, expected))
+            || config
+                .admin_token
+                .as_deref()
+                .zip(bearer)
+                .is_some_and(|(admin, provided)| token_matches(provided, admin));
+        if !authorized {
+            return status_error::<()>(
+                StatusCode::UNAUTHORIZED,
+                "Invalid or missing subscription token",
+            )
+            .into_response();
+        }
+    }
+    drop(config);
+    next.run(request).await
+}
+
+/// 依赖 sing-box Clash API 的接口在 enable_clash_api 关闭时直接返回明确的错误，
+/// 而不是连接失败后的 502
+async fn clash_api_guard(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if !state.config.read().await.enable_clash_api {
+        return status_error::<()>(
+            StatusCode::CONFLICT,
+            "Clash API disabled (enable_clash_api: false)",
+        )
+        .into_response();
+    }
+    next.run(request).await
+}
+
+/// 超出并发上限的请求最多排队等待的时间
+const REQUEST_QUEUE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 限制同时处理的请求数，避免轮询高峰时大量请求同时拉起 sing-box 子进程耗尽文件描述符
+async fn concurrency_limit(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let permit = tokio::time::timeout(REQUEST_QUEUE_TIMEOUT, state.request_limit.acquire()).await;
+    let Ok(Ok(_permit)) = permit else {
+        return status_error::<()>(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Server is busy, please retry later",
+        )
+        .into_response();
+    };
+    next.run(request).await
+}
+
 pub fn build_router(app_state: Arc<AppState>) -> Router {
+    let clash_routes = Router::new()
+        .route("/api/clash/traffic", get(proxy_clash_traffic))
+        .route("/api/clash/{*path}", any(proxy_clash_http))
+        .route("/api/nodes/latency", get(get_node_latency))
+        .route("/api/nodes/select", post(select_node))
+        .route("/api/nodes/traffic", get(get_node_traffic))
+        .route_layer(middleware::from_fn_with_state(
+            app_state.clone(),
+            clash_api_guard,
+        ));
+    let credential_routes = Router::new()
+        .route("/api/config", get(get_generated_config))
+        .route("/api/config/clash", get(get_clash_config))
+        .route("/api/subscription", get(export_subscription))
+        .route_layer(middleware::from_fn_with_state(
+            app_state.clone(),
+            subscription_token_guard,
+        ));
+    // 探针不受并发上限影响，面板请求排队时仍能及时响应
+    let probe_routes = Router::new()
+        .route("/api/health", get(get_health))
+        .route("/api/ready", get(get_ready));
+
     Router::new()
         .route("/", get(serve_index))
         .route("/favicon.svg", get(serve_favicon))
         .route("/api/status", get(get_status))
         .route("/api/service/start", post(start_service))
         .route("/api/service/stop", post(stop_service))
+        .route("/api/service/restart", post(restart_service))
         .route("/api/route-mode", post(set_route_mode))
         .route("/api/connectivity", post(test_connectivity))
-        .route("/api/clash/traffic", get(proxy_clash_traffic))
-        .route("/api/clash/{*path}", any(proxy_clash_http))
+        .route("/api/sing/logs/ws", get(stream_sing_logs))
+        .route("/api/config/diff", get(get_config_diff))
+        .route("/api/config/testrun", post(test_run))
+        .route("/api/config/backups", get(list_config_backups))
+        .route("/api/config/rollback/{id}", post(rollback_config))
+        .route("/api/reload", post(reload_config_file))
+        .route("/api/setup", post(setup))
+        .route("/api/diagnostics", get(get_diagnostics))
         .route("/api/version", get(get_version))
         .route("/api/upgrade", post(upgrade))
         .route("/api/subs", get(get_subs))
         .route("/api/subs", post(add_sub))
         .route("/api/subs", delete(delete_sub))
         .route("/api/subs/refresh", post(refresh_subs))
+        .route("/api/subs/preview", post(preview_sub))
         .route("/api/nodes", get(get_nodes))
         .route("/api/nodes", post(add_node))
         .route("/api/nodes", delete(delete_node))
+        .route("/api/nodes/health", get(get_node_health))
         .route("/api/last-proxy", post(set_last_proxy))
+        .merge(clash_routes)
+        .merge(credential_routes)
+        .layer(middleware::from_fn_with_state(
+            app_state.clone(),
+            readonly_guard,
+        ))
+        .layer(middleware::from_fn_with_state(
+            app_state.clone(),
+            concurrency_limit,
+        ))
+        .merge(probe_routes)
         .with_state(app_state)
 }
 
 #[cfg(test)]
 mod tests {
-    use axum::http::{header::CONTENT_TYPE, StatusCode};
+    use axum::http::{
+        header::{AUTHORIZATION, CONTENT_TYPE},
+        StatusCode,
+    };
     use serde_json::json;
     use tower::ServiceExt;
 
+    use super::build_router;
     use crate::{
         models::Config,
-        test_support::{empty_request, json_request, response_json, response_text, test_app},
+        test_support::{
+            app_state, empty_request, json_request, response_json, response_text, test_app,
+        },
     };
 
     #[tokio::test]
@@ -60,6 +244,7 @@ mod tests {
             custom_rules: vec![],
             route_mode: Default::default(),
             vps_ip: None,
+            ..Default::default()
         })
         .await;
 
@@ -79,6 +264,7 @@ mod tests {
             custom_rules: vec![],
             route_mode: Default::default(),
             vps_ip: None,
+            ..Default::default()
         })
         .await;
 
@@ -105,6 +291,7 @@ mod tests {
             custom_rules: vec![],
             route_mode: Default::default(),
             vps_ip: None,
+            ..Default::default()
         })
         .await;
 
@@ -131,6 +318,7 @@ mod tests {
             custom_rules: vec![],
             route_mode: Default::default(),
             vps_ip: None,
+            ..Default::default()
         })
         .await;
 
@@ -152,11 +340,12 @@ mod tests {
     async fn router_returns_subscription_list_payload() {
         let app = test_app(Config {
             port: None,
-            subs: vec!["https://example.com/subscription".to_string()],
+            subs: vec!["https://example.com/subscription".into()],
             nodes: vec![],
             custom_rules: vec![],
             route_mode: Default::default(),
             vps_ip: None,
+            ..Default::default()
         })
         .await;
 
@@ -173,15 +362,39 @@ mod tests {
         assert_eq!(json["data"][0]["node_count"], 0);
     }
 
+    #[tokio::test]
+    async fn router_returns_masked_diagnostics() {
+        let app = test_app(Config {
+            subs: vec!["https://example.com/subscription?token=secret".into()],
+            ..Default::default()
+        })
+        .await;
+
+        let response = app
+            .oneshot(empty_request("GET", "/api/diagnostics"))
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let json = response_json(response).await;
+        assert_eq!(
+            json["data"]["subscriptions"][0]["url"],
+            "https://example.com/***"
+        );
+        assert_eq!(json["data"]["config"]["subs"][0], "https://example.com/***");
+        assert!(!json.to_string().contains("secret"));
+    }
+
     #[tokio::test]
     async fn router_rejects_duplicate_subscription_with_bad_request() {
         let app = test_app(Config {
             port: None,
-            subs: vec!["https://example.com/subscription".to_string()],
+            subs: vec!["https://example.com/subscription".into()],
             nodes: vec![],
             custom_rules: vec![],
             route_mode: Default::default(),
             vps_ip: None,
+            ..Default::default()
         })
         .await;
 
@@ -204,11 +417,12 @@ mod tests {
     async fn router_returns_not_found_when_deleting_missing_subscription() {
         let app = test_app(Config {
             port: None,
-            subs: vec!["https://example.com/subscription".to_string()],
+            subs: vec!["https://example.com/subscription".into()],
             nodes: vec![],
             custom_rules: vec![],
             route_mode: Default::default(),
             vps_ip: None,
+            ..Default::default()
         })
         .await;
 
@@ -238,6 +452,7 @@ mod tests {
             custom_rules: vec![],
             route_mode: Default::default(),
             vps_ip: None,
+            ..Default::default()
         })
         .await;
 
@@ -272,6 +487,7 @@ mod tests {
             custom_rules: vec![],
             route_mode: Default::default(),
             vps_ip: None,
+            ..Default::default()
         })
         .await;
 
@@ -289,4 +505,195 @@ mod tests {
         assert_eq!(json["success"], false);
         assert_eq!(json["message"], "Node not found");
     }
+
+    #[tokio::test]
+    async fn router_rejects_mutations_in_readonly_mode() {
+        let app = test_app(Config {
+            readonly_api: true,
+            admin_token: Some("admin-secret".to_string()),
+            ..Default::default()
+        })
+        .await;
+
+        let response = app
+            .clone()
+            .oneshot(empty_request("POST", "/api/service/stop"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let json = response_json(response).await;
+        assert_eq!(json["success"], false);
+        assert_eq!(json["message"], "API is in read-only mode");
+
+        let response = app
+            .clone()
+            .oneshot(empty_request("GET", "/api/status"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let mut request = json_request(
+            "DELETE",
+            "/api/subs",
+            json!({ "url": "https://example.com/missing" }),
+        );
+        request
+            .headers_mut()
+            .insert(AUTHORIZATION, "Bearer admin-secret".parse().unwrap());
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn router_requires_subscription_token_when_configured() {
+        let app = test_app(Config {
+            subscription_token: Some("share-secret".to_string()),
+            ..Default::default()
+        })
+        .await;
+
+        for uri in [
+            "/api/subscription",
+            "/api/subscription?token=wrong",
+            "/api/config",
+            "/api/config/clash",
+        ] {
+            let response = app
+                .clone()
+                .oneshot(empty_request("GET", uri))
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        }
+
+        let response = app
+            .clone()
+            .oneshot(empty_request(
+                "GET",
+                "/api/subscription?format=clash&token=share-secret",
+            ))
+            .await
+            .unwrap();
+        assert_ne!(response.status(), StatusCode::UNAUTHORIZED);
+
+        for (uri, bearer) in [
+            ("/api/config?token=share-secret", None),
+            ("/api/config/clash", Some("Bearer share-secret")),
+        ] {
+            let mut request = empty_request("GET", uri);
+            if let Some(bearer) = bearer {
+                request
+                    .headers_mut()
+                    .insert(AUTHORIZATION, bearer.parse().unwrap());
+            }
+            let response = app.clone().oneshot(request).await.unwrap();
+            assert_ne!(response.status(), StatusCode::UNAUTHORIZED);
+        }
+    }
+
+    #[tokio::test]
+    async fn router_rejects_clash_endpoints_when_clash_api_disabled() {
+        let app = test_app(Config {
+            enable_clash_api: false,
+            ..Default::default()
+        })
+        .await;
+
+        let response = app
+            .clone()
+            .oneshot(empty_request("GET", "/api/clash/proxies"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        let json = response_json(response).await;
+        assert_eq!(
+            json["message"],
+            "Clash API disabled (enable_clash_api: false)"
+        );
+
+        let response = app
+            .clone()
+            .oneshot(json_request(
+                "POST",
+                "/api/nodes/select",
+                json!({ "group": "proxy", "name": "node" }),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+
+        let response = app
+            .oneshot(empty_request("GET", "/api/status"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn router_reload_keeps_current_config_when_file_is_invalid() {
+        let state = app_state(Config {
+            port: Some(7000),
+            ..Default::default()
+        });
+        std::fs::write(&state.config_path, "port: [not a port\n").unwrap();
+        let app = build_router(state.clone());
+
+        let response = app
+            .clone()
+            .oneshot(empty_request("POST", "/api/reload"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        std::fs::write(&state.config_path, "port: 7001\ntun_mtu: 1\n").unwrap();
+        let response = app
+            .oneshot(empty_request("POST", "/api/reload"))
+            .await
+            .unwrap();
+        let _ = std::fs::remove_file(&state.config_path);
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(state.config.read().await.port, Some(7000));
+    }
+
+    #[tokio::test]
+    async fn router_queues_requests_beyond_concurrency_limit() {
+        let state = app_state(Config {
+            max_concurrent_requests: Some(1),
+            ..Default::default()
+        });
+        let app = build_router(state.clone());
+
+        let permit = state.request_limit.acquire().await.unwrap();
+        let queued = tokio::spawn(app.clone().oneshot(empty_request("GET", "/api/status")));
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        assert!(!queued.is_finished());
+        drop(permit);
+        let response = queued.await.unwrap().unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(state.request_limit.available_permits(), 1);
+
+        state.request_limit.close();
+        let response = app
+            .oneshot(empty_request("GET", "/api/status"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[tokio::test]
+    async fn router_serves_health_probe_when_concurrency_limit_is_exhausted() {
+        let state = app_state(Config {
+            max_concurrent_requests: Some(1),
+            ..Default::default()
+        });
+        let app = build_router(state.clone());
+
+        let _permit = state.request_limit.acquire().await.unwrap();
+        let response = app
+            .oneshot(empty_request("GET", "/api/health"))
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+    }
 }
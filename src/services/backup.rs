@@ -0,0 +1,157 @@
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::error::{AppError, AppResult};
+use crate::models::{Config, ConfigBackup};
+use crate::services::config::parse_config;
+use crate::services::housekeeping::prune_config_backups;
+use crate::services::subscription::format_unix_timestamp;
+use crate::validation::Validator;
+
+/// 备份文件名为 config.yaml.bak.<id>，id 为创建时的 Unix 时间戳（同一秒内重复时追加序号）
+const BACKUP_INFIX: &str = ".bak.";
+
+fn backup_prefix(config_path: &Path) -> String {
+    let file_name = config_path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    format!("{}{}", file_name, BACKUP_INFIX)
+}
+
+fn backup_dir(config_path: &Path) -> PathBuf {
+    match config_path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    }
+}
+
+fn backup_path(config_path: &Path, id: &str) -> PathBuf {
+    backup_dir(config_path).join(format!("{}{}", backup_prefix(config_path), id))
+}
+
+fn is_valid_backup_id(id: &str) -> bool {
+    !id.is_empty()
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// 修改配置前备份当前的 config.yaml，并按 retention 删除最旧的备份
+pub async fn backup_config(config_path: &Path, retention: usize) -> AppResult<Option<String>> {
+    if !tokio::fs::try_exists(config_path).await.unwrap_or(false) {
+        return Ok(None);
+    }
+
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let mut id = timestamp.to_string();
+    let mut suffix = 1;
+    while tokio::fs::try_exists(backup_path(config_path, &id))
+        .await
+        .unwrap_or(false)
+    {
+        suffix += 1;
+        id = format!("{}-{}", timestamp, suffix);
+    }
+
+    tokio::fs::copy(config_path, backup_path(config_path, &id))
+        .await
+        .map_err(|e| AppError::context("Failed to back up config", e))?;
+
+    let config_path = config_path.to_path_buf();
+    let _ =
+        tokio::task::spawn_blocking(move || prune_config_backups(&config_path, retention)).await;
+    Ok(Some(id))
+}
+
+/// 列出现有备份，最新的在前
+pub async fn list_backups(config_path: &Path) -> AppResult<Vec<ConfigBackup>> {
+    let prefix = backup_prefix(config_path);
+    let mut entries = match tokio::fs::read_dir(backup_dir(config_path)).await {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(AppError::context("Failed to read backup directory", e)),
+    };
+
+    let mut backups = Vec::new();
+    while let Some(entry) = entries.next_entry().await? {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let Some(id) = name
+            .strip_prefix(&prefix)
+            .filter(|id| is_valid_backup_id(id))
+        else {
+            continue;
+        };
+        let metadata = entry.metadata().await?;
+        if !metadata.is_file() {
+            continue;
+        }
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |duration| duration.as_secs() as i64);
+        backups.push((
+            modified,
+            ConfigBackup {
+                id: id.to_string(),
+                created_at: format_unix_timestamp(modified),
+                size: metadata.len(),
+            },
+        ));
+    }
+
+    backups.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| b.1.id.cmp(&a.1.id)));
+    Ok(backups.into_iter().map(|(_, backup)| backup).collect())
+}
+
+/// 读取并校验指定备份，返回其中的配置
+pub async fn load_backup(config_path: &Path, id: &str) -> AppResult<Config> {
+    if !is_valid_backup_id(id) {
+        return Err(AppError::message(format!("Invalid backup id '{}'", id)));
+    }
+    let content = tokio::fs::read_to_string(backup_path(config_path, id))
+        .await
+        .map_err(|e| AppError::context(format!("Failed to read backup '{}'", id), e))?;
+    let config = parse_config(&content, config_path)
+        .map_err(|e| AppError::context(format!("Backup '{}' is not a valid config", id), e))?;
+    Validator::config(&config)
+        .map_err(|e| AppError::message(format!("Backup '{}' failed validation: {}", id, e)))?;
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{backup_config, list_backups, load_backup};
+
+    #[tokio::test]
+    async fn backups_can_be_listed_and_loaded() {
+        let dir = std::env::temp_dir().join(format!("miao-backup-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let config_path = dir.join("config.yaml");
+
+        assert_eq!(backup_config(&config_path, 5).await.unwrap(), None);
+
+        std::fs::write(&config_path, "port: 7000\n").unwrap();
+        let first = backup_config(&config_path, 5).await.unwrap().unwrap();
+        std::fs::write(&config_path, "port: 7001\n").unwrap();
+        let second = backup_config(&config_path, 5).await.unwrap().unwrap();
+        std::fs::write(dir.join(format!("config.yaml.bak.{}", "broken")), "port: [").unwrap();
+
+        let backups = list_backups(&config_path).await.unwrap();
+        let restored = load_backup(&config_path, &first).await.unwrap();
+        let broken = load_backup(&config_path, "broken").await;
+        let traversal = load_backup(&config_path, "../config.yaml").await;
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_ne!(first, second);
+        assert_eq!(backups.len(), 3);
+        assert_eq!(restored.port, Some(7000));
+        assert!(broken.is_err());
+        assert!(traversal.is_err());
+    }
+}
@@ -0,0 +1,248 @@
+use futures::StreamExt;
+use std::collections::HashMap;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+use crate::error::{AppError, AppResult};
+use crate::models::{BandwidthProbeConfig, LastProxy};
+use crate::services::config::load_generated_config;
+use crate::services::proxy::put_selection;
+use crate::state::AppState;
+
+/// 测速专用的本地 mixed 入站，经路由规则固定走 PROBE_SELECTOR_TAG
+const PROBE_INBOUND_TAG: &str = "bandwidth-probe-in";
+const PROBE_SELECTOR_TAG: &str = "bandwidth-probe";
+const PROBE_PORT: u16 = 6263;
+const TRANSFER_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// 单个 hysteria2 节点测得的带宽（Mbps）
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct NodeBandwidth {
+    pub up_mbps: u32,
+    pub down_mbps: u32,
+}
+
+fn hysteria2_tags(sing_box_config: &serde_json::Value) -> Vec<String> {
+    sing_box_config["outbounds"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|outbound| outbound["type"] == "hysteria2")
+        .filter_map(|outbound| outbound["tag"].as_str().map(str::to_string))
+        .collect()
+}
+
+/// 用已测得的带宽设置 hysteria2 节点的 up_mbps/down_mbps，并加入测速用的入站、
+/// selector 和路由规则，供 sing-box 启动后逐个测量尚未测速的节点
+pub fn apply_bandwidth_probe(
+    sing_box_config: &mut serde_json::Value,
+    measured: &HashMap<String, NodeBandwidth>,
+) {
+    let tags = hysteria2_tags(sing_box_config);
+    if tags.is_empty() {
+        return;
+    }
+
+    if let Some(outbounds) = sing_box_config["outbounds"].as_array_mut() {
+        for outbound in outbounds.iter_mut() {
+            let Some(bandwidth) = outbound["tag"].as_str().and_then(|tag| measured.get(tag)) else {
+                continue;
+            };
+            outbound["up_mbps"] = serde_json::json!(bandwidth.up_mbps);
+            outbound["down_mbps"] = serde_json::json!(bandwidth.down_mbps);
+        }
+        outbounds.push(serde_json::json!({
+            "type": "selector",
+            "tag": PROBE_SELECTOR_TAG,
+            "outbounds": tags
+        }));
+    }
+    if let Some(inbounds) = sing_box_config["inbounds"].as_array_mut() {
+        inbounds.push(serde_json::json!({
+            "type": "mixed",
+            "tag": PROBE_INBOUND_TAG,
+            "listen": "127.0.0.1",
+            "listen_port": PROBE_PORT
+        }));
+    }
+    if let Some(rules) = sing_box_config["route"]["rules"].as_array_mut() {
+        rules.insert(
+            0,
+            serde_json::json!({
+                "inbound": [PROBE_INBOUND_TAG],
+                "action": "route",
+                "outbound": PROBE_SELECTOR_TAG
+            }),
+        );
+    }
+}
+
+fn to_mbps(bytes: u64, elapsed: Duration) -> u32 {
+    let bits_per_sec = bytes as f64 * 8.0 / elapsed.as_secs_f64().max(0.001);
+    ((bits_per_sec / 1_000_000.0).round() as u32).max(1)
+}
+
+/// 下载最多 test_bytes 字节，达到上限后立即停止读取
+async fn measure_download(
+    client: &reqwest::Client,
+    settings: &BandwidthProbeConfig,
+) -> AppResult<u32> {
+    let started = Instant::now();
+    let response = client
+        .get(&settings.download_url)
+        .timeout(TRANSFER_TIMEOUT)
+        .send()
+        .await
+        .and_then(|res| res.error_for_status())
+        .map_err(|e| AppError::context("Download probe failed", e))?;
+
+    let mut received = 0u64;
+    let mut body = response.bytes_stream();
+    while let Some(chunk) = body.next().await {
+        let chunk = chunk.map_err(|e| AppError::context("Download probe interrupted", e))?;
+        received += chunk.len() as u64;
+        if received >= settings.test_bytes {
+            break;
+        }
+    }
+    if received == 0 {
+        return Err(AppError::message("Download probe received no data"));
+    }
+    Ok(to_mbps(received, started.elapsed()))
+}
+
+async fn measure_upload(
+    client: &reqwest::Client,
+    settings: &BandwidthProbeConfig,
+) -> AppResult<u32> {
+    let payload = vec![0u8; settings.test_bytes as usize];
+    let started = Instant::now();
+    client
+        .post(&settings.upload_url)
+        .timeout(TRANSFER_TIMEOUT)
+        .body(payload)
+        .send()
+        .await
+        .and_then(|res| res.error_for_status())
+        .map_err(|e| AppError::context("Upload probe failed", e))?;
+    Ok(to_mbps(settings.test_bytes, started.elapsed()))
+}
+
+async fn probe_node(
+    state: &AppState,
+    client: &reqwest::Client,
+    tag: &str,
+    settings: &BandwidthProbeConfig,
+) -> AppResult<NodeBandwidth> {
+    put_selection(
+        &state.http_client,
+        &LastProxy {
+            group: PROBE_SELECTOR_TAG.to_string(),
+            name: tag.to_string(),
+            identity: None,
+        },
+    )
+    .await?;
+    let down_mbps = measure_download(client, settings).await?;
+    let up_mbps = measure_upload(client, settings).await?;
+    Ok(NodeBandwidth { up_mbps, down_mbps })
+}
+
+/// 逐个测量尚未测速的 hysteria2 节点，结果在下次生成配置时写入节点。
+/// 顺序执行以免占满线路；同一时间只运行一轮。切换测速节点依赖 Clash API
+pub async fn probe_unmeasured_nodes(state: Arc<AppState>, settings: BandwidthProbeConfig) {
+    if !state.config.read().await.enable_clash_api {
+        warn!("Bandwidth probe skipped: clash API disabled");
+        return;
+    }
+    if state.bandwidth_probing.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    let generated = load_generated_config(&state.sing_box_paths)
+        .await
+        .ok()
+        .flatten()
+        .unwrap_or_default();
+    let measured = state.node_bandwidth.lock().await.clone();
+    let pending: Vec<String> = hysteria2_tags(&generated)
+        .into_iter()
+        .filter(|tag| !measured.contains_key(tag))
+        .collect();
+
+    let client = reqwest::Proxy::all(format!("http://127.0.0.1:{PROBE_PORT}"))
+        .and_then(|proxy| reqwest::Client::builder().proxy(proxy).build());
+    match client {
+        Ok(client) if !pending.is_empty() => {
+            info!(nodes = pending.len(), "Measuring hysteria2 node bandwidth");
+            for tag in pending {
+                match probe_node(&state, &client, &tag, &settings).await {
+                    Ok(bandwidth) => {
+                        info!(
+                            node = %tag,
+                            up_mbps = bandwidth.up_mbps,
+                            down_mbps = bandwidth.down_mbps,
+                            "Measured node bandwidth"
+                        );
+                        state.node_bandwidth.lock().await.insert(tag, bandwidth);
+                    }
+                    Err(e) => warn!(node = %tag, error = %e, "Bandwidth probe failed"),
+                }
+            }
+        }
+        Ok(_) => {}
+        Err(e) => warn!(error = %e, "Failed to build bandwidth probe client"),
+    }
+
+    state.bandwidth_probing.store(false, Ordering::SeqCst);
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use std::collections::HashMap;
+    use std::time::Duration;
+
+    use super::{apply_bandwidth_probe, to_mbps, NodeBandwidth, PROBE_INBOUND_TAG};
+
+    #[test]
+    fn apply_bandwidth_probe_sets_measured_values_and_probe_route() {
+        let mut config = json!({
+            "inbounds": [{"type": "tun", "tag": "tun-in"}],
+            "outbounds": [
+                {"type": "selector", "tag": "proxy", "outbounds": ["hy-a", "hy-b", "ss"]},
+                {"type": "hysteria2", "tag": "hy-a", "up_mbps": 40, "down_mbps": 350},
+                {"type": "hysteria2", "tag": "hy-b"},
+                {"type": "shadowsocks", "tag": "ss"}
+            ],
+            "route": {"rules": [{"action": "sniff"}]}
+        });
+        let measured = HashMap::from([(
+            "hy-a".to_string(),
+            NodeBandwidth {
+                up_mbps: 20,
+                down_mbps: 180,
+            },
+        )]);
+
+        apply_bandwidth_probe(&mut config, &measured);
+
+        assert_eq!(config["outbounds"][1]["up_mbps"], 20);
+        assert_eq!(config["outbounds"][1]["down_mbps"], 180);
+        assert!(config["outbounds"][2].get("up_mbps").is_none());
+        assert_eq!(config["outbounds"][4]["outbounds"], json!(["hy-a", "hy-b"]));
+        assert_eq!(config["inbounds"][1]["tag"], PROBE_INBOUND_TAG);
+        assert_eq!(
+            config["route"]["rules"][0]["inbound"],
+            json!([PROBE_INBOUND_TAG])
+        );
+    }
+
+    #[test]
+    fn to_mbps_rounds_and_never_reports_zero() {
+        assert_eq!(to_mbps(12_500_000, Duration::from_secs(1)), 100);
+        assert_eq!(to_mbps(10, Duration::from_secs(10)), 1);
+    }
+}
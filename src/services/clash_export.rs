@@ -0,0 +1,405 @@
+use serde_json::Value as JsonValue;
+use serde_yaml::{Mapping, Value};
+use tracing::warn;
+
+use crate::error::{AppError, AppResult};
+
+const CLASH_PROXY_GROUP: &str = "proxy";
+
+fn key(name: &str) -> Value {
+    Value::String(name.to_string())
+}
+
+fn insert_str(map: &mut Mapping, name: &str, value: Option<&str>) {
+    if let Some(value) = value.filter(|value| !value.is_empty()) {
+        map.insert(key(name), Value::String(value.to_string()));
+    }
+}
+
+fn insert_bool(map: &mut Mapping, name: &str, value: Option<bool>) {
+    if let Some(value) = value {
+        map.insert(key(name), Value::Bool(value));
+    }
+}
+
+fn json_to_yaml(value: &JsonValue) -> Option<Value> {
+    serde_yaml::to_value(value).ok()
+}
+
+fn str_field<'a>(value: &'a JsonValue, name: &str) -> Option<&'a str> {
+    value.get(name).and_then(|value| value.as_str())
+}
+
+fn apply_tls(proxy: &mut Mapping, outbound: &JsonValue, sni_key: &str, emit_tls_flag: bool) {
+    let Some(tls) = outbound.get("tls").filter(|tls| {
+        tls.get("enabled")
+            .and_then(|enabled| enabled.as_bool())
+            .unwrap_or(false)
+    }) else {
+        return;
+    };
+
+    if emit_tls_flag {
+        proxy.insert(key("tls"), Value::Bool(true));
+    }
+    insert_str(proxy, sni_key, str_field(tls, "server_name"));
+    if tls
+        .get("insecure")
+        .and_then(|insecure| insecure.as_bool())
+        .unwrap_or(false)
+    {
+        proxy.insert(key("skip-cert-verify"), Value::Bool(true));
+    }
+    if let Some(alpn) = tls.get("alpn").and_then(json_to_yaml) {
+        proxy.insert(key("alpn"), alpn);
+    }
+    insert_str(
+        proxy,
+        "client-fingerprint",
+        tls.get("utls")
+            .and_then(|utls| str_field(utls, "fingerprint")),
+    );
+    if let Some(reality) = tls.get("reality") {
+        let mut opts = Mapping::new();
+        insert_str(&mut opts, "public-key", str_field(reality, "public_key"));
+        insert_str(&mut opts, "short-id", str_field(reality, "short_id"));
+        proxy.insert(key("reality-opts"), Value::Mapping(opts));
+    }
+    insert_bool(
+        proxy,
+        "disable-sni",
+        tls.get("disable_sni").and_then(|value| value.as_bool()),
+    );
+}
+
+fn apply_transport(proxy: &mut Mapping, outbound: &JsonValue) -> Result<(), String> {
+    let Some(transport) = outbound.get("transport") else {
+        return Ok(());
+    };
+    let tls_enabled = outbound
+        .get("tls")
+        .and_then(|tls| tls.get("enabled"))
+        .and_then(|enabled| enabled.as_bool())
+        .unwrap_or(false);
+
+    match str_field(transport, "type").unwrap_or_default() {
+        "ws" => {
+            let mut opts = Mapping::new();
+            insert_str(&mut opts, "path", str_field(transport, "path"));
+            if let Some(headers) = transport.get("headers").and_then(json_to_yaml) {
+                opts.insert(key("headers"), headers);
+            }
+            proxy.insert(key("network"), key("ws"));
+            proxy.insert(key("ws-opts"), Value::Mapping(opts));
+        }
+        "grpc" => {
+            let mut opts = Mapping::new();
+            insert_str(
+                &mut opts,
+                "grpc-service-name",
+                str_field(transport, "service_name"),
+            );
+            proxy.insert(key("network"), key("grpc"));
+            proxy.insert(key("grpc-opts"), Value::Mapping(opts));
+        }
+        "http" => {
+            let mut opts = Mapping::new();
+            if let Some(hosts) = transport.get("host").and_then(json_to_yaml) {
+                opts.insert(key("host"), hosts);
+            }
+            if tls_enabled {
+                insert_str(&mut opts, "path", str_field(transport, "path"));
+                proxy.insert(key("network"), key("h2"));
+                proxy.insert(key("h2-opts"), Value::Mapping(opts));
+            } else {
+                insert_str(&mut opts, "method", str_field(transport, "method"));
+                if let Some(path) = str_field(transport, "path") {
+                    opts.insert(key("path"), Value::Sequence(vec![key(path)]));
+                }
+                proxy.insert(key("network"), key("http"));
+                proxy.insert(key("http-opts"), Value::Mapping(opts));
+            }
+        }
+        "quic" => {
+            proxy.insert(key("network"), key("quic"));
+        }
+        other => return Err(format!("unsupported transport '{}'", other)),
+    }
+
+    Ok(())
+}
+
+/// 将 sing-box outbound 转换为 Clash proxy 条目；不支持的协议返回错误原因
+pub fn outbound_to_clash_proxy(outbound: &JsonValue) -> Result<Mapping, String> {
+    let outbound_type = str_field(outbound, "type").ok_or("missing outbound type")?;
+    let tag = str_field(outbound, "tag").ok_or("missing outbound tag")?;
+    let server = str_field(outbound, "server").ok_or("missing outbound server")?;
+    let port = outbound
+        .get("server_port")
+        .and_then(|port| port.as_u64())
+        .ok_or("missing outbound server_port")?;
+
+    let mut proxy = Mapping::new();
+    proxy.insert(key("name"), key(tag));
+
+    let clash_type = match outbound_type {
+        "shadowsocks" => "ss",
+        "hysteria2" | "anytls" | "vmess" | "vless" | "trojan" | "tuic" => outbound_type,
+        other => return Err(format!("unsupported outbound type '{}'", other)),
+    };
+    proxy.insert(key("type"), key(clash_type));
+    proxy.insert(key("server"), key(server));
+    proxy.insert(key("port"), Value::Number(port.into()));
+
+    match outbound_type {
+        "hysteria2" => {
+            insert_str(&mut proxy, "password", str_field(outbound, "password"));
+            if let Some(up) = outbound.get("up_mbps").and_then(|value| value.as_u64()) {
+                proxy.insert(key("up"), key(&format!("{up} Mbps")));
+            }
+            if let Some(down) = outbound.get("down_mbps").and_then(|value| value.as_u64()) {
+                proxy.insert(key("down"), key(&format!("{down} Mbps")));
+            }
+            if let Some(obfs) = outbound.get("obfs") {
+                insert_str(&mut proxy, "obfs", str_field(obfs, "type"));
+                insert_str(&mut proxy, "obfs-password", str_field(obfs, "password"));
+            }
+            apply_tls(&mut proxy, outbound, "sni", false);
+        }
+        "anytls" => {
+            insert_str(&mut proxy, "password", str_field(outbound, "password"));
+            apply_tls(&mut proxy, outbound, "sni", false);
+        }
+        "shadowsocks" => {
+            insert_str(&mut proxy, "cipher", str_field(outbound, "method"));
+            insert_str(&mut proxy, "password", str_field(outbound, "password"));
+        }
+        "vmess" => {
+            insert_str(&mut proxy, "uuid", str_field(outbound, "uuid"));
+            proxy.insert(
+                key("alterId"),
+                Value::Number(
+                    outbound
+                        .get("alter_id")
+                        .and_then(|value| value.as_u64())
+                        .unwrap_or(0)
+                        .into(),
+                ),
+            );
+            insert_str(
+                &mut proxy,
+                "cipher",
+                Some(str_field(outbound, "security").unwrap_or("auto")),
+            );
+            insert_str(
+                &mut proxy,
+                "packet-encoding",
+                str_field(outbound, "packet_encoding"),
+            );
+            apply_tls(&mut proxy, outbound, "servername", true);
+            apply_transport(&mut proxy, outbound)?;
+        }
+        "vless" => {
+            insert_str(&mut proxy, "uuid", str_field(outbound, "uuid"));
+            insert_str(&mut proxy, "flow", str_field(outbound, "flow"));
+            insert_str(
+                &mut proxy,
+                "packet-encoding",
+                str_field(outbound, "packet_encoding"),
+            );
+            apply_tls(&mut proxy, outbound, "servername", true);
+            apply_transport(&mut proxy, outbound)?;
+        }
+        "trojan" => {
+            insert_str(&mut proxy, "password", str_field(outbound, "password"));
+            apply_tls(&mut proxy, outbound, "sni", false);
+            apply_transport(&mut proxy, outbound)?;
+        }
+        "tuic" => {
+            insert_str(&mut proxy, "uuid", str_field(outbound, "uuid"));
+            insert_str(&mut proxy, "password", str_field(outbound, "password"));
+            insert_str(
+                &mut proxy,
+                "congestion-controller",
+                str_field(outbound, "congestion_control"),
+            );
+            insert_str(
+                &mut proxy,
+                "udp-relay-mode",
+                str_field(outbound, "udp_relay_mode"),
+            );
+            insert_bool(
+                &mut proxy,
+                "reduce-rtt",
+                outbound
+                    .get("zero_rtt_handshake")
+                    .and_then(|value| value.as_bool()),
+            );
+            apply_tls(&mut proxy, outbound, "sni", false);
+        }
+        _ => unreachable!("outbound type already checked"),
+    }
+
+    Ok(proxy)
+}
+
+/// 由已合并的节点 outbound 生成最小可用的 Clash 配置（proxies + proxy-groups + rules）
+pub fn build_clash_config(outbounds: &[JsonValue]) -> AppResult<String> {
+    let mut proxies = Vec::new();
+    let mut names = Vec::new();
+
+    for outbound in outbounds {
+        match outbound_to_clash_proxy(outbound) {
+            Ok(proxy) => {
+                if let Some(name) = proxy.get("name").and_then(|name| name.as_str()) {
+                    names.push(key(name));
+                }
+                proxies.push(Value::Mapping(proxy));
+            }
+            Err(e) => {
+                let tag = str_field(outbound, "tag").unwrap_or("<unknown>");
+                warn!(tag = %tag, reason = %e, "Skipping outbound for Clash export");
+            }
+        }
+    }
+
+    if proxies.is_empty() {
+        return Err(AppError::message(
+            "No nodes could be converted to Clash format",
+        ));
+    }
+
+    let mut group = Mapping::new();
+    group.insert(key("name"), key(CLASH_PROXY_GROUP));
+    group.insert(key("type"), key("select"));
+    names.push(key("DIRECT"));
+    group.insert(key("proxies"), Value::Sequence(names));
+
+    let rules = [
+        "DOMAIN-SUFFIX,hdslb.com,DIRECT".to_string(),
+        "GEOIP,LAN,DIRECT,no-resolve".to_string(),
+        "GEOIP,CN,DIRECT".to_string(),
+        format!("MATCH,{CLASH_PROXY_GROUP}"),
+    ];
+
+    let mut clash = Mapping::new();
+    clash.insert(key("mixed-port"), Value::Number(7890.into()));
+    clash.insert(key("allow-lan"), Value::Bool(false));
+    clash.insert(key("mode"), key("rule"));
+    clash.insert(key("log-level"), key("info"));
+    clash.insert(key("proxies"), Value::Sequence(proxies));
+    clash.insert(
+        key("proxy-groups"),
+        Value::Sequence(vec![Value::Mapping(group)]),
+    );
+    clash.insert(
+        key("rules"),
+        Value::Sequence(rules.iter().map(|rule| key(rule)).collect()),
+    );
+
+    Ok(serde_yaml::to_string(&Value::Mapping(clash))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{build_clash_config, outbound_to_clash_proxy};
+    use crate::services::node_parser::parse_clash_proxies;
+
+    #[test]
+    fn outbound_to_clash_proxy_maps_vless_reality_grpc() {
+        let outbound = json!({
+            "type": "vless",
+            "tag": "vless-reality",
+            "server": "vl.example.com",
+            "server_port": 443,
+            "uuid": "223e4567-e89b-12d3-a456-426614174000",
+            "flow": "xtls-rprx-vision",
+            "tls": {
+                "enabled": true,
+                "insecure": false,
+                "server_name": "vl.example.com",
+                "utls": {"enabled": true, "fingerprint": "chrome"},
+                "reality": {"enabled": true, "public_key": "public-key", "short_id": "abcd"}
+            },
+            "transport": {"type": "grpc", "service_name": "edge"}
+        });
+
+        let proxy = outbound_to_clash_proxy(&outbound).unwrap();
+
+        assert_eq!(proxy["type"], "vless");
+        assert_eq!(proxy["tls"], true);
+        assert_eq!(proxy["servername"], "vl.example.com");
+        assert_eq!(proxy["client-fingerprint"], "chrome");
+        assert_eq!(proxy["reality-opts"]["public-key"], "public-key");
+        assert_eq!(proxy["network"], "grpc");
+        assert_eq!(proxy["grpc-opts"]["grpc-service-name"], "edge");
+    }
+
+    #[test]
+    fn outbound_to_clash_proxy_rejects_unsupported_types() {
+        let err = outbound_to_clash_proxy(&json!({
+            "type": "wireguard",
+            "tag": "wg",
+            "server": "wg.example.com",
+            "server_port": 51820
+        }))
+        .unwrap_err();
+
+        assert!(err.contains("wireguard"));
+    }
+
+    #[test]
+    fn build_clash_config_round_trips_through_subscription_parser() {
+        let outbounds = vec![
+            json!({
+                "type": "hysteria2",
+                "tag": "hy2",
+                "server": "hy.example.com",
+                "server_port": 443,
+                "password": "pass-hy",
+                "tls": {"enabled": true, "insecure": true, "server_name": "hy.example.com"},
+                "obfs": {"type": "salamander", "password": "obfs-pass"}
+            }),
+            json!({
+                "type": "shadowsocks",
+                "tag": "ss",
+                "server": "ss.example.com",
+                "server_port": 8388,
+                "method": "2022-blake3-aes-128-gcm",
+                "password": "pass-ss"
+            }),
+            json!({
+                "type": "vmess",
+                "tag": "vmess",
+                "server": "vm.example.com",
+                "server_port": 443,
+                "uuid": "123e4567-e89b-12d3-a456-426614174000",
+                "security": "auto",
+                "alter_id": 0,
+                "tls": {"enabled": true, "insecure": false, "server_name": "vm.example.com"},
+                "transport": {"type": "ws", "path": "/ws", "headers": {"Host": "cdn.example.com"}}
+            }),
+        ];
+
+        let yaml = build_clash_config(&outbounds).unwrap();
+        let parsed = parse_clash_proxies(&yaml).unwrap();
+
+        assert!(parsed.errors.is_empty());
+        let round_tripped: Vec<_> = parsed.nodes.into_iter().map(|(_, o)| o).collect();
+        assert_eq!(round_tripped, outbounds);
+
+        let clash: serde_yaml::Value = serde_yaml::from_str(&yaml).unwrap();
+        assert_eq!(clash["proxy-groups"][0]["name"], "proxy");
+        assert_eq!(clash["proxy-groups"][0]["proxies"][3], "DIRECT");
+        assert_eq!(clash["rules"][3], "MATCH,proxy");
+    }
+
+    #[test]
+    fn build_clash_config_errors_without_convertible_nodes() {
+        let err = build_clash_config(&[json!({"type": "direct", "tag": "direct"})]).unwrap_err();
+
+        assert!(err.to_string().contains("No nodes could be converted"));
+    }
+}
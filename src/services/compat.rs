@@ -0,0 +1,146 @@
+use tracing::{info, warn};
+
+use crate::models::SingBoxChannel;
+use crate::services::config::load_generated_config;
+use crate::services::singbox::{sing_box_version, SingBoxPaths};
+
+/// 生成的配置所用特性及引入该特性的 sing-box 版本
+struct FeatureRequirement {
+    feature: &'static str,
+    min_version: semver::Version,
+    used_by: fn(&serde_json::Value) -> bool,
+}
+
+fn array<'a>(
+    value: &'a serde_json::Value,
+    key: &str,
+) -> impl Iterator<Item = &'a serde_json::Value> {
+    value[key].as_array().into_iter().flatten()
+}
+
+fn requirements() -> Vec<FeatureRequirement> {
+    vec![
+        FeatureRequirement {
+            feature: "route rule actions (action: sniff / hijack-dns)",
+            min_version: semver::Version::new(1, 11, 0),
+            used_by: |config| {
+                array(&config["route"], "rules").any(|rule| rule.get("action").is_some())
+            },
+        },
+        FeatureRequirement {
+            feature: "typed DNS servers (type/server instead of address)",
+            min_version: semver::Version::new(1, 12, 0),
+            used_by: |config| {
+                array(&config["dns"], "servers").any(|server| server.get("type").is_some())
+            },
+        },
+        FeatureRequirement {
+            feature: "domain_resolver",
+            min_version: semver::Version::new(1, 12, 0),
+            used_by: |config| {
+                config["route"].get("default_domain_resolver").is_some()
+                    || array(config, "outbounds")
+                        .any(|outbound| outbound.get("domain_resolver").is_some())
+            },
+        },
+        FeatureRequirement {
+            feature: "anytls outbounds",
+            min_version: semver::Version::new(1, 12, 0),
+            used_by: |config| {
+                array(config, "outbounds").any(|outbound| outbound["type"] == "anytls")
+            },
+        },
+        FeatureRequirement {
+            feature: "tun auto_redirect",
+            min_version: semver::Version::new(1, 10, 0),
+            used_by: |config| {
+                array(config, "inbounds").any(|inbound| inbound["auto_redirect"] == true)
+            },
+        },
+    ]
+}
+
+/// 从 "sing-box version 1.12.0-beta.3" 中取出版本号，预发布后缀不参与比较
+pub fn parse_sing_box_version(line: &str) -> Option<semver::Version> {
+    let raw = line.split_whitespace().find_map(|word| {
+        let word = word.trim_start_matches('v');
+        semver::Version::parse(word).ok()
+    })?;
+    Some(semver::Version::new(raw.major, raw.minor, raw.patch))
+}
+
+/// 返回配置用到、但 `version` 尚不支持的特性及其所需版本
+pub fn unsupported_features(
+    generated: &serde_json::Value,
+    version: &semver::Version,
+) -> Vec<(&'static str, semver::Version)> {
+    requirements()
+        .into_iter()
+        .filter(|req| req.min_version > *version && (req.used_by)(generated))
+        .map(|req| (req.feature, req.min_version))
+        .collect()
+}
+
+/// 启动时检查 sing-box 版本是否满足生成的配置，不满足时只记录警告
+pub async fn warn_on_version_skew(paths: &SingBoxPaths, channel: SingBoxChannel) {
+    let generated = match load_generated_config(paths).await {
+        Ok(Some(generated)) => generated,
+        Ok(None) => return,
+        Err(e) => {
+            warn!(error = %e, "Failed to load generated config for sing-box version check");
+            return;
+        }
+    };
+    let line = match sing_box_version(paths, channel).await {
+        Ok(line) => line,
+        Err(e) => {
+            warn!(error = %e, "Failed to determine sing-box version");
+            return;
+        }
+    };
+    let Some(version) = parse_sing_box_version(&line) else {
+        warn!(output = %line, "Unrecognized sing-box version output, skipping compatibility check");
+        return;
+    };
+
+    let missing = unsupported_features(&generated, &version);
+    if missing.is_empty() {
+        info!(version = %version, "sing-box version supports the generated config");
+    }
+    for (feature, min_version) in missing {
+        warn!(
+            binary = ?paths.binary_path(channel),
+            version = %version,
+            required = %min_version,
+            feature,
+            "sing-box is older than the generated config assumes and will likely fail to start"
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{parse_sing_box_version, unsupported_features};
+
+    #[test]
+    fn older_sing_box_reports_features_used_by_the_config() {
+        let generated = json!({
+            "dns": {"servers": [{"type": "udp", "tag": "local", "server": "223.5.5.5"}]},
+            "route": {"rules": [{"action": "sniff"}]},
+            "outbounds": [{"type": "direct", "tag": "direct"}]
+        });
+
+        let old = parse_sing_box_version("sing-box version 1.11.4").unwrap();
+        let missing: Vec<_> = unsupported_features(&generated, &old)
+            .into_iter()
+            .map(|(_, version)| version.to_string())
+            .collect();
+        assert_eq!(missing, vec!["1.12.0"]);
+
+        let beta = parse_sing_box_version("sing-box version 1.12.0-beta.3").unwrap();
+        assert!(unsupported_features(&generated, &beta).is_empty());
+        assert_eq!(parse_sing_box_version("garbage"), None);
+    }
+}
@@ -1,24 +1,70 @@
 use futures::{stream, StreamExt};
-use std::{collections::HashSet, path::Path, sync::Arc};
+use sha2::{Digest, Sha256};
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashMap, HashSet},
+    path::Path,
+    sync::Arc,
+    time::Instant,
+};
 use tokio::time::Duration;
 use tracing::{error, info, warn};
 
 use crate::error::{AppError, AppResult};
-use crate::models::{Config, RouteMode, SubStatus};
+use crate::models::{
+    AppRule, Config, ConfigOutput, DnsConfig, DnsServer, DnsServerType, DomainRule,
+    Hysteria2Tuning, PortRule, ReloadScope, RouteMode, RuleSetAction, RuleSetFormat, RuleSetRule,
+    SingLogConfig, SubStatus, Subscription, TagCollision, DEFAULT_PORT,
+};
+use crate::paths::ConfigFormat;
 use crate::services::{
-    proxy::restore_last_proxy,
+    backup::backup_config,
+    bandwidth::{apply_bandwidth_probe, probe_unmeasured_nodes},
+    config_diff::diff_configs,
+    health::recorded_latencies,
+    mtu::resolve_tun_mtu,
+    node_groups::apply_node_groups,
+    node_parser::ParseOptions,
+    proxy::{node_identity, restore_last_proxy},
+    region::apply_region_groups,
+    resolve_check::drop_unresolvable_nodes,
+    rule_set_mirror::apply_rule_set_mirrors,
     singbox::{
-        get_sing_box_home, start_sing_internal, stop_sing_internal, validate_sing_box_config,
+        record_exit, reload_sing_internal, restart_sing_internal, start_sing_internal,
+        stop_sing_internal, validate_sing_box_config, validate_sing_box_config_file, SingBoxPaths,
     },
-    subscription::fetch_sub,
+    staged_write::StagedWrite,
+    subscription::{
+        apply_node_filter, client_with_identity, dedupe_subscriptions, fetch_sub, fetch_sub_with,
+        format_unix_timestamp, FetchResult,
+    },
+    version::current_version,
 };
 use crate::state::AppState;
+use crate::validation::{parse_port_spec, split_host_port, Validator};
 
-const CONFIG_CACHE_PATH: &str = "/tmp/miao-sing-box/config.json.cache";
 const MAX_CONCURRENT_SUBS: usize = 5;
+/// subscription_failover 下未被获取的订阅的状态说明
+const FAILOVER_SKIPPED: &str = "Skipped: earlier subscriptions provided enough nodes";
+/// resolve_check 去掉的节点在订阅状态 dropped 中的原因
+const UNRESOLVABLE_DROP_REASON: &str = "server does not resolve";
+/// annotate_sources 开启时写入 outbound 的来源字段
+const SOURCE_ANNOTATION_KEY: &str = "_source";
+const MANUAL_SOURCE: &str = "manual";
+/// 模板中直连 DNS 服务器的 tag，也是默认的域名解析器
+pub const LOCAL_DNS_SERVER: &str = "local";
+/// 模板中经代理访问的远程 DNS 服务器的 tag
+const REMOTE_DNS_SERVER: &str = "cfdns";
+/// sing-box Clash API 的监听地址，生成的配置和访问 Clash API 的各处共用
+pub(crate) const CLASH_API_ADDR: &str = "127.0.0.1:6262";
+
+/// Clash API 上 `path`（以 "/" 开头）的 HTTP 地址
+pub(crate) fn clash_api_url(path: &str) -> String {
+    format!("http://{CLASH_API_ADDR}{path}")
+}
 
 /// 原子写入文件：先写入临时文件，再重命名为目标文件
-async fn write_file_atomic(path: &Path, content: &str) -> AppResult<()> {
+pub(crate) async fn write_file_atomic(path: &Path, content: &str) -> AppResult<()> {
     if let Some(parent) = path
         .parent()
         .filter(|parent| !parent.as_os_str().is_empty())
@@ -43,40 +89,224 @@ async fn write_file_atomic(path: &Path, content: &str) -> AppResult<()> {
     Ok(())
 }
 
+/// 按配置文件扩展名选择 YAML 或 TOML 解析
+pub fn parse_config(content: &str, path: &Path) -> AppResult<Config> {
+    Ok(match ConfigFormat::from_path(path) {
+        ConfigFormat::Yaml => serde_yaml::from_str(content)?,
+        ConfigFormat::Toml => toml::from_str(content)?,
+    })
+}
+
+fn serialize_config(config: &Config, path: &Path) -> AppResult<String> {
+    Ok(match ConfigFormat::from_path(path) {
+        ConfigFormat::Yaml => serde_yaml::to_string(config)?,
+        ConfigFormat::Toml => toml::to_string(config)?,
+    })
+}
+
 pub async fn save_config_to(path: &Path, config: &Config) -> AppResult<()> {
-    let yaml = serde_yaml::to_string(config)?;
+    let content = serialize_config(config, path)?;
     if let Ok(existing) = tokio::fs::read_to_string(path).await {
-        if existing == yaml {
+        if existing == content {
             info!(config_path = ?path, "Config file already up to date, skipping write");
             return Ok(());
         }
     }
 
-    write_file_atomic(path, &yaml).await
+    write_file_atomic(path, &content).await
 }
 
-pub async fn save_config_cache() {
-    let config_path = get_sing_box_home().join("config.json");
-    if let Err(e) = tokio::fs::copy(&config_path, CONFIG_CACHE_PATH).await {
+pub async fn save_config_cache(paths: &SingBoxPaths) {
+    let cache = paths.config_cache_path();
+    let result = match tokio::fs::read_to_string(paths.config_path()).await {
+        Ok(content) => write_file_atomic(&cache, &content).await,
+        Err(e) => Err(AppError::from(e)),
+    };
+    if let Err(e) = result {
         error!("Failed to save config cache: {}", e);
     } else {
-        info!("Config cache saved to {}", CONFIG_CACHE_PATH);
+        info!("Config cache saved to {:?}", cache);
+    }
+}
+
+/// 生成的配置必须是 JSON 对象；进程在写入中途崩溃可能留下截断的文件
+fn check_generated_json(content: &str) -> AppResult<()> {
+    match serde_json::from_str::<serde_json::Value>(content)? {
+        serde_json::Value::Object(_) => Ok(()),
+        _ => Err(AppError::message("Generated config is not a JSON object")),
+    }
+}
+
+fn content_sha256(content: &str) -> String {
+    hex::encode(Sha256::digest(content.as_bytes()))
+}
+
+/// 加入标记之前的 miao 生成的配置：同时带有模板中的 sing-tun 入站、
+/// CLASH_API_ADDR 上的 Clash API 和 "proxy" selector
+fn looks_like_legacy_miao_config(content: &str) -> bool {
+    let Ok(config) = serde_json::from_str::<serde_json::Value>(content) else {
+        return false;
+    };
+    let any = |key: &str, pred: &dyn Fn(&serde_json::Value) -> bool| {
+        config[key]
+            .as_array()
+            .is_some_and(|items| items.iter().any(pred))
+    };
+    any("outbounds", &|outbound| {
+        outbound["type"] == "selector" && outbound["tag"] == "proxy"
+    }) && any("inbounds", &|inbound| {
+        inbound["type"] == "tun" && inbound["interface_name"] == "sing-tun"
+    }) && config["experimental"]["clash_api"]["external_controller"] == CLASH_API_ADDR
+}
+
+/// 现有 config.json 是否可以由 miao 覆盖：不存在或为空、内容与标记文件中记录的哈希一致，
+/// 有标记文件但内容已损坏（写入中途崩溃），或是加入标记（哈希）之前的 miao 生成的
+async fn is_miao_managed(paths: &SingBoxPaths) -> bool {
+    let content = match tokio::fs::read_to_string(paths.config_path()).await {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return true,
+        Err(_) => return false,
+    };
+    if content.trim().is_empty() {
+        return true;
+    }
+    let marker = tokio::fs::read_to_string(paths.config_marker_path())
+        .await
+        .ok();
+    if marker.is_some() && check_generated_json(&content).is_err() {
+        return true;
+    }
+    let recorded_hash = marker
+        .and_then(|marker| serde_json::from_str::<serde_json::Value>(&marker).ok())
+        .and_then(|marker| marker["config_sha256"].as_str().map(str::to_string));
+    match recorded_hash {
+        Some(hash) => hash == content_sha256(&content),
+        None => looks_like_legacy_miao_config(&content),
     }
 }
 
-pub async fn restore_config_from_cache() -> AppResult<()> {
-    let cache = std::path::Path::new(CONFIG_CACHE_PATH);
-    if !cache.exists() {
-        return Err(AppError::message("No cached config available"));
+/// 已有的 config.json 不是 miao 生成且未设置 overwrite_foreign_config 时返回错误
+async fn ensure_overwritable(paths: &SingBoxPaths, overwrite_foreign: bool) -> AppResult<()> {
+    if !overwrite_foreign && !is_miao_managed(paths).await {
+        return Err(AppError::message(format!(
+            "Refusing to overwrite {:?}: it was not generated by miao; remove it or set overwrite_foreign_config: true",
+            paths.config_path()
+        )));
     }
-    let config_path = get_sing_box_home().join("config.json");
-    tokio::fs::copy(CONFIG_CACHE_PATH, &config_path)
+    Ok(())
+}
+
+/// 标记文件内容，`content` 是同时写入的 config.json
+fn config_marker(content: &str) -> String {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default();
+    serde_json::json!({
+        "generated_by": format!("miao {}", current_version()),
+        "generated_at": format_unix_timestamp(now),
+        "config_sha256": content_sha256(content),
+    })
+    .to_string()
+}
+
+/// 写入 config.json 并更新标记文件；已有的 config.json 不是 miao 生成的时拒绝覆盖，
+/// 除非设置了 overwrite_foreign_config
+async fn write_generated_config(
+    paths: &SingBoxPaths,
+    content: &str,
+    overwrite_foreign: bool,
+) -> AppResult<()> {
+    ensure_overwritable(paths, overwrite_foreign).await?;
+    write_file_atomic(&paths.config_path(), content).await?;
+    write_file_atomic(&paths.config_marker_path(), &config_marker(content)).await
+}
+
+pub async fn restore_config_from_cache(
+    paths: &SingBoxPaths,
+    overwrite_foreign: bool,
+) -> AppResult<()> {
+    let cache = paths.config_cache_path();
+    let content = match tokio::fs::read_to_string(&cache).await {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Err(AppError::message("No cached config available"));
+        }
+        Err(e) => return Err(AppError::context("Failed to read cached config", e)),
+    };
+    check_generated_json(&content).map_err(|e| AppError::context("Cached config is corrupt", e))?;
+    write_generated_config(paths, &content, overwrite_foreign)
         .await
         .map_err(|e| AppError::context("Failed to restore config from cache", e))?;
     info!("Restored config from cache");
     Ok(())
 }
 
+/// 启动 sing-box 前确认 config.json 完整可解析；损坏时重新生成。
+/// 返回 true 表示检测到损坏并已重新生成
+pub async fn regenerate_if_corrupt(config: &Config, state: &Arc<AppState>) -> AppResult<bool> {
+    let config_path = state.sing_box_paths.config_path();
+    let content = match tokio::fs::read_to_string(&config_path).await {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(AppError::context("Failed to read generated config", e)),
+    };
+    let Err(corruption) = check_generated_json(&content) else {
+        return Ok(false);
+    };
+
+    warn!(
+        path = ?config_path,
+        error = %corruption,
+        "Corrupt or missing sing-box config detected, regenerating before start"
+    );
+    gen_config(config, state)
+        .await
+        .map_err(|e| AppError::context("Failed to regenerate corrupt config", e))?;
+    Ok(true)
+}
+
+/// 读取当前已生成的 sing-box 配置；尚未生成时返回 None
+pub async fn load_generated_config(paths: &SingBoxPaths) -> AppResult<Option<serde_json::Value>> {
+    let config_path = paths.config_path();
+    let content = match tokio::fs::read_to_string(&config_path).await {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(AppError::context("Failed to read generated config", e)),
+    };
+    let value = serde_json::from_str(&content)
+        .map_err(|e| AppError::context("Failed to parse generated config", e))?;
+    Ok(Some(value))
+}
+
+/// 取出 "proxy" selector 引用的节点 outbound，保持 selector 中的顺序
+pub fn node_outbounds(sing_box_config: &serde_json::Value) -> Vec<serde_json::Value> {
+    let Some(outbounds) = sing_box_config["outbounds"].as_array() else {
+        return vec![];
+    };
+    let Some(selector) = outbounds
+        .iter()
+        .find(|outbound| outbound["type"] == "selector" && outbound["tag"] == "proxy")
+    else {
+        return vec![];
+    };
+
+    selector["outbounds"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|tag| tag.as_str())
+        .filter_map(|tag| outbounds.iter().find(|outbound| outbound["tag"] == tag))
+        .filter(|outbound| {
+            !matches!(
+                outbound["type"].as_str(),
+                Some("selector" | "urltest" | "direct")
+            )
+        })
+        .cloned()
+        .collect()
+}
+
 pub async fn regenerate_and_restart_runtime(
     config: &Config,
     state: &Arc<AppState>,
@@ -86,7 +316,7 @@ pub async fn regenerate_and_restart_runtime(
         .map_err(|e| AppError::context("Failed to regenerate config", e))?;
     info!("Config regenerated successfully");
 
-    validate_sing_box_config()
+    validate_sing_box_config(&state.sing_box_paths, config.sing_box_channel)
         .await
         .map_err(|e| AppError::context("Config validation failed, not restarting", e))?;
 
@@ -117,10 +347,13 @@ pub async fn finalize_started_config(config: &Config, state: &Arc<AppState>, has
     tokio::spawn(async move {
         restore_last_proxy(&state_for_proxy).await;
     });
+    if let Some(settings) = config.bandwidth_probe.clone() {
+        tokio::spawn(probe_unmeasured_nodes(state.clone(), settings));
+    }
 }
 
 async fn update_config_warning(config: &Config, state: &Arc<AppState>, has_sub_nodes: bool) {
-    save_config_cache().await;
+    save_config_cache(&state.sing_box_paths).await;
 
     if has_sub_nodes {
         *state.config_warning.lock().await = None;
@@ -140,34 +373,130 @@ pub async fn regenerate_without_restart_runtime(
         .map_err(|e| AppError::context("Failed to regenerate config", e))?;
     info!("Config regenerated successfully");
 
-    validate_sing_box_config()
+    validate_sing_box_config(&state.sing_box_paths, config.sing_box_channel)
         .await
         .map_err(|e| AppError::context("Config validation failed", e))?;
 
     Ok(has_sub_nodes)
 }
 
-fn config_with_route_override(config: &Config, route_mode: Option<RouteMode>) -> Config {
+pub fn config_with_route_override(config: &Config, route_mode: Option<RouteMode>) -> Config {
     let mut config = config.clone();
     config.route_mode = route_mode.unwrap_or_default();
     config
 }
 
+/// 启动与重新加载共用：应用环境变量覆盖，未开启 strict_subs 时去掉重复订阅，再完整校验
+pub fn prepare_loaded_config(config: Config) -> AppResult<Config> {
+    let (mut config, env_overrides) = crate::env_config::apply_env_overrides(
+        config,
+        std::env::vars_os().filter_map(|(name, value)| {
+            Some((name.into_string().ok()?, value.into_string().ok()?))
+        }),
+    )?;
+    if !env_overrides.is_empty() {
+        info!(vars = ?env_overrides, "Applied configuration overrides from environment");
+    }
+    if !config.strict_subs {
+        let removed = dedupe_subscriptions(&mut config.subs);
+        if !removed.is_empty() {
+            warn!(urls = ?removed, "Ignoring duplicate subscription URLs");
+        }
+    }
+    Validator::config(&config).map_err(AppError::message)?;
+    Ok(config)
+}
+
+/// 从磁盘重新读取配置文件，返回文件中的内容（不含环境变量覆盖）；
+/// route_mode 只在本次会话中有效，文件中的值被忽略
+pub async fn read_config_file(path: &Path) -> AppResult<Config> {
+    let content = tokio::fs::read_to_string(path)
+        .await
+        .map_err(|e| AppError::context(format!("Failed to read {:?}", path), e))?;
+    let mut config = parse_config(&content, path)?;
+    config.route_mode = Default::default();
+    Ok(config)
+}
+
+/// 修改配置文件中的内容并写回，不影响正在运行的配置
+pub async fn persist_file_config_change(
+    state: &AppState,
+    change: impl FnOnce(&mut Config),
+) -> AppResult<()> {
+    let mut file_config = state.file_config.write().await;
+    let mut updated = file_config.clone();
+    change(&mut updated);
+    save_config_to(&state.config_path, &updated).await?;
+    *file_config = updated;
+    Ok(())
+}
+
+/// 应用从磁盘重新读取的配置：与 apply_config_change 相同地重新生成并重启，
+/// 但不回写配置文件，以免覆盖手工编辑的格式与注释；失败时恢复之前运行的配置。
+/// `file_config` 是文件中的内容，`new_config` 是应用环境变量覆盖后的结果
+pub async fn apply_reloaded_config(
+    state: &Arc<AppState>,
+    old_config: &Config,
+    file_config: Config,
+    new_config: Config,
+) -> AppResult<()> {
+    let route_override = *state.route_mode_override.read().await;
+    let runtime_old_config = config_with_route_override(old_config, route_override);
+    let runtime_new_config = config_with_route_override(&new_config, route_override);
+
+    match regenerate_and_restart_runtime(&runtime_new_config, state).await {
+        Ok(has_sub_nodes) => {
+            crate::log_redact::set_enabled(!new_config.log_secrets);
+            *state.config.write().await = new_config;
+            *state.file_config.write().await = file_config;
+            finalize_started_config(&runtime_new_config, state, has_sub_nodes).await;
+            Ok(())
+        }
+        Err(apply_err) => {
+            error!(error = %apply_err, "Failed to apply reloaded config, attempting runtime rollback");
+            match restore_previous_running_config(&runtime_old_config, state).await {
+                Ok(()) => Err(AppError::context(
+                    "Failed to apply reloaded config; restored previous runtime config",
+                    apply_err,
+                )),
+                Err(rollback_err) => Err(AppError::message(format!(
+                    "Failed to apply reloaded config: {}. Runtime rollback failed: {}",
+                    apply_err, rollback_err
+                ))),
+            }
+        }
+    }
+}
+
+/// 应用面板对配置的修改：`old_config` 是当前生效的配置，`new_file_config` 是修改后的
+/// 配置文件内容（在 `state.file_config` 的基础上修改）。写回文件的只有文件中的内容，
+/// MIAO_* 环境变量覆盖只作用于运行中的配置，不会被写进配置文件
 pub async fn apply_config_change(
     state: &Arc<AppState>,
     old_config: &Config,
-    new_config: &Config,
+    new_file_config: &Config,
 ) -> AppResult<()> {
+    let persisted_new_config = config_with_route_override(new_file_config, None);
+    let new_config = prepare_loaded_config(persisted_new_config.clone())?;
     let route_override = *state.route_mode_override.read().await;
     let runtime_old_config = config_with_route_override(old_config, route_override);
-    let runtime_new_config = config_with_route_override(new_config, route_override);
-    let persisted_new_config = config_with_route_override(new_config, None);
+    let runtime_new_config = config_with_route_override(&new_config, route_override);
 
     match regenerate_and_restart_runtime(&runtime_new_config, state).await {
         Ok(has_sub_nodes) => {
+            let old_file_config = state.file_config.read().await.clone();
+            if persisted_new_config != config_with_route_override(&old_file_config, None) {
+                if let Err(e) =
+                    backup_config(&state.config_path, new_config.backup_retention()).await
+                {
+                    warn!(error = %e, "Failed to back up config before saving");
+                }
+            }
             match save_config_to(&state.config_path, &persisted_new_config).await {
                 Ok(()) => {
-                    *state.config.write().await = persisted_new_config;
+                    crate::log_redact::set_enabled(!new_config.log_secrets);
+                    *state.config.write().await = config_with_route_override(&new_config, None);
+                    *state.file_config.write().await = persisted_new_config;
                     finalize_started_config(&runtime_new_config, state, has_sub_nodes).await;
                     Ok(())
                 }
@@ -202,6 +531,65 @@ pub async fn apply_config_change(
     }
 }
 
+/// 定时刷新：只重新获取 `due` 中的订阅，其余复用缓存结果；
+/// 生成的配置有变化且 sing-box 正在运行时才重启；只有 outbounds 变化时
+/// 先尝试 SIGHUP 重载，失败再完整重启
+pub async fn refresh_due_subscriptions(
+    state: &Arc<AppState>,
+    due: &HashSet<String>,
+) -> AppResult<()> {
+    let _config_update = state.config_update.lock().await;
+    let config = state.config.read().await.clone();
+    let route_override = *state.route_mode_override.read().await;
+    let runtime_config = config_with_route_override(&config, route_override);
+
+    let (due_results, reuse): (HashMap<String, FetchResult>, HashMap<String, FetchResult>) = state
+        .sub_cache
+        .lock()
+        .await
+        .iter()
+        .map(|(url, result)| (url.clone(), result.clone()))
+        .partition(|(url, _)| due.contains(url));
+
+    let previous = load_generated_config(&state.sing_box_paths).await?;
+    let has_sub_nodes = gen_config_reusing(&runtime_config, state, &reuse, &due_results).await?;
+    let fresh = load_generated_config(&state.sing_box_paths).await?;
+    if fresh == previous {
+        info!("Scheduled refresh produced no config changes");
+        return Ok(());
+    }
+
+    if !sing_box_is_running(state).await {
+        info!("Scheduled refresh updated config; sing-box is not running");
+        return Ok(());
+    }
+
+    let scope =
+        diff_configs(&previous.unwrap_or_default(), &fresh.unwrap_or_default()).reload_scope;
+    if scope == ReloadScope::OutboundsOnly {
+        info!("Scheduled refresh changed only outbounds, reloading sing-box");
+        match reload_sing_internal(state).await {
+            Ok(()) => {
+                finalize_started_config(&runtime_config, state, has_sub_nodes).await;
+                info!("Scheduled refresh applied and sing-box reloaded");
+                return Ok(());
+            }
+            Err(e) => warn!(error = %e, "sing-box reload failed, falling back to restart"),
+        }
+    }
+    if let Err(e) = restart_sing_internal(state).await {
+        restore_previous_running_config(&runtime_config, state).await?;
+        return Err(AppError::context(
+            "Scheduled refresh failed to restart sing-box",
+            e,
+        ));
+    }
+    finalize_started_config(&runtime_config, state, has_sub_nodes).await;
+    info!("Scheduled refresh applied and sing-box restarted");
+
+    Ok(())
+}
+
 pub async fn apply_runtime_config_change(
     state: &Arc<AppState>,
     old_config: &Config,
@@ -247,12 +635,13 @@ pub async fn apply_runtime_config_change(
     }
 }
 
-async fn sing_box_is_running(state: &Arc<AppState>) -> bool {
+pub(crate) async fn sing_box_is_running(state: &Arc<AppState>) -> bool {
     let mut lock = state.sing_process.lock().await;
     match &mut *lock {
         Some(proc) => match proc.child.try_wait() {
-            Ok(Some(_)) => {
+            Ok(Some(exit_status)) => {
                 *lock = None;
+                record_exit(state, exit_status).await;
                 false
             }
             Ok(None) => true,
@@ -267,7 +656,9 @@ async fn restore_previous_running_config(
     state: &Arc<AppState>,
 ) -> AppResult<()> {
     if sing_box_is_running(state).await {
-        match restore_config_from_cache().await {
+        match restore_config_from_cache(&state.sing_box_paths, old_config.overwrite_foreign_config)
+            .await
+        {
             Ok(()) => {}
             Err(cache_err) => {
                 warn!(error = %cache_err, "Failed to restore runtime config from cache while previous sing-box process is still running");
@@ -284,7 +675,9 @@ async fn restore_previous_running_config(
 async fn restart_with_previous_config(old_config: &Config, state: &Arc<AppState>) -> AppResult<()> {
     stop_sing_internal(state).await;
 
-    if let Err(cache_err) = restore_config_from_cache().await {
+    if let Err(cache_err) =
+        restore_config_from_cache(&state.sing_box_paths, old_config.overwrite_foreign_config).await
+    {
         warn!(error = %cache_err, "Failed to restore runtime config from cache for rollback; regenerating previous config");
     } else {
         match start_sing_internal(state).await {
@@ -315,92 +708,184 @@ async fn restore_previous_stopped_config(
     Ok(())
 }
 
-/// Returns `true` if at least one subscription node was fetched successfully.
-pub async fn gen_config(config: &Config, state: &Arc<AppState>) -> AppResult<bool> {
-    let (my_outbounds, my_names) = collect_manual_outbounds(config);
-    let mut final_outbounds: Vec<serde_json::Value> = vec![];
-    let mut final_node_names: Vec<String> = vec![];
+/// 订阅获取结果，按合并顺序排列
+struct FetchedSubscriptions {
+    node_names: Vec<String>,
+    outbounds: Vec<serde_json::Value>,
+    statuses: Vec<SubStatus>,
+    /// 成功获取（或复用缓存）的原始结果，供下次定时刷新复用
+    results: Vec<(String, FetchResult)>,
+}
 
-    {
-        let mut status_map = state.sub_status.lock().await;
-        status_map.retain(|url, _| config.subs.contains(url));
-    }
+/// 获取所有订阅：`reuse` 中的订阅直接使用缓存结果，不再发起请求；
+/// `previous` 是其余订阅上次的结果，正文未变时复用其解析结果；
+/// `latencies` 是最近测得的节点延迟（按节点标识索引），用于 limit_by: fastest
+async fn fetch_subscriptions(
+    config: &Config,
+    client: &reqwest::Client,
+    reuse: &HashMap<String, FetchResult>,
+    previous: &HashMap<String, FetchResult>,
+    latencies: HashMap<String, u64>,
+) -> FetchedSubscriptions {
+    let mut fetched = FetchedSubscriptions {
+        node_names: vec![],
+        outbounds: vec![],
+        statuses: vec![],
+        results: vec![],
+    };
 
-    let sub_futures: Vec<_> = config
-        .subs
-        .iter()
-        .map(|sub| {
-            let sub = sub.clone();
-            let client = state.http_client.clone();
-            async move {
-                info!(url = %sub, "Fetching subscription");
-                let result =
-                    tokio::time::timeout(Duration::from_secs(30), fetch_sub(&sub, &client)).await;
-
-                match result {
-                    Ok(Ok(fetch_result)) => {
-                        let valid_count = fetch_result.node_names.len();
-                        let total_count = fetch_result.total_count;
-                        let error_count = fetch_result.parse_errors.len();
-
-                        if error_count > 0 {
-                            warn!(
-                                url = %sub,
-                                valid = valid_count,
-                                total = total_count,
-                                errors = error_count,
-                                "Partial fetch: some nodes failed to parse"
-                            );
-                        } else {
-                            info!(
-                                url = %sub,
-                                nodes = valid_count,
-                                "Subscription fetched successfully"
-                            );
-                        }
-
-                        (sub.clone(), Ok(fetch_result))
-                    }
-                    Ok(Err(e)) => {
-                        error!(url = %sub, error = %e, "Failed to fetch subscription");
-                        (sub.clone(), Err(e.to_string()))
-                    }
-                    Err(_) => {
-                        error!(url = %sub, timeout_secs = 30, "Subscription fetch timed out");
-                        (sub.clone(), Err("Request timeout".to_string()))
+    let parse_options = ParseOptions {
+        passthrough_keys: config.passthrough_keys.clone(),
+        name_transforms: config.name_transforms.clone(),
+        max_body_bytes: Some(config.max_sub_bytes()),
+        latencies,
+    };
+
+    let fetch_one = |sub: &Subscription| {
+        let sub = sub.clone();
+        let parse_options = parse_options.clone();
+        let filter = sub.resolved_filter(config.node_filter.as_ref()).clone();
+        let cached = reuse.get(&sub.url).cloned();
+        let previous = previous.get(&sub.url);
+        async move {
+            let url = sub.url.clone();
+            if let Some(cached) = cached {
+                info!(url = %url, "Subscription not due for refresh, reusing cached result");
+                return (url, Ok(cached), None);
+            }
+            let client = match subscription_client(&sub, config, client).await {
+                Ok(client) => client,
+                Err(e) => {
+                    error!(url = %url, error = %e, "Failed to prepare subscription client");
+                    return (url, Err(e.to_string()), None);
+                }
+            };
+            info!(url = %url, "Fetching subscription");
+            let started = Instant::now();
+            let result = tokio::time::timeout(
+                Duration::from_secs(30),
+                fetch_sub_with(&sub, &client, &parse_options, &filter, previous),
+            )
+            .await;
+            let fetch_ms = Some(started.elapsed().as_millis() as u64);
+
+            match result {
+                Ok(Ok(fetch_result)) => {
+                    let valid_count = fetch_result.node_names.len();
+                    let total_count = fetch_result.total_count;
+                    let error_count = fetch_result.parse_errors.len();
+
+                    if error_count > 0 {
+                        warn!(
+                            url = %url,
+                            valid = valid_count,
+                            total = total_count,
+                            errors = error_count,
+                            "Partial fetch: some nodes failed to parse"
+                        );
+                    } else {
+                        info!(
+                            url = %url,
+                            nodes = valid_count,
+                            fetch_ms = ?fetch_ms,
+                            "Subscription fetched successfully"
+                        );
                     }
+
+                    (url, Ok(fetch_result), fetch_ms)
+                }
+                Ok(Err(e)) => {
+                    error!(url = %url, error = %e, "Failed to fetch subscription");
+                    (url, Err(e.to_string()), fetch_ms)
+                }
+                Err(_) => {
+                    error!(url = %url, timeout_secs = 30, "Subscription fetch timed out");
+                    (url, Err("Request timeout".to_string()), fetch_ms)
                 }
             }
-        })
-        .collect();
-
-    // 使用 buffer_unordered 限制并发数，避免同时发起过多请求
-    let mut results: Vec<_> = stream::iter(sub_futures)
-        .buffer_unordered(MAX_CONCURRENT_SUBS)
-        .collect()
-        .await;
+        }
+    };
 
-    // 按原始顺序排序结果
-    let subs_order: Vec<String> = config.subs.clone();
-    results.sort_by_key(|(url, _)| {
-        subs_order
-            .iter()
-            .position(|s| s == url)
-            .unwrap_or(usize::MAX)
-    });
+    let mut skipped = Vec::new();
+    let results = if config.subscription_failover {
+        // 主备模式：按合并顺序逐个获取，节点足够后不再请求后面的订阅
+        let mut ordered: Vec<&Subscription> = config.subs.iter().collect();
+        ordered.sort_by_key(|sub| subscription_merge_key(&config.subs, &sub.url));
+        let min_nodes = config.failover_min_nodes();
+        let mut usable = 0;
+        let mut results = Vec::new();
+        for sub in ordered {
+            if usable >= min_nodes {
+                skipped.push(sub.url.clone());
+                continue;
+            }
+            let (url, result, fetch_ms) = fetch_one(sub).await;
+            if let Ok(fetch_result) = &result {
+                usable += fetch_result.node_names.len();
+            }
+            results.push((url, result, fetch_ms));
+        }
+        let used: Vec<&str> = results.iter().map(|(url, ..)| url.as_str()).collect();
+        info!(used = ?used, skipped = ?skipped, nodes = usable, "Subscription failover finished");
+        results
+    } else {
+        // 使用 buffer_unordered 限制并发数，避免同时发起过多请求
+        let mut results: Vec<_> = stream::iter(config.subs.iter().map(fetch_one))
+            .buffer_unordered(MAX_CONCURRENT_SUBS)
+            .collect()
+            .await;
+
+        // 按优先级（高在前）排序结果，同优先级保持配置中的原始顺序
+        results.sort_by_key(|(url, ..)| subscription_merge_key(&config.subs, url));
+        results
+    };
 
-    for (url, result) in results {
+    for (url, result, fetch_ms) in results {
         let status = match result {
-            Ok(fetch_result) => {
+            Ok(mut fetch_result) => {
+                // 缓存未经解析检查的结果，复用时重新检查，恢复解析的节点可以回来
+                fetched.results.push((url.clone(), fetch_result.clone()));
+                if config.resolve_check {
+                    let (node_names, outbounds, unresolvable) = drop_unresolvable_nodes(
+                        std::mem::take(&mut fetch_result.node_names),
+                        std::mem::take(&mut fetch_result.outbounds),
+                    )
+                    .await;
+                    fetch_result.node_names = node_names;
+                    fetch_result.outbounds = outbounds;
+                    if unresolvable > 0 {
+                        *fetch_result
+                            .dropped
+                            .entry(UNRESOLVABLE_DROP_REASON.to_string())
+                            .or_default() += unresolvable;
+                    }
+                }
                 let count = fetch_result.node_names.len();
-                final_node_names.extend(fetch_result.node_names);
-                final_outbounds.extend(fetch_result.outbounds);
+                fetched.node_names.extend(fetch_result.node_names);
+                let mut outbounds = fetch_result.outbounds;
+                if config.annotate_sources {
+                    annotate_source(&mut outbounds, &url);
+                }
+                fetched.outbounds.extend(outbounds);
+
+                if !fetch_result.dropped.is_empty() {
+                    warn!(
+                        url = %url,
+                        dropped = ?fetch_result.dropped,
+                        "Skipped nodes with unsupported protocols"
+                    );
+                }
 
                 let error_info = if !fetch_result.parse_errors.is_empty() {
                     Some(format!(
                         "{} nodes skipped due to parse errors",
                         fetch_result.parse_errors.len()
                     ))
+                } else if count == 0 && !fetch_result.dropped.is_empty() {
+                    Some(format!(
+                        "No supported nodes: {}",
+                        format_dropped(&fetch_result.dropped)
+                    ))
                 } else if count == 0 && fetch_result.total_count > 0 {
                     Some("All nodes invalid (missing required fields)".into())
                 } else if count == 0 {
@@ -410,84 +895,549 @@ pub async fn gen_config(config: &Config, state: &Arc<AppState>) -> AppResult<boo
                 };
 
                 SubStatus {
-                    url: url.clone(),
+                    url,
                     success: count > 0,
                     node_count: count,
                     error: error_info,
+                    dropped: fetch_result.dropped,
+                    userinfo: fetch_result.userinfo,
+                    fetch_ms,
                 }
             }
             Err(e) => SubStatus {
-                url: url.clone(),
+                url,
                 success: false,
                 node_count: 0,
                 error: Some(e),
+                dropped: Default::default(),
+                userinfo: None,
+                fetch_ms,
             },
         };
-        state.sub_status.lock().await.insert(url, status);
+        fetched.statuses.push(status);
+    }
+    fetched
+        .statuses
+        .extend(skipped.into_iter().map(|url| SubStatus {
+            url,
+            success: true,
+            node_count: 0,
+            error: Some(FAILOVER_SKIPPED.to_string()),
+            dropped: Default::default(),
+            userinfo: None,
+            fetch_ms: None,
+        }));
+    fetched
+}
+
+/// 配置了 rule_set_mirrors 时，把 GitHub 托管的规则集地址换成可访问的镜像
+async fn with_rule_set_mirrors<'a>(
+    config: &'a Config,
+    client: &reqwest::Client,
+) -> Cow<'a, Config> {
+    if config.rule_set_mirrors.is_empty()
+        || config.rule_sets.is_empty()
+        || config.route_mode == RouteMode::Global
+    {
+        return Cow::Borrowed(config);
     }
+    let mut mirrored = config.clone();
+    mirrored.rule_sets =
+        apply_rule_set_mirrors(client, &config.rule_sets, &config.rule_set_mirrors).await;
+    Cow::Owned(mirrored)
+}
 
-    let has_sub_nodes = !final_node_names.is_empty();
+/// 在内存中获取订阅并生成完整的 sing-box 配置，不写磁盘也不更新订阅状态
+pub async fn build_config(config: &Config, state: &AppState) -> AppResult<serde_json::Value> {
+    let config = &*with_rule_set_mirrors(config, &state.http_client).await;
+    let (my_outbounds, my_names) = collect_manual_outbounds(config);
+    let fetched = fetch_subscriptions(
+        config,
+        &state.http_client,
+        &HashMap::new(),
+        &HashMap::new(),
+        recorded_latencies(state).await,
+    )
+    .await;
 
-    let sing_box_config = build_sing_box_config(
+    let mut sing_box_config = build_sing_box_config(
         config,
         my_names,
         my_outbounds,
-        final_node_names,
-        final_outbounds,
+        fetched.node_names,
+        fetched.outbounds,
     )?;
+    apply_measurements(config, &mut sing_box_config, state).await;
+    Ok(sing_box_config)
+}
 
-    let sing_box_home = get_sing_box_home();
-    let config_output_loc = sing_box_home.join("config.json");
-    write_file_atomic(
-        &config_output_loc,
-        &serde_json::to_string(&sing_box_config)?,
-    )
-    .await?;
+/// 应用运行期间测得的数据：地区分组按延迟排序、hysteria2 节点带宽
+async fn apply_measurements(
+    config: &Config,
+    sing_box_config: &mut serde_json::Value,
+    state: &AppState,
+) {
+    if config.region_groups {
+        apply_region_groups(sing_box_config, &*state.region_latency.lock().await);
+    }
+    if config.bandwidth_probe.is_some() {
+        apply_bandwidth_probe(sing_box_config, &*state.node_bandwidth.lock().await);
+    }
+}
 
-    Ok(has_sub_nodes)
+/// Returns `true` if at least one subscription node was fetched successfully.
+pub async fn gen_config(config: &Config, state: &Arc<AppState>) -> AppResult<bool> {
+    gen_config_reusing(config, state, &HashMap::new(), &HashMap::new()).await
 }
 
-fn collect_manual_outbounds(config: &Config) -> (Vec<serde_json::Value>, Vec<String>) {
-    use crate::services::node_parser::parse_node_json;
+/// 与 gen_config 相同，但 `reuse` 中的订阅直接使用缓存结果，不重新获取；
+/// 重新获取的订阅在 `previous` 中都有结果且正文哈希全部未变时，不再重新生成配置
+async fn gen_config_reusing(
+    config: &Config,
+    state: &Arc<AppState>,
+    reuse: &HashMap<String, FetchResult>,
+    previous: &HashMap<String, FetchResult>,
+) -> AppResult<bool> {
+    let started = Instant::now();
+    let config = &*with_rule_set_mirrors(config, &state.http_client).await;
+    let (my_outbounds, my_names) = collect_manual_outbounds(config);
+    let fetched = fetch_subscriptions(
+        config,
+        &state.http_client,
+        reuse,
+        previous,
+        recorded_latencies(state).await,
+    )
+    .await;
+    let content_unchanged = !previous.is_empty()
+        && config
+            .subs
+            .iter()
+            .filter(|sub| !reuse.contains_key(&sub.url))
+            .all(|sub| {
+                let hash = |result: &FetchResult| result.content_hash;
+                let fresh = fetched
+                    .results
+                    .iter()
+                    .find(|(url, _)| *url == sub.url)
+                    .and_then(|(_, result)| hash(result));
+                fresh.is_some() && fresh == previous.get(&sub.url).and_then(hash)
+            });
 
-    let mut my_outbounds = vec![];
-    let mut my_names = vec![];
+    {
+        let mut status_map = state.sub_status.lock().await;
+        status_map.retain(|url, _| config.subs.iter().any(|sub| &sub.url == url));
+        for status in fetched.statuses {
+            status_map.insert(status.url.clone(), status);
+        }
+    }
+    {
+        let mut cache = state.sub_cache.lock().await;
+        cache.retain(|url, _| config.subs.iter().any(|sub| &sub.url == url));
+        cache.extend(fetched.results);
+    }
 
-    for (idx, node_str) in config.nodes.iter().enumerate() {
-        // 验证节点并获取解析后的 Value
-        match parse_node_json(node_str) {
-            Ok((info, outbound)) => {
-                my_names.push(info.tag);
-                my_outbounds.push(outbound);
-            }
-            Err(e) => {
-                warn!("[collect_manual_outbounds] Skipping node #{}: {}", idx, e);
-            }
+    let has_sub_nodes = !fetched.node_names.is_empty();
+    if content_unchanged {
+        info!("Subscription content unchanged, skipping config regeneration");
+        return Ok(has_sub_nodes);
+    }
+
+    let extra_output_nodes = (!config.outputs.is_empty()).then(|| {
+        (
+            (my_names.clone(), my_outbounds.clone()),
+            (fetched.node_names.clone(), fetched.outbounds.clone()),
+        )
+    });
+
+    let mut sing_box_config = build_sing_box_config(
+        config,
+        my_names,
+        my_outbounds,
+        fetched.node_names,
+        fetched.outbounds,
+    )?;
+    apply_measurements(config, &mut sing_box_config, state).await;
+    validate_listen_ports(&sing_box_config, config.port.unwrap_or(DEFAULT_PORT))?;
+
+    let outputs = match extra_output_nodes {
+        Some((manual, subscribed)) => {
+            render_extra_outputs(config, state, &manual, &subscribed).await?
         }
+        None => Vec::new(),
+    };
+    write_config_set(config, state, sing_box_config, outputs).await?;
+
+    let elapsed = started.elapsed();
+    info!(duration_ms = elapsed.as_millis() as u64, "Config generated");
+    *state.last_gen_duration.lock().await = Some(elapsed);
+
+    Ok(has_sub_nodes)
+}
+
+/// 把 output 的 template_patch 叠加到全局 template_patch 上，两者都是对象时逐键合并
+fn compose_template_patch(
+    base: Option<&serde_json::Value>,
+    overlay: Option<&serde_json::Value>,
+) -> Option<serde_json::Value> {
+    let Some(overlay) = overlay else {
+        return base.cloned();
+    };
+    let (Some(base), Some(overlay_obj)) = (base.and_then(|b| b.as_object()), overlay.as_object())
+    else {
+        return Some(overlay.clone());
+    };
+    let mut composed = base.clone();
+    for (key, value) in overlay_obj {
+        let merged = compose_template_patch(composed.get(key), Some(value));
+        composed.insert(key.clone(), merged.unwrap_or(serde_json::Value::Null));
     }
+    Some(serde_json::Value::Object(composed))
+}
 
-    (my_outbounds, my_names)
+/// 节点名与 outbound 列表，顺序一一对应
+type NodeList = (Vec<String>, Vec<serde_json::Value>);
+
+/// 用已获取的节点为单个 output 生成 sing-box 配置
+fn build_output_config(
+    config: &Config,
+    output: &ConfigOutput,
+    manual: &NodeList,
+    subscribed: &NodeList,
+) -> AppResult<serde_json::Value> {
+    let output_config = Config {
+        template_patch: compose_template_patch(
+            config.template_patch.as_ref(),
+            output.template_patch.as_ref(),
+        ),
+        ..config.clone()
+    };
+    let mut nodes: Vec<(String, serde_json::Value)> = subscribed
+        .0
+        .iter()
+        .cloned()
+        .zip(subscribed.1.iter().cloned())
+        .collect();
+    apply_node_filter(&mut nodes, &output.filter, &mut BTreeMap::new())?;
+    let (sub_names, sub_outbounds) = nodes.into_iter().unzip();
+    build_sing_box_config(
+        &output_config,
+        manual.0.clone(),
+        manual.1.clone(),
+        sub_names,
+        sub_outbounds,
+    )
 }
 
-fn make_unique_tag(tag: &str, used: &mut HashSet<String>) -> String {
-    let base = if tag.trim().is_empty() { "node" } else { tag };
-    if used.insert(base.to_string()) {
-        return base.to_string();
+/// 并发生成 outputs 中的各个配置，任何一个失败都使本次生成失败
+async fn render_extra_outputs<'a>(
+    config: &'a Config,
+    state: &AppState,
+    manual: &NodeList,
+    subscribed: &NodeList,
+) -> AppResult<Vec<(&'a ConfigOutput, serde_json::Value)>> {
+    futures::future::try_join_all(config.outputs.iter().map(|output| async move {
+        let mut sing_box_config = build_output_config(config, output, manual, subscribed)
+            .map_err(|e| AppError::context(format!("Failed to build output {}", output.path), e))?;
+        apply_measurements(config, &mut sing_box_config, state).await;
+        Ok::<_, AppError>((output, sing_box_config))
+    }))
+    .await
+}
+
+/// 把主配置与 outputs 作为一组写入：全部暂存并校验通过后才一起替换，
+/// 任何一个校验失败时所有文件都保持原样。没有 outputs 时主配置仍由调用方校验
+async fn write_config_set(
+    config: &Config,
+    state: &AppState,
+    mut sing_box_config: serde_json::Value,
+    outputs: Vec<(&ConfigOutput, serde_json::Value)>,
+) -> AppResult<()> {
+    let paths = &state.sing_box_paths;
+    let channel = config.sing_box_channel;
+    ensure_overwritable(paths, config.overwrite_foreign_config).await?;
+
+    let mut staged = StagedWrite::default();
+    let main_path = paths.config_path();
+    let mut main_content = serde_json::to_string(&sing_box_config)?;
+    let main_temp = staged.stage(&main_path, &main_content).await?;
+    let mut main_checked = false;
+    if config.annotate_sources {
+        if let Err(e) = validate_sing_box_config_file(paths, channel, &main_temp).await {
+            warn!(error = %e, "sing-box check failed with source annotations; writing config without them");
+            strip_source_annotations(&mut sing_box_config);
+            main_content = serde_json::to_string(&sing_box_config)?;
+            staged.stage(&main_path, &main_content).await?;
+        } else {
+            main_checked = true;
+        }
     }
 
-    for index in 2.. {
-        let candidate = format!("{base} ({index})");
-        if used.insert(candidate.clone()) {
-            return candidate;
+    if !outputs.is_empty() {
+        let mut output_temps = Vec::with_capacity(outputs.len());
+        for (output, output_config) in &outputs {
+            let temp = staged
+                .stage(
+                    Path::new(&output.path),
+                    &serde_json::to_string(output_config)?,
+                )
+                .await?;
+            output_temps.push((output.path.as_str(), temp));
+        }
+        if !main_checked {
+            validate_sing_box_config_file(paths, channel, &main_temp).await?;
+        }
+        futures::future::try_join_all(output_temps.iter().map(|(path, temp)| async move {
+            validate_sing_box_config_file(paths, channel, temp)
+                .await
+                .map_err(|e| AppError::context(format!("Output {} failed validation", path), e))
+        }))
+        .await?;
+    }
+
+    staged
+        .stage(&paths.config_marker_path(), &config_marker(&main_content))
+        .await?;
+    staged.commit().await?;
+    for (output, _) in &outputs {
+        info!(path = %output.path, "Extra config output written");
+    }
+    Ok(())
+}
+
+fn annotate_source(outbounds: &mut [serde_json::Value], source: &str) {
+    for outbound in outbounds {
+        if let Some(obj) = outbound.as_object_mut() {
+            obj.insert(SOURCE_ANNOTATION_KEY.to_string(), serde_json::json!(source));
+        }
+    }
+}
+
+fn strip_source_annotations(sing_box_config: &mut serde_json::Value) {
+    if let Some(outbounds) = sing_box_config["outbounds"].as_array_mut() {
+        for outbound in outbounds {
+            if let Some(obj) = outbound.as_object_mut() {
+                obj.remove(SOURCE_ANNOTATION_KEY);
+            }
+        }
+    }
+}
+
+/// 订阅自身的 client_cert/client_key 优先，否则使用全局配置；两者必须成对出现
+fn client_identity_paths<'a>(
+    sub: &'a Subscription,
+    config: &'a Config,
+) -> AppResult<Option<(&'a str, &'a str)>> {
+    let (cert, key) = if sub.client_cert.is_some() || sub.client_key.is_some() {
+        (sub.client_cert.as_deref(), sub.client_key.as_deref())
+    } else {
+        (config.client_cert.as_deref(), config.client_key.as_deref())
+    };
+
+    match (cert, key) {
+        (Some(cert), Some(key)) => Ok(Some((cert, key))),
+        (None, None) => Ok(None),
+        _ => Err(AppError::message(
+            "client_cert and client_key must be configured together",
+        )),
+    }
+}
+
+/// 按配置中的顺序返回各订阅最近一次获取的状态，尚未获取的订阅视为成功、0 个节点
+pub async fn subscription_statuses(state: &AppState) -> Vec<SubStatus> {
+    let config = state.config.read().await;
+    let status_map = state.sub_status.lock().await;
+
+    config
+        .subs
+        .iter()
+        .map(|sub| {
+            status_map.get(&sub.url).cloned().unwrap_or(SubStatus {
+                url: sub.url.clone(),
+                success: true,
+                node_count: 0,
+                error: None,
+                dropped: Default::default(),
+                userinfo: None,
+                fetch_ms: None,
+            })
+        })
+        .collect()
+}
+
+/// 单独获取并解析一个订阅，用于排查订阅内容；沿用配置中的解析选项与客户端证书，
+/// 不写入配置，也不影响正在运行的 sing-box
+pub async fn preview_subscription(
+    config: &Config,
+    client: &reqwest::Client,
+    sub: &Subscription,
+) -> AppResult<FetchResult> {
+    let parse_options = ParseOptions {
+        passthrough_keys: config.passthrough_keys.clone(),
+        name_transforms: config.name_transforms.clone(),
+        max_body_bytes: Some(config.max_sub_bytes()),
+        // 预览不依赖测速结果，limit_by: fastest 时按订阅顺序截取
+        latencies: HashMap::new(),
+    };
+    let client = subscription_client(sub, config, client).await?;
+    fetch_sub(
+        sub,
+        &client,
+        &parse_options,
+        sub.resolved_filter(config.node_filter.as_ref()),
+    )
+    .await
+}
+
+async fn subscription_client(
+    sub: &Subscription,
+    config: &Config,
+    default_client: &reqwest::Client,
+) -> AppResult<reqwest::Client> {
+    match client_identity_paths(sub, config)? {
+        Some((cert, key)) => client_with_identity(cert, key).await,
+        None => Ok(default_client.clone()),
+    }
+}
+
+/// 订阅合并顺序：手动节点始终最先；订阅按 priority 从高到低，同优先级按配置顺序。
+/// 同名节点中排在前面的保留原 tag，后面的重命名为 "tag (2)" 等。
+fn subscription_merge_key(subs: &[Subscription], url: &str) -> (std::cmp::Reverse<i32>, usize) {
+    subs.iter()
+        .position(|sub| sub.url == url)
+        .map(|idx| (std::cmp::Reverse(subs[idx].priority), idx))
+        .unwrap_or((std::cmp::Reverse(i32::MIN), usize::MAX))
+}
+
+fn format_dropped(dropped: &BTreeMap<String, usize>) -> String {
+    dropped
+        .iter()
+        .map(|(reason, count)| format!("{} dropped ({})", count, reason))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn collect_manual_outbounds(config: &Config) -> (Vec<serde_json::Value>, Vec<String>) {
+    use crate::services::node_parser::parse_node_json;
+
+    let mut my_outbounds = vec![];
+    let mut my_names = vec![];
+
+    for (idx, node_str) in config.nodes.iter().enumerate() {
+        // 验证节点并获取解析后的 Value
+        match parse_node_json(node_str) {
+            Ok((info, outbound)) => {
+                my_names.push(info.tag);
+                my_outbounds.push(outbound);
+            }
+            Err(e) => {
+                warn!("[collect_manual_outbounds] Skipping node #{}: {}", idx, e);
+            }
+        }
+    }
+
+    if config.annotate_sources {
+        annotate_source(&mut my_outbounds, MANUAL_SOURCE);
+    }
+
+    (my_outbounds, my_names)
+}
+
+/// 重名时使用的协议缩写，未列出的协议直接使用 outbound type
+fn protocol_suffix(outbound_type: &str) -> &str {
+    match outbound_type {
+        "hysteria2" => "hy2",
+        "shadowsocks" => "ss",
+        "shadowsocksr" => "ssr",
+        "wireguard" => "wg",
+        other => other,
+    }
+}
+
+/// 生成不冲突的 tag。提供 protocol 时先尝试 "name [协议]"，仍冲突再追加序号
+fn make_unique_tag(tag: &str, protocol: Option<&str>, used: &mut HashSet<String>) -> String {
+    let base = if tag.trim().is_empty() { "node" } else { tag };
+    if used.insert(base.to_string()) {
+        return base.to_string();
+    }
+
+    let suffixed;
+    let base = match protocol {
+        Some(protocol) => {
+            suffixed = format!("{base} [{}]", protocol_suffix(protocol));
+            if used.insert(suffixed.clone()) {
+                return suffixed;
+            }
+            suffixed.as_str()
+        }
+        None => base,
+    };
+
+    for index in 2.. {
+        let candidate = format!("{base} ({index})");
+        if used.insert(candidate.clone()) {
+            return candidate;
         }
     }
 
     unreachable!("unbounded duplicate tag search should always find a value")
 }
 
+/// 节点名称与对应的 outbound
+type NamedOutbound = (String, serde_json::Value);
+
+/// 按 tag_collision 处理手动节点与订阅节点同名的情况；rename 时保持不变，
+/// 由 normalize_outbound_tags 为订阅节点改名
+fn resolve_tag_collisions(
+    mut manual: Vec<NamedOutbound>,
+    mut subscribed: Vec<NamedOutbound>,
+    strategy: TagCollision,
+) -> (Vec<NamedOutbound>, Vec<NamedOutbound>) {
+    let (kept, dropped_from, reason) = match strategy {
+        TagCollision::Rename => return (manual, subscribed),
+        TagCollision::PreferManual => (&manual, &mut subscribed, "subscription"),
+        TagCollision::PreferSub => (&subscribed, &mut manual, "manual"),
+    };
+    let kept: HashSet<String> = kept.iter().map(|(name, _)| name.clone()).collect();
+    dropped_from.retain(|(name, _)| {
+        let collides = kept.contains(name);
+        if collides {
+            info!(node = %name, dropped = reason, "Dropped node whose tag collides");
+        }
+        !collides
+    });
+    (manual, subscribed)
+}
+
+/// 相同稳定标识的节点只保留第一个，手动节点排在订阅节点之前因此优先保留
+fn dedupe_by_identity(
+    node_names: Vec<String>,
+    outbounds: Vec<serde_json::Value>,
+) -> (Vec<String>, Vec<serde_json::Value>) {
+    let mut seen = HashSet::new();
+    let mut names = node_names.into_iter();
+    let mut kept_names = Vec::with_capacity(outbounds.len());
+    let mut kept_outbounds = Vec::with_capacity(outbounds.len());
+
+    for outbound in outbounds {
+        let name = names.next();
+        if let Some(identity) = node_identity(&outbound) {
+            if !seen.insert(identity.clone()) {
+                info!(node = ?name, identity = %identity, "Dropped node with duplicate identity");
+                continue;
+            }
+        }
+        kept_names.extend(name);
+        kept_outbounds.push(outbound);
+    }
+
+    (kept_names, kept_outbounds)
+}
+
 fn normalize_outbound_tags(
     node_names: Vec<String>,
     outbounds: Vec<serde_json::Value>,
+    protocol_tag_suffix: bool,
 ) -> (Vec<String>, Vec<serde_json::Value>) {
     let names_len = node_names.len();
     let mut used = HashSet::new();
@@ -508,7 +1458,10 @@ fn normalize_outbound_tags(
                     .map(str::to_string)
             })
             .unwrap_or_else(|| format!("node-{}", idx + 1));
-        let unique_name = make_unique_tag(&original_name, &mut used);
+        let protocol = protocol_tag_suffix
+            .then(|| outbound["type"].as_str())
+            .flatten();
+        let unique_name = make_unique_tag(&original_name, protocol, &mut used);
 
         if unique_name != original_name {
             warn!(
@@ -542,6 +1495,125 @@ fn normalize_outbound_tags(
     (unique_names, unique_outbounds)
 }
 
+/// 内置模板叠加 template_patch 和 dns 段，尚未注入节点
+fn base_config_template(config: &Config) -> AppResult<serde_json::Value> {
+    let mut sing_box_config = get_config_template();
+    if let Some(tun_mtu) = config.tun_mtu {
+        apply_tun_mtu(&mut sing_box_config, resolve_tun_mtu(tun_mtu));
+    }
+    if let Some(sing_log) = &config.sing_log {
+        apply_sing_log(&mut sing_box_config, sing_log);
+    }
+    if let Some(patch) = &config.template_patch {
+        apply_merge_patch(&mut sing_box_config, patch);
+    }
+    if let Some(dns) = &config.dns {
+        apply_dns_servers(&mut sing_box_config, dns)?;
+        apply_dns_routing(&mut sing_box_config, dns)?;
+    }
+    if !config.enable_clash_api {
+        remove_clash_api(&mut sing_box_config);
+    }
+    Ok(sing_box_config)
+}
+
+/// 只覆盖 sing_log 中设置了的字段，template_patch 仍可在之后修改 log 段
+fn apply_sing_log(sing_box_config: &mut serde_json::Value, sing_log: &SingLogConfig) {
+    let log = &mut sing_box_config["log"];
+    if let Some(level) = &sing_log.level {
+        log["level"] = serde_json::json!(level);
+    }
+    if let Some(timestamp) = sing_log.timestamp {
+        log["timestamp"] = serde_json::json!(timestamp);
+    }
+    if sing_log.disabled {
+        log["disabled"] = serde_json::json!(true);
+    }
+    if let Some(output) = &sing_log.output {
+        log["output"] = serde_json::json!(output);
+    }
+}
+
+/// 在 template_patch 之后执行，补丁无法重新打开 Clash API；experimental 为空时整段删除
+fn remove_clash_api(sing_box_config: &mut serde_json::Value) {
+    let Some(obj) = sing_box_config.as_object_mut() else {
+        return;
+    };
+    let now_empty = obj
+        .get_mut("experimental")
+        .and_then(|experimental| experimental.as_object_mut())
+        .map(|experimental| {
+            experimental.remove("clash_api");
+            experimental.remove("external_ui");
+            experimental.is_empty()
+        })
+        .unwrap_or(false);
+    if now_empty {
+        obj.remove("experimental");
+    }
+}
+
+fn apply_tun_mtu(sing_box_config: &mut serde_json::Value, mtu: u32) {
+    for inbound in sing_box_config["inbounds"]
+        .as_array_mut()
+        .into_iter()
+        .flatten()
+        .filter(|inbound| inbound["type"] == "tun")
+    {
+        inbound["mtu"] = serde_json::json!(mtu);
+    }
+}
+
+fn proxy_selector_outbounds(
+    sing_box_config: &mut serde_json::Value,
+) -> AppResult<&mut Vec<serde_json::Value>> {
+    sing_box_config["outbounds"]
+        .as_array_mut()
+        .and_then(|outbounds| {
+            outbounds
+                .iter_mut()
+                .find(|outbound| outbound["type"] == "selector" && outbound["tag"] == "proxy")
+        })
+        .and_then(|selector| selector.get_mut("outbounds"))
+        .and_then(|outbounds| outbounds.as_array_mut())
+        .ok_or_else(|| {
+            AppError::message(
+                "template_patch removed the \"proxy\" selector outbound required for node injection",
+            )
+        })
+}
+
+/// 没有任何可用节点时的直连兜底配置：proxy selector 只包含 direct，route.final 为 direct。
+/// app_rules、port_rules、domain_rules 可能引用不存在的节点，兜底模式下不应用
+fn build_direct_fallback_config(config: &Config) -> AppResult<serde_json::Value> {
+    let mut sing_box_config = base_config_template(config)?;
+    proxy_selector_outbounds(&mut sing_box_config)?.push(serde_json::json!("direct"));
+    prune_dangling_selector_entries(&mut sing_box_config);
+    apply_route_mode(
+        &mut sing_box_config,
+        config.route_mode,
+        &config.custom_rules,
+        &[],
+        &[],
+        &[],
+        &config.rule_sets,
+    )?;
+    sing_box_config["route"]["final"] = serde_json::json!("direct");
+    apply_sniff_settings(&mut sing_box_config, config)?;
+    Ok(sing_box_config)
+}
+
+/// 写入直连兜底配置，供订阅全部失败且没有缓存时让 TUN 以直通模式启动
+pub async fn write_direct_fallback_config(config: &Config, state: &Arc<AppState>) -> AppResult<()> {
+    let sing_box_config = build_direct_fallback_config(config)?;
+    write_generated_config(
+        &state.sing_box_paths,
+        &serde_json::to_string(&sing_box_config)?,
+        config.overwrite_foreign_config,
+    )
+    .await
+}
+
 fn build_sing_box_config(
     config: &Config,
     my_names: Vec<String>,
@@ -556,30 +1628,301 @@ fn build_sing_box_config(
         ));
     }
 
-    let (node_names, outbounds) = normalize_outbound_tags(
-        my_names.into_iter().chain(final_node_names).collect(),
-        my_outbounds.into_iter().chain(final_outbounds).collect(),
+    let (my_nodes, sub_nodes) = resolve_tag_collisions(
+        my_names.into_iter().zip(my_outbounds).collect(),
+        final_node_names.into_iter().zip(final_outbounds).collect(),
+        config.tag_collision,
     );
-
-    let mut sing_box_config = get_config_template();
-    if let Some(selector_outbounds) = sing_box_config["outbounds"][0].get_mut("outbounds") {
-        if let Some(arr) = selector_outbounds.as_array_mut() {
-            arr.extend(node_names.into_iter().map(serde_json::Value::String));
-        }
+    let (mut node_names, mut outbounds): (Vec<String>, Vec<serde_json::Value>) =
+        my_nodes.into_iter().chain(sub_nodes).unzip();
+    if config.stable_node_identity {
+        (node_names, outbounds) = dedupe_by_identity(node_names, outbounds);
+    }
+    let (node_names, mut outbounds) =
+        normalize_outbound_tags(node_names, outbounds, config.protocol_tag_suffix);
+
+    let mut sing_box_config = base_config_template(config)?;
+    let group_node_names = (!config.groups.is_empty()).then(|| node_names.clone());
+    proxy_selector_outbounds(&mut sing_box_config)?
+        .extend(node_names.into_iter().map(serde_json::Value::String));
+
+    apply_hysteria2_tuning(
+        &mut outbounds,
+        config
+            .hysteria2
+            .as_ref()
+            .unwrap_or(&Hysteria2Tuning::default()),
+    );
+    apply_tls_versions(
+        &mut outbounds,
+        config.tls_min_version.as_deref(),
+        config.tls_max_version.as_deref(),
+    );
+    if let Some(strategy) = config.outbound_domain_strategy.as_deref() {
+        Validator::domain_strategy(strategy).map_err(AppError::message)?;
+        let resolver = default_domain_resolver(&sing_box_config);
+        apply_outbound_domain_strategy(&mut outbounds, &resolver, strategy);
     }
     if let Some(arr) = sing_box_config["outbounds"].as_array_mut() {
         arr.extend(outbounds);
     }
+    if let Some(node_names) = group_node_names {
+        apply_node_groups(&mut sing_box_config, &node_names, &config.groups)?;
+    }
+
+    for (selector, tag) in prune_dangling_selector_entries(&mut sing_box_config) {
+        warn!(selector = %selector, tag = %tag, "Pruned selector entry referencing missing outbound");
+    }
 
+    validate_app_rules(&sing_box_config, &config.app_rules)?;
+    validate_port_rules(&sing_box_config, &config.port_rules)?;
+    validate_domain_rules(&sing_box_config, &config.domain_rules)?;
+    if let Some(route_final) = config.route_final.as_deref() {
+        if !outbound_tags(&sing_box_config).contains(route_final) {
+            return Err(AppError::message(format!(
+                "route_final references unknown outbound '{}'",
+                route_final
+            )));
+        }
+        if config.route_mode == RouteMode::Rule {
+            sing_box_config["route"]["final"] = serde_json::json!(route_final);
+        }
+    }
+    if let Some(dns_outbound) = config.dns_outbound.as_deref() {
+        if !outbound_tags(&sing_box_config).contains(dns_outbound) {
+            return Err(AppError::message(format!(
+                "dns_outbound references unknown outbound '{}'",
+                dns_outbound
+            )));
+        }
+        apply_dns_outbound(&mut sing_box_config, dns_outbound);
+    }
     apply_route_mode(
         &mut sing_box_config,
         config.route_mode,
         &config.custom_rules,
-    );
+        &config.app_rules,
+        &config.port_rules,
+        &config.domain_rules,
+        &config.rule_sets,
+    )?;
+    apply_sniff_settings(&mut sing_box_config, config)?;
 
     Ok(sing_box_config)
 }
 
+/// 移除 selector/urltest 中引用了不存在 outbound 的条目，返回 (selector, tag) 列表
+fn prune_dangling_selector_entries(
+    sing_box_config: &mut serde_json::Value,
+) -> Vec<(String, String)> {
+    let tags: HashSet<String> = outbound_tags(sing_box_config)
+        .into_iter()
+        .map(str::to_string)
+        .collect();
+    let mut pruned = Vec::new();
+
+    let Some(outbounds) = sing_box_config["outbounds"].as_array_mut() else {
+        return pruned;
+    };
+    for outbound in outbounds {
+        if !matches!(outbound["type"].as_str(), Some("selector" | "urltest")) {
+            continue;
+        }
+        let selector_tag = outbound["tag"].as_str().unwrap_or_default().to_string();
+
+        if let Some(members) = outbound["outbounds"].as_array_mut() {
+            members.retain(|member| {
+                let keep = member.as_str().is_some_and(|tag| tags.contains(tag));
+                if !keep {
+                    let tag = member.as_str().map(str::to_string);
+                    pruned.push((
+                        selector_tag.clone(),
+                        tag.unwrap_or_else(|| member.to_string()),
+                    ));
+                }
+                keep
+            });
+        }
+        let dangling_default = outbound["default"]
+            .as_str()
+            .is_some_and(|tag| !tags.contains(tag));
+        if dangling_default {
+            if let Some(obj) = outbound.as_object_mut() {
+                if let Some(serde_json::Value::String(default)) = obj.remove("default") {
+                    pruned.push((selector_tag.clone(), default));
+                }
+            }
+        }
+    }
+
+    pruned
+}
+
+fn outbound_tags(sing_box_config: &serde_json::Value) -> HashSet<&str> {
+    sing_box_config["outbounds"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|outbound| outbound["tag"].as_str())
+        .collect()
+}
+
+/// 校验 app_rules 引用的 outbound 是否存在于最终配置中
+fn validate_app_rules(sing_box_config: &serde_json::Value, app_rules: &[AppRule]) -> AppResult<()> {
+    let tags = outbound_tags(sing_box_config);
+
+    for rule in app_rules {
+        if rule.process.trim().is_empty() {
+            return Err(AppError::message(
+                "app_rules entry has an empty process name",
+            ));
+        }
+        if !tags.contains(rule.outbound.as_str()) {
+            return Err(AppError::message(format!(
+                "app_rules entry for '{}' references unknown outbound '{}'",
+                rule.process, rule.outbound
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// 校验 port_rules 的端口格式及引用的 outbound 是否存在于最终配置中
+fn validate_port_rules(
+    sing_box_config: &serde_json::Value,
+    port_rules: &[PortRule],
+) -> AppResult<()> {
+    let tags = outbound_tags(sing_box_config);
+
+    for rule in port_rules {
+        parse_port_spec(&rule.ports).map_err(|e| {
+            AppError::message(format!(
+                "port_rules entry '{}' is invalid: {}",
+                rule.ports, e
+            ))
+        })?;
+        if !tags.contains(rule.outbound.as_str()) {
+            return Err(AppError::message(format!(
+                "port_rules entry for '{}' references unknown outbound '{}'",
+                rule.ports, rule.outbound
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// 校验 miao 面板、Clash API 与各入站的监听端口互不重复，
+/// 避免 sing-box 启动时才报出难以理解的端口占用错误
+fn validate_listen_ports(sing_box_config: &serde_json::Value, miao_port: u16) -> AppResult<()> {
+    let mut listeners = vec![("miao port".to_string(), miao_port)];
+    if let Some(port) = sing_box_config["experimental"]["clash_api"]["external_controller"]
+        .as_str()
+        .and_then(|address| split_host_port(address).ok())
+        .and_then(|(_, port)| port)
+    {
+        listeners.push(("clash_api".to_string(), port));
+    }
+    for inbound in sing_box_config["inbounds"].as_array().into_iter().flatten() {
+        let Some(port) = inbound["listen_port"]
+            .as_u64()
+            .and_then(|port| u16::try_from(port).ok())
+        else {
+            continue;
+        };
+        let name = inbound["tag"]
+            .as_str()
+            .or_else(|| inbound["type"].as_str())
+            .unwrap_or_default();
+        listeners.push((format!("inbound '{}'", name), port));
+    }
+
+    let mut first_user: HashMap<u16, &str> = HashMap::new();
+    let mut conflicts = Vec::new();
+    for (name, port) in &listeners {
+        if let Some(first) = first_user.insert(*port, name) {
+            conflicts.push(format!("port {} used by both {} and {}", port, first, name));
+            first_user.insert(*port, first);
+        }
+    }
+    if conflicts.is_empty() {
+        return Ok(());
+    }
+    Err(AppError::message(format!(
+        "Listen port conflict: {}",
+        conflicts.join("; ")
+    )))
+}
+
+/// 校验 domain_rules 引用的 outbound 是否存在于最终配置中
+fn validate_domain_rules(
+    sing_box_config: &serde_json::Value,
+    domain_rules: &[DomainRule],
+) -> AppResult<()> {
+    let tags = outbound_tags(sing_box_config);
+
+    for rule in domain_rules {
+        if !tags.contains(rule.outbound.as_str()) {
+            return Err(AppError::message(format!(
+                "domain_rules entry for '{}' references unknown outbound '{}'",
+                rule.domain_suffix
+                    .iter()
+                    .chain(&rule.domain)
+                    .map(String::as_str)
+                    .collect::<Vec<_>>()
+                    .join(","),
+                rule.outbound
+            )));
+        }
+    }
+    Ok(())
+}
+
+fn domain_rules_to_route_rules(domain_rules: &[DomainRule]) -> Vec<serde_json::Value> {
+    domain_rules
+        .iter()
+        .map(|rule| {
+            let mut route_rule = serde_json::json!({"action": "route", "outbound": rule.outbound});
+            if !rule.domain.is_empty() {
+                route_rule["domain"] = serde_json::json!(rule.domain);
+            }
+            if !rule.domain_suffix.is_empty() {
+                route_rule["domain_suffix"] = serde_json::json!(rule.domain_suffix);
+            }
+            route_rule
+        })
+        .collect()
+}
+
+fn port_rules_to_route_rules(port_rules: &[PortRule]) -> Vec<serde_json::Value> {
+    port_rules
+        .iter()
+        .filter_map(|rule| {
+            let (ports, ranges) = parse_port_spec(&rule.ports).ok()?;
+            let mut route_rule = serde_json::json!({"action": "route", "outbound": rule.outbound});
+            if !ports.is_empty() {
+                route_rule["port"] = serde_json::json!(ports);
+            }
+            if !ranges.is_empty() {
+                route_rule["port_range"] = serde_json::json!(ranges);
+            }
+            Some(route_rule)
+        })
+        .collect()
+}
+
+fn app_rules_to_route_rules(app_rules: &[AppRule]) -> Vec<serde_json::Value> {
+    app_rules
+        .iter()
+        .map(|rule| {
+            serde_json::json!({
+                "process_name": [rule.process.trim()],
+                "action": "route",
+                "outbound": rule.outbound
+            })
+        })
+        .collect()
+}
+
 fn parse_custom_rules(custom_rules: &[String]) -> Vec<serde_json::Value> {
     let mut parsed = Vec::new();
     for rule_str in custom_rules {
@@ -592,19 +1935,109 @@ fn parse_custom_rules(custom_rules: &[String]) -> Vec<serde_json::Value> {
     parsed
 }
 
+/// 按配置调整或移除 sniff 路由规则。在插入用户规则之后执行，
+/// 因为 apply_route_mode 依赖 sniff 与 hijack-dns 占据前两条规则
+fn apply_sniff_settings(sing_box_config: &mut serde_json::Value, config: &Config) -> AppResult<()> {
+    let Some(rules) = sing_box_config["route"]["rules"].as_array_mut() else {
+        return Ok(());
+    };
+    if !config.sniff {
+        rules.retain(|rule| rule["action"] != "sniff");
+        return Ok(());
+    }
+
+    Validator::sniff_protocols(&config.sniff_protocols).map_err(AppError::message)?;
+    for rule in rules.iter_mut().filter(|rule| rule["action"] == "sniff") {
+        if let Some(timeout_ms) = config.sniff_timeout_ms {
+            rule["timeout"] = serde_json::json!(format!("{}ms", timeout_ms));
+        }
+        if !config.sniff_protocols.is_empty() {
+            rule["sniffer"] = serde_json::json!(config.sniff_protocols);
+        }
+    }
+    Ok(())
+}
+
+/// 把 rule_sets 写入 route.rule_set，返回对应的路由规则；tag 不能与模板中已有的规则集重复
+fn apply_rule_sets(
+    sing_box_config: &mut serde_json::Value,
+    rule_sets: &[RuleSetRule],
+) -> AppResult<Vec<serde_json::Value>> {
+    if rule_sets.is_empty() {
+        return Ok(Vec::new());
+    }
+    Validator::rule_sets(rule_sets).map_err(AppError::message)?;
+
+    let route = &mut sing_box_config["route"];
+    if route["rule_set"].is_null() {
+        route["rule_set"] = serde_json::json!([]);
+    }
+    let Some(definitions) = route["rule_set"].as_array_mut() else {
+        return Err(AppError::message(
+            "route.rule_set in template is not an array",
+        ));
+    };
+
+    let mut rules = Vec::with_capacity(rule_sets.len());
+    for rule_set in rule_sets {
+        let tag = rule_set.tag.trim();
+        if definitions.iter().any(|existing| existing["tag"] == tag) {
+            return Err(AppError::message(format!(
+                "rule_sets entry '{}' collides with a rule set already defined in the template",
+                tag
+            )));
+        }
+        let format = match rule_set.format {
+            RuleSetFormat::Binary => "binary",
+            RuleSetFormat::Source => "source",
+        };
+        definitions.push(serde_json::json!({
+            "type": "remote",
+            "tag": tag,
+            "format": format,
+            "url": rule_set.url
+        }));
+
+        rules.push(match rule_set.action {
+            RuleSetAction::Direct => {
+                serde_json::json!({"rule_set": [tag], "action": "route", "outbound": "direct"})
+            }
+            RuleSetAction::Proxy => {
+                serde_json::json!({"rule_set": [tag], "action": "route", "outbound": "proxy"})
+            }
+            RuleSetAction::Block => serde_json::json!({"rule_set": [tag], "action": "reject"}),
+        });
+    }
+    Ok(rules)
+}
+
 fn apply_route_mode(
     sing_box_config: &mut serde_json::Value,
     route_mode: RouteMode,
     custom_rules: &[String],
-) {
+    app_rules: &[AppRule],
+    port_rules: &[PortRule],
+    domain_rules: &[DomainRule],
+    rule_sets: &[RuleSetRule],
+) -> AppResult<()> {
+    // 全局模式下不使用分流规则，规则集也无需下载
+    let rule_set_rules = match route_mode {
+        RouteMode::Rule => apply_rule_sets(sing_box_config, rule_sets)?,
+        RouteMode::Global => Vec::new(),
+    };
+
     if let Some(rules) = sing_box_config["route"]["rules"].as_array_mut() {
         match route_mode {
             RouteMode::Rule => {
-                let custom_rules = parse_custom_rules(custom_rules);
+                let mut user_rules = parse_custom_rules(custom_rules);
+                user_rules.extend(app_rules_to_route_rules(app_rules));
+                user_rules.extend(port_rules_to_route_rules(port_rules));
+                user_rules.extend(domain_rules_to_route_rules(domain_rules));
+                user_rules.extend(rule_set_rules);
                 // Preserve the mandatory pre-routing actions, then let user rules take
                 // precedence over the built-in direct/proxy split rules.
                 let insertion_index = rules.len().min(2);
-                rules.splice(insertion_index..insertion_index, custom_rules);
+                rules.splice(insertion_index..insertion_index, user_rules);
             }
             RouteMode::Global => {
                 rules.truncate(2);
@@ -617,12 +2050,228 @@ fn apply_route_mode(
             dns_rules.clear();
         }
     }
+    Ok(())
+}
+
+fn dns_server_entry(
+    tag: &str,
+    server: &DnsServer,
+    detour: Option<&str>,
+) -> AppResult<serde_json::Value> {
+    Validator::dns_server(server).map_err(AppError::message)?;
+    let address = server.address.trim();
+
+    let server_type = match server.server_type {
+        DnsServerType::Udp => "udp",
+        DnsServerType::Tls => "tls",
+        DnsServerType::Https => "https",
+        DnsServerType::Quic => "quic",
+    };
+    let mut entry = serde_json::json!({"type": server_type, "tag": tag});
+
+    if server.server_type == DnsServerType::Https {
+        let url = url::Url::parse(address)
+            .map_err(|e| AppError::message(format!("Invalid DoH URL '{}': {}", address, e)))?;
+        let host = url.host_str().unwrap_or_default();
+        entry["server"] = serde_json::json!(host.trim_start_matches('[').trim_end_matches(']'));
+        if let Some(port) = url.port() {
+            entry["server_port"] = serde_json::json!(port);
+        }
+        if url.path() != "/" {
+            entry["path"] = serde_json::json!(url.path());
+        }
+    } else {
+        let (host, port) = split_host_port(address).map_err(AppError::message)?;
+        entry["server"] = serde_json::json!(host);
+        if let Some(port) = port {
+            entry["server_port"] = serde_json::json!(port);
+        }
+    }
+
+    if let Some(detour) = detour {
+        entry["detour"] = serde_json::json!(detour);
+    }
+    Ok(entry)
+}
+
+/// 用配置中的 dns 段替换模板里的远程/本地 DNS 服务器，远程服务器始终经 proxy 出站
+fn apply_dns_servers(sing_box_config: &mut serde_json::Value, dns: &DnsConfig) -> AppResult<()> {
+    let overrides = [
+        (REMOTE_DNS_SERVER, dns.remote.as_ref(), Some("proxy")),
+        (LOCAL_DNS_SERVER, dns.local.as_ref(), None),
+    ];
+
+    for (tag, server, detour) in overrides {
+        let Some(server) = server else {
+            continue;
+        };
+        let entry = dns_server_entry(tag, server, detour)?;
+        let Some(servers) = sing_box_config["dns"]["servers"].as_array_mut() else {
+            sing_box_config["dns"]["servers"] = serde_json::json!([entry]);
+            continue;
+        };
+        match servers.iter_mut().find(|existing| existing["tag"] == tag) {
+            Some(existing) => *existing = entry,
+            None => servers.push(entry),
+        }
+    }
+    Ok(())
+}
+
+/// 设置 dns.final，并在 no_split 时去掉路由到本地 DNS 服务器的规则
+fn apply_dns_routing(sing_box_config: &mut serde_json::Value, dns: &DnsConfig) -> AppResult<()> {
+    if dns.no_split {
+        if let Some(rules) = sing_box_config["dns"]["rules"].as_array_mut() {
+            rules.retain(|rule| rule["server"] != LOCAL_DNS_SERVER);
+        }
+    }
+    let Some(final_server) = &dns.final_server else {
+        return Ok(());
+    };
+    let defined = sing_box_config["dns"]["servers"]
+        .as_array()
+        .is_some_and(|servers| servers.iter().any(|server| server["tag"] == *final_server));
+    if !defined {
+        return Err(AppError::message(format!(
+            "dns.final references unknown DNS server: {}",
+            final_server
+        )));
+    }
+    sing_box_config["dns"]["final"] = serde_json::json!(final_server);
+    Ok(())
+}
+
+/// 让远程 DNS 服务器经指定 outbound 发出查询，而不是整个 proxy 选择器
+fn apply_dns_outbound(sing_box_config: &mut serde_json::Value, outbound: &str) {
+    let remote = sing_box_config["dns"]["servers"]
+        .as_array_mut()
+        .and_then(|servers| {
+            servers
+                .iter_mut()
+                .find(|server| server["tag"] == REMOTE_DNS_SERVER)
+        });
+    match remote {
+        Some(server) => server["detour"] = serde_json::json!(outbound),
+        None => warn!(
+            outbound = %outbound,
+            "dns_outbound ignored: remote DNS server missing from template"
+        ),
+    }
+}
+
+fn default_domain_resolver(sing_box_config: &serde_json::Value) -> String {
+    let resolver = &sing_box_config["route"]["default_domain_resolver"];
+    resolver
+        .as_str()
+        .or_else(|| resolver["server"].as_str())
+        .unwrap_or(LOCAL_DNS_SERVER)
+        .to_string()
+}
+
+/// 基于 QUIC 的协议，只能使用 TLS 1.3
+const QUIC_PROTOCOLS: &[&str] = &["hysteria", "hysteria2", "tuic"];
+
+/// 为启用了 TLS 的节点补上全局 TLS 版本范围，节点自带的 min_version/max_version 不变；
+/// QUIC 节点不补 max_version，避免低于 1.3 的上限让它们无法握手
+fn apply_tls_versions(outbounds: &mut [serde_json::Value], min: Option<&str>, max: Option<&str>) {
+    if min.is_none() && max.is_none() {
+        return;
+    }
+    for outbound in outbounds {
+        let quic = outbound["type"]
+            .as_str()
+            .is_some_and(|protocol| QUIC_PROTOCOLS.contains(&protocol));
+        let Some(tls) = outbound.get_mut("tls").and_then(|tls| tls.as_object_mut()) else {
+            continue;
+        };
+        if tls.get("enabled") != Some(&serde_json::Value::Bool(true)) {
+            continue;
+        }
+        let max = max.filter(|_| !quic);
+        for (key, version) in [("min_version", min), ("max_version", max)] {
+            if let Some(version) = version {
+                tls.entry(key).or_insert_with(|| serde_json::json!(version));
+            }
+        }
+    }
+}
+
+/// 为没有带宽提示的 hysteria2 节点补上默认带宽，或去掉带宽提示改用 BBR；
+/// 节点自身的 ignore_bandwidth 优先于全局设置
+fn apply_hysteria2_tuning(outbounds: &mut [serde_json::Value], tuning: &Hysteria2Tuning) {
+    for outbound in outbounds
+        .iter_mut()
+        .filter(|outbound| outbound["type"] == "hysteria2")
+    {
+        let Some(obj) = outbound.as_object_mut() else {
+            continue;
+        };
+        let node_ignores = obj.remove("ignore_bandwidth") == Some(serde_json::json!(true));
+        if node_ignores || tuning.ignore_bandwidth {
+            obj.remove("up_mbps");
+            obj.remove("down_mbps");
+            continue;
+        }
+        if obj.contains_key("up_mbps") || obj.contains_key("down_mbps") {
+            continue;
+        }
+        if let Some(up) = tuning.up_mbps {
+            obj.insert("up_mbps".to_string(), serde_json::json!(up));
+        }
+        if let Some(down) = tuning.down_mbps {
+            obj.insert("down_mbps".to_string(), serde_json::json!(down));
+        }
+    }
+}
+
+/// 为节点设置全局域名解析策略；节点自身已配置 domain_resolver/domain_strategy 时优先保留
+fn apply_outbound_domain_strategy(
+    outbounds: &mut [serde_json::Value],
+    resolver: &str,
+    strategy: &str,
+) {
+    for outbound in outbounds {
+        let Some(obj) = outbound.as_object_mut() else {
+            continue;
+        };
+        if obj.contains_key("domain_resolver") || obj.contains_key("domain_strategy") {
+            continue;
+        }
+        obj.insert(
+            "domain_resolver".to_string(),
+            serde_json::json!({"server": resolver, "strategy": strategy}),
+        );
+    }
+}
+
+/// 按 RFC 7386 将 merge patch 应用到目标 JSON：null 删除字段，对象递归合并，其余值直接替换
+fn apply_merge_patch(target: &mut serde_json::Value, patch: &serde_json::Value) {
+    let Some(patch) = patch.as_object() else {
+        *target = patch.clone();
+        return;
+    };
+
+    if !target.is_object() {
+        *target = serde_json::Value::Object(Default::default());
+    }
+    if let Some(target) = target.as_object_mut() {
+        for (key, value) in patch {
+            if value.is_null() {
+                target.remove(key);
+            } else {
+                apply_merge_patch(
+                    target.entry(key.clone()).or_insert(serde_json::Value::Null),
+                    value,
+                );
+            }
+        }
+    }
 }
 
-fn get_config_template() -> serde_json::Value {
+pub(crate) fn get_config_template() -> serde_json::Value {
     serde_json::json!({
         "log": {"disabled": false, "timestamp": true, "level": "info"},
-        "experimental": {"clash_api": {"external_controller": "127.0.0.1:6262"}},
+        "experimental": {"clash_api": {"external_controller": CLASH_API_ADDR}},
         "dns": {
             "final": "cfdns",
             "strategy": "ipv4_only",
@@ -666,10 +2315,28 @@ fn get_config_template() -> serde_json::Value {
 #[cfg(test)]
 mod tests {
     use super::{
-        build_sing_box_config, collect_manual_outbounds, config_with_route_override, save_config_to,
+        annotate_source, apply_hysteria2_tuning, apply_merge_patch, apply_tls_versions,
+        build_direct_fallback_config, build_output_config, build_sing_box_config,
+        check_generated_json, client_identity_paths, collect_manual_outbounds,
+        compose_template_patch, config_marker, config_with_route_override, fetch_subscriptions,
+        get_config_template, is_miao_managed, node_outbounds, outbound_tags, parse_config,
+        persist_file_config_change, regenerate_if_corrupt, restore_config_from_cache,
+        save_config_to, strip_source_annotations, subscription_merge_key, validate_listen_ports,
+        write_generated_config, FAILOVER_SKIPPED, LOCAL_DNS_SERVER, REMOTE_DNS_SERVER,
     };
-    use crate::models::{Config, RouteMode};
+    use crate::models::{
+        AppRule, Config, ConfigOutput, DnsConfig, DnsServer, DnsServerType, DomainRule,
+        Hysteria2Tuning, NodeFilter, PortRule, RouteMode, RuleSetAction, RuleSetFormat,
+        RuleSetRule, SingLogConfig, Subscription, TagCollision,
+    };
+    use crate::services::singbox::SingBoxPaths;
+    use crate::services::subscription::FetchResult;
+    use crate::state::AppState;
+    use crate::validation::Validator;
     use serde_json::json;
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use std::time::Instant;
 
     #[test]
     fn collect_manual_outbounds_ignores_invalid_json_nodes() {
@@ -683,6 +2350,7 @@ mod tests {
             custom_rules: vec![],
             route_mode: Default::default(),
             vps_ip: None,
+            ..Default::default()
         };
 
         let (outbounds, names) = collect_manual_outbounds(&config);
@@ -705,6 +2373,7 @@ mod tests {
             custom_rules: vec![],
             route_mode: Default::default(),
             vps_ip: None,
+            ..Default::default()
         };
 
         let (outbounds, names) = collect_manual_outbounds(&config);
@@ -729,6 +2398,7 @@ mod tests {
             ],
             route_mode: Default::default(),
             vps_ip: None,
+            ..Default::default()
         };
 
         let my_outbounds = vec![json!({
@@ -786,6 +2456,7 @@ mod tests {
             ],
             route_mode: RouteMode::Global,
             vps_ip: None,
+            ..Default::default()
         };
 
         let my_outbounds = vec![json!({
@@ -825,6 +2496,7 @@ mod tests {
             custom_rules: vec![],
             route_mode: RouteMode::Global,
             vps_ip: None,
+            ..Default::default()
         };
 
         let runtime_config = config_with_route_override(&config, None);
@@ -841,6 +2513,7 @@ mod tests {
             custom_rules: vec![],
             route_mode: Default::default(),
             vps_ip: None,
+            ..Default::default()
         };
 
         let my_outbounds = vec![json!({
@@ -868,134 +2541,1336 @@ mod tests {
             }),
         ];
 
-        let built = build_sing_box_config(
-            &config,
-            vec!["dup".to_string()],
-            my_outbounds,
-            vec!["dup".to_string(), "dup".to_string()],
-            final_outbounds,
-        )
-        .unwrap();
+        let built = build_sing_box_config(
+            &config,
+            vec!["dup".to_string()],
+            my_outbounds,
+            vec!["dup".to_string(), "dup".to_string()],
+            final_outbounds,
+        )
+        .unwrap();
+
+        let selector = built["outbounds"][0]["outbounds"].as_array().unwrap();
+        let selector_tags: Vec<_> = selector
+            .iter()
+            .map(|value| value.as_str().unwrap())
+            .collect();
+        assert_eq!(selector_tags, vec!["dup", "dup (2)", "dup (3)"]);
+
+        let all_outbounds = built["outbounds"].as_array().unwrap();
+        assert_eq!(all_outbounds[2]["tag"], "dup");
+        assert_eq!(all_outbounds[3]["tag"], "dup (2)");
+        assert_eq!(all_outbounds[4]["tag"], "dup (3)");
+    }
+
+    #[test]
+    fn build_sing_box_config_suffixes_duplicate_tags_with_protocol() {
+        let config = Config {
+            protocol_tag_suffix: true,
+            ..Default::default()
+        };
+        let outbound = |kind: &str| json!({"type": kind, "tag": "Tokyo", "server": "example.com"});
+
+        let built = build_sing_box_config(
+            &config,
+            vec![],
+            vec![],
+            vec!["Tokyo".to_string(); 4],
+            vec![
+                outbound("vmess"),
+                outbound("hysteria2"),
+                outbound("shadowsocks"),
+                outbound("hysteria2"),
+            ],
+        )
+        .unwrap();
+
+        let selector_tags: Vec<_> = built["outbounds"][0]["outbounds"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|value| value.as_str().unwrap())
+            .collect();
+        assert_eq!(
+            selector_tags,
+            vec!["Tokyo", "Tokyo [hy2]", "Tokyo [ss]", "Tokyo [hy2] (2)"]
+        );
+        assert_eq!(built["outbounds"][3]["tag"], "Tokyo [hy2]");
+    }
+
+    #[test]
+    fn build_sing_box_config_dedupes_nodes_by_stable_identity() {
+        let config = Config {
+            stable_node_identity: true,
+            ..Default::default()
+        };
+        let outbound = |tag: &str, server: &str| json!({"type": "hysteria2", "tag": tag, "server": server, "server_port": 443});
+
+        let built = build_sing_box_config(
+            &config,
+            vec!["mine".to_string()],
+            vec![outbound("mine", "jp.example.com")],
+            vec!["JP 01".to_string(), "JP 02".to_string()],
+            vec![
+                outbound("JP 01", "jp.example.com"),
+                outbound("JP 02", "jp2.example.com"),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(built["outbounds"][0]["outbounds"], json!(["mine", "JP 02"]));
+    }
+
+    #[test]
+    fn build_sing_box_config_renames_tags_reserved_by_template() {
+        let config = Config {
+            port: None,
+            subs: vec![],
+            nodes: vec![],
+            custom_rules: vec![],
+            route_mode: Default::default(),
+            vps_ip: None,
+            ..Default::default()
+        };
+
+        let my_outbounds = vec![
+            json!({
+                "type": "hysteria2",
+                "tag": "proxy",
+                "server": "proxy.example.com",
+                "server_port": 443,
+                "password": "proxy-secret"
+            }),
+            json!({
+                "type": "hysteria2",
+                "tag": "direct",
+                "server": "direct.example.com",
+                "server_port": 443,
+                "password": "direct-secret"
+            }),
+        ];
+
+        let built = build_sing_box_config(
+            &config,
+            vec!["proxy".to_string(), "direct".to_string()],
+            my_outbounds,
+            vec![],
+            vec![],
+        )
+        .unwrap();
+
+        let selector = built["outbounds"][0]["outbounds"].as_array().unwrap();
+        let selector_tags: Vec<_> = selector
+            .iter()
+            .map(|value| value.as_str().unwrap())
+            .collect();
+        assert_eq!(selector_tags, vec!["proxy (2)", "direct (2)"]);
+
+        let all_outbounds = built["outbounds"].as_array().unwrap();
+        assert_eq!(all_outbounds[0]["tag"], "proxy");
+        assert_eq!(all_outbounds[1]["tag"], "direct");
+        assert_eq!(all_outbounds[2]["tag"], "proxy (2)");
+        assert_eq!(all_outbounds[3]["tag"], "direct (2)");
+    }
+
+    #[test]
+    fn build_sing_box_config_errors_when_no_nodes_available() {
+        let config = Config {
+            port: None,
+            subs: vec![],
+            nodes: vec![],
+            custom_rules: vec![],
+            route_mode: Default::default(),
+            vps_ip: None,
+            ..Default::default()
+        };
+
+        let err = build_sing_box_config(&config, vec![], vec![], vec![], vec![]).unwrap_err();
+
+        assert!(err.to_string().contains(
+            "No nodes available: all subscriptions failed and no manual nodes configured"
+        ));
+    }
+
+    #[test]
+    fn collect_manual_outbounds_handles_empty_nodes() {
+        let config = Config {
+            port: None,
+            subs: vec![],
+            nodes: vec![],
+            custom_rules: vec![],
+            route_mode: Default::default(),
+            vps_ip: None,
+            ..Default::default()
+        };
+
+        let (outbounds, names) = collect_manual_outbounds(&config);
+
+        assert!(outbounds.is_empty());
+        assert!(names.is_empty());
+    }
+
+    #[test]
+    fn collect_manual_outbounds_handles_all_invalid_nodes() {
+        let config = Config {
+            port: None,
+            subs: vec![],
+            nodes: vec![
+                "not-json".to_string(),
+                r#"{}"#.to_string(),                   // Valid JSON but no tag
+                r#"{"type":"hysteria2"}"#.to_string(), // Valid JSON but no tag
+            ],
+            custom_rules: vec![],
+            route_mode: Default::default(),
+            vps_ip: None,
+            ..Default::default()
+        };
+
+        let (outbounds, names) = collect_manual_outbounds(&config);
+
+        // All nodes fail validation (missing required fields)
+        assert!(outbounds.is_empty());
+        assert!(names.is_empty());
+    }
+
+    #[test]
+    fn build_sing_box_config_applies_domain_strategy_with_node_precedence() {
+        let config = Config {
+            outbound_domain_strategy: Some("ipv4_only".to_string()),
+            ..Default::default()
+        };
+
+        let built = build_sing_box_config(
+            &config,
+            vec!["global".to_string(), "own".to_string()],
+            vec![
+                json!({"type": "hysteria2", "tag": "global", "server": "g.example.com", "server_port": 443, "password": "p"}),
+                json!({"type": "hysteria2", "tag": "own", "server": "o.example.com", "server_port": 443, "password": "p", "domain_resolver": {"server": "local", "strategy": "prefer_ipv6"}}),
+            ],
+            vec![],
+            vec![],
+        )
+        .unwrap();
+
+        let outbounds = built["outbounds"].as_array().unwrap();
+        assert_eq!(
+            outbounds[2]["domain_resolver"],
+            json!({"server": "local", "strategy": "ipv4_only"})
+        );
+        assert_eq!(outbounds[3]["domain_resolver"]["strategy"], "prefer_ipv6");
+        assert!(outbounds[0].get("domain_resolver").is_none());
+    }
+
+    #[test]
+    fn build_sing_box_config_rejects_invalid_domain_strategy() {
+        let config = Config {
+            outbound_domain_strategy: Some("ipv4".to_string()),
+            ..Default::default()
+        };
+
+        let err = build_sing_box_config(
+            &config,
+            vec!["node".to_string()],
+            vec![json!({"type": "hysteria2", "tag": "node", "server": "n.example.com", "server_port": 443, "password": "p"})],
+            vec![],
+            vec![],
+        )
+        .unwrap_err();
+
+        assert!(err.to_string().contains("ipv4"));
+    }
+
+    #[test]
+    fn build_sing_box_config_injects_encrypted_dns_servers() {
+        let config = Config {
+            dns: Some(DnsConfig {
+                remote: Some(DnsServer {
+                    server_type: DnsServerType::Https,
+                    address: "https://dns.example.com:8443/custom-query".to_string(),
+                }),
+                local: Some(DnsServer {
+                    server_type: DnsServerType::Tls,
+                    address: "223.5.5.5:853".to_string(),
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        let built = build_sing_box_config(
+            &config,
+            vec!["node".to_string()],
+            vec![json!({"type": "hysteria2", "tag": "node", "server": "n.example.com", "server_port": 443, "password": "p"})],
+            vec![],
+            vec![],
+        )
+        .unwrap();
+
+        assert_eq!(
+            built["dns"]["servers"],
+            json!([
+                {"type": "https", "tag": "cfdns", "server": "dns.example.com", "server_port": 8443, "path": "/custom-query", "detour": "proxy"},
+                {"type": "tls", "tag": "local", "server": "223.5.5.5", "server_port": 853}
+            ])
+        );
+    }
+
+    #[test]
+    fn compose_template_patch_merges_objects_key_by_key() {
+        let base = json!({"log": {"level": "warn", "timestamp": false}, "ntp": {"enabled": true}});
+        let overlay = json!({"log": {"level": "error"}, "ntp": null});
+
+        assert_eq!(
+            compose_template_patch(Some(&base), Some(&overlay)),
+            Some(json!({"log": {"level": "error", "timestamp": false}, "ntp": null}))
+        );
+        assert_eq!(
+            compose_template_patch(Some(&base), None),
+            Some(base.clone())
+        );
+        assert_eq!(compose_template_patch(None, Some(&overlay)), Some(overlay));
+    }
+
+    #[test]
+    fn build_output_config_filters_subscription_nodes_and_patches_template() {
+        let config = Config {
+            template_patch: Some(json!({"log": {"level": "warn"}})),
+            ..Default::default()
+        };
+        let output = ConfigOutput {
+            path: "/tmp/mobile.json".to_string(),
+            template_patch: Some(json!({"log": {"timestamp": false}})),
+            filter: NodeFilter {
+                include: Some("^JP".to_string()),
+                ..Default::default()
+            },
+        };
+        let node = |tag: &str| json!({"type": "hysteria2", "tag": tag, "server": "n.example.com", "server_port": 443, "password": "p"});
+        let manual = (vec!["Home".to_string()], vec![node("Home")]);
+        let subscribed = (
+            vec!["JP 01".to_string(), "US 01".to_string()],
+            vec![node("JP 01"), node("US 01")],
+        );
+
+        let built = build_output_config(&config, &output, &manual, &subscribed).unwrap();
+
+        assert_eq!(built["log"]["level"], "warn");
+        assert_eq!(built["log"]["timestamp"], false);
+        let tags = outbound_tags(&built);
+        assert!(tags.contains("Home"));
+        assert!(tags.contains("JP 01"));
+        assert!(!tags.contains("US 01"));
+    }
+
+    #[test]
+    fn build_sing_box_config_applies_dns_final_and_no_split() {
+        let build = |dns: DnsConfig| {
+            let config = Config {
+                dns: Some(dns),
+                ..Default::default()
+            };
+            build_sing_box_config(
+                &config,
+                vec!["node".to_string()],
+                vec![
+                    json!({"type": "hysteria2", "tag": "node", "server": "n.example.com", "server_port": 443, "password": "p"}),
+                ],
+                vec![],
+                vec![],
+            )
+        };
+
+        let built = build(DnsConfig {
+            final_server: Some(REMOTE_DNS_SERVER.to_string()),
+            no_split: true,
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(built["dns"]["final"], REMOTE_DNS_SERVER);
+        assert!(built["dns"]["rules"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .all(|rule| rule["server"] != LOCAL_DNS_SERVER));
+
+        let built = build(DnsConfig {
+            final_server: Some(LOCAL_DNS_SERVER.to_string()),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(built["dns"]["final"], LOCAL_DNS_SERVER);
+        assert!(!built["dns"]["rules"].as_array().unwrap().is_empty());
+
+        let err = build(DnsConfig {
+            final_server: Some("googledns".to_string()),
+            ..Default::default()
+        })
+        .unwrap_err();
+        assert!(err.to_string().contains("unknown DNS server: googledns"));
+    }
+
+    #[test]
+    fn build_sing_box_config_rejects_doh_without_url() {
+        let config = Config {
+            dns: Some(DnsConfig {
+                remote: Some(DnsServer {
+                    server_type: DnsServerType::Https,
+                    address: "1.1.1.1".to_string(),
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        let err = build_sing_box_config(
+            &config,
+            vec!["node".to_string()],
+            vec![json!({"type": "hysteria2", "tag": "node", "server": "n.example.com", "server_port": 443, "password": "p"})],
+            vec![],
+            vec![],
+        )
+        .unwrap_err();
+
+        assert!(err.to_string().contains("https://"));
+    }
+
+    #[test]
+    fn build_direct_fallback_config_routes_everything_direct() {
+        let config = Config {
+            direct_fallback: true,
+            app_rules: vec![AppRule {
+                process: "curl".to_string(),
+                outbound: "missing-node".to_string(),
+            }],
+            ..Default::default()
+        };
+
+        let built = build_direct_fallback_config(&config).unwrap();
+
+        assert_eq!(built["route"]["final"], "direct");
+        let selector = built["outbounds"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|outbound| outbound["tag"] == "proxy")
+            .unwrap();
+        assert_eq!(selector["outbounds"], json!(["direct"]));
+        assert!(built["route"]["rules"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .all(|rule| rule.get("process_name").is_none()));
+    }
+
+    #[test]
+    fn collect_manual_outbounds_annotates_sources_when_enabled() {
+        let config = Config {
+            nodes: vec![r#"{"type":"hysteria2","tag":"manual-a","server":"m.example.com","server_port":443,"password":"p"}"#.to_string()],
+            annotate_sources: true,
+            ..Default::default()
+        };
+
+        let (outbounds, _) = collect_manual_outbounds(&config);
+
+        assert_eq!(outbounds[0]["_source"], "manual");
+    }
+
+    #[test]
+    fn strip_source_annotations_removes_only_annotation_field() {
+        let mut outbounds = vec![json!({"type": "direct", "tag": "sub-node"})];
+        annotate_source(&mut outbounds, "https://example.com/sub");
+        assert_eq!(outbounds[0]["_source"], "https://example.com/sub");
+
+        let mut built = json!({"outbounds": outbounds});
+        strip_source_annotations(&mut built);
+
+        assert_eq!(
+            built["outbounds"][0],
+            json!({"type": "direct", "tag": "sub-node"})
+        );
+    }
+
+    #[test]
+    fn client_identity_paths_prefers_subscription_and_requires_pairs() {
+        let config = Config {
+            client_cert: Some("/etc/miao/global.crt".to_string()),
+            client_key: Some("/etc/miao/global.key".to_string()),
+            ..Default::default()
+        };
+        let plain = Subscription::from("https://a.example.com");
+        let own = Subscription {
+            url: "https://b.example.com".to_string(),
+            client_cert: Some("/etc/miao/b.crt".to_string()),
+            client_key: Some("/etc/miao/b.key".to_string()),
+            ..Default::default()
+        };
+        let partial = Subscription {
+            url: "https://c.example.com".to_string(),
+            client_cert: Some("/etc/miao/c.crt".to_string()),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            client_identity_paths(&plain, &config).unwrap(),
+            Some(("/etc/miao/global.crt", "/etc/miao/global.key"))
+        );
+        assert_eq!(
+            client_identity_paths(&own, &config).unwrap(),
+            Some(("/etc/miao/b.crt", "/etc/miao/b.key"))
+        );
+        assert!(client_identity_paths(&partial, &config)
+            .unwrap_err()
+            .to_string()
+            .contains("must be configured together"));
+        assert_eq!(
+            client_identity_paths(&plain, &Config::default()).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn subscription_merge_key_orders_by_priority_then_config_order() {
+        let subs = vec![
+            Subscription::from("https://a.example.com"),
+            Subscription {
+                url: "https://b.example.com".to_string(),
+                priority: 10,
+                ..Default::default()
+            },
+            Subscription {
+                url: "https://c.example.com".to_string(),
+                priority: 10,
+                ..Default::default()
+            },
+            Subscription {
+                url: "https://d.example.com".to_string(),
+                priority: -1,
+                ..Default::default()
+            },
+        ];
+
+        let mut urls = vec![
+            "https://d.example.com",
+            "https://c.example.com",
+            "https://a.example.com",
+            "https://b.example.com",
+        ];
+        urls.sort_by_key(|url| subscription_merge_key(&subs, url));
+
+        assert_eq!(
+            urls,
+            vec![
+                "https://b.example.com",
+                "https://c.example.com",
+                "https://a.example.com",
+                "https://d.example.com",
+            ]
+        );
+    }
+
+    #[test]
+    fn build_sing_box_config_resolves_manual_and_subscription_tag_collisions() {
+        let node = |server: &str| json!({"type": "hysteria2", "tag": "Tokyo", "server": server, "server_port": 443});
+        let build = |tag_collision| {
+            let config = Config {
+                tag_collision,
+                ..Default::default()
+            };
+            build_sing_box_config(
+                &config,
+                vec!["Tokyo".to_string()],
+                vec![node("manual.example.com")],
+                vec!["Tokyo".to_string(), "Osaka".to_string()],
+                vec![node("sub.example.com"), node("osaka.example.com")],
+            )
+            .unwrap()
+        };
+
+        for (strategy, selector, server) in [
+            (
+                TagCollision::Rename,
+                json!(["Tokyo", "Tokyo (2)", "Osaka"]),
+                "manual.example.com",
+            ),
+            (
+                TagCollision::PreferManual,
+                json!(["Tokyo", "Osaka"]),
+                "manual.example.com",
+            ),
+            (
+                TagCollision::PreferSub,
+                json!(["Tokyo", "Osaka"]),
+                "sub.example.com",
+            ),
+        ] {
+            let built = build(strategy);
+            let tokyo = node_outbounds(&built)
+                .into_iter()
+                .find(|outbound| outbound["tag"] == "Tokyo")
+                .unwrap();
+
+            assert_eq!(built["outbounds"][0]["outbounds"], selector);
+            assert_eq!(tokyo["server"], server);
+        }
+    }
+
+    #[tokio::test]
+    async fn fetch_subscriptions_failover_stops_once_enough_nodes() {
+        let sub = |url: &str, priority: i32| Subscription {
+            url: url.to_string(),
+            priority,
+            ..Default::default()
+        };
+        let config = Config {
+            subs: vec![
+                sub("https://backup.example.com", 0),
+                sub("https://empty.example.com", 20),
+                sub("https://primary.example.com", 10),
+            ],
+            subscription_failover: true,
+            failover_min_nodes: Some(2),
+            ..Default::default()
+        };
+        let cached = |names: &[&str]| FetchResult {
+            node_names: names.iter().map(|name| name.to_string()).collect(),
+            outbounds: names.iter().map(|name| json!({"tag": name})).collect(),
+            parse_errors: vec![],
+            total_count: names.len(),
+            dropped: Default::default(),
+            userinfo: None,
+            fetched_at: Instant::now(),
+            content_hash: None,
+        };
+        let reuse = HashMap::from([
+            ("https://empty.example.com".to_string(), cached(&[])),
+            (
+                "https://primary.example.com".to_string(),
+                cached(&["p1", "p2"]),
+            ),
+            ("https://backup.example.com".to_string(), cached(&["b1"])),
+        ]);
+
+        let fetched = fetch_subscriptions(
+            &config,
+            &reqwest::Client::new(),
+            &reuse,
+            &HashMap::new(),
+            HashMap::new(),
+        )
+        .await;
+
+        assert_eq!(fetched.node_names, vec!["p1", "p2"]);
+        let skipped: Vec<_> = fetched
+            .statuses
+            .iter()
+            .filter(|status| status.error.as_deref() == Some(FAILOVER_SKIPPED))
+            .map(|status| status.url.as_str())
+            .collect();
+        assert_eq!(skipped, vec!["https://backup.example.com"]);
+    }
+
+    #[tokio::test]
+    async fn fetch_subscriptions_records_fetch_duration_only_for_real_requests() {
+        let config = Config {
+            subs: vec![
+                Subscription {
+                    url: "http://127.0.0.1:1/unreachable".to_string(),
+                    ..Default::default()
+                },
+                Subscription {
+                    url: "https://cached.example.com".to_string(),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        let reuse = HashMap::from([(
+            "https://cached.example.com".to_string(),
+            FetchResult {
+                node_names: vec!["c1".to_string()],
+                outbounds: vec![json!({"tag": "c1"})],
+                parse_errors: vec![],
+                total_count: 1,
+                dropped: Default::default(),
+                userinfo: None,
+                fetched_at: Instant::now(),
+                content_hash: None,
+            },
+        )]);
+
+        let fetched = fetch_subscriptions(
+            &config,
+            &reqwest::Client::new(),
+            &reuse,
+            &HashMap::new(),
+            HashMap::new(),
+        )
+        .await;
+
+        let fetch_ms = |url: &str| {
+            fetched
+                .statuses
+                .iter()
+                .find(|status| status.url == url)
+                .unwrap()
+                .fetch_ms
+        };
+        assert!(fetch_ms("http://127.0.0.1:1/unreachable").is_some());
+        assert!(fetch_ms("https://cached.example.com").is_none());
+    }
+
+    #[tokio::test]
+    async fn fetch_subscriptions_counts_nodes_after_resolve_check() {
+        let url = "https://resolve.example.com";
+        let config = Config {
+            subs: vec![Subscription {
+                url: url.to_string(),
+                ..Default::default()
+            }],
+            resolve_check: true,
+            ..Default::default()
+        };
+        let reuse = HashMap::from([(
+            url.to_string(),
+            FetchResult {
+                node_names: vec!["alive".to_string(), "stale".to_string()],
+                outbounds: vec![
+                    json!({"tag": "alive", "server": "localhost", "server_port": 443}),
+                    json!({"tag": "stale", "server": "stale-node.invalid", "server_port": 443}),
+                ],
+                parse_errors: vec![],
+                total_count: 2,
+                dropped: Default::default(),
+                userinfo: None,
+                fetched_at: Instant::now(),
+                content_hash: None,
+            },
+        )]);
+
+        let fetched = fetch_subscriptions(
+            &config,
+            &reqwest::Client::new(),
+            &reuse,
+            &HashMap::new(),
+            HashMap::new(),
+        )
+        .await;
+
+        let status = &fetched.statuses[0];
+        assert_eq!(status.node_count, 1);
+        assert_eq!(status.dropped.get("server does not resolve"), Some(&1));
+        assert_eq!(fetched.node_names, vec!["alive"]);
+        // 缓存保留检查前的结果，下次复用时重新解析
+        assert_eq!(fetched.results[0].1.node_names.len(), 2);
+    }
+
+    #[test]
+    fn build_sing_box_config_splices_app_rules_after_custom_rules() {
+        let config = Config {
+            custom_rules: vec![
+                r#"{"domain_suffix":["example.com"],"action":"route","outbound":"proxy"}"#
+                    .to_string(),
+            ],
+            app_rules: vec![
+                AppRule {
+                    process: "qbittorrent".to_string(),
+                    outbound: "direct".to_string(),
+                },
+                AppRule {
+                    process: "firefox".to_string(),
+                    outbound: "manual-a".to_string(),
+                },
+            ],
+            ..Default::default()
+        };
+
+        let built = build_sing_box_config(
+            &config,
+            vec!["manual-a".to_string()],
+            vec![json!({"type": "hysteria2", "tag": "manual-a", "server": "m.example.com", "server_port": 443, "password": "p"})],
+            vec![],
+            vec![],
+        )
+        .unwrap();
+
+        let rules = built["route"]["rules"].as_array().unwrap();
+        assert_eq!(rules[2]["domain_suffix"], json!(["example.com"]));
+        assert_eq!(
+            rules[3],
+            json!({"process_name": ["qbittorrent"], "action": "route", "outbound": "direct"})
+        );
+        assert_eq!(rules[4]["process_name"], json!(["firefox"]));
+        assert_eq!(rules[4]["outbound"], "manual-a");
+    }
+
+    #[test]
+    fn build_sing_box_config_adds_domain_rules_after_sniff() {
+        let mut config = Config {
+            domain_rules: vec![DomainRule {
+                domain_suffix: vec!["netflix.com".to_string()],
+                domain: vec![],
+                outbound: "manual-a".to_string(),
+            }],
+            ..Default::default()
+        };
+        let node = || {
+            vec![
+                json!({"type": "hysteria2", "tag": "manual-a", "server": "m.example.com", "server_port": 443, "password": "p"}),
+            ]
+        };
+
+        let built = build_sing_box_config(
+            &config,
+            vec!["manual-a".to_string()],
+            node(),
+            vec![],
+            vec![],
+        )
+        .unwrap();
+
+        let rules = built["route"]["rules"].as_array().unwrap();
+        let sniff = rules
+            .iter()
+            .position(|rule| rule["action"] == "sniff")
+            .unwrap();
+        let domain = rules
+            .iter()
+            .position(|rule| {
+                *rule
+                    == json!({"domain_suffix": ["netflix.com"], "action": "route", "outbound": "manual-a"})
+            })
+            .unwrap();
+        assert!(sniff < domain);
+
+        config.domain_rules[0].outbound = "missing".to_string();
+        let err = build_sing_box_config(
+            &config,
+            vec!["manual-a".to_string()],
+            node(),
+            vec![],
+            vec![],
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("unknown outbound 'missing'"));
+    }
+
+    #[test]
+    fn build_sing_box_config_adds_port_rules_and_rejects_unknown_outbound() {
+        let mut config = Config {
+            port_rules: vec![PortRule {
+                ports: "3478-3480, 27015".to_string(),
+                outbound: "direct".to_string(),
+            }],
+            ..Default::default()
+        };
+        let node = || {
+            vec![
+                json!({"type": "hysteria2", "tag": "manual-a", "server": "m.example.com", "server_port": 443, "password": "p"}),
+            ]
+        };
+
+        let built = build_sing_box_config(
+            &config,
+            vec!["manual-a".to_string()],
+            node(),
+            vec![],
+            vec![],
+        )
+        .unwrap();
+
+        assert_eq!(
+            built["route"]["rules"][2],
+            json!({"port": [27015], "port_range": ["3478:3480"], "action": "route", "outbound": "direct"})
+        );
+
+        config.port_rules[0].outbound = "missing".to_string();
+        let err = build_sing_box_config(
+            &config,
+            vec!["manual-a".to_string()],
+            node(),
+            vec![],
+            vec![],
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("unknown outbound 'missing'"));
+    }
+
+    #[test]
+    fn validate_listen_ports_reports_colliding_inbounds() {
+        let config = Config {
+            template_patch: Some(json!({
+                "inbounds": [
+                    {"type": "tun", "tag": "tun-in"},
+                    {"type": "mixed", "tag": "mixed-in", "listen": "0.0.0.0", "listen_port": 6262},
+                    {"type": "http", "listen_port": 7890},
+                    {"type": "socks", "tag": "socks-in", "listen_port": 7890}
+                ]
+            })),
+            ..Default::default()
+        };
+        let built = build_sing_box_config(
+            &config,
+            vec!["manual-a".to_string()],
+            vec![
+                json!({"type": "hysteria2", "tag": "manual-a", "server": "m.example.com", "server_port": 443, "password": "p"}),
+            ],
+            vec![],
+            vec![],
+        )
+        .unwrap();
+
+        let err = validate_listen_ports(&built, 7890).unwrap_err().to_string();
+        assert_eq!(
+            err,
+            "Listen port conflict: port 6262 used by both clash_api and inbound 'mixed-in'; \
+             port 7890 used by both miao port and inbound 'http'; \
+             port 7890 used by both miao port and inbound 'socks-in'"
+        );
+        assert!(validate_listen_ports(
+            &build_direct_fallback_config(&Config::default()).unwrap(),
+            6161
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn build_sing_box_config_adds_rule_sets_with_actions() {
+        let config = Config {
+            rule_sets: vec![
+                RuleSetRule {
+                    tag: "geosite-google".to_string(),
+                    url: "https://example.com/geosite-google.srs".to_string(),
+                    action: RuleSetAction::Proxy,
+                    format: RuleSetFormat::Binary,
+                },
+                RuleSetRule {
+                    tag: "geosite-category-ads-all".to_string(),
+                    url: "https://example.com/ads.json".to_string(),
+                    action: RuleSetAction::Block,
+                    format: RuleSetFormat::Source,
+                },
+            ],
+            ..Default::default()
+        };
+
+        let built = build_sing_box_config(
+            &config,
+            vec!["manual-a".to_string()],
+            vec![json!({"type": "hysteria2", "tag": "manual-a", "server": "m.example.com", "server_port": 443, "password": "p"})],
+            vec![],
+            vec![],
+        )
+        .unwrap();
+
+        let rules = built["route"]["rules"].as_array().unwrap();
+        assert_eq!(
+            rules[2],
+            json!({"rule_set": ["geosite-google"], "action": "route", "outbound": "proxy"})
+        );
+        assert_eq!(
+            rules[3],
+            json!({"rule_set": ["geosite-category-ads-all"], "action": "reject"})
+        );
+        let definitions = built["route"]["rule_set"].as_array().unwrap();
+        assert_eq!(
+            definitions[3],
+            json!({"type": "remote", "tag": "geosite-category-ads-all", "format": "source", "url": "https://example.com/ads.json"})
+        );
+
+        let colliding = Config {
+            rule_sets: vec![RuleSetRule {
+                tag: "chinasite".to_string(),
+                url: "https://example.com/chinasite.srs".to_string(),
+                action: RuleSetAction::Direct,
+                format: RuleSetFormat::Binary,
+            }],
+            ..Default::default()
+        };
+        let err = build_sing_box_config(
+            &colliding,
+            vec!["manual-a".to_string()],
+            vec![json!({"type": "hysteria2", "tag": "manual-a", "server": "m.example.com", "server_port": 443, "password": "p"})],
+            vec![],
+            vec![],
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("chinasite"));
+    }
+
+    #[test]
+    fn build_sing_box_config_applies_sniff_settings() {
+        let node = || {
+            vec![
+                json!({"type": "hysteria2", "tag": "manual-a", "server": "m.example.com", "server_port": 443, "password": "p"}),
+            ]
+        };
+        let tuned = Config {
+            sniff_timeout_ms: Some(500),
+            sniff_protocols: vec!["tls".to_string(), "quic".to_string()],
+            ..Default::default()
+        };
+        let built =
+            build_sing_box_config(&tuned, vec!["manual-a".to_string()], node(), vec![], vec![])
+                .unwrap();
+        assert_eq!(
+            built["route"]["rules"][0],
+            json!({"action": "sniff", "timeout": "500ms", "sniffer": ["tls", "quic"]})
+        );
+
+        let disabled = Config {
+            sniff: false,
+            custom_rules: vec![
+                r#"{"domain_suffix":["example.com"],"action":"route","outbound":"direct"}"#
+                    .to_string(),
+            ],
+            ..Default::default()
+        };
+        let built = build_sing_box_config(
+            &disabled,
+            vec!["manual-a".to_string()],
+            node(),
+            vec![],
+            vec![],
+        )
+        .unwrap();
+        let rules = built["route"]["rules"].as_array().unwrap();
+        assert!(rules.iter().all(|rule| rule["action"] != "sniff"));
+        assert_eq!(rules[0]["action"], "hijack-dns");
+        assert_eq!(rules[1]["domain_suffix"], json!(["example.com"]));
+    }
+
+    #[test]
+    fn build_sing_box_config_prunes_dangling_selector_entries() {
+        let config = Config {
+            template_patch: Some(json!({
+                "outbounds": [
+                    {"type": "selector", "tag": "proxy", "outbounds": ["gone-node"], "default": "gone-node"},
+                    {"type": "direct", "tag": "direct"}
+                ]
+            })),
+            ..Default::default()
+        };
+
+        let built = build_sing_box_config(
+            &config,
+            vec!["manual-a".to_string()],
+            vec![json!({"type": "hysteria2", "tag": "manual-a", "server": "m.example.com", "server_port": 443, "password": "p"})],
+            vec![],
+            vec![],
+        )
+        .unwrap();
+
+        assert_eq!(built["outbounds"][0]["outbounds"], json!(["manual-a"]));
+        assert!(built["outbounds"][0].get("default").is_none());
+    }
+
+    #[test]
+    fn build_sing_box_config_applies_route_final_in_rule_mode_only() {
+        let manual = || {
+            vec![
+                json!({"type": "hysteria2", "tag": "manual-a", "server": "m.example.com", "server_port": 443, "password": "p"}),
+            ]
+        };
+        let mut config = Config {
+            route_final: Some("direct".to_string()),
+            ..Default::default()
+        };
+
+        let built = build_sing_box_config(
+            &config,
+            vec!["manual-a".to_string()],
+            manual(),
+            vec![],
+            vec![],
+        )
+        .unwrap();
+        assert_eq!(built["route"]["final"], "direct");
+
+        config.route_mode = RouteMode::Global;
+        let built = build_sing_box_config(
+            &config,
+            vec!["manual-a".to_string()],
+            manual(),
+            vec![],
+            vec![],
+        )
+        .unwrap();
+        assert_eq!(built["route"]["final"], "proxy");
+
+        config.route_final = Some("nowhere".to_string());
+        let err = build_sing_box_config(
+            &config,
+            vec!["manual-a".to_string()],
+            manual(),
+            vec![],
+            vec![],
+        )
+        .unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("route_final references unknown outbound 'nowhere'"));
+    }
+
+    #[test]
+    fn build_sing_box_config_routes_remote_dns_through_dns_outbound() {
+        let manual = || {
+            vec![
+                json!({"type": "hysteria2", "tag": "manual-a", "server": "m.example.com", "server_port": 443, "password": "p"}),
+            ]
+        };
+        let mut config = Config {
+            dns_outbound: Some("manual-a".to_string()),
+            ..Default::default()
+        };
+
+        let built = build_sing_box_config(
+            &config,
+            vec!["manual-a".to_string()],
+            manual(),
+            vec![],
+            vec![],
+        )
+        .unwrap();
+        let servers = built["dns"]["servers"].as_array().unwrap();
+        let server = |tag: &str| servers.iter().find(|server| server["tag"] == tag).unwrap();
+        assert_eq!(server(REMOTE_DNS_SERVER)["detour"], "manual-a");
+        assert!(server(LOCAL_DNS_SERVER).get("detour").is_none());
+
+        config.dns_outbound = Some("nowhere".to_string());
+        let err = build_sing_box_config(
+            &config,
+            vec!["manual-a".to_string()],
+            manual(),
+            vec![],
+            vec![],
+        )
+        .unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("dns_outbound references unknown outbound 'nowhere'"));
+    }
+
+    #[test]
+    fn apply_tls_versions_fills_enabled_tls_without_overriding_nodes() {
+        let mut outbounds = vec![
+            json!({"type": "trojan", "tag": "a", "tls": {"enabled": true}}),
+            json!({"type": "vless", "tag": "b", "tls": {"enabled": true, "min_version": "1.2"}}),
+            json!({"type": "shadowsocks", "tag": "c"}),
+            json!({"type": "vmess", "tag": "d", "tls": {"enabled": false}}),
+        ];
+
+        apply_tls_versions(&mut outbounds, Some("1.3"), None);
+
+        assert_eq!(outbounds[0]["tls"]["min_version"], "1.3");
+        assert!(outbounds[0]["tls"].get("max_version").is_none());
+        assert_eq!(outbounds[1]["tls"]["min_version"], "1.2");
+        assert!(outbounds[2].get("tls").is_none());
+        assert!(outbounds[3]["tls"].get("min_version").is_none());
+    }
+
+    #[test]
+    fn apply_tls_versions_leaves_quic_nodes_on_tls13() {
+        let mut outbounds = vec![
+            json!({"type": "trojan", "tag": "a", "tls": {"enabled": true}}),
+            json!({"type": "hysteria2", "tag": "b", "tls": {"enabled": true}}),
+            json!({"type": "tuic", "tag": "c", "tls": {"enabled": true}}),
+        ];
+
+        apply_tls_versions(&mut outbounds, Some("1.2"), Some("1.2"));
 
-        let selector = built["outbounds"][0]["outbounds"].as_array().unwrap();
-        let selector_tags: Vec<_> = selector
-            .iter()
-            .map(|value| value.as_str().unwrap())
-            .collect();
-        assert_eq!(selector_tags, vec!["dup", "dup (2)", "dup (3)"]);
+        assert_eq!(outbounds[0]["tls"]["max_version"], "1.2");
+        for quic in &outbounds[1..] {
+            assert_eq!(quic["tls"]["min_version"], "1.2");
+            assert!(quic["tls"].get("max_version").is_none());
+        }
+    }
 
-        let all_outbounds = built["outbounds"].as_array().unwrap();
-        assert_eq!(all_outbounds[2]["tag"], "dup");
-        assert_eq!(all_outbounds[3]["tag"], "dup (2)");
-        assert_eq!(all_outbounds[4]["tag"], "dup (3)");
+    #[test]
+    fn apply_hysteria2_tuning_fills_defaults_or_strips_hints() {
+        let nodes = || {
+            vec![
+                json!({"type": "hysteria2", "tag": "hinted", "up_mbps": 50, "down_mbps": 300}),
+                json!({"type": "hysteria2", "tag": "bare"}),
+                json!({"type": "trojan", "tag": "other"}),
+                json!({"type": "hysteria2", "tag": "bbr", "up_mbps": 50, "ignore_bandwidth": true}),
+            ]
+        };
+
+        let mut filled = nodes();
+        apply_hysteria2_tuning(
+            &mut filled,
+            &Hysteria2Tuning {
+                up_mbps: Some(20),
+                down_mbps: Some(100),
+                ignore_bandwidth: false,
+            },
+        );
+        let mut stripped = nodes();
+        apply_hysteria2_tuning(
+            &mut stripped,
+            &Hysteria2Tuning {
+                ignore_bandwidth: true,
+                ..Default::default()
+            },
+        );
+
+        assert_eq!(filled[0]["up_mbps"], 50);
+        assert_eq!(filled[1]["up_mbps"], 20);
+        assert_eq!(filled[1]["down_mbps"], 100);
+        assert!(filled[2].get("up_mbps").is_none());
+        assert!(stripped[0].get("up_mbps").is_none());
+        assert!(stripped[0].get("down_mbps").is_none());
+        assert!(filled[3].get("up_mbps").is_none());
+        assert!(filled[3].get("down_mbps").is_none());
+        assert!(filled[3].get("ignore_bandwidth").is_none());
     }
 
     #[test]
-    fn build_sing_box_config_renames_tags_reserved_by_template() {
+    fn build_sing_box_config_rejects_app_rule_with_unknown_outbound() {
         let config = Config {
-            port: None,
-            subs: vec![],
-            nodes: vec![],
-            custom_rules: vec![],
-            route_mode: Default::default(),
-            vps_ip: None,
+            app_rules: vec![AppRule {
+                process: "qbittorrent".to_string(),
+                outbound: "missing-node".to_string(),
+            }],
+            ..Default::default()
         };
 
-        let my_outbounds = vec![
-            json!({
-                "type": "hysteria2",
-                "tag": "proxy",
-                "server": "proxy.example.com",
-                "server_port": 443,
-                "password": "proxy-secret"
-            }),
-            json!({
-                "type": "hysteria2",
-                "tag": "direct",
-                "server": "direct.example.com",
-                "server_port": 443,
-                "password": "direct-secret"
+        let err = build_sing_box_config(
+            &config,
+            vec!["manual-a".to_string()],
+            vec![json!({"type": "hysteria2", "tag": "manual-a", "server": "m.example.com", "server_port": 443, "password": "p"})],
+            vec![],
+            vec![],
+        )
+        .unwrap_err();
+
+        assert!(err
+            .to_string()
+            .contains("references unknown outbound 'missing-node'"));
+    }
+
+    #[test]
+    fn apply_merge_patch_follows_rfc7386() {
+        let mut target = json!({"a": "b", "c": {"d": "e", "f": "g"}, "list": [1, 2]});
+
+        apply_merge_patch(
+            &mut target,
+            &json!({"a": "z", "c": {"f": null, "h": 1}, "list": [3]}),
+        );
+
+        assert_eq!(
+            target,
+            json!({"a": "z", "c": {"d": "e", "h": 1}, "list": [3]})
+        );
+    }
+
+    #[test]
+    fn build_sing_box_config_overrides_sing_box_log_settings() {
+        let config = Config {
+            sing_log: Some(SingLogConfig {
+                level: Some("debug".to_string()),
+                timestamp: Some(false),
+                output: Some("/var/log/sing-box.log".to_string()),
+                ..Default::default()
             }),
-        ];
+            ..Default::default()
+        };
 
         let built = build_sing_box_config(
             &config,
-            vec!["proxy".to_string(), "direct".to_string()],
-            my_outbounds,
+            vec!["manual-a".to_string()],
+            vec![json!({"type": "hysteria2", "tag": "manual-a", "server": "m.example.com", "server_port": 443, "password": "p"})],
             vec![],
             vec![],
         )
         .unwrap();
 
-        let selector = built["outbounds"][0]["outbounds"].as_array().unwrap();
-        let selector_tags: Vec<_> = selector
-            .iter()
-            .map(|value| value.as_str().unwrap())
-            .collect();
-        assert_eq!(selector_tags, vec!["proxy (2)", "direct (2)"]);
-
-        let all_outbounds = built["outbounds"].as_array().unwrap();
-        assert_eq!(all_outbounds[0]["tag"], "proxy");
-        assert_eq!(all_outbounds[1]["tag"], "direct");
-        assert_eq!(all_outbounds[2]["tag"], "proxy (2)");
-        assert_eq!(all_outbounds[3]["tag"], "direct (2)");
+        assert_eq!(
+            built["log"],
+            json!({"disabled": false, "timestamp": false, "level": "debug", "output": "/var/log/sing-box.log"})
+        );
+        assert!(Validator::sing_log(&SingLogConfig {
+            level: Some("verbose".to_string()),
+            ..Default::default()
+        })
+        .unwrap_err()
+        .contains("无效的 sing-box 日志级别"));
     }
 
     #[test]
-    fn build_sing_box_config_errors_when_no_nodes_available() {
+    fn build_sing_box_config_applies_template_patch_before_injecting_nodes() {
         let config = Config {
-            port: None,
-            subs: vec![],
-            nodes: vec![],
-            custom_rules: vec![],
-            route_mode: Default::default(),
-            vps_ip: None,
+            template_patch: Some(json!({
+                "log": {"level": "warn"},
+                "route": {"final": "direct"},
+                "experimental": null
+            })),
+            ..Default::default()
         };
 
-        let err = build_sing_box_config(&config, vec![], vec![], vec![], vec![]).unwrap_err();
+        let built = build_sing_box_config(
+            &config,
+            vec!["manual-a".to_string()],
+            vec![json!({"type": "hysteria2", "tag": "manual-a", "server": "m.example.com", "server_port": 443, "password": "p"})],
+            vec![],
+            vec![],
+        )
+        .unwrap();
 
-        assert!(err.to_string().contains(
-            "No nodes available: all subscriptions failed and no manual nodes configured"
-        ));
+        assert_eq!(built["log"]["level"], "warn");
+        assert_eq!(built["log"]["timestamp"], true);
+        assert_eq!(built["route"]["final"], "direct");
+        assert!(built.get("experimental").is_none());
+        assert_eq!(built["outbounds"][0]["outbounds"], json!(["manual-a"]));
     }
 
     #[test]
-    fn collect_manual_outbounds_handles_empty_nodes() {
+    fn build_sing_box_config_rejects_patch_that_drops_selector() {
         let config = Config {
-            port: None,
-            subs: vec![],
-            nodes: vec![],
-            custom_rules: vec![],
-            route_mode: Default::default(),
-            vps_ip: None,
+            template_patch: Some(json!({"outbounds": [{"type": "direct", "tag": "direct"}]})),
+            ..Default::default()
         };
 
-        let (outbounds, names) = collect_manual_outbounds(&config);
+        let err = build_sing_box_config(
+            &config,
+            vec!["manual-a".to_string()],
+            vec![json!({"type": "hysteria2", "tag": "manual-a", "server": "m.example.com", "server_port": 443, "password": "p"})],
+            vec![],
+            vec![],
+        )
+        .unwrap_err();
 
-        assert!(outbounds.is_empty());
-        assert!(names.is_empty());
+        assert!(err.to_string().contains("\"proxy\" selector"));
     }
 
     #[test]
-    fn collect_manual_outbounds_handles_all_invalid_nodes() {
+    fn node_outbounds_follows_proxy_selector_order() {
         let config = Config {
             port: None,
             subs: vec![],
-            nodes: vec![
-                "not-json".to_string(),
-                r#"{}"#.to_string(),                   // Valid JSON but no tag
-                r#"{"type":"hysteria2"}"#.to_string(), // Valid JSON but no tag
-            ],
+            nodes: vec![],
             custom_rules: vec![],
             route_mode: Default::default(),
             vps_ip: None,
+            ..Default::default()
         };
 
-        let (outbounds, names) = collect_manual_outbounds(&config);
+        let built = build_sing_box_config(
+            &config,
+            vec!["manual-a".to_string()],
+            vec![json!({"type": "hysteria2", "tag": "manual-a", "server": "m.example.com", "server_port": 443, "password": "p"})],
+            vec!["sub-a".to_string()],
+            vec![json!({"type": "trojan", "tag": "sub-a", "server": "s.example.com", "server_port": 443, "password": "p"})],
+        )
+        .unwrap();
 
-        // All nodes fail validation (missing required fields)
-        assert!(outbounds.is_empty());
-        assert!(names.is_empty());
+        let nodes = node_outbounds(&built);
+        let tags: Vec<_> = nodes
+            .iter()
+            .map(|outbound| outbound["tag"].as_str().unwrap())
+            .collect();
+        assert_eq!(tags, vec!["manual-a", "sub-a"]);
+        assert!(node_outbounds(&json!({})).is_empty());
     }
 
     #[test]
@@ -1007,6 +3882,7 @@ mod tests {
             custom_rules: vec![],
             route_mode: Default::default(),
             vps_ip: None,
+            ..Default::default()
         };
 
         let my_outbounds = vec![
@@ -1044,6 +3920,7 @@ mod tests {
             custom_rules: vec![],
             route_mode: Default::default(),
             vps_ip: None,
+            ..Default::default()
         };
 
         let my_outbounds = vec![json!({
@@ -1077,6 +3954,7 @@ mod tests {
             custom_rules: vec![],
             route_mode: Default::default(),
             vps_ip: None,
+            ..Default::default()
         };
 
         let my_outbounds = vec![json!({
@@ -1151,6 +4029,7 @@ mod tests {
             custom_rules: vec![],
             route_mode: Default::default(),
             vps_ip: None,
+            ..Default::default()
         };
 
         let built = build_sing_box_config(
@@ -1174,6 +4053,35 @@ mod tests {
         );
     }
 
+    #[test]
+    fn build_sing_box_config_drops_clash_api_when_disabled() {
+        let config = Config {
+            enable_clash_api: false,
+            template_patch: Some(json!({
+                "experimental": {"cache_file": {"enabled": true}}
+            })),
+            ..Default::default()
+        };
+
+        let built = build_sing_box_config(
+            &config,
+            vec!["manual-a".to_string()],
+            vec![json!({"type": "hysteria2", "tag": "manual-a", "server": "m.example.com", "server_port": 443, "password": "p"})],
+            vec![],
+            vec![],
+        )
+        .unwrap();
+        let fallback = build_direct_fallback_config(&Config {
+            enable_clash_api: false,
+            ..Default::default()
+        })
+        .unwrap();
+
+        assert!(built["experimental"].get("clash_api").is_none());
+        assert_eq!(built["experimental"]["cache_file"]["enabled"], true);
+        assert!(fallback.get("experimental").is_none());
+    }
+
     #[test]
     fn build_sing_box_config_ignores_all_invalid_custom_rules() {
         let config = Config {
@@ -1187,6 +4095,7 @@ mod tests {
             ],
             route_mode: Default::default(),
             vps_ip: None,
+            ..Default::default()
         };
 
         let my_outbounds = vec![json!({
@@ -1222,11 +4131,12 @@ mod tests {
 
         let config = Config {
             port: Some(8080),
-            subs: vec!["https://example.com/sub".to_string()],
+            subs: vec!["https://example.com/sub".into()],
             nodes: vec![],
             custom_rules: vec![],
             route_mode: Default::default(),
             vps_ip: None,
+            ..Default::default()
         };
 
         save_config_to(&config_path, &config).await.unwrap();
@@ -1267,6 +4177,7 @@ mod tests {
             custom_rules: vec![],
             route_mode: Default::default(),
             vps_ip: None,
+            ..Default::default()
         };
         save_config_to(&config_path, &config).await.unwrap();
 
@@ -1278,6 +4189,216 @@ mod tests {
         let _ = tokio::fs::remove_dir_all(&temp_dir).await;
     }
 
+    #[tokio::test]
+    async fn restore_config_from_cache_rejects_truncated_cache() {
+        let temp_dir = std::env::temp_dir().join(format!(
+            "miao-test-cache-{}-{}",
+            std::process::id(),
+            "truncated"
+        ));
+        tokio::fs::create_dir_all(&temp_dir).await.unwrap();
+        let paths = SingBoxPaths::from_config(&Config {
+            sing_box_config_dir: Some(temp_dir.to_string_lossy().into_owned()),
+            ..Default::default()
+        });
+        tokio::fs::write(
+            paths.config_cache_path(),
+            r#"{"outbounds": [{"type": "dire"#,
+        )
+        .await
+        .unwrap();
+
+        let err = restore_config_from_cache(&paths, false).await.unwrap_err();
+        let restored = paths.config_path().exists();
+
+        tokio::fs::write(paths.config_cache_path(), r#"{"outbounds": []}"#)
+            .await
+            .unwrap();
+        restore_config_from_cache(&paths, false).await.unwrap();
+        let content = tokio::fs::read_to_string(paths.config_path())
+            .await
+            .unwrap();
+        let _ = tokio::fs::remove_dir_all(&temp_dir).await;
+
+        assert!(err.to_string().contains("Cached config is corrupt"));
+        assert!(!restored);
+        assert_eq!(content, r#"{"outbounds": []}"#);
+    }
+
+    #[tokio::test]
+    async fn write_generated_config_refuses_foreign_config() {
+        let temp_dir = std::env::temp_dir().join(format!(
+            "miao-test-marker-{}-{}",
+            std::process::id(),
+            "foreign"
+        ));
+        tokio::fs::create_dir_all(&temp_dir).await.unwrap();
+        let paths = SingBoxPaths::from_config(&Config {
+            sing_box_config_dir: Some(temp_dir.to_string_lossy().into_owned()),
+            ..Default::default()
+        });
+        let hand_written = r#"{"outbounds": [{"type": "direct", "tag": "direct"}]}"#;
+        tokio::fs::write(paths.config_path(), hand_written)
+            .await
+            .unwrap();
+
+        let refused = write_generated_config(&paths, "{}", false).await;
+        let untouched = tokio::fs::read_to_string(paths.config_path())
+            .await
+            .unwrap();
+        write_generated_config(&paths, r#"{"outbounds": []}"#, true)
+            .await
+            .unwrap();
+        let marker: serde_json::Value = serde_json::from_str(
+            &tokio::fs::read_to_string(paths.config_marker_path())
+                .await
+                .unwrap(),
+        )
+        .unwrap();
+        // 有了标记之后，不再需要 overwrite_foreign_config
+        let rewritten = write_generated_config(&paths, "{}", false).await;
+        // 标记存在但 config.json 之后被手动替换时，仍然拒绝覆盖
+        tokio::fs::write(paths.config_path(), hand_written)
+            .await
+            .unwrap();
+        let replaced = write_generated_config(&paths, "{}", false).await;
+        let _ = tokio::fs::remove_dir_all(&temp_dir).await;
+
+        assert!(refused
+            .unwrap_err()
+            .to_string()
+            .contains("not generated by miao"));
+        assert_eq!(untouched, hand_written);
+        assert!(marker["generated_by"]
+            .as_str()
+            .unwrap()
+            .starts_with("miao v"));
+        assert!(marker["generated_at"].is_string());
+        assert!(marker["config_sha256"].is_string());
+        assert!(rewritten.is_ok());
+        assert!(replaced.is_err());
+    }
+
+    #[tokio::test]
+    async fn is_miao_managed_accepts_configs_from_before_markers() {
+        let temp_dir = std::env::temp_dir().join(format!(
+            "miao-test-marker-{}-{}",
+            std::process::id(),
+            "legacy"
+        ));
+        tokio::fs::create_dir_all(&temp_dir).await.unwrap();
+        let paths = SingBoxPaths::from_config(&Config {
+            sing_box_config_dir: Some(temp_dir.to_string_lossy().into_owned()),
+            ..Default::default()
+        });
+
+        let missing = is_miao_managed(&paths).await;
+        tokio::fs::write(
+            paths.config_path(),
+            r#"{"outbounds": [{"type": "selector", "tag": "proxy", "outbounds": ["direct"]}]}"#,
+        )
+        .await
+        .unwrap();
+        let bare_selector = is_miao_managed(&paths).await;
+        tokio::fs::write(paths.config_path(), get_config_template().to_string())
+            .await
+            .unwrap();
+        let legacy = is_miao_managed(&paths).await;
+        let _ = tokio::fs::remove_dir_all(&temp_dir).await;
+
+        assert!(missing);
+        assert!(!bare_selector);
+        assert!(legacy);
+    }
+
+    #[tokio::test]
+    async fn persist_file_config_change_keeps_env_overrides_out_of_the_file() {
+        let config_path =
+            std::env::temp_dir().join(format!("miao-test-file-config-{}.yaml", std::process::id()));
+        let file_config = Config::default();
+        let runtime_config = Config {
+            admin_token: Some("from-env".to_string()),
+            port: Some(9090),
+            ..file_config.clone()
+        };
+        let state =
+            AppState::with_file_config(runtime_config, file_config, config_path.clone()).unwrap();
+
+        persist_file_config_change(&state, |config| {
+            config.subs.push(Subscription {
+                url: "https://sub.example.com/api".to_string(),
+                ..Default::default()
+            })
+        })
+        .await
+        .unwrap();
+        let saved = tokio::fs::read_to_string(&config_path).await.unwrap();
+        let _ = tokio::fs::remove_file(&config_path).await;
+
+        assert!(saved.contains("https://sub.example.com/api"));
+        assert!(!saved.contains("from-env"));
+        assert!(!saved.contains("9090"));
+        assert_eq!(state.file_config.read().await.subs.len(), 1);
+        assert_eq!(state.config.read().await.subs.len(), 0);
+    }
+
+    #[tokio::test]
+    async fn regenerate_if_corrupt_overwrites_truncated_config_with_marker() {
+        let temp_dir = std::env::temp_dir().join(format!(
+            "miao-test-marker-{}-{}",
+            std::process::id(),
+            "truncated"
+        ));
+        tokio::fs::create_dir_all(&temp_dir).await.unwrap();
+        let dir = temp_dir.to_string_lossy().into_owned();
+        let config = Config {
+            sing_box_config_dir: Some(dir.clone()),
+            sing_box_data_dir: Some(dir),
+            nodes: vec![r#"{"type":"shadowsocks","tag":"ss-node","server":"203.0.113.1","server_port":8388,"method":"aes-128-gcm","password":"secret"}"#.to_string()],
+            ..Default::default()
+        };
+        let state = Arc::new(AppState::new(config.clone()).unwrap());
+        let paths = &state.sing_box_paths;
+        let complete = get_config_template().to_string();
+        tokio::fs::write(paths.config_marker_path(), config_marker(&complete))
+            .await
+            .unwrap();
+        tokio::fs::write(paths.config_path(), &complete[..complete.len() / 2])
+            .await
+            .unwrap();
+
+        let regenerated = regenerate_if_corrupt(&config, &state).await;
+        let content = tokio::fs::read_to_string(paths.config_path())
+            .await
+            .unwrap();
+        let _ = tokio::fs::remove_dir_all(&temp_dir).await;
+
+        assert!(regenerated.unwrap());
+        assert!(check_generated_json(&content).is_ok());
+    }
+
+    #[tokio::test]
+    async fn save_config_round_trips_toml() {
+        let temp_dir =
+            std::env::temp_dir().join(format!("miao-test-save-{}-{}", std::process::id(), "toml"));
+        let config_path = temp_dir.join("miao.toml");
+        let config = Config {
+            port: Some(6262),
+            subs: vec!["https://example.com/sub".into()],
+            custom_rules: vec![r#"{"domain_suffix":["example.com"],"outbound":"proxy"}"#.into()],
+            direct_fallback: true,
+            ..Default::default()
+        };
+
+        save_config_to(&config_path, &config).await.unwrap();
+
+        let content = tokio::fs::read_to_string(&config_path).await.unwrap();
+        assert!(content.contains("port = 6262"));
+        assert!(parse_config(&content, &config_path).unwrap() == config);
+
+        let _ = tokio::fs::remove_dir_all(&temp_dir).await;
+    }
+
     #[tokio::test]
     async fn save_config_skips_identical_content() {
         let temp_dir =
@@ -1292,6 +4413,7 @@ mod tests {
             custom_rules: vec![],
             route_mode: Default::default(),
             vps_ip: None,
+            ..Default::default()
         };
 
         save_config_to(&config_path, &config).await.unwrap();
@@ -0,0 +1,129 @@
+use serde_json::Value as JsonValue;
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::models::{ConfigDiff, ReloadScope};
+use crate::services::config::node_outbounds;
+
+fn nodes_by_tag(sing_box_config: &JsonValue) -> BTreeMap<String, JsonValue> {
+    node_outbounds(sing_box_config)
+        .into_iter()
+        .filter_map(|outbound| {
+            let tag = outbound["tag"].as_str()?.to_string();
+            Some((tag, outbound))
+        })
+        .collect()
+}
+
+fn reload_scope(changed_sections: &[String]) -> ReloadScope {
+    match changed_sections {
+        [] => ReloadScope::Unchanged,
+        [section] if section == "outbounds" => ReloadScope::OutboundsOnly,
+        _ => ReloadScope::Full,
+    }
+}
+
+/// 比较两份 sing-box 配置：节点按 tag 对比，其余按顶层配置段对比
+pub fn diff_configs(current: &JsonValue, fresh: &JsonValue) -> ConfigDiff {
+    let current_nodes = nodes_by_tag(current);
+    let fresh_nodes = nodes_by_tag(fresh);
+
+    let added_nodes = fresh_nodes
+        .keys()
+        .filter(|tag| !current_nodes.contains_key(*tag))
+        .cloned()
+        .collect();
+    let removed_nodes = current_nodes
+        .keys()
+        .filter(|tag| !fresh_nodes.contains_key(*tag))
+        .cloned()
+        .collect();
+    let changed_nodes = fresh_nodes
+        .iter()
+        .filter(|(tag, outbound)| {
+            current_nodes
+                .get(*tag)
+                .is_some_and(|current| current != *outbound)
+        })
+        .map(|(tag, _)| tag.clone())
+        .collect();
+
+    let empty = serde_json::Map::new();
+    let current_sections = current.as_object().unwrap_or(&empty);
+    let fresh_sections = fresh.as_object().unwrap_or(&empty);
+    let changed_sections: Vec<String> = current_sections
+        .keys()
+        .chain(fresh_sections.keys())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .filter(|key| current_sections.get(*key) != fresh_sections.get(*key))
+        .cloned()
+        .collect();
+
+    ConfigDiff {
+        added_nodes,
+        removed_nodes,
+        changed_nodes,
+        reload_scope: reload_scope(&changed_sections),
+        changed_sections,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::diff_configs;
+    use crate::models::ReloadScope;
+
+    fn config_with_nodes(nodes: &[(&str, &str)]) -> serde_json::Value {
+        let mut outbounds = vec![json!({
+            "type": "selector",
+            "tag": "proxy",
+            "outbounds": nodes.iter().map(|(tag, _)| *tag).collect::<Vec<_>>()
+        })];
+        outbounds.push(json!({"type": "direct", "tag": "direct"}));
+        outbounds.extend(nodes.iter().map(|(tag, server)| {
+            json!({"type": "hysteria2", "tag": tag, "server": server, "server_port": 443, "password": "p"})
+        }));
+        json!({"log": {"level": "info"}, "outbounds": outbounds})
+    }
+
+    #[test]
+    fn diff_configs_reports_node_and_section_changes() {
+        let current = config_with_nodes(&[("a", "a.example.com"), ("b", "b.example.com")]);
+        let mut fresh = config_with_nodes(&[
+            ("a", "a2.example.com"),
+            ("c", "c.example.com"),
+            ("d", "d.example.com"),
+        ]);
+        fresh["log"]["level"] = json!("warn");
+
+        let diff = diff_configs(&current, &fresh);
+
+        assert_eq!(diff.added_nodes, vec!["c", "d"]);
+        assert_eq!(diff.removed_nodes, vec!["b"]);
+        assert_eq!(diff.changed_nodes, vec!["a"]);
+        assert_eq!(diff.changed_sections, vec!["log", "outbounds"]);
+        assert_eq!(diff.reload_scope, ReloadScope::Full);
+    }
+
+    #[test]
+    fn diff_configs_detects_outbounds_only_changes() {
+        let current = config_with_nodes(&[("a", "a.example.com")]);
+        let fresh = config_with_nodes(&[("a", "a.example.com"), ("b", "b.example.com")]);
+
+        let diff = diff_configs(&current, &fresh);
+
+        assert_eq!(diff.added_nodes, vec!["b"]);
+        assert_eq!(diff.reload_scope, ReloadScope::OutboundsOnly);
+    }
+
+    #[test]
+    fn diff_configs_is_empty_for_identical_configs() {
+        let config = config_with_nodes(&[("a", "a.example.com")]);
+
+        let diff = diff_configs(&config, &config);
+
+        assert_eq!(diff, Default::default());
+    }
+}
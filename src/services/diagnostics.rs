@@ -0,0 +1,220 @@
+use serde_json::Value as JsonValue;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::warn;
+
+use crate::error::AppResult;
+use crate::models::{DiagnosticsBundle, SubStatus, SystemInfo};
+use crate::services::config::{load_generated_config, sing_box_is_running, subscription_statuses};
+use crate::services::singbox::{recent_logs, sing_box_version};
+use crate::services::subscription::format_unix_timestamp;
+use crate::services::version::current_version;
+use crate::state::AppState;
+
+const MASK: &str = "***";
+
+/// miao 配置和 sing-box 配置中需要脱敏的字段名
+const SECRET_KEYS: &[&str] = &[
+    "password",
+    "uuid",
+    "private_key",
+    "pre_shared_key",
+    "auth_str",
+    "token",
+    "secret",
+    "admin_token",
+];
+
+/// 订阅地址只保留协议与主机，路径和查询参数里通常带着 token
+fn mask_url(url: &str) -> String {
+    match url::Url::parse(url) {
+        Ok(parsed) => {
+            let host = parsed.host_str().unwrap_or_default();
+            let port = parsed.port().map(|p| format!(":{p}")).unwrap_or_default();
+            let has_secret =
+                parsed.path() != "/" || parsed.query().is_some() || !parsed.username().is_empty();
+            let suffix = if has_secret { "/***" } else { "" };
+            format!("{}://{host}{port}{suffix}", parsed.scheme())
+        }
+        Err(_) => MASK.to_string(),
+    }
+}
+
+/// 递归替换 SECRET_KEYS 中字段的值
+fn mask_secrets(value: &mut JsonValue) {
+    match value {
+        JsonValue::Object(map) => {
+            for (key, field) in map.iter_mut() {
+                if SECRET_KEYS.contains(&key.as_str()) && !field.is_null() {
+                    *field = JsonValue::String(MASK.to_string());
+                } else {
+                    mask_secrets(field);
+                }
+            }
+        }
+        JsonValue::Array(items) => items.iter_mut().for_each(mask_secrets),
+        _ => {}
+    }
+}
+
+/// 订阅条目可能是纯 URL 字符串，也可能是带 url 字段的对象；
+/// 手动节点以 JSON 字符串保存，需要解析后再脱敏
+fn mask_config(config: &mut JsonValue) {
+    mask_secrets(config);
+    for node in config["nodes"].as_array_mut().into_iter().flatten() {
+        let Some(mut parsed) = node
+            .as_str()
+            .and_then(|raw| serde_json::from_str::<JsonValue>(raw).ok())
+        else {
+            *node = JsonValue::String(MASK.to_string());
+            continue;
+        };
+        mask_secrets(&mut parsed);
+        *node = parsed;
+    }
+    for sub in config["subs"].as_array_mut().into_iter().flatten() {
+        if let Some(headers) = sub.get_mut("headers").and_then(JsonValue::as_object_mut) {
+            headers
+                .values_mut()
+                .for_each(|value| *value = JsonValue::String(MASK.to_string()));
+        }
+        let target = if sub.is_string() {
+            sub
+        } else {
+            &mut sub["url"]
+        };
+        if let Some(url) = target.as_str() {
+            *target = JsonValue::String(mask_url(url));
+        }
+    }
+}
+
+/// 错误信息中可能带有完整订阅地址，一并替换
+fn mask_sub_status(mut status: SubStatus) -> SubStatus {
+    let masked = mask_url(&status.url);
+    status.error = status.error.map(|e| e.replace(&status.url, &masked));
+    status.url = masked;
+    status
+}
+
+fn system_info() -> SystemInfo {
+    SystemInfo {
+        os: std::env::consts::OS.to_string(),
+        arch: std::env::consts::ARCH.to_string(),
+        kernel: std::fs::read_to_string("/proc/sys/kernel/osrelease")
+            .ok()
+            .map(|release| release.trim().to_string()),
+        openwrt: Path::new("/etc/openwrt_release").exists(),
+    }
+}
+
+/// 汇总配置、订阅状态、sing-box 版本与最近日志，所有密钥在返回前脱敏
+pub async fn collect_diagnostics(state: &Arc<AppState>) -> AppResult<DiagnosticsBundle> {
+    let config = state.config.read().await.clone();
+    let mut config_json = serde_json::to_value(&config)?;
+    mask_config(&mut config_json);
+
+    let generated_config =
+        load_generated_config(&state.sing_box_paths)
+            .await?
+            .map(|mut generated| {
+                mask_secrets(&mut generated);
+                generated
+            });
+
+    let sing_box_version =
+        match sing_box_version(&state.sing_box_paths, config.sing_box_channel).await {
+            Ok(version) => Some(version),
+            Err(e) => {
+                warn!(error = %e, "Failed to read sing-box version for diagnostics");
+                None
+            }
+        };
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default();
+
+    Ok(DiagnosticsBundle {
+        generated_at: format_unix_timestamp(now),
+        miao_version: current_version(),
+        sing_box_version,
+        system: system_info(),
+        running: sing_box_is_running(state).await,
+        warning: state.config_warning.lock().await.clone(),
+        config: config_json,
+        generated_config,
+        subscriptions: subscription_statuses(state)
+            .await
+            .into_iter()
+            .map(mask_sub_status)
+            .collect(),
+        logs: recent_logs(&state.sing_box_logs),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{mask_config, mask_sub_status, mask_url};
+    use crate::models::SubStatus;
+
+    #[test]
+    fn mask_url_keeps_only_scheme_and_host() {
+        assert_eq!(
+            mask_url("https://sub.example.com/api/v1/client/subscribe?token=abc"),
+            "https://sub.example.com/***"
+        );
+        assert_eq!(
+            mask_url("http://sub.example.com:8080/"),
+            "http://sub.example.com:8080"
+        );
+        assert_eq!(mask_url("not a url"), "***");
+    }
+
+    #[test]
+    fn mask_config_hides_sub_urls_and_secrets() {
+        let mut config = json!({
+            "port": 6161,
+            "admin_token": "hunter2",
+            "subs": [
+                "https://a.example.com/sub?token=1",
+                {"url": "https://b.example.com/sub/xyz", "priority": 1, "headers": {"Cookie": "cf_clearance=1"}}
+            ],
+            "nodes": [r#"{"tag": "mine", "server": "1.2.3.4", "password": "p", "obfs": {"password": "o"}}"#]
+        });
+
+        mask_config(&mut config);
+
+        assert_eq!(config["admin_token"], "***");
+        assert_eq!(config["subs"][0], "https://a.example.com/***");
+        assert_eq!(config["subs"][1]["url"], "https://b.example.com/***");
+        assert_eq!(config["subs"][1]["priority"], 1);
+        assert_eq!(config["subs"][1]["headers"]["Cookie"], "***");
+        assert_eq!(config["nodes"][0]["password"], "***");
+        assert_eq!(config["nodes"][0]["obfs"]["password"], "***");
+        assert_eq!(config["nodes"][0]["server"], "1.2.3.4");
+    }
+
+    #[test]
+    fn mask_sub_status_hides_url_in_errors() {
+        let url = "https://a.example.com/sub?token=secret";
+        let status = SubStatus {
+            url: url.to_string(),
+            success: false,
+            node_count: 0,
+            error: Some(format!("error sending request for url ({url})")),
+            dropped: Default::default(),
+            userinfo: None,
+            fetch_ms: None,
+        };
+
+        let masked = mask_sub_status(status);
+
+        assert_eq!(masked.url, "https://a.example.com/***");
+        assert!(!masked.error.unwrap().contains("secret"));
+    }
+}
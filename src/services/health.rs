@@ -0,0 +1,143 @@
+use std::collections::{BTreeMap, HashMap};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::models::NodeHealth;
+use crate::services::config::{load_generated_config, node_outbounds};
+use crate::services::proxy::{load_last_proxy, node_identity};
+use crate::state::AppState;
+
+/// 记录一轮延迟测试的结果：成功的节点清零连续失败次数，失败的节点累加；
+/// 本轮未测试的节点（已从配置中移除）一并清除
+pub async fn record_latencies(state: &AppState, latencies: &BTreeMap<String, Option<u64>>) {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default();
+    let mut health = state.node_health.lock().await;
+    health.retain(|tag, _| latencies.contains_key(tag));
+    for (tag, latency) in latencies {
+        let entry = health.entry(tag.clone()).or_insert_with(|| NodeHealth {
+            tag: tag.clone(),
+            ..Default::default()
+        });
+        entry.last_latency_ms = *latency;
+        if latency.is_some() {
+            entry.last_ok_at = Some(now);
+            entry.consecutive_failures = 0;
+        } else {
+            entry.consecutive_failures += 1;
+        }
+    }
+}
+
+/// 最近一次测试成功的各节点延迟，按节点标识（type://server:port）索引；
+/// 延迟按当前配置中的 tag 记录，而 tag 会随重命名、冲突处理变化，标识则不会
+pub async fn recorded_latencies(state: &AppState) -> HashMap<String, u64> {
+    let Ok(Some(generated)) = load_generated_config(&state.sing_box_paths).await else {
+        return HashMap::new();
+    };
+    let health = state.node_health.lock().await;
+    node_outbounds(&generated)
+        .iter()
+        .filter_map(|outbound| {
+            let latency = health.get(outbound["tag"].as_str()?)?.last_latency_ms?;
+            Some((node_identity(outbound)?, latency))
+        })
+        .collect()
+}
+
+/// 按 tag 排序返回各节点的健康状态，selected 标记当前记住的节点选择
+pub async fn node_health(state: &AppState) -> Vec<NodeHealth> {
+    let selected = load_last_proxy(state).await.map(|proxy| proxy.name);
+    let health = state.node_health.lock().await;
+    let mut nodes: Vec<NodeHealth> = health
+        .values()
+        .cloned()
+        .map(|mut node| {
+            node.selected = selected.as_deref() == Some(node.tag.as_str());
+            node
+        })
+        .collect();
+    nodes.sort_by(|a, b| a.tag.cmp(&b.tag));
+    nodes
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use std::sync::Arc;
+
+    use super::{record_latencies, recorded_latencies};
+    use crate::models::Config;
+    use crate::state::AppState;
+    use crate::test_support::app_state;
+
+    #[tokio::test]
+    async fn record_latencies_tracks_consecutive_failures() {
+        let state = app_state(Config::default());
+        let round = |a: Option<u64>, b: Option<u64>| {
+            BTreeMap::from([("a".to_string(), a), ("b".to_string(), b)])
+        };
+
+        record_latencies(&state, &round(Some(120), None)).await;
+        record_latencies(&state, &round(None, None)).await;
+        record_latencies(&state, &BTreeMap::from([("b".to_string(), Some(80))])).await;
+
+        let health = state.node_health.lock().await;
+        assert!(!health.contains_key("a"));
+        let b = &health["b"];
+        assert_eq!(b.last_latency_ms, Some(80));
+        assert_eq!(b.consecutive_failures, 0);
+        assert!(b.last_ok_at.is_some());
+        drop(health);
+
+        record_latencies(&state, &round(None, None)).await;
+        record_latencies(&state, &round(None, None)).await;
+        let health = state.node_health.lock().await;
+        assert_eq!(health["a"].consecutive_failures, 2);
+        assert!(health["a"].last_ok_at.is_none());
+        assert_eq!(health["b"].last_latency_ms, None);
+        assert!(health["b"].last_ok_at.is_some());
+    }
+
+    #[tokio::test]
+    async fn recorded_latencies_are_keyed_by_node_identity() {
+        let temp_dir =
+            std::env::temp_dir().join(format!("miao-test-latency-identity-{}", std::process::id()));
+        tokio::fs::create_dir_all(&temp_dir).await.unwrap();
+        let dir = temp_dir.to_string_lossy().into_owned();
+        let state = Arc::new(
+            AppState::new(Config {
+                sing_box_config_dir: Some(dir.clone()),
+                sing_box_data_dir: Some(dir),
+                ..Default::default()
+            })
+            .unwrap(),
+        );
+        let generated = serde_json::json!({
+            "outbounds": [
+                {"type": "selector", "tag": "proxy", "outbounds": ["HK 01 [sub]", "JP 01"]},
+                {"type": "shadowsocks", "tag": "HK 01 [sub]", "server": "hk.example.com", "server_port": 8388},
+                {"type": "trojan", "tag": "JP 01", "server": "jp.example.com", "server_port": 443}
+            ]
+        });
+        tokio::fs::write(state.sing_box_paths.config_path(), generated.to_string())
+            .await
+            .unwrap();
+        record_latencies(
+            &state,
+            &BTreeMap::from([
+                ("HK 01 [sub]".to_string(), Some(90)),
+                ("JP 01".to_string(), None),
+            ]),
+        )
+        .await;
+
+        let latencies = recorded_latencies(&state).await;
+        let _ = tokio::fs::remove_dir_all(&temp_dir).await;
+
+        assert_eq!(latencies.len(), 1);
+        assert_eq!(latencies["shadowsocks://hk.example.com:8388"], 90);
+    }
+}
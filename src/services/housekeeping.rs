@@ -0,0 +1,290 @@
+use flate2::{write::GzEncoder, Compression};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+use tokio::time::sleep;
+use tracing::{info, warn};
+
+use crate::models::HousekeepingConfig;
+use crate::services::subscription::FetchResult;
+use crate::state::AppState;
+
+/// 未启用清理时检查配置是否变化的间隔
+const IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(600);
+
+/// 轮转后的日志，如 sing-box.log.1、box.log.2026-01-01；正在写入的 *.log 不处理
+fn is_rotated_log(name: &str) -> bool {
+    name.split_once(".log.").is_some_and(|(stem, suffix)| {
+        !stem.is_empty() && !suffix.is_empty() && !name.ends_with(".gz")
+    })
+}
+
+/// 已压缩的轮转日志按原日志名分组，如 sing-box.log.1.gz 属于 sing-box.log
+fn compressed_log_group(name: &str) -> Option<&str> {
+    let stem = name.strip_suffix(".gz")?;
+    let (base, _) = stem.split_once(".log.")?;
+    Some(&name[..base.len() + ".log".len()])
+}
+
+fn compress_file(path: &Path) -> io::Result<PathBuf> {
+    let mut target = path.as_os_str().to_owned();
+    target.push(".gz");
+    let target = PathBuf::from(target);
+
+    let mut input = File::open(path)?;
+    let mut encoder = GzEncoder::new(File::create(&target)?, Compression::default());
+    io::copy(&mut input, &mut encoder)?;
+    encoder.finish()?;
+    fs::remove_file(path)?;
+    Ok(target)
+}
+
+fn dir_entries(dir: &Path) -> Vec<(String, PathBuf, SystemTime)> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter(|entry| entry.file_type().is_ok_and(|t| t.is_file()))
+        .filter_map(|entry| {
+            let modified = entry.metadata().ok()?.modified().ok()?;
+            Some((
+                entry.file_name().to_string_lossy().into_owned(),
+                entry.path(),
+                modified,
+            ))
+        })
+        .collect()
+}
+
+/// 按修改时间从新到旧保留 keep 个文件，其余删除，返回删除数量
+fn prune_oldest(mut files: Vec<(PathBuf, SystemTime)>, keep: usize) -> usize {
+    files.sort_by_key(|(_, modified)| std::cmp::Reverse(*modified));
+    files
+        .into_iter()
+        .skip(keep)
+        .filter(|(path, _)| match fs::remove_file(path) {
+            Ok(()) => true,
+            Err(e) => {
+                warn!("Failed to remove {}: {}", path.display(), e);
+                false
+            }
+        })
+        .count()
+}
+
+/// 压缩目录中轮转后的日志，并按日志名分别只保留最新的 retention 个压缩文件
+fn compress_rotated_logs(dir: &Path, retention: usize) -> (usize, usize) {
+    let mut compressed = 0;
+    for (name, path, _) in dir_entries(dir) {
+        if !is_rotated_log(&name) {
+            continue;
+        }
+        match compress_file(&path) {
+            Ok(_) => compressed += 1,
+            Err(e) => warn!("Failed to compress {}: {}", path.display(), e),
+        }
+    }
+
+    let mut groups: BTreeMap<String, Vec<(PathBuf, SystemTime)>> = BTreeMap::new();
+    for (name, path, modified) in dir_entries(dir) {
+        if let Some(group) = compressed_log_group(&name) {
+            groups
+                .entry(group.to_string())
+                .or_default()
+                .push((path, modified));
+        }
+    }
+    let pruned = groups
+        .into_values()
+        .map(|files| prune_oldest(files, retention))
+        .sum();
+    (compressed, pruned)
+}
+
+/// 删除多余的配置备份（config.yaml.bak、config.yaml.bak.1 等），只保留最新的 retention 个
+pub(crate) fn prune_config_backups(config_path: &Path, retention: usize) -> usize {
+    let (Some(dir), Some(file_name)) = (config_path.parent(), config_path.file_name()) else {
+        return 0;
+    };
+    let dir = if dir.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        dir
+    };
+    let prefix = format!("{}.bak", file_name.to_string_lossy());
+    let backups = dir_entries(dir)
+        .into_iter()
+        .filter(|(name, _, _)| name.starts_with(&prefix))
+        .map(|(_, path, modified)| (path, modified))
+        .collect();
+    prune_oldest(backups, retention)
+}
+
+/// 淘汰获取时间早于 ttl 的订阅缓存，下次生成配置时会重新获取
+fn evict_stale_sub_cache(
+    cache: &mut HashMap<String, FetchResult>,
+    ttl: Duration,
+    now: Instant,
+) -> usize {
+    let before = cache.len();
+    cache.retain(|_, result| now.saturating_duration_since(result.fetched_at) < ttl);
+    before - cache.len()
+}
+
+async fn run_once(state: &Arc<AppState>, settings: &HousekeepingConfig) {
+    if let Some(ttl) = settings.sub_cache_ttl_secs {
+        let evicted = evict_stale_sub_cache(
+            &mut *state.sub_cache.lock().await,
+            Duration::from_secs(ttl),
+            Instant::now(),
+        );
+        if evicted > 0 {
+            info!(evicted, "Evicted stale subscription cache entries");
+        }
+    }
+
+    let paths = state.sing_box_paths.clone();
+    let config_path = state.config_path.clone();
+    let log_retention = settings.log_retention;
+    let backup_retention = settings.backup_retention;
+    let result = tokio::task::spawn_blocking(move || {
+        let mut log_dirs = vec![paths.data_dir, paths.config_dir];
+        log_dirs.dedup();
+        let (compressed, pruned_logs) = log_dirs
+            .iter()
+            .map(|dir| compress_rotated_logs(dir, log_retention))
+            .fold((0, 0), |acc, (c, p)| (acc.0 + c, acc.1 + p));
+        let pruned_backups = prune_config_backups(&config_path, backup_retention);
+        (compressed, pruned_logs, pruned_backups)
+    })
+    .await;
+
+    match result {
+        Ok((compressed, pruned_logs, pruned_backups))
+            if compressed + pruned_logs + pruned_backups > 0 =>
+        {
+            info!(
+                compressed,
+                pruned_logs, pruned_backups, "Housekeeping cleaned up files"
+            );
+        }
+        Ok(_) => {}
+        Err(e) => warn!("Housekeeping task failed: {}", e),
+    }
+}
+
+/// 按 housekeeping 配置定期清理；未配置时只定期检查配置是否被启用
+pub async fn run_housekeeping(state: Arc<AppState>) {
+    loop {
+        let settings = state.config.read().await.housekeeping.clone();
+        let Some(settings) = settings else {
+            sleep(IDLE_CHECK_INTERVAL).await;
+            continue;
+        };
+
+        run_once(&state, &settings).await;
+        sleep(Duration::from_secs(settings.interval_secs.max(60))).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::fs;
+    use std::time::{Duration, Instant};
+
+    use super::{
+        compress_rotated_logs, compressed_log_group, evict_stale_sub_cache, is_rotated_log,
+        prune_config_backups,
+    };
+    use crate::services::subscription::FetchResult;
+
+    fn test_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("miao-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn recognizes_rotated_logs() {
+        assert!(is_rotated_log("sing-box.log.1"));
+        assert!(is_rotated_log("box.log.2026-01-01"));
+        assert!(!is_rotated_log("sing-box.log"));
+        assert!(!is_rotated_log("sing-box.log.1.gz"));
+        assert!(!is_rotated_log("chinasite.srs"));
+        assert_eq!(
+            compressed_log_group("sing-box.log.3.gz"),
+            Some("sing-box.log")
+        );
+        assert_eq!(compressed_log_group("sing-box.log"), None);
+    }
+
+    #[test]
+    fn compresses_rotated_logs_and_keeps_retention() {
+        let dir = test_dir("housekeeping-logs");
+        fs::write(dir.join("sing-box.log"), "current").unwrap();
+        for i in 1..=3 {
+            fs::write(dir.join(format!("sing-box.log.{i}")), "old line\n").unwrap();
+        }
+
+        let (compressed, pruned) = compress_rotated_logs(&dir, 2);
+
+        let mut names: Vec<String> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!((compressed, pruned), (3, 1));
+        assert_eq!(names.len(), 3);
+        assert!(names.contains(&"sing-box.log".to_string()));
+        assert_eq!(names.iter().filter(|name| name.ends_with(".gz")).count(), 2);
+    }
+
+    #[test]
+    fn prunes_config_backups_beyond_retention() {
+        let dir = test_dir("housekeeping-backups");
+        let config_path = dir.join("config.yaml");
+        fs::write(&config_path, "port: 6161").unwrap();
+        for i in 1..=4 {
+            fs::write(dir.join(format!("config.yaml.bak.{i}")), "old").unwrap();
+        }
+
+        let pruned = prune_config_backups(&config_path, 1);
+        let remaining = fs::read_dir(&dir).unwrap().count();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(pruned, 3);
+        assert_eq!(remaining, 2);
+    }
+
+    #[test]
+    fn evicts_only_stale_sub_cache_entries() {
+        let now = Instant::now();
+        let entry = |age: u64| FetchResult {
+            node_names: vec![],
+            outbounds: vec![],
+            parse_errors: vec![],
+            total_count: 0,
+            dropped: Default::default(),
+            userinfo: None,
+            fetched_at: now - Duration::from_secs(age),
+            content_hash: None,
+        };
+        let mut cache = HashMap::from([
+            ("https://fresh.example.com".to_string(), entry(10)),
+            ("https://stale.example.com".to_string(), entry(7200)),
+        ]);
+
+        let evicted = evict_stale_sub_cache(&mut cache, Duration::from_secs(3600), now);
+
+        assert_eq!(evicted, 1);
+        assert!(cache.contains_key("https://fresh.example.com"));
+    }
+}
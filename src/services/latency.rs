@@ -0,0 +1,127 @@
+use futures::{stream, StreamExt};
+use std::collections::BTreeMap;
+use tokio::time::Duration;
+
+use crate::models::RegionLatency;
+use crate::services::config::clash_api_url;
+use crate::services::region::{detect_region, region_group_tag};
+
+const LATENCY_TEST_URL: &str = "https://www.gstatic.com/generate_204";
+const LATENCY_TIMEOUT_MS: u64 = 5000;
+const MAX_CONCURRENT_PROBES: usize = 10;
+
+async fn probe_latency(client: &reqwest::Client, tag: &str) -> Option<u64> {
+    let url = clash_api_url(&format!(
+        "/proxies/{}/delay?url={}&timeout={}",
+        urlencoding::encode(tag),
+        urlencoding::encode(LATENCY_TEST_URL),
+        LATENCY_TIMEOUT_MS
+    ));
+    let response = client
+        .get(&url)
+        .timeout(Duration::from_millis(LATENCY_TIMEOUT_MS + 1000))
+        .send()
+        .await
+        .ok()?
+        .error_for_status()
+        .ok()?;
+    let body: serde_json::Value = response.json().await.ok()?;
+    body["delay"].as_u64()
+}
+
+/// 通过 sing-box 的 Clash API 测量各节点延迟，失败或超时的节点为 None
+pub async fn probe_latencies(
+    client: &reqwest::Client,
+    tags: Vec<String>,
+) -> BTreeMap<String, Option<u64>> {
+    stream::iter(tags)
+        .map(|tag| async move {
+            let latency = probe_latency(client, &tag).await;
+            (tag, latency)
+        })
+        .buffer_unordered(MAX_CONCURRENT_PROBES)
+        .collect()
+        .await
+}
+
+fn median(sorted: &[u64]) -> Option<u64> {
+    match sorted.len() {
+        0 => None,
+        len if len % 2 == 1 => Some(sorted[len / 2]),
+        len => Some((sorted[len / 2 - 1] + sorted[len / 2]) / 2),
+    }
+}
+
+/// 按地区汇总节点延迟，结果按中位延迟从低到高排列，全部不可达的地区排在最后
+pub fn aggregate_region_latency(latencies: &BTreeMap<String, Option<u64>>) -> Vec<RegionLatency> {
+    let mut by_region: BTreeMap<&'static str, (usize, Vec<u64>)> = BTreeMap::new();
+    for (tag, latency) in latencies {
+        let Some(region) = detect_region(tag) else {
+            continue;
+        };
+        let entry = by_region.entry(region).or_default();
+        entry.0 += 1;
+        entry.1.extend(latency);
+    }
+
+    let mut regions: Vec<RegionLatency> = by_region
+        .into_iter()
+        .map(|(region, (nodes, mut measured))| {
+            measured.sort_unstable();
+            RegionLatency {
+                region: region.to_string(),
+                group: region_group_tag(region),
+                nodes,
+                reachable: measured.len(),
+                min_ms: measured.first().copied(),
+                median_ms: median(&measured),
+            }
+        })
+        .collect();
+    regions.sort_by_key(|region| region.median_ms.unwrap_or(u64::MAX));
+    regions
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::aggregate_region_latency;
+
+    #[test]
+    fn aggregates_min_and_median_per_region() {
+        let latencies = BTreeMap::from([
+            ("HK 01".to_string(), Some(120)),
+            ("HK 02".to_string(), Some(40)),
+            ("HK 03".to_string(), None),
+            ("SG 01".to_string(), Some(60)),
+            ("SG 02".to_string(), Some(90)),
+            ("JP 01".to_string(), None),
+            ("unknown".to_string(), Some(10)),
+        ]);
+
+        let regions = aggregate_region_latency(&latencies);
+
+        let summary: Vec<_> = regions
+            .iter()
+            .map(|r| {
+                (
+                    r.region.as_str(),
+                    r.nodes,
+                    r.reachable,
+                    r.min_ms,
+                    r.median_ms,
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("SG", 2, 2, Some(60), Some(75)),
+                ("HK", 3, 2, Some(40), Some(80)),
+                ("JP", 1, 0, None, None),
+            ]
+        );
+        assert_eq!(regions[0].group, "Region SG");
+    }
+}
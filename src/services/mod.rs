@@ -1,8 +1,29 @@
+pub mod backup;
+pub mod bandwidth;
+pub mod clash_export;
+pub mod compat;
 pub mod config;
+pub mod config_diff;
+pub mod diagnostics;
+pub mod health;
+pub mod housekeeping;
+pub mod latency;
+pub mod mtu;
+pub mod name_transform;
+pub mod network_wait;
+pub mod node_groups;
 pub mod node_parser;
 pub mod openwrt;
 pub mod proxy;
+pub mod region;
+pub mod resolve_check;
+pub mod rule_set_mirror;
+pub mod scheduler;
+pub mod share_link;
 pub mod singbox;
+pub mod staged_write;
 pub mod subscription;
+pub mod testrun;
+pub mod traffic;
 pub mod version;
 pub mod vps;
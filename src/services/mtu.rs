@@ -0,0 +1,79 @@
+use std::fs;
+use tracing::{info, warn};
+
+use crate::models::TunMtu;
+
+/// 无法探测默认路由网卡 MTU 时使用的值
+pub const FALLBACK_MTU: u32 = 1500;
+/// sing-box 创建的 TUN 网卡名，探测时需跳过，避免读到自身的 MTU
+const TUN_INTERFACE_NAME: &str = "sing-tun";
+
+/// 从 /proc/net/route 中找出默认路由（Destination 为 0）所在网卡，多条时取 metric 最小的
+fn default_route_interface(route_table: &str) -> Option<String> {
+    route_table
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let (iface, destination, mask, metric) = (
+                fields.first()?,
+                fields.get(1)?,
+                fields.get(7)?,
+                fields.get(6)?,
+            );
+            (*destination == "00000000" && *mask == "00000000" && *iface != TUN_INTERFACE_NAME)
+                .then(|| (metric.parse::<u32>().unwrap_or(u32::MAX), iface.to_string()))
+        })
+        .min_by_key(|(metric, _)| *metric)
+        .map(|(_, iface)| iface)
+}
+
+fn detect_default_route_mtu() -> Option<(String, u32)> {
+    let route_table = fs::read_to_string("/proc/net/route").ok()?;
+    let iface = default_route_interface(&route_table)?;
+    let mtu = fs::read_to_string(format!("/sys/class/net/{iface}/mtu"))
+        .ok()?
+        .trim()
+        .parse()
+        .ok()?;
+    Some((iface, mtu))
+}
+
+/// 把配置中的 tun_mtu 解析为具体数值；auto 时读取默认路由网卡的 MTU，失败回退到 1500
+pub fn resolve_tun_mtu(tun_mtu: TunMtu) -> u32 {
+    match tun_mtu {
+        TunMtu::Fixed(mtu) => mtu,
+        TunMtu::Auto => match detect_default_route_mtu() {
+            Some((iface, mtu)) => {
+                info!("Detected MTU {} on default route interface {}", mtu, iface);
+                mtu
+            }
+            None => {
+                warn!(
+                    "Failed to detect default route MTU, falling back to {}",
+                    FALLBACK_MTU
+                );
+                FALLBACK_MTU
+            }
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::default_route_interface;
+
+    #[test]
+    fn default_route_interface_picks_lowest_metric_and_skips_tun() {
+        let table = "\
+Iface\tDestination\tGateway \tFlags\tRefCnt\tUse\tMetric\tMask\t\tMTU\tWindow\tIRTT
+sing-tun\t00000000\t00000000\t0001\t0\t0\t0\t00000000\t0\t0\t0
+wlan0\t00000000\t0101A8C0\t0003\t0\t0\t600\t00000000\t0\t0\t0
+pppoe-wan\t00000000\t00000000\t0001\t0\t0\t100\t00000000\t0\t0\t0
+eth0\t0001A8C0\t00000000\t0001\t0\t0\t0\t00FFFFFF\t0\t0\t0
+";
+
+        assert_eq!(default_route_interface(table).as_deref(), Some("pppoe-wan"));
+        assert_eq!(default_route_interface("Iface\tDestination\n"), None);
+    }
+}
@@ -0,0 +1,115 @@
+use regex::Regex;
+
+use crate::models::NameTransform;
+
+enum CompiledTransform {
+    StripEmoji,
+    Trim,
+    Replace { regex: Regex, replacement: String },
+}
+
+/// 预编译的节点名称转换规则
+pub struct NameTransformer {
+    transforms: Vec<CompiledTransform>,
+}
+
+fn is_emoji(c: char) -> bool {
+    matches!(
+        c,
+        '\u{1F000}'..='\u{1FAFF}'
+            | '\u{2300}'..='\u{23FF}'
+            | '\u{2600}'..='\u{27BF}'
+            | '\u{2B00}'..='\u{2BFF}'
+            | '\u{FE00}'..='\u{FE0F}'
+            | '\u{E0020}'..='\u{E007F}'
+            | '\u{200D}'
+            | '\u{20E3}'
+    )
+}
+
+impl NameTransformer {
+    pub fn new(transforms: &[NameTransform]) -> Result<Self, String> {
+        let transforms = transforms
+            .iter()
+            .map(|transform| match transform {
+                NameTransform::StripEmoji => Ok(CompiledTransform::StripEmoji),
+                NameTransform::Trim => Ok(CompiledTransform::Trim),
+                NameTransform::Replace {
+                    pattern,
+                    replacement,
+                } => Regex::new(pattern)
+                    .map(|regex| CompiledTransform::Replace {
+                        regex,
+                        replacement: replacement.clone(),
+                    })
+                    .map_err(|e| format!("无效的名称替换正则 '{}': {}", pattern, e)),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self { transforms })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.transforms.is_empty()
+    }
+
+    /// 依次应用所有转换；结果为空时保留原名，避免产生空 tag
+    pub fn apply(&self, name: &str) -> String {
+        let mut result = name.to_string();
+        for transform in &self.transforms {
+            result = match transform {
+                CompiledTransform::StripEmoji => result.chars().filter(|c| !is_emoji(*c)).collect(),
+                CompiledTransform::Trim => result.trim().to_string(),
+                CompiledTransform::Replace { regex, replacement } => regex
+                    .replace_all(&result, replacement.as_str())
+                    .into_owned(),
+            };
+        }
+
+        if result.trim().is_empty() {
+            name.to_string()
+        } else {
+            result
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::NameTransformer;
+    use crate::models::NameTransform;
+
+    #[test]
+    fn applies_transforms_in_order() {
+        let transformer = NameTransformer::new(&[
+            NameTransform::StripEmoji,
+            NameTransform::Replace {
+                pattern: r"\s+".to_string(),
+                replacement: " ".to_string(),
+            },
+            NameTransform::Trim,
+        ])
+        .unwrap();
+
+        assert_eq!(transformer.apply("🇭🇰 Hong   Kong 01 ⚡️"), "Hong Kong 01");
+        assert_eq!(transformer.apply("日本 東京"), "日本 東京");
+    }
+
+    #[test]
+    fn keeps_original_name_when_result_is_empty() {
+        let transformer = NameTransformer::new(&[NameTransform::StripEmoji]).unwrap();
+
+        assert_eq!(transformer.apply("🚀"), "🚀");
+    }
+
+    #[test]
+    fn rejects_invalid_regex() {
+        let err = NameTransformer::new(&[NameTransform::Replace {
+            pattern: "(".to_string(),
+            replacement: String::new(),
+        }])
+        .err()
+        .unwrap();
+
+        assert!(err.contains("("));
+    }
+}
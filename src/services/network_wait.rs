@@ -0,0 +1,70 @@
+use tokio::time::{sleep, Duration, Instant};
+use tracing::{info, warn};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+const LOOKUP_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// /proc/net/route 中是否有默认路由（Destination 与 Mask 均为 0）
+fn has_default_route(route_table: &str) -> bool {
+    route_table.lines().skip(1).any(|line| {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        fields.len() > 7 && fields[1] == "00000000" && fields[7] == "00000000"
+    })
+}
+
+async fn network_ready(probe_host: Option<&str>) -> bool {
+    let route_ready = tokio::fs::read_to_string("/proc/net/route")
+        .await
+        .is_ok_and(|table| has_default_route(&table));
+    if !route_ready {
+        return false;
+    }
+    let Some(host) = probe_host else {
+        return true;
+    };
+    match tokio::time::timeout(LOOKUP_TIMEOUT, tokio::net::lookup_host((host, 0))).await {
+        Ok(Ok(mut addrs)) => addrs.next().is_some(),
+        _ => false,
+    }
+}
+
+/// 开机时网络可能尚未就绪，首次获取订阅会直接失败。等待出现默认路由且 probe_host
+/// （通常是第一个订阅的主机名）可以解析，最多等待 timeout；超时后照常继续
+pub async fn wait_for_network(probe_host: Option<&str>, timeout: Duration) -> bool {
+    let deadline = Instant::now() + timeout;
+    loop {
+        if network_ready(probe_host).await {
+            return true;
+        }
+        if Instant::now() >= deadline {
+            warn!(
+                timeout_secs = timeout.as_secs(),
+                "Network still not ready, continuing startup anyway"
+            );
+            return false;
+        }
+        info!(probe_host = ?probe_host, "Waiting for network to come up");
+        sleep(POLL_INTERVAL).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::has_default_route;
+
+    const HEADER: &str =
+        "Iface\tDestination\tGateway \tFlags\tRefCnt\tUse\tMetric\tMask\t\tMTU\tWindow\tIRTT";
+
+    #[test]
+    fn has_default_route_requires_zero_destination_and_mask() {
+        let with_default = format!(
+            "{HEADER}\neth0\t00000000\t0101A8C0\t0003\t0\t0\t100\t00000000\t0\t0\t0\neth0\t0001A8C0\t00000000\t0001\t0\t0\t100\t00FFFFFF\t0\t0\t0\n"
+        );
+        let without_default =
+            format!("{HEADER}\neth0\t0001A8C0\t00000000\t0001\t0\t0\t100\t00FFFFFF\t0\t0\t0\n");
+
+        assert!(has_default_route(&with_default));
+        assert!(!has_default_route(&without_default));
+        assert!(!has_default_route(HEADER));
+    }
+}
@@ -0,0 +1,149 @@
+use regex::Regex;
+use tracing::warn;
+
+use crate::error::{AppError, AppResult};
+use crate::models::NodeGroup;
+
+/// 未匹配任何自定义分组的节点所在的分组
+pub const DEFAULT_GROUP_TAG: &str = "Other";
+/// 包含全部自定义分组的顶层 selector
+pub const GROUPS_SELECTOR_TAG: &str = "Groups";
+
+/// 按 `groups` 为节点生成 selector 分组，未匹配任何分组的节点归入 Other；
+/// 各分组再汇总到 Groups selector，插在 proxy selector 最前面。
+/// 与已有 outbound 同名的分组跳过（其节点视为未匹配），空分组不生成
+pub fn apply_node_groups(
+    sing_box_config: &mut serde_json::Value,
+    node_names: &[String],
+    groups: &[NodeGroup],
+) -> AppResult<()> {
+    let Some(outbounds) = sing_box_config["outbounds"].as_array_mut() else {
+        return Ok(());
+    };
+    let tag_taken =
+        |outbounds: &[serde_json::Value], tag: &str| outbounds.iter().any(|o| o["tag"] == tag);
+    if tag_taken(outbounds, GROUPS_SELECTOR_TAG) {
+        warn!(
+            tag = GROUPS_SELECTOR_TAG,
+            "Outbound tag collides with node groups selector, skipping groups"
+        );
+        return Ok(());
+    }
+
+    let mut matched = vec![false; node_names.len()];
+    let mut built: Vec<(String, Vec<String>)> = Vec::new();
+    for group in groups {
+        let tag = group.name.trim();
+        if tag_taken(outbounds, tag) {
+            warn!(tag = %tag, "Outbound tag collides with node group, skipping group");
+            continue;
+        }
+        let patterns = group
+            .patterns
+            .iter()
+            .map(|pattern| Regex::new(pattern))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| {
+                AppError::message(format!("Invalid pattern in group {}: {}", group.name, e))
+            })?;
+        let members: Vec<String> = node_names
+            .iter()
+            .enumerate()
+            .filter(|(_, name)| patterns.iter().any(|regex| regex.is_match(name)))
+            .map(|(idx, name)| {
+                matched[idx] = true;
+                name.clone()
+            })
+            .collect();
+        built.push((tag.to_string(), members));
+    }
+    let unmatched: Vec<String> = node_names
+        .iter()
+        .zip(&matched)
+        .filter(|(_, matched)| !**matched)
+        .map(|(name, _)| name.clone())
+        .collect();
+    if tag_taken(outbounds, DEFAULT_GROUP_TAG) {
+        warn!(
+            tag = DEFAULT_GROUP_TAG,
+            "Outbound tag collides with default node group, skipping group"
+        );
+    } else {
+        built.push((DEFAULT_GROUP_TAG.to_string(), unmatched));
+    }
+    built.retain(|(_, members)| !members.is_empty());
+    if built.is_empty() {
+        return Ok(());
+    }
+
+    if let Some(proxy) = outbounds
+        .iter_mut()
+        .find(|outbound| outbound["type"] == "selector" && outbound["tag"] == "proxy")
+    {
+        if let Some(members) = proxy["outbounds"].as_array_mut() {
+            members.insert(0, serde_json::json!(GROUPS_SELECTOR_TAG));
+        }
+    }
+    outbounds.push(serde_json::json!({
+        "type": "selector",
+        "tag": GROUPS_SELECTOR_TAG,
+        "outbounds": built.iter().map(|(tag, _)| tag).collect::<Vec<_>>()
+    }));
+    outbounds.extend(built.into_iter().map(|(tag, members)| {
+        serde_json::json!({
+            "type": "selector",
+            "tag": tag,
+            "outbounds": members
+        })
+    }));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::apply_node_groups;
+    use crate::models::NodeGroup;
+
+    #[test]
+    fn apply_node_groups_allows_overlap_and_collects_unmatched_nodes() {
+        let names = ["🎬 HK Netflix", "🎮 JP Game", "🎬🎮 SG", "US 01"].map(String::from);
+        let mut config = json!({
+            "outbounds": [
+                {"type": "selector", "tag": "proxy", "outbounds": names},
+                {"type": "direct", "tag": "direct"}
+            ]
+        });
+        let groups = [
+            NodeGroup {
+                name: "Streaming".to_string(),
+                patterns: vec!["🎬".to_string(), "(?i)netflix".to_string()],
+            },
+            NodeGroup {
+                name: "Gaming".to_string(),
+                patterns: vec!["🎮".to_string()],
+            },
+            NodeGroup {
+                name: "direct".to_string(),
+                patterns: vec!["US".to_string()],
+            },
+        ];
+
+        apply_node_groups(&mut config, &names, &groups).unwrap();
+
+        let outbounds = config["outbounds"].as_array().unwrap();
+        assert_eq!(outbounds[0]["outbounds"][0], "Groups");
+        assert_eq!(
+            outbounds[2],
+            json!({"type": "selector", "tag": "Groups", "outbounds": ["Streaming", "Gaming", "Other"]})
+        );
+        assert_eq!(
+            outbounds[3]["outbounds"],
+            json!(["🎬 HK Netflix", "🎬🎮 SG"])
+        );
+        assert_eq!(outbounds[4]["outbounds"], json!(["🎮 JP Game", "🎬🎮 SG"]));
+        assert_eq!(outbounds[5]["outbounds"], json!(["US 01"]));
+        assert_eq!(outbounds.len(), 6);
+    }
+}
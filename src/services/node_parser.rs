@@ -1,9 +1,12 @@
 use regex::Regex;
 use serde_json::{json, Map, Value as JsonValue};
 use serde_yaml::{Mapping, Value};
+use std::collections::{BTreeMap, HashMap};
 use std::sync::LazyLock;
+use tracing::warn;
 
 use crate::error::{AppError, AppResult};
+use crate::models::{NameTransform, Transport};
 
 static UUID_REGEX: LazyLock<Regex> = LazyLock::new(|| {
     Regex::new(r"^[0-9a-fA-F]{8}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{12}$")
@@ -31,13 +34,85 @@ static VALID_TUIC_UDP_RELAY_MODES: &[&str] = &["native", "quic"];
 /// 节点解析结果，包含有效节点和错误记录
 #[derive(Debug)]
 pub struct ParseResult {
-    pub nodes: Vec<(String, JsonValue)>, // (name, outbound_json)
-    pub errors: Vec<String>,             // 记录解析失败的节点及原因
-    pub total_count: usize,              // YAML 中 proxies 列表的原始总数
+    pub nodes: Vec<(String, JsonValue)>,  // (name, outbound_json)
+    pub errors: Vec<String>,              // 记录解析失败的节点及原因
+    pub total_count: usize,               // YAML 中 proxies 列表的原始总数
+    pub dropped: BTreeMap<String, usize>, // 按原因统计被跳过的不支持节点
+    pub providers: Vec<ProxyProvider>,    // proxy-providers 中需要另行获取的 http 提供者
 }
 
-/// 从 Clash 配置中解析节点，跳过无效节点并记录错误
+/// Clash proxy-providers 中 type 为 http 的条目
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProxyProvider {
+    pub name: String,
+    pub url: String,
+}
+
+/// 提取 proxy-providers 中的 http 提供者；file 等本地类型无法获取，记录到 errors
+fn collect_proxy_providers(clash_obj: &Value, errors: &mut Vec<String>) -> Vec<ProxyProvider> {
+    let Some(providers) = clash_obj
+        .get("proxy-providers")
+        .and_then(|p| p.as_mapping())
+    else {
+        return Vec::new();
+    };
+
+    let mut result = Vec::new();
+    for (name, provider) in providers {
+        let name = name.as_str().unwrap_or("<unnamed>").to_string();
+        let provider_type = provider
+            .get("type")
+            .and_then(|t| t.as_str())
+            .unwrap_or("unknown");
+        if !provider_type.eq_ignore_ascii_case("http") {
+            errors.push(format!(
+                "Proxy provider '{}' has unsupported type '{}'",
+                name, provider_type
+            ));
+            continue;
+        }
+        match provider.get("url").and_then(|u| u.as_str()) {
+            Some(url) if !url.trim().is_empty() => result.push(ProxyProvider {
+                name,
+                url: url.trim().to_string(),
+            }),
+            _ => errors.push(format!("Proxy provider '{}' has no url", name)),
+        }
+    }
+    result
+}
+
+/// sing-box 已移除 ShadowsocksR 支持，SSR 节点只能跳过。
+/// 若以后要支持，可行的方式是另起一个 SSR 客户端暴露本地 socks 入站，
+/// 再由 sing-box 的 socks outbound 转发过去。
+const SSR_DROP_REASON: &str = "ssr not supported by sing-box";
+
+/// 订阅解析选项
+#[derive(Debug, Default, Clone)]
+pub struct ParseOptions {
+    /// 转换器未识别、需要原样复制到 outbound 的 Clash 字段
+    pub passthrough_keys: Vec<String>,
+    /// 节点名称转换规则，由 fetch_sub 在筛选后、截断前应用
+    pub name_transforms: Vec<NameTransform>,
+    /// 订阅响应体的字节上限，未设置时使用 DEFAULT_MAX_SUB_BYTES
+    pub max_body_bytes: Option<usize>,
+    /// 最近测得的节点延迟（毫秒），按节点标识（type://server:port）索引，供 limit_by: fastest 排序
+    pub latencies: HashMap<String, u64>,
+}
+
+/// 由转换器本身处理的 Clash 字段，即使列入 passthrough 也不会复制
+const RESERVED_CLASH_KEYS: &[&str] = &["name", "type", "server", "port"];
+
+#[cfg(test)]
 pub fn parse_clash_proxies(clash_yaml: &str) -> AppResult<ParseResult> {
+    parse_clash_proxies_with(clash_yaml, &ParseOptions::default())
+}
+
+/// 从 Clash 配置中解析节点，跳过无效节点并记录错误
+pub fn parse_clash_proxies_with(
+    clash_yaml: &str,
+    options: &ParseOptions,
+) -> AppResult<ParseResult> {
     let clash_obj: Value = serde_yaml::from_str(clash_yaml)
         .map_err(|e| AppError::context("Failed to parse subscription YAML", e))?;
 
@@ -51,7 +126,10 @@ pub fn parse_clash_proxies(clash_yaml: &str) -> AppResult<ParseResult> {
         nodes: vec![],
         errors: vec![],
         total_count: proxies.len(),
+        dropped: BTreeMap::new(),
+        providers: vec![],
     };
+    result.providers = collect_proxy_providers(&clash_obj, &mut result.errors);
 
     for (idx, node) in proxies.iter().enumerate() {
         let node_type = node
@@ -60,13 +138,32 @@ pub fn parse_clash_proxies(clash_yaml: &str) -> AppResult<ParseResult> {
             .unwrap_or("unknown");
         let normalized_type = node_type.to_ascii_lowercase();
 
-        // Skip unsupported node types silently
+        if matches!(normalized_type.as_str(), "ssr" | "shadowsocksr") {
+            let name = node
+                .get("name")
+                .and_then(|n| n.as_str())
+                .unwrap_or("<unnamed>");
+            warn!("SSR not supported by sing-box, skipping node {}", name);
+            *result
+                .dropped
+                .entry(SSR_DROP_REASON.to_string())
+                .or_default() += 1;
+            continue;
+        }
+
         if !is_supported_node_type(&normalized_type) {
+            *result
+                .dropped
+                .entry(format!("unsupported type '{}'", normalized_type))
+                .or_default() += 1;
             continue;
         }
 
         match parse_single_node(node) {
-            Ok((name, outbound)) => result.nodes.push((name, outbound)),
+            Ok((name, mut outbound)) => {
+                apply_passthrough_keys(node, &mut outbound, &options.passthrough_keys);
+                result.nodes.push((name, outbound));
+            }
             Err(err) => {
                 let name = node
                     .get("name")
@@ -83,6 +180,22 @@ pub fn parse_clash_proxies(clash_yaml: &str) -> AppResult<ParseResult> {
     Ok(result)
 }
 
+/// 将列出的 Clash 字段原样复制到 outbound；已由转换器映射的字段不会被覆盖
+fn apply_passthrough_keys(node: &Value, outbound: &mut JsonValue, keys: &[String]) {
+    let Some(obj) = outbound.as_object_mut() else {
+        return;
+    };
+
+    for key in keys {
+        if RESERVED_CLASH_KEYS.contains(&key.as_str()) || obj.contains_key(key) {
+            continue;
+        }
+        if let Some(value) = node.get(key.as_str()).and_then(yaml_to_json) {
+            obj.insert(key.clone(), value);
+        }
+    }
+}
+
 fn is_supported_node_type(node_type: &str) -> bool {
     matches!(
         node_type,
@@ -239,6 +352,24 @@ fn first_string(value: &Value) -> Option<String> {
     string_list(value).into_iter().next()
 }
 
+/// Clash 订阅中的 IPv6 地址可能带方括号（如 "[2001:db8::1]"），sing-box 需要去掉方括号
+fn normalize_server(server: &str) -> &str {
+    server
+        .strip_prefix('[')
+        .and_then(|rest| rest.strip_suffix(']'))
+        .filter(|inner| inner.parse::<std::net::Ipv6Addr>().is_ok())
+        .unwrap_or(server)
+}
+
+/// sni/servername 为 IPv6 字面量时不是合法的主机名，不写入 server_name
+fn tls_server_name(node: &Value) -> Option<&str> {
+    get_str_any(node, &["sni", "servername"]).filter(|name| {
+        normalize_server(name)
+            .parse::<std::net::Ipv6Addr>()
+            .is_err()
+    })
+}
+
 fn base_outbound(typ: &str, name: &str, server: &str, port: u16) -> Map<String, JsonValue> {
     let mut obj = Map::new();
     obj.insert("type".to_string(), json!(typ));
@@ -271,6 +402,35 @@ fn parse_hysteria2_obfs(node: &Value) -> Result<Option<JsonValue>, String> {
     })))
 }
 
+/// 解析 Clash 的 up/down 带宽，如 `30`、`"30 Mbps"`、`"1 Gbps"`；纯数字按 Mbps 计
+fn parse_bandwidth_mbps(node: &Value, key: &str) -> Result<Option<u32>, String> {
+    let Some(value) = node.get(key).filter(|value| !value.is_null()) else {
+        return Ok(None);
+    };
+    let invalid = || format!("invalid Hysteria2 bandwidth '{}': {:?}", key, value);
+    if let Some(mbps) = value.as_u64() {
+        return u32::try_from(mbps).map(Some).map_err(|_| invalid());
+    }
+    let text = value.as_str().ok_or_else(invalid)?.trim();
+    let split = text
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let number: f64 = number.parse().map_err(|_| invalid())?;
+    let factor = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "mbps" | "m" => 1.0,
+        "gbps" | "g" => 1000.0,
+        "kbps" | "k" => 0.001,
+        "tbps" | "t" => 1_000_000.0,
+        _ => return Err(invalid()),
+    };
+    let mbps = (number * factor).round();
+    if !(1.0..=u32::MAX as f64).contains(&mbps) {
+        return Err(invalid());
+    }
+    Ok(Some(mbps as u32))
+}
+
 fn parse_alpn(node: &Value) -> Vec<String> {
     node.get("alpn").map(string_list).unwrap_or_default()
 }
@@ -304,11 +464,7 @@ fn build_tls(node: &Value, default_enabled: bool) -> Result<Option<JsonValue>, S
         "insecure".to_string(),
         json!(get_bool(node, "skip-cert-verify")),
     );
-    insert_optional_string(
-        &mut tls,
-        "server_name",
-        get_str_any(node, &["sni", "servername"]),
-    );
+    insert_optional_string(&mut tls, "server_name", tls_server_name(node));
 
     let alpn = parse_alpn(node);
     if !alpn.is_empty() {
@@ -365,15 +521,17 @@ fn build_required_tls(node: &Value) -> Result<JsonValue, String> {
         "insecure".to_string(),
         json!(get_bool(node, "skip-cert-verify")),
     );
-    insert_optional_string(
-        &mut tls,
-        "server_name",
-        get_str_any(node, &["sni", "servername"]),
-    );
+    insert_optional_string(&mut tls, "server_name", tls_server_name(node));
     Ok(JsonValue::Object(tls))
 }
 
-fn build_v2ray_transport(node: &Value) -> Result<Option<JsonValue>, String> {
+/// alpn 只有 h3 时，http/h2 网络实际是 HTTP/3
+fn is_h3_only(node: &Value) -> bool {
+    let alpn = node.get("alpn").map(string_list).unwrap_or_default();
+    !alpn.is_empty() && alpn.iter().all(|value| value.eq_ignore_ascii_case("h3"))
+}
+
+fn build_v2ray_transport(node: &Value) -> Result<Option<Transport>, String> {
     let network = get_str(node, "network")
         .unwrap_or("tcp")
         .to_ascii_lowercase();
@@ -382,29 +540,26 @@ fn build_v2ray_transport(node: &Value) -> Result<Option<JsonValue>, String> {
         "" | "tcp" => Ok(None),
         "ws" => {
             let opts = node.get("ws-opts").and_then(|value| value.as_mapping());
-            let mut transport = Map::new();
-            transport.insert("type".to_string(), json!("ws"));
-            if let Some(path) = opts.and_then(|opts| map_get_str(opts, "path")) {
-                transport.insert("path".to_string(), json!(path));
-            }
-            if let Some(headers) = opts
-                .and_then(|opts| map_get_value(opts, "headers"))
-                .and_then(yaml_to_json)
-            {
-                transport.insert("headers".to_string(), headers);
-            }
-            Ok(Some(JsonValue::Object(transport)))
+            Ok(Some(Transport {
+                path: opts
+                    .and_then(|opts| map_get_str(opts, "path"))
+                    .map(str::to_string),
+                headers: opts
+                    .and_then(|opts| map_get_value(opts, "headers"))
+                    .and_then(yaml_to_json),
+                ..Transport::new("ws")
+            }))
         }
         "grpc" => {
             let opts = node.get("grpc-opts").and_then(|value| value.as_mapping());
-            let mut transport = Map::new();
-            transport.insert("type".to_string(), json!("grpc"));
-            if let Some(service_name) = opts.and_then(|opts| map_get_str(opts, "grpc-service-name"))
-            {
-                transport.insert("service_name".to_string(), json!(service_name));
-            }
-            Ok(Some(JsonValue::Object(transport)))
+            Ok(Some(Transport {
+                service_name: opts
+                    .and_then(|opts| map_get_str(opts, "grpc-service-name"))
+                    .map(str::to_string),
+                ..Transport::new("grpc")
+            }))
         }
+        "quic" | "h3" => Ok(Some(Transport::new("quic"))),
         "http" | "h2" => {
             let opts_key = if network == "h2" {
                 "h2-opts"
@@ -412,42 +567,63 @@ fn build_v2ray_transport(node: &Value) -> Result<Option<JsonValue>, String> {
                 "http-opts"
             };
             let opts = node.get(opts_key).and_then(|value| value.as_mapping());
-            let mut transport = Map::new();
-            transport.insert("type".to_string(), json!("http"));
-            if let Some(method) = opts.and_then(|opts| map_get_str(opts, "method")) {
-                transport.insert("method".to_string(), json!(method));
-            }
-            if let Some(path) = opts
-                .and_then(|opts| map_get_value(opts, "path"))
-                .and_then(first_string)
-            {
-                transport.insert("path".to_string(), json!(path));
-            }
-            if let Some(hosts) = opts
-                .and_then(|opts| map_get_value(opts, "host"))
-                .map(string_list)
-                .filter(|hosts| !hosts.is_empty())
-            {
-                transport.insert("host".to_string(), json!(hosts));
+            let transport = Transport {
+                method: opts
+                    .and_then(|opts| map_get_str(opts, "method"))
+                    .map(str::to_string),
+                path: opts
+                    .and_then(|opts| map_get_value(opts, "path"))
+                    .and_then(first_string),
+                host: opts
+                    .and_then(|opts| map_get_value(opts, "host"))
+                    .map(string_list)
+                    .unwrap_or_default(),
+                headers: opts
+                    .and_then(|opts| map_get_value(opts, "headers"))
+                    .and_then(yaml_to_json),
+                ..Transport::new("http")
+            };
+            if !is_h3_only(node) {
+                return Ok(Some(transport));
             }
-            if let Some(headers) = opts
-                .and_then(|opts| map_get_value(opts, "headers"))
-                .and_then(yaml_to_json)
-            {
-                transport.insert("headers".to_string(), headers);
+            // sing-box 的 http 传输层不支持 HTTP/3，只能用没有任何选项的 QUIC 传输层表示
+            if transport != Transport::new("http") {
+                return Err(format!(
+                    "unsupported HTTP/3 transport with {} options; sing-box QUIC transport has no path/host/headers",
+                    opts_key
+                ));
             }
-            Ok(Some(JsonValue::Object(transport)))
+            Ok(Some(Transport::new("quic")))
         }
         "xhttp" => Err("unsupported transport network 'xhttp'".to_string()),
         other => Err(format!("unsupported transport network '{}'", other)),
     }
 }
 
+/// vmess/vless/trojan 共用：传输层是否可用取决于 TLS 设置
+fn insert_tls_and_transport(
+    obj: &mut Map<String, JsonValue>,
+    node: &Value,
+    tls: Option<JsonValue>,
+) -> Result<(), String> {
+    let transport = build_v2ray_transport(node)?;
+    if let Some(transport) = &transport {
+        transport.check_tls(tls.as_ref())?;
+    }
+    if let Some(tls) = tls {
+        obj.insert("tls".to_string(), tls);
+    }
+    if let Some(transport) = transport {
+        obj.insert("transport".to_string(), json!(transport));
+    }
+    Ok(())
+}
+
 fn parse_single_node(node: &Value) -> Result<(String, JsonValue), String> {
     let typ = get_required_str(node, "type")?.to_ascii_lowercase();
     let name = get_required_str(node, "name")?;
 
-    let server = get_required_str(node, "server")?;
+    let server = normalize_server(get_required_str(node, "server")?);
     let port = get_port(node)?;
 
     let outbound = match typ.as_str() {
@@ -459,6 +635,11 @@ fn parse_single_node(node: &Value) -> Result<(String, JsonValue), String> {
             if let Some(obfs) = parse_hysteria2_obfs(node)? {
                 obj.insert("obfs".to_string(), obfs);
             }
+            for (key, field) in [("up", "up_mbps"), ("down", "down_mbps")] {
+                if let Some(mbps) = parse_bandwidth_mbps(node, key)? {
+                    obj.insert(field.to_string(), json!(mbps));
+                }
+            }
             JsonValue::Object(obj)
         }
         "anytls" => {
@@ -492,12 +673,7 @@ fn parse_single_node(node: &Value) -> Result<(String, JsonValue), String> {
                 validate_packet_encoding(packet_encoding)?;
                 obj.insert("packet_encoding".to_string(), json!(packet_encoding));
             }
-            if let Some(tls) = build_tls(node, false)? {
-                obj.insert("tls".to_string(), tls);
-            }
-            if let Some(transport) = build_v2ray_transport(node)? {
-                obj.insert("transport".to_string(), transport);
-            }
+            insert_tls_and_transport(&mut obj, node, build_tls(node, false)?)?;
             JsonValue::Object(obj)
         }
         "vless" => {
@@ -521,12 +697,7 @@ fn parse_single_node(node: &Value) -> Result<(String, JsonValue), String> {
                 validate_packet_encoding(packet_encoding)?;
                 obj.insert("packet_encoding".to_string(), json!(packet_encoding));
             }
-            if let Some(tls) = build_tls(node, false)? {
-                obj.insert("tls".to_string(), tls);
-            }
-            if let Some(transport) = build_v2ray_transport(node)? {
-                obj.insert("transport".to_string(), transport);
-            }
+            insert_tls_and_transport(&mut obj, node, build_tls(node, false)?)?;
             JsonValue::Object(obj)
         }
         "trojan" => {
@@ -541,12 +712,7 @@ fn parse_single_node(node: &Value) -> Result<(String, JsonValue), String> {
             let password = get_required_str(node, "password")?;
             let mut obj = base_outbound("trojan", name, server, port);
             obj.insert("password".to_string(), json!(password));
-            if let Some(tls) = build_tls(node, true)? {
-                obj.insert("tls".to_string(), tls);
-            }
-            if let Some(transport) = build_v2ray_transport(node)? {
-                obj.insert("transport".to_string(), transport);
-            }
+            insert_tls_and_transport(&mut obj, node, build_tls(node, true)?)?;
             JsonValue::Object(obj)
         }
         "tuic" => {
@@ -637,6 +803,56 @@ pub fn parse_node_json(node_str: &str) -> Result<(NodeDisplayInfo, serde_json::V
     Ok((info, v))
 }
 
+/// sing-box 内置的非代理 outbound 类型，出现在节点列表中时跳过
+const NON_PROXY_OUTBOUND_TYPES: &[&str] = &["direct", "block", "dns", "selector", "urltest"];
+
+/// 解析 sing-box outbound JSON 数组（也接受带 outbounds 字段的完整配置），
+/// 每个 outbound 按手动节点的规则校验，无效项记录到 errors
+pub fn parse_singbox_outbounds(json: &str) -> AppResult<ParseResult> {
+    let value: JsonValue = serde_json::from_str(json)
+        .map_err(|e| AppError::context("Failed to parse sing-box outbound JSON", e))?;
+    let outbounds = match value {
+        JsonValue::Array(items) => items,
+        JsonValue::Object(mut obj) => match obj.remove("outbounds") {
+            Some(JsonValue::Array(items)) => items,
+            _ => {
+                return Err(AppError::message(
+                    "sing-box JSON must be an outbound array or contain an outbounds array",
+                ))
+            }
+        },
+        _ => {
+            return Err(AppError::message(
+                "sing-box JSON must be an outbound array or contain an outbounds array",
+            ))
+        }
+    };
+
+    let mut result = ParseResult {
+        nodes: vec![],
+        errors: vec![],
+        total_count: outbounds.len(),
+        dropped: BTreeMap::new(),
+        providers: vec![],
+    };
+    for (idx, outbound) in outbounds.into_iter().enumerate() {
+        let node_type = outbound["type"].as_str().unwrap_or("unknown");
+        if NON_PROXY_OUTBOUND_TYPES.contains(&node_type) {
+            *result
+                .dropped
+                .entry(format!("non-proxy type '{}'", node_type))
+                .or_default() += 1;
+            continue;
+        }
+
+        match parse_node_json(&outbound.to_string()) {
+            Ok((info, outbound)) => result.nodes.push((info.tag, outbound)),
+            Err(e) => result.errors.push(format!("Outbound #{}: {}", idx + 1, e)),
+        }
+    }
+    Ok(result)
+}
+
 /// 节点显示信息结构
 #[derive(Debug, Clone)]
 pub struct NodeDisplayInfo {
@@ -661,6 +877,74 @@ impl std::fmt::Display for NodeDisplayInfo {
 mod tests {
     use super::*;
 
+    #[test]
+    fn parse_clash_proxies_tallies_dropped_node_types() {
+        let yaml = r#"
+proxies:
+  - name: ssr-a
+    type: ssr
+    server: ssr.example.com
+    port: 443
+  - name: ssr-b
+    type: ShadowsocksR
+    server: ssr.example.com
+    port: 444
+  - name: wg
+    type: wireguard
+    server: wg.example.com
+    port: 51820
+  - name: ss-node
+    type: ss
+    server: ss.example.com
+    port: 8388
+    cipher: aes-128-gcm
+    password: pass-ss
+"#;
+
+        let result = parse_clash_proxies(yaml).unwrap();
+
+        assert_eq!(result.nodes.len(), 1);
+        assert!(result.errors.is_empty());
+        assert_eq!(result.dropped.get(SSR_DROP_REASON), Some(&2));
+        assert_eq!(result.dropped.get("unsupported type 'wireguard'"), Some(&1));
+    }
+
+    #[test]
+    fn parse_clash_proxies_with_copies_passthrough_keys() {
+        let yaml = r#"
+proxies:
+  - name: hy2-node
+    type: hysteria2
+    server: hy.example.com
+    port: 443
+    password: pass-hy
+    tcp_fast_open: true
+    udp_over_stream: {enabled: true}
+    unlisted: dropped
+"#;
+        let options = ParseOptions {
+            passthrough_keys: vec![
+                "tcp_fast_open".to_string(),
+                "udp_over_stream".to_string(),
+                "password".to_string(),
+                "port".to_string(),
+                "missing".to_string(),
+            ],
+            ..Default::default()
+        };
+
+        let result = parse_clash_proxies_with(yaml, &options).unwrap();
+
+        let outbound = &result.nodes[0].1;
+        assert_eq!(outbound["tcp_fast_open"], true);
+        assert_eq!(outbound["udp_over_stream"]["enabled"], true);
+        assert_eq!(outbound["password"], "pass-hy");
+        assert_eq!(outbound["server_port"], 443);
+        assert!(outbound.get("port").is_none());
+        assert!(outbound.get("missing").is_none());
+        assert!(outbound.get("unlisted").is_none());
+    }
+
     #[test]
     fn parse_clash_proxies_extracts_valid_nodes() {
         let yaml = r#"
@@ -862,6 +1146,35 @@ proxies:
         assert_eq!(info.server, "::1");
     }
 
+    #[test]
+    fn parse_clash_proxies_strips_ipv6_brackets_and_ip_sni() {
+        let yaml = r#"
+proxies:
+  - name: hy2-v6
+    type: hysteria2
+    server: "[2001:db8::1]"
+    port: 443
+    password: pass
+    sni: "[2001:db8::1]"
+  - name: hy2-v6-bare
+    type: hysteria2
+    server: "2001:db8::2"
+    port: 443
+    password: pass
+    sni: hy.example.com
+"#;
+
+        let result = parse_clash_proxies(yaml).unwrap();
+
+        assert!(result.errors.is_empty());
+        let bracketed = &result.nodes[0].1;
+        assert_eq!(bracketed["server"], "2001:db8::1");
+        assert!(bracketed["tls"].get("server_name").is_none());
+        let bare = &result.nodes[1].1;
+        assert_eq!(bare["server"], "2001:db8::2");
+        assert_eq!(bare["tls"]["server_name"], "hy.example.com");
+    }
+
     #[test]
     fn parse_clash_proxies_handles_all_supported_types() {
         let yaml = r#"
@@ -999,6 +1312,87 @@ proxies:
         assert_eq!(tuic["tls"]["disable_sni"], true);
     }
 
+    #[test]
+    fn parse_clash_proxies_maps_alpn_and_fingerprint_for_quic_protocols() {
+        let yaml = r#"
+proxies:
+  - name: hy2
+    type: hysteria2
+    server: hy.example.com
+    port: 443
+    password: secret
+    alpn: [h3]
+    client-fingerprint: Firefox
+  - name: tuic
+    type: tuic
+    server: tuic.example.com
+    port: 443
+    uuid: 323e4567-e89b-12d3-a456-426614174000
+    password: tuic-pass
+    alpn: h3
+    client-fingerprint: chrome
+"#;
+
+        let result = parse_clash_proxies(yaml).unwrap();
+
+        assert!(result.errors.is_empty());
+        for (outbound, fingerprint) in result
+            .nodes
+            .iter()
+            .map(|(_, o)| o)
+            .zip(["firefox", "chrome"])
+        {
+            assert_eq!(outbound["tls"]["alpn"], json!(["h3"]));
+            assert_eq!(outbound["tls"]["utls"]["enabled"], true);
+            assert_eq!(outbound["tls"]["utls"]["fingerprint"], fingerprint);
+        }
+    }
+
+    #[test]
+    fn parse_clash_proxies_maps_quic_and_http3_transports() {
+        let yaml = r#"
+proxies:
+  - name: vless-quic
+    type: vless
+    server: vl.example.com
+    port: 443
+    uuid: 123e4567-e89b-12d3-a456-426614174000
+    tls: true
+    network: quic
+  - name: trojan-h3
+    type: trojan
+    server: tr.example.com
+    port: 443
+    password: trojan-pass
+    alpn: [h3]
+    network: http
+  - name: vmess-quic-plain
+    type: vmess
+    server: vm.example.com
+    port: 443
+    uuid: 223e4567-e89b-12d3-a456-426614174000
+    network: quic
+  - name: trojan-h3-path
+    type: trojan
+    server: tr2.example.com
+    port: 443
+    password: trojan-pass
+    alpn: [h3]
+    network: http
+    http-opts:
+      path: [/h3]
+"#;
+
+        let result = parse_clash_proxies(yaml).unwrap();
+
+        assert_eq!(result.nodes.len(), 2);
+        assert_eq!(result.nodes[0].1["transport"], json!({"type": "quic"}));
+        assert_eq!(result.nodes[1].1["transport"], json!({"type": "quic"}));
+        assert_eq!(result.errors.len(), 2);
+        assert!(result.errors[0].contains("QUIC transport requires TLS"));
+        assert!(result.errors[1].contains("unsupported HTTP/3 transport"));
+    }
+
     #[test]
     fn parse_clash_proxies_reports_unsupported_extended_variants() {
         let yaml = r#"
@@ -1145,6 +1539,42 @@ proxies:
         assert_eq!(outbound["obfs"]["password"], "obfs-pass");
     }
 
+    #[test]
+    fn parse_clash_proxies_maps_hysteria2_bandwidth() {
+        let yaml = r#"
+proxies:
+  - name: hy2-units
+    type: hysteria2
+    server: hy.example.com
+    port: 443
+    password: pass
+    up: "30 Mbps"
+    down: "1 Gbps"
+  - name: hy2-plain
+    type: hysteria2
+    server: hy.example.com
+    port: 443
+    password: pass
+    up: 20
+  - name: hy2-bad
+    type: hysteria2
+    server: hy.example.com
+    port: 443
+    password: pass
+    down: fast
+"#;
+
+        let result = parse_clash_proxies(yaml).unwrap();
+
+        assert_eq!(result.nodes.len(), 2);
+        assert_eq!(result.nodes[0].1["up_mbps"], 30);
+        assert_eq!(result.nodes[0].1["down_mbps"], 1000);
+        assert_eq!(result.nodes[1].1["up_mbps"], 20);
+        assert!(result.nodes[1].1.get("down_mbps").is_none());
+        assert_eq!(result.errors.len(), 1);
+        assert!(result.errors[0].contains("invalid Hysteria2 bandwidth 'down'"));
+    }
+
     #[test]
     fn parse_clash_proxies_maps_hysteria2_gecko_obfs() {
         let yaml = r#"
@@ -1,14 +1,19 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 use tokio::time::{sleep, Duration};
 use tracing::{error, info, warn};
 
-use crate::error::AppResult;
-use crate::models::LastProxy;
+use crate::error::{AppError, AppResult};
+use crate::models::{LastProxy, PersistedState};
+use crate::services::config::{
+    clash_api_url, load_generated_config, node_outbounds, write_file_atomic,
+};
 use crate::services::singbox::get_sing_box_home;
 use crate::state::AppState;
 
+const STATE_FILENAME: &str = "state.json";
+
 fn is_openwrt_system() -> bool {
     std::path::Path::new("/etc/openwrt_release").exists()
 }
@@ -21,27 +26,142 @@ fn get_last_proxy_path_for(openwrt: bool) -> PathBuf {
     }
 }
 
+/// 旧版本保存选择节点的位置，仅在 state.json 中没有记录时读取
 fn get_last_proxy_path() -> PathBuf {
     get_last_proxy_path_for(is_openwrt_system())
 }
 
-pub async fn save_last_proxy(proxy: &LastProxy) -> AppResult<()> {
-    let json = serde_json::to_string(proxy)?;
-    tokio::fs::write(get_last_proxy_path(), json).await?;
-    Ok(())
+/// state.json 与 config.yaml 放在同一目录，不像 sing-box 目录那样位于 /tmp，重启后仍然保留
+fn state_file_path(config_path: &Path) -> PathBuf {
+    config_path
+        .parent()
+        .map(|dir| dir.join(STATE_FILENAME))
+        .unwrap_or_else(|| PathBuf::from(STATE_FILENAME))
 }
 
-async fn load_last_proxy() -> Option<LastProxy> {
-    let path = get_last_proxy_path();
-    if let Ok(content) = tokio::fs::read_to_string(&path).await {
-        serde_json::from_str(&content).ok()
-    } else {
-        None
+async fn load_persisted_state(path: &Path) -> PersistedState {
+    match tokio::fs::read_to_string(path).await {
+        Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+            warn!("Ignoring unreadable state file {}: {}", path.display(), e);
+            PersistedState::default()
+        }),
+        Err(_) => PersistedState::default(),
+    }
+}
+
+async fn save_last_proxy_to(path: &Path, proxy: &LastProxy) -> AppResult<()> {
+    let mut persisted = load_persisted_state(path).await;
+    persisted.last_proxy = Some(proxy.clone());
+    let json = serde_json::to_string_pretty(&persisted)?;
+    write_file_atomic(path, &json).await
+}
+
+/// 节点的稳定标识：协议、服务器与端口，不随订阅中的显示名称变化
+pub(crate) fn node_identity(outbound: &serde_json::Value) -> Option<String> {
+    let protocol = outbound["type"].as_str()?;
+    let server = outbound["server"].as_str()?;
+    let port = outbound["server_port"].as_u64()?;
+    Some(format!("{protocol}://{server}:{port}"))
+}
+
+/// 在生成的配置中查找与 identity 相同的节点，返回它当前的 tag
+fn tag_for_identity(sing_box_config: &serde_json::Value, identity: &str) -> Option<String> {
+    node_outbounds(sing_box_config)
+        .into_iter()
+        .find(|outbound| node_identity(outbound).as_deref() == Some(identity))
+        .and_then(|outbound| outbound["tag"].as_str().map(str::to_string))
+}
+
+fn identity_for_tag(sing_box_config: &serde_json::Value, tag: &str) -> Option<String> {
+    node_outbounds(sing_box_config)
+        .into_iter()
+        .find(|outbound| outbound["tag"] == tag)
+        .and_then(|outbound| node_identity(&outbound))
+}
+
+/// 决定恢复哪个节点：启用稳定标识且记录了标识时优先按标识查找，
+/// 避免订阅重新编号后同名节点指向了另一台服务器；找不到时再按名称
+fn restore_target(
+    proxy: &LastProxy,
+    sing_box_config: &serde_json::Value,
+    available: &[serde_json::Value],
+    stable_node_identity: bool,
+) -> Option<String> {
+    let is_available = |tag: &str| available.iter().any(|n| n.as_str() == Some(tag));
+    let by_identity = proxy
+        .identity
+        .as_deref()
+        .filter(|_| stable_node_identity)
+        .and_then(|identity| tag_for_identity(sing_box_config, identity))
+        .filter(|tag| is_available(tag));
+    by_identity.or_else(|| is_available(&proxy.name).then(|| proxy.name.clone()))
+}
+
+async fn generated_config(state: &AppState) -> serde_json::Value {
+    load_generated_config(&state.sing_box_paths)
+        .await
+        .ok()
+        .flatten()
+        .unwrap_or_default()
+}
+
+pub async fn save_last_proxy(state: &AppState, proxy: &LastProxy) -> AppResult<()> {
+    let mut proxy = proxy.clone();
+    if state.config.read().await.stable_node_identity {
+        proxy.identity =
+            identity_for_tag(&generated_config(state).await, &proxy.name).or(proxy.identity);
+    }
+    save_last_proxy_to(&state_file_path(&state.config_path), &proxy).await
+}
+
+pub(crate) async fn load_last_proxy(state: &AppState) -> Option<LastProxy> {
+    if let Some(proxy) = load_persisted_state(&state_file_path(&state.config_path))
+        .await
+        .last_proxy
+    {
+        return Some(proxy);
+    }
+    let content = tokio::fs::read_to_string(get_last_proxy_path())
+        .await
+        .ok()?;
+    serde_json::from_str(&content).ok()
+}
+
+fn group_url(group: &str) -> String {
+    clash_api_url(&format!("/proxies/{}", urlencoding::encode(group)))
+}
+
+pub(crate) async fn put_selection(client: &reqwest::Client, proxy: &LastProxy) -> AppResult<()> {
+    let res = client
+        .put(group_url(&proxy.group))
+        .timeout(Duration::from_secs(5))
+        .json(&serde_json::json!({ "name": proxy.name }))
+        .send()
+        .await
+        .map_err(|e| AppError::context("Failed to reach clash API", e))?;
+    if !res.status().is_success() {
+        let status = res.status();
+        let details = res.text().await.unwrap_or_default();
+        return Err(AppError::message(format!(
+            "clash API rejected selection ({}): {}",
+            status,
+            details.trim()
+        )));
     }
+    Ok(())
+}
+
+/// 通过 clash API 切换分组中的节点，成功后写入 state.json
+pub async fn select_proxy(state: &AppState, proxy: &LastProxy) -> AppResult<()> {
+    put_selection(&state.http_client, proxy).await?;
+    save_last_proxy(state, proxy).await
 }
 
 pub async fn restore_last_proxy(state: &Arc<AppState>) {
-    let proxy = match load_last_proxy().await {
+    if !state.config.read().await.enable_clash_api {
+        return;
+    }
+    let mut proxy = match load_last_proxy(state).await {
         Some(p) => p,
         None => return,
     };
@@ -53,13 +173,9 @@ pub async fn restore_last_proxy(state: &Arc<AppState>) {
 
     sleep(Duration::from_secs(1)).await;
 
-    let url = format!(
-        "http://127.0.0.1:6262/proxies/{}",
-        urlencoding::encode(&proxy.group)
-    );
     let group_info = match state
         .http_client
-        .get(&url)
+        .get(group_url(&proxy.group))
         .timeout(Duration::from_secs(5))
         .send()
         .await
@@ -73,41 +189,48 @@ pub async fn restore_last_proxy(state: &Arc<AppState>) {
 
     let all_nodes = group_info.get("all").and_then(|v| v.as_array());
     if let Some(nodes) = all_nodes {
-        let node_exists = nodes.iter().any(|n| n.as_str() == Some(&proxy.name));
-        if !node_exists {
+        let stable_node_identity = state.config.read().await.stable_node_identity;
+        let generated = if stable_node_identity && proxy.identity.is_some() {
+            generated_config(state).await
+        } else {
+            serde_json::Value::Null
+        };
+        let Some(target) = restore_target(&proxy, &generated, nodes, stable_node_identity) else {
             warn!(
                 "Last proxy '{}' not found in current node list, skipping restore",
                 proxy.name
             );
             return;
+        };
+        if target != proxy.name {
+            info!(
+                from = %proxy.name,
+                to = %target,
+                "Last proxy was renamed, restoring by stable identity"
+            );
+            proxy.name = target;
+            if let Err(e) = save_last_proxy_to(&state_file_path(&state.config_path), &proxy).await {
+                warn!("Failed to update renamed last proxy: {}", e);
+            }
         }
     } else {
         return;
     }
 
-    match state
-        .http_client
-        .put(&url)
-        .timeout(Duration::from_secs(5))
-        .json(&serde_json::json!({ "name": proxy.name }))
-        .send()
-        .await
-    {
-        Ok(res) if res.status().is_success() => {
-            info!("Successfully restored last proxy: {}", proxy.name);
-        }
-        Ok(res) => {
-            warn!("Failed to restore last proxy: status {}", res.status());
-        }
-        Err(e) => {
-            error!("Failed to restore last proxy: {}", e);
-        }
+    match put_selection(&state.http_client, &proxy).await {
+        Ok(()) => info!("Successfully restored last proxy: {}", proxy.name),
+        Err(e) => error!("Failed to restore last proxy: {}", e),
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{get_last_proxy_path_for, get_sing_box_home};
+    use super::{
+        get_last_proxy_path_for, get_sing_box_home, identity_for_tag, load_persisted_state,
+        restore_target, save_last_proxy_to, state_file_path, tag_for_identity,
+    };
+    use crate::models::LastProxy;
+    use std::path::{Path, PathBuf};
 
     #[test]
     fn last_proxy_path_uses_tmp_on_openwrt() {
@@ -124,4 +247,86 @@ mod tests {
             std::path::PathBuf::from(".last_proxy")
         );
     }
+
+    #[test]
+    fn state_file_lives_next_to_config() {
+        assert_eq!(
+            state_file_path(Path::new("/etc/miao/config.yaml")),
+            PathBuf::from("/etc/miao/state.json")
+        );
+        assert_eq!(
+            state_file_path(Path::new("config.yaml")),
+            PathBuf::from("state.json")
+        );
+    }
+
+    #[test]
+    fn stable_identity_finds_renamed_node() {
+        let before = serde_json::json!({"outbounds": [
+            {"type": "selector", "tag": "proxy", "outbounds": ["JP 01"]},
+            {"type": "vless", "tag": "JP 01", "server": "jp.example.com", "server_port": 443}
+        ]});
+        let after = serde_json::json!({"outbounds": [
+            {"type": "selector", "tag": "proxy", "outbounds": ["JP 01", "JP 02"]},
+            {"type": "vless", "tag": "JP 01", "server": "jp-new.example.com", "server_port": 443},
+            {"type": "vless", "tag": "JP 02", "server": "jp.example.com", "server_port": 443}
+        ]});
+
+        let identity = identity_for_tag(&before, "JP 01").unwrap();
+
+        assert_eq!(identity, "vless://jp.example.com:443");
+        assert_eq!(
+            tag_for_identity(&after, &identity).as_deref(),
+            Some("JP 02")
+        );
+    }
+
+    #[test]
+    fn restore_prefers_identity_over_reused_name() {
+        let generated = serde_json::json!({"outbounds": [
+            {"type": "selector", "tag": "proxy", "outbounds": ["JP 01", "JP 02"]},
+            {"type": "vless", "tag": "JP 01", "server": "jp-new.example.com", "server_port": 443},
+            {"type": "vless", "tag": "JP 02", "server": "jp.example.com", "server_port": 443}
+        ]});
+        let available = [serde_json::json!("JP 01"), serde_json::json!("JP 02")];
+        let proxy = LastProxy {
+            group: "proxy".to_string(),
+            name: "JP 01".to_string(),
+            identity: Some("vless://jp.example.com:443".to_string()),
+        };
+
+        assert_eq!(
+            restore_target(&proxy, &generated, &available, true).as_deref(),
+            Some("JP 02")
+        );
+        assert_eq!(
+            restore_target(&proxy, &generated, &available, false).as_deref(),
+            Some("JP 01")
+        );
+        let gone = LastProxy {
+            identity: Some("vless://gone.example.com:443".to_string()),
+            ..proxy
+        };
+        assert_eq!(
+            restore_target(&gone, &generated, &available, true).as_deref(),
+            Some("JP 01")
+        );
+    }
+
+    #[tokio::test]
+    async fn save_last_proxy_round_trips_through_state_file() {
+        let dir = std::env::temp_dir().join(format!("miao-state-{}", std::process::id()));
+        let path = dir.join("state.json");
+        let proxy = LastProxy {
+            group: "proxy".to_string(),
+            name: "HK 01".to_string(),
+            identity: Some("hysteria2://hk.example.com:443".to_string()),
+        };
+
+        save_last_proxy_to(&path, &proxy).await.unwrap();
+        let persisted = load_persisted_state(&path).await;
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(persisted.last_proxy, Some(proxy));
+    }
 }
@@ -0,0 +1,192 @@
+use regex::Regex;
+use std::collections::HashMap;
+use std::sync::LazyLock;
+use tracing::warn;
+
+/// 地区代码与节点名称匹配规则。英文缩写两侧不能紧挨字母，避免 "RUSSIA" 命中 US
+static REGION_PATTERNS: LazyLock<Vec<(&'static str, Regex)>> = LazyLock::new(|| {
+    [
+        (
+            "HK",
+            r"香港|🇭🇰|Hong\s*Kong|(?:^|[^A-Za-z])HK(?:[^A-Za-z]|$)",
+        ),
+        (
+            "TW",
+            r"台湾|台灣|🇹🇼|Taiwan|(?:^|[^A-Za-z])TW(?:[^A-Za-z]|$)",
+        ),
+        (
+            "JP",
+            r"日本|东京|大阪|🇯🇵|Japan|Tokyo|(?:^|[^A-Za-z])JP(?:[^A-Za-z]|$)",
+        ),
+        (
+            "SG",
+            r"新加坡|狮城|🇸🇬|Singapore|(?:^|[^A-Za-z])SG(?:[^A-Za-z]|$)",
+        ),
+        (
+            "KR",
+            r"韩国|首尔|🇰🇷|Korea|Seoul|(?:^|[^A-Za-z])KR(?:[^A-Za-z]|$)",
+        ),
+        (
+            "US",
+            r"美国|洛杉矶|硅谷|🇺🇸|United\s*States|America|(?:^|[^A-Za-z])US(?:[^A-Za-z]|$)",
+        ),
+        (
+            "UK",
+            r"英国|伦敦|🇬🇧|United\s*Kingdom|London|(?:^|[^A-Za-z])UK(?:[^A-Za-z]|$)",
+        ),
+        (
+            "DE",
+            r"德国|🇩🇪|Germany|Frankfurt|(?:^|[^A-Za-z])DE(?:[^A-Za-z]|$)",
+        ),
+    ]
+    .into_iter()
+    .map(|(region, pattern)| (region, Regex::new(&format!("(?i){pattern}")).unwrap()))
+    .collect()
+});
+
+/// 支持识别的地区代码
+pub fn known_regions() -> impl Iterator<Item = &'static str> {
+    REGION_PATTERNS.iter().map(|(region, _)| *region)
+}
+
+/// 根据节点名称识别地区，无法识别时返回 None
+pub fn detect_region(tag: &str) -> Option<&'static str> {
+    REGION_PATTERNS
+        .iter()
+        .find(|(_, regex)| regex.is_match(tag))
+        .map(|(region, _)| *region)
+}
+
+pub fn region_group_tag(region: &str) -> String {
+    format!("Region {region}")
+}
+
+/// 按地区为节点生成 urltest 分组，并把分组插到 proxy selector 最前面。
+/// 分组顺序按 `region_latency`（地区 → 中位延迟）从低到高，未测速的地区排在后面并保持出现顺序
+pub fn apply_region_groups(
+    sing_box_config: &mut serde_json::Value,
+    region_latency: &HashMap<String, u64>,
+) {
+    let Some(outbounds) = sing_box_config["outbounds"].as_array_mut() else {
+        return;
+    };
+    let Some(selector_idx) = outbounds
+        .iter()
+        .position(|outbound| outbound["type"] == "selector" && outbound["tag"] == "proxy")
+    else {
+        return;
+    };
+
+    let mut regions: Vec<(&'static str, Vec<String>)> = Vec::new();
+    for member in outbounds[selector_idx]["outbounds"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|member| member.as_str())
+    {
+        let Some(region) = detect_region(member) else {
+            continue;
+        };
+        match regions.iter_mut().find(|(existing, _)| *existing == region) {
+            Some((_, members)) => members.push(member.to_string()),
+            None => regions.push((region, vec![member.to_string()])),
+        }
+    }
+
+    regions.retain(|(region, _)| {
+        let group_tag = region_group_tag(region);
+        let collides = outbounds
+            .iter()
+            .any(|outbound| outbound["tag"] == group_tag);
+        if collides {
+            warn!(tag = %group_tag, "Outbound tag collides with region group, skipping group");
+        }
+        !collides
+    });
+    // sort_by_key 是稳定排序，未测速的地区保持原顺序
+    regions.sort_by_key(|(region, _)| region_latency.get(*region).copied().unwrap_or(u64::MAX));
+    if regions.is_empty() {
+        return;
+    }
+
+    let group_tags: Vec<serde_json::Value> = regions
+        .iter()
+        .map(|(region, _)| serde_json::json!(region_group_tag(region)))
+        .collect();
+    if let Some(members) = outbounds[selector_idx]["outbounds"].as_array_mut() {
+        members.splice(0..0, group_tags.iter().cloned());
+    }
+    if regions
+        .first()
+        .is_some_and(|(region, _)| region_latency.contains_key(*region))
+    {
+        outbounds[selector_idx]["default"] = group_tags[0].clone();
+    }
+
+    outbounds.extend(regions.into_iter().map(|(region, members)| {
+        serde_json::json!({
+            "type": "urltest",
+            "tag": region_group_tag(region),
+            "outbounds": members
+        })
+    }));
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use std::collections::HashMap;
+
+    use super::{apply_region_groups, detect_region};
+
+    #[test]
+    fn detect_region_matches_names_and_avoids_substrings() {
+        assert_eq!(detect_region("🇭🇰 香港 01"), Some("HK"));
+        assert_eq!(detect_region("HK-IPLC-02"), Some("HK"));
+        assert_eq!(detect_region("Tokyo premium"), Some("JP"));
+        assert_eq!(detect_region("sg 3x"), Some("SG"));
+        assert_eq!(detect_region("RUSSIA 01"), None);
+        assert_eq!(detect_region("manual-node"), None);
+    }
+
+    #[test]
+    fn apply_region_groups_orders_groups_by_latency() {
+        let mut config = json!({
+            "outbounds": [
+                {"type": "selector", "tag": "proxy", "outbounds": ["JP 01", "HK 01", "Other", "JP 02"]},
+                {"type": "direct", "tag": "direct"}
+            ]
+        });
+        let latency = HashMap::from([("HK".to_string(), 40), ("JP".to_string(), 80)]);
+
+        apply_region_groups(&mut config, &latency);
+
+        let outbounds = config["outbounds"].as_array().unwrap();
+        assert_eq!(
+            outbounds[0]["outbounds"],
+            json!(["Region HK", "Region JP", "JP 01", "HK 01", "Other", "JP 02"])
+        );
+        assert_eq!(outbounds[0]["default"], "Region HK");
+        assert_eq!(
+            outbounds[3],
+            json!({"type": "urltest", "tag": "Region JP", "outbounds": ["JP 01", "JP 02"]})
+        );
+    }
+
+    #[test]
+    fn apply_region_groups_keeps_first_seen_order_without_latency() {
+        let mut config = json!({
+            "outbounds": [
+                {"type": "selector", "tag": "proxy", "outbounds": ["JP 01", "HK 01"]}
+            ]
+        });
+
+        apply_region_groups(&mut config, &HashMap::new());
+
+        assert_eq!(
+            config["outbounds"][0]["outbounds"],
+            json!(["Region JP", "Region HK", "JP 01", "HK 01"])
+        );
+        assert!(config["outbounds"][0].get("default").is_none());
+    }
+}
@@ -0,0 +1,91 @@
+use futures::{stream, StreamExt};
+use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
+use tokio::time::Duration;
+use tracing::{info, warn};
+
+const LOOKUP_TIMEOUT: Duration = Duration::from_secs(3);
+const MAX_CONCURRENT_LOOKUPS: usize = 16;
+
+/// 需要解析的主机名；IP 字面量和没有 server 字段的 outbound 返回 None
+fn lookup_host_name(outbound: &serde_json::Value) -> Option<&str> {
+    let server = outbound["server"].as_str()?;
+    server.parse::<IpAddr>().is_err().then_some(server)
+}
+
+async fn resolves(host: &str) -> bool {
+    // lookup_host 需要端口，解析结果与端口无关
+    match tokio::time::timeout(LOOKUP_TIMEOUT, tokio::net::lookup_host((host, 0))).await {
+        Ok(Ok(mut addrs)) => addrs.next().is_some(),
+        _ => false,
+    }
+}
+
+/// 并发解析各节点的 server，去掉主机名无法解析的节点；同一主机名只解析一次。
+/// 返回保留的节点名与 outbound（顺序不变）以及被去掉的数量
+pub async fn drop_unresolvable_nodes(
+    node_names: Vec<String>,
+    outbounds: Vec<serde_json::Value>,
+) -> (Vec<String>, Vec<serde_json::Value>, usize) {
+    let hosts: HashSet<String> = outbounds
+        .iter()
+        .filter_map(lookup_host_name)
+        .map(str::to_string)
+        .collect();
+    let resolved: HashMap<String, bool> = stream::iter(hosts)
+        .map(|host| async move {
+            let ok = resolves(&host).await;
+            (host, ok)
+        })
+        .buffer_unordered(MAX_CONCURRENT_LOOKUPS)
+        .collect()
+        .await;
+
+    let mut kept_names = Vec::with_capacity(node_names.len());
+    let mut kept_outbounds = Vec::with_capacity(outbounds.len());
+    let mut dropped = 0;
+    for (name, outbound) in node_names.into_iter().zip(outbounds) {
+        let unresolvable = lookup_host_name(&outbound)
+            .is_some_and(|host| !resolved.get(host).copied().unwrap_or(true));
+        if unresolvable {
+            warn!(node = %name, server = %outbound["server"], "Dropping node whose server does not resolve");
+            dropped += 1;
+            continue;
+        }
+        kept_names.push(name);
+        kept_outbounds.push(outbound);
+    }
+    if dropped > 0 {
+        info!(dropped, "Pruned nodes with unresolvable server hostnames");
+    }
+    (kept_names, kept_outbounds, dropped)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::drop_unresolvable_nodes;
+
+    #[tokio::test]
+    async fn drop_unresolvable_nodes_keeps_ip_literals_and_resolvable_hosts() {
+        let names = vec![
+            "ip".to_string(),
+            "local".to_string(),
+            "dead".to_string(),
+            "v6".to_string(),
+        ];
+        let outbounds = vec![
+            json!({"tag": "ip", "server": "203.0.113.1", "server_port": 443}),
+            json!({"tag": "local", "server": "localhost", "server_port": 443}),
+            json!({"tag": "dead", "server": "stale-node.invalid", "server_port": 443}),
+            json!({"tag": "v6", "server": "2001:db8::1", "server_port": 443}),
+        ];
+
+        let (names, outbounds, dropped) = drop_unresolvable_nodes(names, outbounds).await;
+
+        assert_eq!(names, vec!["ip", "local", "v6"]);
+        assert_eq!(outbounds.len(), 3);
+        assert_eq!(dropped, 1);
+    }
+}
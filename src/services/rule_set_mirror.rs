@@ -0,0 +1,202 @@
+use std::sync::LazyLock;
+use std::time::Instant;
+
+use reqwest::header::RANGE;
+use tokio::time::Duration;
+use tracing::{info, warn};
+
+use crate::models::RuleSetRule;
+
+/// 需要走镜像的规则集地址前缀；raw.githubusercontent.com 在部分网络下经常被限速或屏蔽
+const GITHUB_RAW_ORIGIN: &str = "https://raw.githubusercontent.com";
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+/// 选中的镜像在这段时间内直接复用，不再逐个探测
+const MIRROR_CACHE_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// 最近一次选中的镜像
+struct ChosenMirror {
+    /// 选择时配置中的镜像列表，列表变化后重新探测
+    mirrors: Vec<String>,
+    mirror: String,
+    chosen_at: Instant,
+}
+
+static CHOSEN_MIRROR: LazyLock<tokio::sync::Mutex<Option<ChosenMirror>>> =
+    LazyLock::new(Default::default);
+
+/// 把 GitHub raw 地址的源替换为镜像，如 `https://raw.gitmirror.com`
+/// 或前缀式代理 `https://ghfast.top/https://raw.githubusercontent.com`
+fn mirror_url(url: &str, mirror: &str) -> Option<String> {
+    github_raw_path(url).map(|path| format!("{}{}", mirror.trim_end_matches('/'), path))
+}
+
+fn github_raw_path(url: &str) -> Option<&str> {
+    url.strip_prefix(GITHUB_RAW_ORIGIN)
+        .filter(|path| path.starts_with('/'))
+}
+
+/// 只请求第一个字节探测镜像是否可用，不下载整个规则集
+async fn is_reachable(client: &reqwest::Client, url: &str) -> bool {
+    client
+        .get(url)
+        .header(RANGE, "bytes=0-0")
+        .timeout(PROBE_TIMEOUT)
+        .send()
+        .await
+        .and_then(|res| res.error_for_status())
+        .is_ok()
+}
+
+/// sing-box 启动时代理尚未就绪，规则集只能直连下载。对 GitHub 托管的规则集按顺序尝试
+/// 各镜像，使用第一个可以访问的地址；镜像全部不可用时保留原地址。
+/// 选中的镜像缓存 MIRROR_CACHE_TTL，期间其余规则集和之后的配置生成直接使用
+pub async fn apply_rule_set_mirrors(
+    client: &reqwest::Client,
+    rule_sets: &[RuleSetRule],
+    mirrors: &[String],
+) -> Vec<RuleSetRule> {
+    let mut cache = CHOSEN_MIRROR.lock().await;
+    if cache.as_ref().is_some_and(|chosen| {
+        chosen.mirrors != mirrors || chosen.chosen_at.elapsed() >= MIRROR_CACHE_TTL
+    }) {
+        *cache = None;
+    }
+
+    let mut resolved = Vec::with_capacity(rule_sets.len());
+    for rule_set in rule_sets {
+        let mut rule_set = rule_set.clone();
+        if github_raw_path(&rule_set.url).is_none() || mirrors.is_empty() {
+            resolved.push(rule_set);
+            continue;
+        }
+        if let Some(url) = cache
+            .as_ref()
+            .and_then(|chosen| mirror_url(&rule_set.url, &chosen.mirror))
+        {
+            rule_set.url = url;
+            resolved.push(rule_set);
+            continue;
+        }
+
+        let mut chosen = None;
+        for mirror in mirrors {
+            let Some(candidate) = mirror_url(&rule_set.url, mirror) else {
+                continue;
+            };
+            if is_reachable(client, &candidate).await {
+                *cache = Some(ChosenMirror {
+                    mirrors: mirrors.to_vec(),
+                    mirror: mirror.clone(),
+                    chosen_at: Instant::now(),
+                });
+                chosen = Some(candidate);
+                break;
+            }
+            warn!(tag = %rule_set.tag, mirror = %candidate, "Rule set mirror unreachable");
+        }
+        match chosen {
+            Some(url) => {
+                info!(tag = %rule_set.tag, url = %url, "Using rule set mirror");
+                rule_set.url = url;
+            }
+            None => warn!(
+                tag = %rule_set.tag,
+                "All rule set mirrors unreachable, keeping original URL"
+            ),
+        }
+        resolved.push(rule_set);
+    }
+    resolved
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{apply_rule_set_mirrors, mirror_url, GITHUB_RAW_ORIGIN};
+    use crate::models::{RuleSetAction, RuleSetFormat, RuleSetRule};
+
+    const GOOGLE_SRS: &str =
+        "https://raw.githubusercontent.com/SagerNet/sing-geosite/rule-set/geosite-google.srs";
+
+    #[test]
+    fn mirror_url_replaces_github_raw_origin() {
+        assert_eq!(
+            mirror_url(GOOGLE_SRS, "https://raw.gitmirror.com/").as_deref(),
+            Some("https://raw.gitmirror.com/SagerNet/sing-geosite/rule-set/geosite-google.srs")
+        );
+        assert_eq!(
+            mirror_url(GOOGLE_SRS, "https://ghfast.top/https://raw.githubusercontent.com")
+                .as_deref(),
+            Some("https://ghfast.top/https://raw.githubusercontent.com/SagerNet/sing-geosite/rule-set/geosite-google.srs")
+        );
+        assert!(mirror_url("https://example.com/geosite.srs", "https://m.example").is_none());
+        assert!(mirror_url(
+            "https://raw.githubusercontent.com.evil.example/x.srs",
+            "https://m.example"
+        )
+        .is_none());
+    }
+
+    #[tokio::test]
+    async fn apply_rule_set_mirrors_keeps_original_when_mirrors_unreachable() {
+        let rule_sets = vec![RuleSetRule {
+            tag: "geosite-google".to_string(),
+            url: GOOGLE_SRS.to_string(),
+            action: RuleSetAction::Proxy,
+            format: RuleSetFormat::default(),
+        }];
+
+        let resolved = apply_rule_set_mirrors(
+            &reqwest::Client::new(),
+            &rule_sets,
+            &["http://127.0.0.1:1".to_string()],
+        )
+        .await;
+
+        assert_eq!(resolved, rule_sets);
+    }
+
+    #[tokio::test]
+    async fn chosen_mirror_is_probed_with_a_range_request_and_reused() {
+        use axum::{http::HeaderMap, routing::get, Router};
+        use std::sync::{Arc, Mutex};
+
+        let ranges = Arc::new(Mutex::new(Vec::new()));
+        let recorded = ranges.clone();
+        let app = Router::new().fallback(get(move |headers: HeaderMap| {
+            let recorded = recorded.clone();
+            async move {
+                let range = headers.get("range").and_then(|v| v.to_str().ok());
+                recorded.lock().unwrap().push(range.map(str::to_string));
+                "x"
+            }
+        }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+
+        let rule_set = |tag: &str| RuleSetRule {
+            tag: tag.to_string(),
+            url: format!(
+                "{}/SagerNet/sing-geosite/rule-set/{}.srs",
+                GITHUB_RAW_ORIGIN, tag
+            ),
+            action: RuleSetAction::Proxy,
+            format: RuleSetFormat::default(),
+        };
+        let rule_sets = vec![rule_set("geosite-google"), rule_set("geosite-github")];
+        let mirrors = vec!["http://127.0.0.1:1".to_string(), format!("http://{addr}")];
+        let client = reqwest::Client::new();
+
+        let first = apply_rule_set_mirrors(&client, &rule_sets, &mirrors).await;
+        let second = apply_rule_set_mirrors(&client, &rule_sets, &mirrors).await;
+
+        assert_eq!(
+            first[1].url,
+            format!("http://{addr}/SagerNet/sing-geosite/rule-set/geosite-github.srs")
+        );
+        assert_eq!(first, second);
+        assert_eq!(*ranges.lock().unwrap(), vec![Some("bytes=0-0".to_string())]);
+    }
+}
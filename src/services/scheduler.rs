@@ -0,0 +1,173 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use tokio::time::{sleep, Duration, Instant};
+use tracing::{error, info};
+
+use crate::models::{Config, Subscription};
+use crate::services::config::{
+    load_generated_config, node_outbounds, refresh_due_subscriptions, sing_box_is_running,
+};
+use crate::services::health::record_latencies;
+use crate::services::latency::probe_latencies;
+use crate::state::AppState;
+
+const SCHEDULER_TICK: Duration = Duration::from_secs(30);
+/// 后台测速的间隔，结果写入节点健康状态
+const HEALTH_PROBE_INTERVAL: Duration = Duration::from_secs(300);
+
+/// 订阅自身的间隔优先，否则使用全局 refresh_interval_secs；0 表示不定时刷新
+fn refresh_interval(config: &Config, sub: &Subscription) -> Option<Duration> {
+    sub.refresh_interval_secs
+        .or(config.refresh_interval_secs)
+        .filter(|secs| *secs > 0)
+        .map(Duration::from_secs)
+}
+
+/// 取出本轮到期的订阅并更新它们的下次获取时间。
+/// 首次出现的订阅从现在开始计时，因为启动或修改配置时已经获取过一次
+fn take_due_subscriptions(
+    config: &Config,
+    next_fetch: &mut HashMap<String, Instant>,
+    now: Instant,
+) -> HashSet<String> {
+    next_fetch.retain(|url, _| {
+        config
+            .subs
+            .iter()
+            .any(|sub| &sub.url == url && refresh_interval(config, sub).is_some())
+    });
+
+    let mut due = HashSet::new();
+    for sub in &config.subs {
+        let Some(interval) = refresh_interval(config, sub) else {
+            continue;
+        };
+        let next = next_fetch
+            .entry(sub.url.clone())
+            .or_insert_with(|| now + interval);
+        if *next <= now {
+            due.insert(sub.url.clone());
+            *next = now + interval;
+        }
+    }
+    due
+}
+
+/// 按各订阅独立的刷新间隔定时获取，任一订阅到期时重新生成合并后的配置
+pub async fn run_refresh_scheduler(state: Arc<AppState>) {
+    let mut next_fetch = HashMap::new();
+
+    loop {
+        sleep(SCHEDULER_TICK).await;
+        if state
+            .initializing
+            .load(std::sync::atomic::Ordering::Relaxed)
+        {
+            continue;
+        }
+
+        let config = state.config.read().await.clone();
+        let due = take_due_subscriptions(&config, &mut next_fetch, Instant::now());
+        if due.is_empty() {
+            continue;
+        }
+
+        info!(due = ?due, "Refreshing due subscriptions");
+        if let Err(e) = refresh_due_subscriptions(&state, &due).await {
+            error!(error = %e, "Scheduled subscription refresh failed");
+        }
+    }
+}
+
+/// 定期通过 Clash API 测量所有节点的延迟并更新健康状态，
+/// 使 /api/nodes/health 不依赖客户端手动调用 /api/nodes/latency
+pub async fn run_health_probe(state: Arc<AppState>) {
+    loop {
+        sleep(HEALTH_PROBE_INTERVAL).await;
+        if state
+            .initializing
+            .load(std::sync::atomic::Ordering::Relaxed)
+            || !state.config.read().await.enable_clash_api
+            || !sing_box_is_running(&state).await
+        {
+            continue;
+        }
+
+        let generated = match load_generated_config(&state.sing_box_paths).await {
+            Ok(Some(generated)) => generated,
+            Ok(None) => continue,
+            Err(e) => {
+                error!(error = %e, "Failed to load generated config for health probe");
+                continue;
+            }
+        };
+        let tags: Vec<String> = node_outbounds(&generated)
+            .iter()
+            .filter_map(|outbound| outbound["tag"].as_str().map(str::to_string))
+            .collect();
+        if tags.is_empty() {
+            continue;
+        }
+        let latencies = probe_latencies(&state.http_client, tags).await;
+        let reachable = latencies
+            .values()
+            .filter(|latency| latency.is_some())
+            .count();
+        info!(
+            nodes = latencies.len(),
+            reachable, "Background health probe finished"
+        );
+        record_latencies(&state, &latencies).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use tokio::time::{Duration, Instant};
+
+    use super::take_due_subscriptions;
+    use crate::models::{Config, Subscription};
+
+    #[test]
+    fn subscriptions_become_due_on_their_own_schedule() {
+        let config = Config {
+            refresh_interval_secs: Some(3600),
+            subs: vec![
+                "https://global.example.com/sub".into(),
+                Subscription {
+                    url: "https://fast.example.com/sub".to_string(),
+                    refresh_interval_secs: Some(600),
+                    ..Default::default()
+                },
+                Subscription {
+                    url: "https://manual.example.com/sub".to_string(),
+                    refresh_interval_secs: Some(0),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        let mut next_fetch = HashMap::new();
+        let start = Instant::now();
+
+        assert!(take_due_subscriptions(&config, &mut next_fetch, start).is_empty());
+        assert_eq!(next_fetch.len(), 2);
+
+        let due =
+            take_due_subscriptions(&config, &mut next_fetch, start + Duration::from_secs(600));
+        assert_eq!(due.len(), 1);
+        assert!(due.contains("https://fast.example.com/sub"));
+
+        let due =
+            take_due_subscriptions(&config, &mut next_fetch, start + Duration::from_secs(3600));
+        assert_eq!(due.len(), 2);
+
+        let config = Config {
+            subs: vec!["https://global.example.com/sub".into()],
+            ..config
+        };
+        take_due_subscriptions(&config, &mut next_fetch, start + Duration::from_secs(3601));
+        assert_eq!(next_fetch.len(), 1);
+    }
+}
@@ -0,0 +1,354 @@
+use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
+use base64::Engine;
+use serde_json::Value as JsonValue;
+use tracing::warn;
+
+use crate::error::{AppError, AppResult};
+
+fn str_field<'a>(value: &'a JsonValue, name: &str) -> Option<&'a str> {
+    value
+        .get(name)
+        .and_then(|value| value.as_str())
+        .filter(|value| !value.is_empty())
+}
+
+fn enc(value: &str) -> String {
+    urlencoding::encode(value).into_owned()
+}
+
+/// IPv6 地址在 URI 中需要加方括号
+fn host_port(server: &str, port: u64) -> String {
+    if server.contains(':') {
+        format!("[{}]:{}", server, port)
+    } else {
+        format!("{}:{}", server, port)
+    }
+}
+
+/// 启用时返回 tls 段
+fn enabled_tls(outbound: &JsonValue) -> Option<&JsonValue> {
+    outbound.get("tls").filter(|tls| {
+        tls.get("enabled")
+            .and_then(|enabled| enabled.as_bool())
+            .unwrap_or(false)
+    })
+}
+
+/// 按顺序收集 URI 查询参数，空值跳过
+#[derive(Default)]
+struct Query(Vec<(&'static str, String)>);
+
+impl Query {
+    fn push(&mut self, key: &'static str, value: Option<&str>) {
+        if let Some(value) = value.filter(|value| !value.is_empty()) {
+            self.0.push((key, value.to_string()));
+        }
+    }
+
+    fn finish(self) -> String {
+        if self.0.is_empty() {
+            return String::new();
+        }
+        let pairs: Vec<String> = self
+            .0
+            .iter()
+            .map(|(key, value)| format!("{}={}", key, enc(value)))
+            .collect();
+        format!("?{}", pairs.join("&"))
+    }
+}
+
+fn push_tls_params(query: &mut Query, tls: &JsonValue, insecure_key: &'static str) {
+    query.push("sni", str_field(tls, "server_name"));
+    if tls.get("insecure").and_then(|value| value.as_bool()) == Some(true) {
+        query.push(insecure_key, Some("1"));
+    }
+    let alpn: Vec<&str> = tls
+        .get("alpn")
+        .and_then(|alpn| alpn.as_array())
+        .map(|alpn| alpn.iter().filter_map(|value| value.as_str()).collect())
+        .unwrap_or_default();
+    if !alpn.is_empty() {
+        query.push("alpn", Some(&alpn.join(",")));
+    }
+    query.push(
+        "fp",
+        tls.get("utls")
+            .and_then(|utls| str_field(utls, "fingerprint")),
+    );
+}
+
+/// vless/trojan 的 type/path/host/serviceName 参数
+fn push_transport_params(query: &mut Query, outbound: &JsonValue) -> Result<(), String> {
+    let Some(transport) = outbound.get("transport") else {
+        query.push("type", Some("tcp"));
+        return Ok(());
+    };
+    match str_field(transport, "type").unwrap_or_default() {
+        "ws" => {
+            query.push("type", Some("ws"));
+            query.push("path", str_field(transport, "path"));
+            query.push(
+                "host",
+                transport
+                    .get("headers")
+                    .and_then(|headers| str_field(headers, "Host")),
+            );
+        }
+        "grpc" => {
+            query.push("type", Some("grpc"));
+            query.push("serviceName", str_field(transport, "service_name"));
+        }
+        "http" => {
+            query.push("type", Some("http"));
+            query.push("path", str_field(transport, "path"));
+            query.push(
+                "host",
+                transport
+                    .get("host")
+                    .and_then(|hosts| hosts.as_array())
+                    .and_then(|hosts| hosts.first())
+                    .and_then(|host| host.as_str()),
+            );
+        }
+        "quic" => query.push("type", Some("quic")),
+        other => return Err(format!("unsupported transport '{}'", other)),
+    }
+    Ok(())
+}
+
+fn vmess_uri(outbound: &JsonValue, tag: &str, server: &str, port: u64) -> Result<String, String> {
+    let tls = enabled_tls(outbound);
+    let transport = outbound.get("transport");
+    let (net, path, host) = match transport.and_then(|t| str_field(t, "type")) {
+        None => ("tcp", None, None),
+        Some("ws") => (
+            "ws",
+            transport.and_then(|t| str_field(t, "path")),
+            transport
+                .and_then(|t| t.get("headers"))
+                .and_then(|headers| str_field(headers, "Host")),
+        ),
+        Some("grpc") => (
+            "grpc",
+            transport.and_then(|t| str_field(t, "service_name")),
+            None,
+        ),
+        Some("http") => (
+            if tls.is_some() { "h2" } else { "http" },
+            transport.and_then(|t| str_field(t, "path")),
+            transport
+                .and_then(|t| t.get("host"))
+                .and_then(|hosts| hosts.as_array())
+                .and_then(|hosts| hosts.first())
+                .and_then(|host| host.as_str()),
+        ),
+        Some("quic") => ("quic", None, None),
+        Some(other) => return Err(format!("unsupported transport '{}'", other)),
+    };
+    let link = serde_json::json!({
+        "v": "2",
+        "ps": tag,
+        "add": server,
+        "port": port.to_string(),
+        "id": str_field(outbound, "uuid").ok_or("missing uuid")?,
+        "aid": outbound.get("alter_id").and_then(|v| v.as_u64()).unwrap_or(0).to_string(),
+        "scy": str_field(outbound, "security").unwrap_or("auto"),
+        "net": net,
+        "type": "none",
+        "host": host.unwrap_or_default(),
+        "path": path.unwrap_or_default(),
+        "tls": if tls.is_some() { "tls" } else { "" },
+        "sni": tls.and_then(|tls| str_field(tls, "server_name")).unwrap_or_default(),
+    });
+    Ok(format!("vmess://{}", STANDARD.encode(link.to_string())))
+}
+
+/// 将 sing-box outbound 还原为分享链接（ss://、vmess://、vless:// 等）；不支持的协议返回错误原因
+pub fn outbound_to_share_uri(outbound: &JsonValue) -> Result<String, String> {
+    let outbound_type = str_field(outbound, "type").ok_or("missing outbound type")?;
+    let tag = str_field(outbound, "tag").ok_or("missing outbound tag")?;
+    let server = str_field(outbound, "server").ok_or("missing outbound server")?;
+    let port = outbound
+        .get("server_port")
+        .and_then(|port| port.as_u64())
+        .ok_or("missing outbound server_port")?;
+    let address = host_port(server, port);
+    let password = || str_field(outbound, "password").ok_or("missing password");
+    let uuid = || str_field(outbound, "uuid").ok_or("missing uuid");
+    let mut query = Query::default();
+
+    let prefix = match outbound_type {
+        "shadowsocks" => {
+            let method = str_field(outbound, "method").ok_or("missing method")?;
+            let userinfo = URL_SAFE_NO_PAD.encode(format!("{}:{}", method, password()?));
+            format!("ss://{}@{}", userinfo, address)
+        }
+        "vmess" => return vmess_uri(outbound, tag, server, port),
+        "vless" => {
+            query.push("encryption", Some("none"));
+            query.push("flow", str_field(outbound, "flow"));
+            match enabled_tls(outbound) {
+                Some(tls) => {
+                    let reality = tls
+                        .get("reality")
+                        .filter(|reality| reality.get("enabled") == Some(&JsonValue::Bool(true)));
+                    query.push(
+                        "security",
+                        Some(if reality.is_some() { "reality" } else { "tls" }),
+                    );
+                    push_tls_params(&mut query, tls, "allowInsecure");
+                    if let Some(reality) = reality {
+                        query.push("pbk", str_field(reality, "public_key"));
+                        query.push("sid", str_field(reality, "short_id"));
+                    }
+                }
+                None => query.push("security", Some("none")),
+            }
+            push_transport_params(&mut query, outbound)?;
+            format!("vless://{}@{}", enc(uuid()?), address)
+        }
+        "trojan" => {
+            query.push("security", Some("tls"));
+            if let Some(tls) = enabled_tls(outbound) {
+                push_tls_params(&mut query, tls, "allowInsecure");
+            }
+            push_transport_params(&mut query, outbound)?;
+            format!("trojan://{}@{}", enc(password()?), address)
+        }
+        "hysteria2" | "anytls" => {
+            if let Some(tls) = enabled_tls(outbound) {
+                push_tls_params(&mut query, tls, "insecure");
+            }
+            if let Some(obfs) = outbound.get("obfs") {
+                query.push("obfs", str_field(obfs, "type"));
+                query.push("obfs-password", str_field(obfs, "password"));
+            }
+            format!("{}://{}@{}", outbound_type, enc(password()?), address)
+        }
+        "tuic" => {
+            query.push(
+                "congestion_control",
+                str_field(outbound, "congestion_control"),
+            );
+            query.push("udp_relay_mode", str_field(outbound, "udp_relay_mode"));
+            if let Some(tls) = enabled_tls(outbound) {
+                push_tls_params(&mut query, tls, "allow_insecure");
+            }
+            format!("tuic://{}:{}@{}", enc(uuid()?), enc(password()?), address)
+        }
+        other => return Err(format!("unsupported outbound type '{}'", other)),
+    };
+
+    Ok(format!("{}{}#{}", prefix, query.finish(), enc(tag)))
+}
+
+/// 由已合并的节点 outbound 生成 V2Ray 订阅：每行一个分享链接，整体 base64 编码
+pub fn build_v2ray_subscription(outbounds: &[JsonValue]) -> AppResult<String> {
+    let links: Vec<String> = outbounds
+        .iter()
+        .filter_map(|outbound| match outbound_to_share_uri(outbound) {
+            Ok(link) => Some(link),
+            Err(e) => {
+                let tag = str_field(outbound, "tag").unwrap_or("<unknown>");
+                warn!(tag = %tag, reason = %e, "Skipping outbound for subscription export");
+                None
+            }
+        })
+        .collect();
+
+    if links.is_empty() {
+        return Err(AppError::message(
+            "No nodes could be converted to share links",
+        ));
+    }
+    Ok(STANDARD.encode(links.join("\n")))
+}
+
+#[cfg(test)]
+mod tests {
+    use base64::engine::general_purpose::STANDARD;
+    use base64::Engine;
+    use serde_json::json;
+
+    use super::{build_v2ray_subscription, outbound_to_share_uri};
+
+    #[test]
+    fn outbound_to_share_uri_builds_standard_links() {
+        let ss = json!({"type": "shadowsocks", "tag": "SS 01", "server": "ss.example.com", "server_port": 8388, "method": "aes-128-gcm", "password": "pw"});
+        assert_eq!(
+            outbound_to_share_uri(&ss).unwrap(),
+            "ss://YWVzLTEyOC1nY206cHc@ss.example.com:8388#SS%2001"
+        );
+
+        let hy2 = json!({
+            "type": "hysteria2", "tag": "HY2", "server": "2001:db8::1", "server_port": 443,
+            "password": "p@ss",
+            "obfs": {"type": "salamander", "password": "o"},
+            "tls": {"enabled": true, "server_name": "hy.example.com", "insecure": true}
+        });
+        assert_eq!(
+            outbound_to_share_uri(&hy2).unwrap(),
+            "hysteria2://p%40ss@[2001:db8::1]:443?sni=hy.example.com&insecure=1&obfs=salamander&obfs-password=o#HY2"
+        );
+
+        let vless = json!({
+            "type": "vless", "tag": "VL", "server": "vl.example.com", "server_port": 443,
+            "uuid": "223e4567-e89b-12d3-a456-426614174000", "flow": "xtls-rprx-vision",
+            "tls": {
+                "enabled": true, "server_name": "vl.example.com",
+                "utls": {"enabled": true, "fingerprint": "chrome"},
+                "reality": {"enabled": true, "public_key": "pk", "short_id": "ab"}
+            },
+            "transport": {"type": "grpc", "service_name": "edge"}
+        });
+        assert_eq!(
+            outbound_to_share_uri(&vless).unwrap(),
+            "vless://223e4567-e89b-12d3-a456-426614174000@vl.example.com:443?encryption=none&flow=xtls-rprx-vision&security=reality&sni=vl.example.com&fp=chrome&pbk=pk&sid=ab&type=grpc&serviceName=edge#VL"
+        );
+
+        let vmess = json!({
+            "type": "vmess", "tag": "VM", "server": "vm.example.com", "server_port": 443,
+            "uuid": "123e4567-e89b-12d3-a456-426614174000", "security": "auto",
+            "tls": {"enabled": true, "server_name": "vm.example.com"},
+            "transport": {"type": "ws", "path": "/ws", "headers": {"Host": "cdn.example.com"}}
+        });
+        let link = outbound_to_share_uri(&vmess).unwrap();
+        let payload: serde_json::Value = serde_json::from_slice(
+            &STANDARD
+                .decode(link.strip_prefix("vmess://").unwrap())
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(payload["ps"], "VM");
+        assert_eq!(payload["net"], "ws");
+        assert_eq!(payload["host"], "cdn.example.com");
+        assert_eq!(payload["tls"], "tls");
+
+        assert!(outbound_to_share_uri(
+            &json!({"type": "wireguard", "tag": "wg", "server": "w", "server_port": 1})
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn build_v2ray_subscription_skips_unsupported_outbounds() {
+        let outbounds = vec![
+            json!({"type": "trojan", "tag": "TJ", "server": "tj.example.com", "server_port": 443, "password": "pw", "tls": {"enabled": true}}),
+            json!({"type": "wireguard", "tag": "wg", "server": "w", "server_port": 1}),
+        ];
+
+        let decoded = String::from_utf8(
+            STANDARD
+                .decode(build_v2ray_subscription(&outbounds).unwrap())
+                .unwrap(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            decoded,
+            "trojan://pw@tj.example.com:443?security=tls&type=tcp#TJ"
+        );
+        assert!(build_v2ray_subscription(&outbounds[1..]).is_err());
+    }
+}
@@ -1,15 +1,22 @@
 use nix::sys::signal::{kill, Signal};
 use nix::unistd::Pid;
+use std::collections::VecDeque;
 use std::fs;
 use std::os::unix::fs::PermissionsExt;
-use std::path::PathBuf;
+use std::os::unix::process::ExitStatusExt;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::Instant;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::sync::broadcast;
 use tokio::time::{sleep, Duration};
-use tracing::info;
+use tracing::{info, warn};
 
 use crate::error::{AppError, AppResult};
+use crate::log_redact::redact;
+use crate::models::{Config, LastExit, SingBoxChannel};
 use crate::state::{AppState, SingBoxProcess};
+use crate::validation::Validator;
 
 #[cfg(target_arch = "x86_64")]
 const SING_BOX_BINARY: &[u8] = include_bytes!("../../embedded/sing-box-amd64");
@@ -20,6 +27,50 @@ const SING_BOX_BINARY: &[u8] = include_bytes!("../../embedded/sing-box-arm64");
 #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
 compile_error!("Unsupported architecture: only x86_64 and aarch64 are supported. Please add support for your target architecture in embedded/ directory.");
 
+#[cfg(all(feature = "sing-box-latest", target_arch = "x86_64"))]
+const SING_BOX_LATEST_BINARY: Option<&[u8]> =
+    Some(include_bytes!("../../embedded/sing-box-latest-amd64"));
+
+#[cfg(all(feature = "sing-box-latest", target_arch = "aarch64"))]
+const SING_BOX_LATEST_BINARY: Option<&[u8]> =
+    Some(include_bytes!("../../embedded/sing-box-latest-arm64"));
+
+#[cfg(not(feature = "sing-box-latest"))]
+const SING_BOX_LATEST_BINARY: Option<&[u8]> = None;
+
+/// sing-box 最近输出的日志行；新行同时广播给实时订阅者（日志 WebSocket）
+pub struct LogCapture {
+    lines: std::sync::Mutex<VecDeque<String>>,
+    live: broadcast::Sender<String>,
+}
+
+impl Default for LogCapture {
+    fn default() -> Self {
+        Self {
+            lines: Default::default(),
+            live: broadcast::channel(LOG_BUFFER_LINES).0,
+        }
+    }
+}
+
+impl LogCapture {
+    /// 返回当前缓冲的日志和之后新日志的接收端；两者在同一把锁内获取，不会漏行或重复
+    pub fn subscribe(&self) -> (Vec<String>, broadcast::Receiver<String>) {
+        let lines = self.lines.lock().unwrap_or_else(|e| e.into_inner());
+        (lines.iter().cloned().collect(), self.live.subscribe())
+    }
+}
+
+pub type LogBuffer = Arc<LogCapture>;
+
+const DEFAULT_START_GRACE: Duration = Duration::from_secs(2);
+/// 停止时发送 SIGTERM 后等待退出的默认时间，超时后发送 SIGKILL
+const DEFAULT_STOP_GRACE: Duration = Duration::from_secs(3);
+/// SIGHUP 重载后等待确认 sing-box 仍在运行的时间
+const RELOAD_GRACE: Duration = Duration::from_secs(1);
+const LOG_BUFFER_LINES: usize = 200;
+const STARTUP_ERROR_LOG_LINES: usize = 20;
+
 const IP_RULE_BINARY: &[u8] = include_bytes!("../../embedded/geoip-cn.srs");
 const SITE_RULE_BINARY: &[u8] = include_bytes!("../../embedded/geosite-geolocation-cn.srs");
 
@@ -27,20 +78,97 @@ pub fn get_sing_box_home() -> PathBuf {
     PathBuf::from("/tmp/miao-sing-box")
 }
 
-pub fn extract_sing_box() -> AppResult<PathBuf> {
-    let sing_box_home = get_sing_box_home();
-    if !sing_box_home.exists() {
-        fs::create_dir_all(&sing_box_home)
-            .map_err(|e| AppError::context("Failed to create sing-box home directory", e))?;
+/// sing-box 相关路径：配置目录存放 config.json，数据目录（-D）存放规则集、面板和缓存，
+/// 二进制目录存放解压出的 sing-box。未配置的目录均为 get_sing_box_home()
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SingBoxPaths {
+    pub config_dir: PathBuf,
+    pub data_dir: PathBuf,
+    pub binary_dir: PathBuf,
+}
+
+impl Default for SingBoxPaths {
+    fn default() -> Self {
+        Self {
+            config_dir: get_sing_box_home(),
+            data_dir: get_sing_box_home(),
+            binary_dir: get_sing_box_home(),
+        }
+    }
+}
+
+impl SingBoxPaths {
+    pub fn from_config(config: &Config) -> Self {
+        let dir = |value: &Option<String>| {
+            value
+                .as_deref()
+                .map(str::trim)
+                .filter(|value| !value.is_empty())
+                .map(PathBuf::from)
+                .unwrap_or_else(get_sing_box_home)
+        };
+        Self {
+            config_dir: dir(&config.sing_box_config_dir),
+            data_dir: dir(&config.sing_box_data_dir),
+            binary_dir: dir(&config.sing_box_binary_dir),
+        }
+    }
+
+    pub fn config_path(&self) -> PathBuf {
+        self.config_dir.join("config.json")
+    }
+
+    /// 标记 config.json 由 miao 生成；sing-box 拒绝未知字段，标记无法写进配置本身
+    pub fn config_marker_path(&self) -> PathBuf {
+        self.config_dir.join("config.json.miao")
+    }
+
+    /// 运行中的 sing-box 的 pid；miao 重启后据此找到上一个实例留下的进程
+    pub fn pid_path(&self) -> PathBuf {
+        self.data_dir.join("sing-box.pid")
+    }
+
+    /// 最近一次成功启动的配置，用于订阅全部失败时回退
+    pub fn config_cache_path(&self) -> PathBuf {
+        self.config_dir.join("config.json.cache")
+    }
+
+    /// 各通道解压到不同文件名，stable 保持原来的 `sing-box`
+    pub fn binary_path(&self, channel: SingBoxChannel) -> PathBuf {
+        self.binary_dir.join(match channel {
+            SingBoxChannel::Stable => "sing-box",
+            SingBoxChannel::Latest => "sing-box-latest",
+        })
+    }
+}
+
+fn embedded_sing_box_binary(channel: SingBoxChannel) -> AppResult<&'static [u8]> {
+    match channel {
+        SingBoxChannel::Stable => Ok(SING_BOX_BINARY),
+        SingBoxChannel::Latest => SING_BOX_LATEST_BINARY.ok_or_else(|| {
+            AppError::message(
+                "sing_box_channel is 'latest' but this build does not embed it; rebuild with --features sing-box-latest",
+            )
+        }),
+    }
+}
+
+pub fn extract_sing_box(paths: &SingBoxPaths, channel: SingBoxChannel) -> AppResult<()> {
+    for dir in [&paths.config_dir, &paths.data_dir, &paths.binary_dir] {
+        if !dir.exists() {
+            fs::create_dir_all(dir).map_err(|e| {
+                AppError::context(format!("Failed to create sing-box directory {:?}", dir), e)
+            })?;
+        }
     }
 
-    let sing_box_path = sing_box_home.join("sing-box");
-    let ip_rule_path = sing_box_home.join("chinaip.srs");
-    let site_rule_path = sing_box_home.join("chinasite.srs");
+    let sing_box_path = paths.binary_path(channel);
+    let ip_rule_path = paths.data_dir.join("chinaip.srs");
+    let site_rule_path = paths.data_dir.join("chinasite.srs");
 
     if !sing_box_path.exists() {
-        info!("Extracting embedded sing-box binary to {:?}", sing_box_path);
-        fs::write(&sing_box_path, SING_BOX_BINARY)
+        info!(channel = ?channel, "Extracting embedded sing-box binary to {:?}", sing_box_path);
+        fs::write(&sing_box_path, embedded_sing_box_binary(channel)?)
             .map_err(|e| AppError::context("Failed to write embedded sing-box binary", e))?;
         fs::set_permissions(&sing_box_path, fs::Permissions::from_mode(0o755))
             .map_err(|e| AppError::context("Failed to set permissions on sing-box binary", e))?;
@@ -57,26 +185,60 @@ pub fn extract_sing_box() -> AppResult<PathBuf> {
         fs::write(&site_rule_path, SITE_RULE_BINARY)
             .map_err(|e| AppError::context("Failed to write geosite rule file", e))?;
     }
-    let dashboard_dir = sing_box_home.join("dashboard");
+    let dashboard_dir = paths.data_dir.join("dashboard");
     if !dashboard_dir.exists() {
         fs::create_dir_all(&dashboard_dir)
             .map_err(|e| AppError::context("Failed to create sing-box dashboard directory", e))?;
     }
 
-    Ok(sing_box_home)
+    Ok(())
+}
+
+/// 启动前确认 sing-box 二进制存在且可执行。使用默认目录时（如 /tmp 被清空）重新解压内置二进制，
+/// 自定义目录缺少二进制时返回明确的错误，而不是 spawn 的原始系统错误
+fn ensure_sing_box_binary(paths: &SingBoxPaths, channel: SingBoxChannel) -> AppResult<PathBuf> {
+    let sing_box_path = paths.binary_path(channel);
+    if !sing_box_path.is_file() && paths.binary_dir == get_sing_box_home() {
+        warn!(path = ?sing_box_path, "sing-box binary is missing, re-extracting embedded binary");
+        extract_sing_box(paths, channel)?;
+    }
+
+    let metadata = fs::metadata(&sing_box_path).map_err(|_| {
+        AppError::message(format!(
+            "sing-box binary not found at {}; extraction may have failed or sing_box_binary_dir lacks the binary",
+            sing_box_path.display()
+        ))
+    })?;
+    if !metadata.is_file() || metadata.permissions().mode() & 0o111 == 0 {
+        return Err(AppError::message(format!(
+            "sing-box binary at {} is not an executable file",
+            sing_box_path.display()
+        )));
+    }
+    Ok(sing_box_path)
 }
 
 /// 在停止运行中的实例前验证 sing-box 配置，避免不必要的服务中断
-pub async fn validate_sing_box_config() -> AppResult<()> {
-    let sing_box_home = get_sing_box_home();
-    let sing_box_path = sing_box_home.join("sing-box");
-    let config_path = sing_box_home.join("config.json");
+pub async fn validate_sing_box_config(
+    paths: &SingBoxPaths,
+    channel: SingBoxChannel,
+) -> AppResult<()> {
+    validate_sing_box_config_file(paths, channel, &paths.config_path()).await
+}
 
-    let output = tokio::process::Command::new(&sing_box_path)
-        .current_dir(&sing_box_home)
+/// 用 sing-box check 校验任意位置的配置文件，如尚未替换到位的暂存文件
+pub async fn validate_sing_box_config_file(
+    paths: &SingBoxPaths,
+    channel: SingBoxChannel,
+    config_path: &Path,
+) -> AppResult<()> {
+    let output = tokio::process::Command::new(paths.binary_path(channel))
+        .current_dir(&paths.data_dir)
         .arg("check")
         .arg("-c")
-        .arg(&config_path)
+        .arg(config_path)
+        .arg("-D")
+        .arg(&paths.data_dir)
         .output()
         .await
         .map_err(|e| AppError::context("Failed to run sing-box config check", e))?;
@@ -107,47 +269,316 @@ pub async fn start_sing_internal(state: &Arc<AppState>) -> AppResult<()> {
         }
     }
 
-    let sing_box_home = get_sing_box_home();
-    let sing_box_path = sing_box_home.join("sing-box");
-    let config_path = sing_box_home.join("config.json");
+    let (channel, extra_args, extra_env, start_grace) = {
+        let config = state.config.read().await;
+        (
+            config.sing_box_channel,
+            config.sing_box_args.clone(),
+            config.sing_box_env.clone(),
+            config
+                .sing_box_start_grace_ms
+                .map(Duration::from_millis)
+                .unwrap_or(DEFAULT_START_GRACE),
+        )
+    };
+    Validator::sing_box_args(&extra_args).map_err(AppError::message)?;
 
-    info!(binary = ?sing_box_path, config = ?config_path, "Starting sing-box");
+    let paths = &state.sing_box_paths;
+    let sing_box_path = ensure_sing_box_binary(paths, channel)?;
+    let config_path = paths.config_path();
+
+    info!(
+        binary = ?sing_box_path,
+        config = ?config_path,
+        data_dir = ?paths.data_dir,
+        args = ?extra_args,
+        "Starting sing-box"
+    );
 
     let mut child = tokio::process::Command::new(&sing_box_path)
-        .current_dir(&sing_box_home)
+        .current_dir(&paths.data_dir)
         .arg("run")
         .arg("-c")
         .arg(&config_path)
-        .stdout(std::process::Stdio::inherit())
-        .stderr(std::process::Stdio::inherit())
+        .arg("-D")
+        .arg(&paths.data_dir)
+        .args(&extra_args)
+        .envs(&extra_env)
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
         .spawn()
         .map_err(|e| AppError::context("Failed to spawn sing-box process", e))?;
 
     let pid = child.id();
     info!(pid = pid, "sing-box process spawned");
 
-    sleep(Duration::from_millis(500)).await;
+    // 每次启动清空日志，启动失败时只报告本次的输出；进程退出后日志保留到下次启动
+    let logs = state.sing_box_logs.clone();
+    if let Ok(mut lines) = logs.lines.lock() {
+        lines.clear();
+    }
+    let forwarders = [
+        child
+            .stdout
+            .take()
+            .map(|stdout| forward_output(stdout, logs.clone(), false)),
+        child
+            .stderr
+            .take()
+            .map(|stderr| forward_output(stderr, logs.clone(), true)),
+    ];
+
+    // 等待一段宽限期再确认进程仍在运行，配置错误通常会让 sing-box 在这段时间内退出
+    sleep(start_grace).await;
     if let Some(exit_status) = child
         .try_wait()
         .map_err(|e| AppError::context("Failed to check sing-box startup status", e))?
     {
+        for forwarder in forwarders.into_iter().flatten() {
+            let _ = tokio::time::timeout(Duration::from_secs(1), forwarder).await;
+        }
+        record_exit(state, exit_status).await;
         let code = exit_status.code().unwrap_or(-1);
         return Err(AppError::message(format!(
-            "sing-box exited immediately with code {}",
-            code
+            "sing-box exited immediately with code {}{}",
+            code,
+            format_log_tail(&logs, STARTUP_ERROR_LOG_LINES)
         )));
     }
 
+    if let Some(pid) = pid {
+        if let Err(e) = fs::write(paths.pid_path(), pid.to_string()) {
+            warn!(error = %e, "Failed to write sing-box pid file");
+        }
+    }
     *lock = Some(SingBoxProcess {
         child,
         started_at: Instant::now(),
     });
     drop(lock);
+    *state.last_exit.lock().await = None;
 
     Ok(())
 }
 
+/// /proc/<pid>/stat 存在且不是僵尸进程
+fn process_alive(pid: i32) -> bool {
+    let Ok(stat) = fs::read_to_string(format!("/proc/{}/stat", pid)) else {
+        return false;
+    };
+    // 进程名可能包含空格和括号，状态字段在最后一个 ')' 之后
+    let state = stat
+        .rfind(')')
+        .and_then(|end| stat[end + 1..].split_whitespace().next());
+    !matches!(state, None | Some("Z" | "X"))
+}
+
+/// 通过 /proc/<pid>/comm 确认 pid 仍属于 sing-box，避免误杀复用了该 pid 的其他进程
+fn is_sing_box_process(pid: i32) -> bool {
+    fs::read_to_string(format!("/proc/{}/comm", pid))
+        .is_ok_and(|comm| comm.trim().starts_with("sing-box"))
+}
+
+/// miao 重启（如升级）时，上一个实例启动的 sing-box 仍在运行并占用 sing-tun。
+/// 启动前按 pid 文件找到它并先 SIGTERM、超时后 SIGKILL 结束，再由本实例重新启动
+pub async fn stop_orphaned_sing_box(paths: &SingBoxPaths, stop_grace: Option<u64>) {
+    let pid_path = paths.pid_path();
+    let Ok(content) = fs::read_to_string(&pid_path) else {
+        return;
+    };
+    let _ = fs::remove_file(&pid_path);
+    let Ok(pid) = content.trim().parse::<i32>() else {
+        warn!(path = ?pid_path, "Ignoring malformed sing-box pid file");
+        return;
+    };
+    if pid <= 0 || !process_alive(pid) || !is_sing_box_process(pid) {
+        return;
+    }
+
+    warn!(
+        pid,
+        "Stopping sing-box left running by a previous miao instance"
+    );
+    let _ = kill(Pid::from_raw(pid), Signal::SIGTERM);
+    let deadline = Instant::now()
+        + stop_grace
+            .map(Duration::from_millis)
+            .unwrap_or(DEFAULT_STOP_GRACE);
+    while process_alive(pid) {
+        if Instant::now() >= deadline {
+            warn!(
+                pid,
+                "Orphaned sing-box did not exit after SIGTERM, sending SIGKILL"
+            );
+            let _ = kill(Pid::from_raw(pid), Signal::SIGKILL);
+            break;
+        }
+        sleep(Duration::from_millis(100)).await;
+    }
+}
+
+/// 逐行转发 sing-box 输出到自身的 stdout/stderr，同时保留最近的日志行
+fn forward_output<R>(reader: R, logs: LogBuffer, to_stderr: bool) -> tokio::task::JoinHandle<()>
+where
+    R: tokio::io::AsyncRead + Unpin + Send + 'static,
+{
+    tokio::spawn(async move {
+        let mut lines = BufReader::new(reader).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            let line = redact(&line).into_owned();
+            if to_stderr {
+                eprintln!("{}", line);
+            } else {
+                println!("{}", line);
+            }
+            push_log_line(&logs, line);
+        }
+    })
+}
+
+pub(crate) fn push_log_line(logs: &LogBuffer, line: String) {
+    if let Ok(mut lines) = logs.lines.lock() {
+        if lines.len() >= LOG_BUFFER_LINES {
+            lines.pop_front();
+        }
+        lines.push_back(line.clone());
+        // 没有订阅者时发送失败，忽略即可
+        let _ = logs.live.send(line);
+    }
+}
+
+pub fn recent_logs(logs: &LogBuffer) -> Vec<String> {
+    logs.lines
+        .lock()
+        .map(|lines| lines.iter().cloned().collect())
+        .unwrap_or_default()
+}
+
+/// 运行 `sing-box version`，返回第一行，如 "sing-box version 1.12.0"
+pub async fn sing_box_version(paths: &SingBoxPaths, channel: SingBoxChannel) -> AppResult<String> {
+    let output = tokio::time::timeout(
+        Duration::from_secs(5),
+        tokio::process::Command::new(paths.binary_path(channel))
+            .arg("version")
+            .output(),
+    )
+    .await
+    .map_err(|_| AppError::message("sing-box version timed out"))?
+    .map_err(|e| AppError::context("Failed to run sing-box version", e))?;
+
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .next()
+        .map(|line| line.trim().to_string())
+        .filter(|line| !line.is_empty())
+        .ok_or_else(|| AppError::message("sing-box version printed nothing"))
+}
+
+fn log_tail(logs: &LogBuffer, lines: usize) -> Vec<String> {
+    let Ok(logs) = logs.lines.lock() else {
+        return Vec::new();
+    };
+    logs.iter()
+        .skip(logs.len().saturating_sub(lines))
+        .cloned()
+        .collect()
+}
+
+fn format_log_tail(logs: &LogBuffer, lines: usize) -> String {
+    let tail = log_tail(logs, lines);
+    if tail.is_empty() {
+        return String::new();
+    }
+    format!(": {}", tail.join("\n"))
+}
+
+/// 记录 sing-box 的意外退出，原因取最近的日志输出，没有输出时说明终止信号
+pub(crate) async fn record_exit(state: &AppState, exit_status: std::process::ExitStatus) {
+    let tail = log_tail(&state.sing_box_logs, STARTUP_ERROR_LOG_LINES);
+    let reason = if !tail.is_empty() {
+        tail.join("\n")
+    } else if let Some(signal) = exit_status.signal() {
+        format!("killed by signal {}", signal)
+    } else {
+        "exited without output".to_string()
+    };
+    warn!(code = ?exit_status.code(), "sing-box exited unexpectedly");
+    *state.last_exit.lock().await = Some(LastExit {
+        code: exit_status.code(),
+        reason,
+    });
+}
+
+/// 先校验当前配置再重启，配置无效时保留正在运行的 sing-box
+pub async fn restart_sing_internal(state: &Arc<AppState>) -> AppResult<()> {
+    let channel = state.config.read().await.sing_box_channel;
+    validate_sing_box_config(&state.sing_box_paths, channel)
+        .await
+        .map_err(|e| {
+            AppError::context(
+                "Config validation failed, keeping current sing-box running",
+                e,
+            )
+        })?;
+
+    stop_sing_internal(state).await;
+    start_sing_internal(state).await
+}
+
+/// 向运行中的 sing-box 发送 SIGHUP，让它在进程内重新加载配置，
+/// 不重建 TUN 网卡也不中断 Clash API；重载后进程退出则返回错误
+pub async fn reload_sing_internal(state: &Arc<AppState>) -> AppResult<()> {
+    let channel = state.config.read().await.sing_box_channel;
+    validate_sing_box_config(&state.sing_box_paths, channel)
+        .await
+        .map_err(|e| {
+            AppError::context(
+                "Config validation failed, keeping current sing-box running",
+                e,
+            )
+        })?;
+
+    let pid = {
+        let mut lock = state.sing_process.lock().await;
+        let Some(proc) = lock.as_mut() else {
+            return Err(AppError::message("sing-box is not running"));
+        };
+        if !matches!(proc.child.try_wait(), Ok(None)) {
+            return Err(AppError::message("sing-box is not running"));
+        }
+        proc.child
+            .id()
+            .ok_or_else(|| AppError::message("sing-box pid is unavailable"))?
+    };
+    kill(Pid::from_raw(pid as i32), Signal::SIGHUP).map_err(|e| {
+        AppError::context("Failed to send SIGHUP to sing-box", std::io::Error::from(e))
+    })?;
+
+    sleep(RELOAD_GRACE).await;
+    let mut lock = state.sing_process.lock().await;
+    match lock.as_mut().map(|proc| proc.child.try_wait()) {
+        Some(Ok(None)) => {
+            info!(pid, "sing-box reloaded config");
+            Ok(())
+        }
+        result => {
+            *lock = None;
+            if let Some(Ok(Some(exit_status))) = result {
+                record_exit(state, exit_status).await;
+            }
+            Err(AppError::message("sing-box exited after reload"))
+        }
+    }
+}
+
 pub async fn stop_sing_internal(state: &Arc<AppState>) {
+    let stop_grace = state
+        .config
+        .read()
+        .await
+        .sing_box_stop_grace_ms
+        .map(Duration::from_millis)
+        .unwrap_or(DEFAULT_STOP_GRACE);
     let mut lock = state.sing_process.lock().await;
     if let Some(ref mut proc) = *lock {
         if proc.child.try_wait().ok().flatten().is_none() {
@@ -155,9 +586,8 @@ pub async fn stop_sing_internal(state: &Arc<AppState>) {
                 // 发送 SIGTERM 信号请求进程优雅退出
                 let _ = kill(Pid::from_raw(pid as i32), Signal::SIGTERM);
 
-                // 使用 timeout 等待进程退出，避免忙等待
-                let wait_result =
-                    tokio::time::timeout(Duration::from_secs(3), proc.child.wait()).await;
+                // 等待 sing-box 清理 TUN 网卡后退出，避免残留 sing-tun 阻塞下次启动
+                let wait_result = tokio::time::timeout(stop_grace, proc.child.wait()).await;
 
                 match wait_result {
                     Ok(Ok(_)) => {
@@ -165,6 +595,10 @@ pub async fn stop_sing_internal(state: &Arc<AppState>) {
                     }
                     _ => {
                         // 超时或等待失败，强制杀死进程
+                        warn!(
+                            grace_ms = stop_grace.as_millis() as u64,
+                            "sing-box did not exit after SIGTERM, sending SIGKILL"
+                        );
                         let _ = proc.child.start_kill();
                         let _ = proc.child.wait().await;
                     }
@@ -172,5 +606,260 @@ pub async fn stop_sing_internal(state: &Arc<AppState>) {
             }
         }
     }
-    *lock = None;
+    if lock.take().is_some() {
+        let _ = fs::remove_file(state.sing_box_paths.pid_path());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::{
+        embedded_sing_box_binary, ensure_sing_box_binary, format_log_tail, get_sing_box_home,
+        process_alive, push_log_line, restart_sing_internal, start_sing_internal,
+        stop_orphaned_sing_box, stop_sing_internal, LogBuffer, SingBoxPaths, LOG_BUFFER_LINES,
+    };
+    use crate::models::{Config, SingBoxChannel};
+    use crate::state::{AppState, SingBoxProcess};
+
+    #[test]
+    fn sing_box_binary_path_is_named_per_channel() {
+        let paths = SingBoxPaths::default();
+
+        assert_eq!(
+            paths.binary_path(SingBoxChannel::Stable),
+            get_sing_box_home().join("sing-box")
+        );
+        assert_eq!(
+            paths.binary_path(SingBoxChannel::Latest),
+            get_sing_box_home().join("sing-box-latest")
+        );
+    }
+
+    #[test]
+    fn ensure_sing_box_binary_reports_missing_or_non_executable_binary() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("miao-binary-check-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let paths = SingBoxPaths {
+            binary_dir: dir.clone(),
+            ..Default::default()
+        };
+
+        let missing = ensure_sing_box_binary(&paths, SingBoxChannel::Stable)
+            .unwrap_err()
+            .to_string();
+        let binary = paths.binary_path(SingBoxChannel::Stable);
+        std::fs::write(&binary, "").unwrap();
+        let not_executable = ensure_sing_box_binary(&paths, SingBoxChannel::Stable)
+            .unwrap_err()
+            .to_string();
+        std::fs::set_permissions(&binary, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let found = ensure_sing_box_binary(&paths, SingBoxChannel::Stable);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(missing.contains("sing-box binary not found at"));
+        assert!(not_executable.contains("not an executable file"));
+        assert_eq!(found.unwrap(), binary);
+    }
+
+    #[test]
+    fn sing_box_paths_separate_configured_directories() {
+        let config = Config {
+            sing_box_config_dir: Some("/etc/miao/sing-box".to_string()),
+            sing_box_data_dir: Some("/var/lib/miao".to_string()),
+            sing_box_binary_dir: Some("  ".to_string()),
+            ..Default::default()
+        };
+
+        let paths = SingBoxPaths::from_config(&config);
+
+        assert_eq!(
+            paths.config_path(),
+            PathBuf::from("/etc/miao/sing-box/config.json")
+        );
+        assert_eq!(paths.data_dir, PathBuf::from("/var/lib/miao"));
+        assert_eq!(paths.binary_dir, get_sing_box_home());
+        assert_eq!(
+            SingBoxPaths::from_config(&Config::default()),
+            SingBoxPaths::default()
+        );
+    }
+
+    #[test]
+    fn log_buffer_keeps_most_recent_lines() {
+        let logs = LogBuffer::default();
+        assert_eq!(format_log_tail(&logs, 5), "");
+
+        for i in 0..LOG_BUFFER_LINES + 3 {
+            push_log_line(&logs, format!("line {i}"));
+        }
+
+        assert_eq!(logs.lines.lock().unwrap().len(), LOG_BUFFER_LINES);
+        let (history, _) = logs.subscribe();
+        assert_eq!(history.len(), LOG_BUFFER_LINES);
+        assert_eq!(
+            format_log_tail(&logs, 2),
+            format!(
+                ": line {}\nline {}",
+                LOG_BUFFER_LINES + 1,
+                LOG_BUFFER_LINES + 2
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn start_records_exit_code_and_output_when_sing_box_exits_immediately() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("miao-early-exit-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let dir_str = dir.to_string_lossy().into_owned();
+        let state = std::sync::Arc::new(
+            AppState::new(Config {
+                sing_box_config_dir: Some(dir_str.clone()),
+                sing_box_data_dir: Some(dir_str.clone()),
+                sing_box_binary_dir: Some(dir_str),
+                sing_box_start_grace_ms: Some(300),
+                ..Default::default()
+            })
+            .unwrap(),
+        );
+        let binary = state.sing_box_paths.binary_path(SingBoxChannel::Stable);
+        std::fs::write(
+            &binary,
+            "#!/bin/sh\necho 'FATAL[0000] decode config at ./config.json: unknown field' >&2\nexit 3\n",
+        )
+        .unwrap();
+        std::fs::set_permissions(&binary, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let err = start_sing_internal(&state).await.unwrap_err().to_string();
+        let last_exit = state.last_exit.lock().await.clone().unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+
+        assert!(err.contains("exited immediately with code 3"));
+        assert_eq!(last_exit.code, Some(3));
+        assert!(last_exit.reason.contains("unknown field"));
+        assert!(state.sing_process.lock().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn restart_keeps_running_sing_box_when_config_is_invalid() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("miao-restart-check-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let dir_str = dir.to_string_lossy().into_owned();
+        let state = std::sync::Arc::new(
+            AppState::new(Config {
+                sing_box_config_dir: Some(dir_str.clone()),
+                sing_box_data_dir: Some(dir_str.clone()),
+                sing_box_binary_dir: Some(dir_str),
+                ..Default::default()
+            })
+            .unwrap(),
+        );
+        let binary = state.sing_box_paths.binary_path(SingBoxChannel::Stable);
+        std::fs::write(
+            &binary,
+            "#!/bin/sh
+[ \"$1\" = check ] && echo 'FATAL[0000] unknown outbound type' >&2 && exit 1
+exec sleep 30
+",
+        )
+        .unwrap();
+        std::fs::set_permissions(&binary, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let child = tokio::process::Command::new("sleep")
+            .arg("30")
+            .spawn()
+            .unwrap();
+        *state.sing_process.lock().await = Some(SingBoxProcess {
+            child,
+            started_at: std::time::Instant::now(),
+        });
+
+        let err = restart_sing_internal(&state).await.unwrap_err().to_string();
+        let mut process = state.sing_process.lock().await.take().unwrap();
+        let still_running = process.child.try_wait().unwrap().is_none();
+        let _ = process.child.kill().await;
+        let _ = std::fs::remove_dir_all(&dir);
+
+        assert!(err.contains("keeping current sing-box running"));
+        assert!(err.contains("unknown outbound type"));
+        assert!(still_running);
+    }
+
+    #[tokio::test]
+    async fn stop_escalates_to_sigkill_after_grace_period() {
+        let state = std::sync::Arc::new(
+            AppState::new(Config {
+                sing_box_stop_grace_ms: Some(200),
+                ..Default::default()
+            })
+            .unwrap(),
+        );
+        // 忽略 SIGTERM 的进程只能被 SIGKILL 结束
+        let child = tokio::process::Command::new("sh")
+            .args(["-c", "trap '' TERM; exec sleep 30"])
+            .spawn()
+            .unwrap();
+        *state.sing_process.lock().await = Some(SingBoxProcess {
+            child,
+            started_at: std::time::Instant::now(),
+        });
+
+        let started = std::time::Instant::now();
+        stop_sing_internal(&state).await;
+
+        assert!(started.elapsed() < std::time::Duration::from_secs(5));
+        assert!(state.sing_process.lock().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn stop_orphaned_sing_box_only_stops_sing_box_processes() {
+        let dir = std::env::temp_dir().join(format!("miao-orphan-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let paths = SingBoxPaths {
+            config_dir: dir.clone(),
+            data_dir: dir.clone(),
+            binary_dir: dir.clone(),
+        };
+        // 复制 sleep 并命名为 sing-box，使 /proc/<pid>/comm 与真实的 sing-box 一致
+        let fake_sing_box = dir.join("sing-box");
+        std::fs::copy("/bin/sleep", &fake_sing_box).unwrap();
+        let mut orphan = std::process::Command::new(&fake_sing_box)
+            .arg("30")
+            .spawn()
+            .unwrap();
+        let mut unrelated = std::process::Command::new("sleep")
+            .arg("30")
+            .spawn()
+            .unwrap();
+
+        std::fs::write(paths.pid_path(), unrelated.id().to_string()).unwrap();
+        stop_orphaned_sing_box(&paths, Some(1000)).await;
+        assert!(process_alive(unrelated.id() as i32));
+        assert!(!paths.pid_path().exists());
+
+        std::fs::write(paths.pid_path(), orphan.id().to_string()).unwrap();
+        stop_orphaned_sing_box(&paths, Some(1000)).await;
+        assert!(!process_alive(orphan.id() as i32));
+
+        let _ = unrelated.kill();
+        let _ = unrelated.wait();
+        let _ = orphan.wait();
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(not(feature = "sing-box-latest"))]
+    #[test]
+    fn latest_channel_errors_when_not_embedded() {
+        let err = embedded_sing_box_binary(SingBoxChannel::Latest).unwrap_err();
+
+        assert!(err.to_string().contains("--features sing-box-latest"));
+        assert!(embedded_sing_box_binary(SingBoxChannel::Stable).is_ok());
+    }
 }
@@ -0,0 +1,196 @@
+use std::path::{Path, PathBuf};
+
+use crate::error::{AppError, AppResult};
+
+struct StagedFile {
+    temp: PathBuf,
+    target: PathBuf,
+}
+
+/// 一组需要一起更新的文件：先全部写入各自目录下的临时文件，调用方校验通过后
+/// 再 commit 依次重命名到位；未 commit 就丢弃时删除临时文件，目标文件保持原样。
+/// commit 中途失败时，已替换的目标会从备份恢复，不会留下新旧混杂的一组文件
+#[derive(Default)]
+pub struct StagedWrite {
+    files: Vec<StagedFile>,
+}
+
+fn sibling_path(target: &Path, suffix: &str) -> PathBuf {
+    let file_name = target
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    target.with_file_name(format!(".{}.{}", file_name, suffix))
+}
+
+fn temp_path_for(target: &Path) -> PathBuf {
+    sibling_path(target, "miao-staged")
+}
+
+/// 为已存在的目标文件留一份副本，目标不存在时返回 None
+async fn backup_target(target: &Path) -> AppResult<Option<PathBuf>> {
+    match tokio::fs::metadata(target).await {
+        Ok(metadata) if metadata.is_file() => {}
+        _ => return Ok(None),
+    }
+    let backup = sibling_path(target, "miao-backup");
+    tokio::fs::copy(target, &backup)
+        .await
+        .map_err(|e| AppError::context(format!("Failed to back up {:?}", target), e))?;
+    Ok(Some(backup))
+}
+
+/// 把已替换的目标恢复为备份内容；原本不存在的目标直接删除
+async fn roll_back(replaced: &[(&Path, Option<PathBuf>)]) {
+    for (target, backup) in replaced.iter().rev() {
+        let restored = match backup {
+            Some(backup) => tokio::fs::rename(backup, target).await,
+            None => tokio::fs::remove_file(target).await,
+        };
+        if let Err(e) = restored {
+            tracing::error!("Failed to roll back {:?}: {}", target, e);
+        }
+    }
+}
+
+impl StagedWrite {
+    /// 暂存 `path` 的新内容并返回临时文件路径，供校验使用；
+    /// 同一目标重复暂存时覆盖之前的内容
+    pub async fn stage(&mut self, path: &Path, content: &str) -> AppResult<PathBuf> {
+        if let Some(parent) = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .map_err(|e| AppError::context("Failed to create config directory", e))?;
+        }
+
+        let temp = temp_path_for(path);
+        tokio::fs::write(&temp, content).await.map_err(|e| {
+            AppError::context(format!("Failed to write staged file for {:?}", path), e)
+        })?;
+        if !self.files.iter().any(|file| file.target == path) {
+            self.files.push(StagedFile {
+                temp: temp.clone(),
+                target: path.to_path_buf(),
+            });
+        }
+        Ok(temp)
+    }
+
+    /// 按暂存顺序把临时文件重命名为目标文件；任一步失败时恢复已替换的目标后返回错误
+    pub async fn commit(mut self) -> AppResult<()> {
+        let mut backups = Vec::with_capacity(self.files.len());
+        for file in &self.files {
+            match backup_target(&file.target).await {
+                Ok(backup) => backups.push(backup),
+                Err(e) => {
+                    for backup in backups.into_iter().flatten() {
+                        let _ = tokio::fs::remove_file(backup).await;
+                    }
+                    return Err(e);
+                }
+            }
+        }
+
+        let mut replaced = Vec::with_capacity(self.files.len());
+        for (file, backup) in self.files.iter().zip(backups) {
+            if let Err(e) = tokio::fs::rename(&file.temp, &file.target).await {
+                if let Some(backup) = backup {
+                    let _ = tokio::fs::remove_file(backup).await;
+                }
+                roll_back(&replaced).await;
+                return Err(AppError::context(
+                    format!("Failed to atomically rename file to {:?}", file.target),
+                    e,
+                ));
+            }
+            replaced.push((file.target.as_path(), backup));
+        }
+
+        for backup in replaced.into_iter().filter_map(|(_, backup)| backup) {
+            let _ = tokio::fs::remove_file(backup).await;
+        }
+        self.files.clear();
+        Ok(())
+    }
+}
+
+impl Drop for StagedWrite {
+    fn drop(&mut self) {
+        for file in &self.files {
+            let _ = std::fs::remove_file(&file.temp);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn staged_files_replace_targets_only_on_commit() {
+        let dir = std::env::temp_dir().join(format!("miao-test-staged-{}", std::process::id()));
+        let main = dir.join("config.json");
+        let extra = dir.join("out").join("router.json");
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        tokio::fs::write(&main, "old").await.unwrap();
+
+        let mut aborted = StagedWrite::default();
+        let main_temp = aborted.stage(&main, "new").await.unwrap();
+        aborted.stage(&extra, "new extra").await.unwrap();
+        drop(aborted);
+        let after_abort = tokio::fs::read_to_string(&main).await.unwrap();
+        let leftovers = (main_temp.exists(), extra.exists());
+
+        let mut staged = StagedWrite::default();
+        staged.stage(&main, "first").await.unwrap();
+        staged.stage(&extra, "extra").await.unwrap();
+        staged.stage(&main, "second").await.unwrap();
+        staged.commit().await.unwrap();
+        let main_after = tokio::fs::read_to_string(&main).await.unwrap();
+        let extra_after = tokio::fs::read_to_string(&extra).await.unwrap();
+        let temp_left = main_temp.exists();
+        let _ = tokio::fs::remove_dir_all(&dir).await;
+
+        assert_eq!(after_abort, "old");
+        assert_eq!(leftovers, (false, false));
+        assert_eq!(main_after, "second");
+        assert_eq!(extra_after, "extra");
+        assert!(!temp_left);
+    }
+
+    #[tokio::test]
+    async fn failed_commit_restores_already_replaced_targets() {
+        let dir =
+            std::env::temp_dir().join(format!("miao-test-staged-rollback-{}", std::process::id()));
+        let main = dir.join("config.json");
+        let fresh = dir.join("fresh.json");
+        // 非空目录无法被文件重命名覆盖，用来让最后一步失败
+        let blocker = dir.join("blocker");
+        tokio::fs::create_dir_all(blocker.join("inner"))
+            .await
+            .unwrap();
+        tokio::fs::write(&main, "old").await.unwrap();
+
+        let mut staged = StagedWrite::default();
+        staged.stage(&main, "new").await.unwrap();
+        staged.stage(&fresh, "fresh").await.unwrap();
+        staged.stage(&blocker, "blocked").await.unwrap();
+        let result = staged.commit().await;
+
+        let main_after = tokio::fs::read_to_string(&main).await.unwrap();
+        let mut leftovers = Vec::new();
+        let mut entries = tokio::fs::read_dir(&dir).await.unwrap();
+        while let Some(entry) = entries.next_entry().await.unwrap() {
+            leftovers.push(entry.file_name().to_string_lossy().into_owned());
+        }
+        leftovers.sort();
+        let _ = tokio::fs::remove_dir_all(&dir).await;
+
+        assert!(result.is_err());
+        assert_eq!(main_after, "old");
+        assert_eq!(leftovers, vec!["blocker", "config.json"]);
+    }
+}
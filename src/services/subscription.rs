@@ -1,49 +1,595 @@
+use futures::StreamExt;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE, USER_AGENT};
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::LazyLock;
+use std::time::{Instant, SystemTime};
+use tracing::{info, warn};
+
 use crate::error::{AppError, AppResult};
-use crate::services::node_parser::parse_clash_proxies;
+use crate::models::{
+    LimitBy, NodeFilter, Subscription, SubscriptionFormat, SubscriptionUserInfo,
+    DEFAULT_MAX_SUB_BYTES,
+};
+use crate::services::name_transform::NameTransformer;
+use crate::services::node_parser::{
+    parse_clash_proxies_with, parse_singbox_outbounds, ParseOptions, ParseResult,
+};
+use crate::services::proxy::node_identity;
+use crate::services::region::detect_region;
+
+const DEFAULT_USER_AGENT: &str = "clash-meta";
+const REGION_DROP_REASON: &str = "outside selected regions";
+const NAME_FILTER_DROP_REASON: &str = "filtered by include/exclude";
 
 /// 订阅获取结果，包含节点和解析错误信息
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct FetchResult {
     pub node_names: Vec<String>,
     pub outbounds: Vec<serde_json::Value>,
     pub parse_errors: Vec<String>,
     pub total_count: usize,
+    pub dropped: BTreeMap<String, usize>,
+    pub userinfo: Option<SubscriptionUserInfo>,
+    /// 实际从服务端获取的时间，复用缓存时保持不变
+    pub fetched_at: Instant,
+    /// 订阅正文的哈希，定时刷新时正文未变则复用解析结果；
+    /// 使用 proxy-providers 的订阅为空，每次都重新处理
+    pub content_hash: Option<u64>,
 }
 
-pub async fn fetch_sub(link: &str, client: &reqwest::Client) -> AppResult<FetchResult> {
-    let res = client
-        .get(link)
-        .timeout(std::time::Duration::from_secs(30))
-        .header("User-Agent", "clash-meta")
-        .send()
+/// 将 Unix 时间戳格式化为 UTC 日期时间（civil-from-days 算法，避免引入日期库）
+pub(crate) fn format_unix_timestamp(timestamp: i64) -> String {
+    let days = timestamp.div_euclid(86_400);
+    let secs = timestamp.rem_euclid(86_400);
+
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        secs / 3_600,
+        secs % 3_600 / 60,
+        secs % 60
+    )
+}
+
+/// 用于比较订阅是否重复的 URL：去掉首尾空白，主机名转为小写
+pub fn normalize_sub_url(url: &str) -> String {
+    let trimmed = url.trim();
+    match url::Url::parse(trimmed) {
+        Ok(parsed) => parsed.to_string(),
+        Err(_) => trimmed.to_string(),
+    }
+}
+
+/// 重复的订阅 URL（按 normalize_sub_url 比较），每个重复项只列出后出现的那些
+pub fn duplicate_sub_urls(subs: &[Subscription]) -> Vec<String> {
+    let mut seen = std::collections::HashSet::new();
+    subs.iter()
+        .filter(|sub| !seen.insert(normalize_sub_url(&sub.url)))
+        .map(|sub| sub.url.clone())
+        .collect()
+}
+
+/// 去掉重复的订阅，保留第一次出现的条目，返回被去掉的 URL
+pub fn dedupe_subscriptions(subs: &mut Vec<Subscription>) -> Vec<String> {
+    let mut seen = std::collections::HashSet::new();
+    let mut removed = Vec::new();
+    subs.retain(|sub| {
+        let keep = seen.insert(normalize_sub_url(&sub.url));
+        if !keep {
+            removed.push(sub.url.clone());
+        }
+        keep
+    });
+    removed
+}
+
+/// 解析 subscription-userinfo 响应头，如 "upload=1; download=2; total=3; expire=1700000000"
+pub fn parse_subscription_userinfo(header: &str) -> Option<SubscriptionUserInfo> {
+    let mut info = SubscriptionUserInfo::default();
+
+    for field in header.split(';') {
+        let Some((key, value)) = field.split_once('=') else {
+            continue;
+        };
+        let value = value.trim();
+        let number = value
+            .parse::<u64>()
+            .ok()
+            .or_else(|| value.parse::<f64>().ok().map(|v| v.max(0.0) as u64));
+        match key.trim().to_ascii_lowercase().as_str() {
+            "upload" => info.upload = number,
+            "download" => info.download = number,
+            "total" => info.total = number,
+            // expire=0 表示永不过期；超出 i64 范围的时间戳同样视为不过期
+            "expire" => {
+                info.expire = number
+                    .filter(|v| *v > 0)
+                    .and_then(|v| i64::try_from(v).ok())
+            }
+            _ => {}
+        }
+    }
+
+    info.expire_at = info.expire.map(format_unix_timestamp);
+    (info != SubscriptionUserInfo::default()).then_some(info)
+}
+
+/// 已构建的双向 TLS 客户端，证书或私钥文件修改后重新构建
+struct IdentityClient {
+    modified: (SystemTime, SystemTime),
+    client: reqwest::Client,
+}
+
+/// 按 (证书路径, 私钥路径) 缓存双向 TLS 客户端，避免每次刷新订阅都重新读取和解析
+static IDENTITY_CLIENTS: LazyLock<tokio::sync::Mutex<HashMap<(String, String), IdentityClient>>> =
+    LazyLock::new(Default::default);
+
+async fn read_identity_file(path: &str, what: &str) -> AppResult<(Vec<u8>, SystemTime)> {
+    let context = || format!("Failed to read client {} {}", what, path);
+    let modified = tokio::fs::metadata(path)
         .await
-        .map_err(|e| AppError::context(format!("Failed to fetch subscription from {}", link), e))?
-        .error_for_status()
+        .and_then(|metadata| metadata.modified())
+        .map_err(|e| AppError::context(context(), e))?;
+    let content = tokio::fs::read(path)
+        .await
+        .map_err(|e| AppError::context(context(), e))?;
+    Ok((content, modified))
+}
+
+fn contains_pem_block(content: &[u8], label: &str) -> bool {
+    String::from_utf8_lossy(content).contains(&format!("{}-----", label))
+}
+
+/// 加载 PEM 格式的客户端证书和私钥，构建用于双向 TLS 订阅的 HTTP 客户端；
+/// 结果按路径缓存，两个文件都未修改时直接复用
+pub async fn client_with_identity(cert_path: &str, key_path: &str) -> AppResult<reqwest::Client> {
+    let (mut pem, cert_modified) = read_identity_file(cert_path, "certificate").await?;
+    let (key, key_modified) = read_identity_file(key_path, "key").await?;
+    let modified = (cert_modified, key_modified);
+    let cache_key = (cert_path.to_string(), key_path.to_string());
+    let mut cache = IDENTITY_CLIENTS.lock().await;
+    if let Some(cached) = cache
+        .get(&cache_key)
+        .filter(|cached| cached.modified == modified)
+    {
+        return Ok(cached.client.clone());
+    }
+
+    if !contains_pem_block(&pem, "BEGIN CERTIFICATE") {
+        return Err(AppError::message(format!(
+            "Client certificate {} contains no PEM certificate",
+            cert_path
+        )));
+    }
+    if !contains_pem_block(&key, "PRIVATE KEY") {
+        return Err(AppError::message(format!(
+            "Client key {} contains no PEM private key",
+            key_path
+        )));
+    }
+    if !pem.ends_with(b"\n") {
+        pem.push(b'\n');
+    }
+    pem.extend_from_slice(&key);
+
+    let identity = reqwest::Identity::from_pem(&pem).map_err(|e| {
+        AppError::context(
+            format!(
+                "Failed to load client identity from {} and {}",
+                cert_path, key_path
+            ),
+            e,
+        )
+    })?;
+
+    let client = reqwest::Client::builder()
+        .timeout(std::time::Duration::from_secs(30))
+        .identity(identity)
+        .build()
         .map_err(|e| {
             AppError::context(
-                format!("Subscription server returned HTTP error for {}", link),
+                format!(
+                    "Client certificate {} does not match key {}",
+                    cert_path, key_path
+                ),
                 e,
             )
         })?;
+    cache.insert(
+        cache_key,
+        IdentityClient {
+            modified,
+            client: client.clone(),
+        },
+    );
+    Ok(client)
+}
+
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// 将订阅响应解码为 UTF-8 文本，去掉开头的 BOM；非 UTF-8 内容直接报错而不是静默替换
+fn decode_subscription_body(body: &[u8]) -> AppResult<&str> {
+    let body = body.strip_prefix(UTF8_BOM).unwrap_or(body);
+    std::str::from_utf8(body).map_err(|e| {
+        AppError::message(format!(
+            "Subscription response is not valid UTF-8 (invalid byte at offset {})",
+            e.valid_up_to()
+        ))
+    })
+}
+
+/// 流式读取响应体，累计超过 max_bytes 时立即中止，避免异常的订阅服务器耗尽内存
+async fn read_body_capped(
+    res: reqwest::Response,
+    link: &str,
+    max_bytes: usize,
+) -> AppResult<Vec<u8>> {
+    let too_large = || {
+        AppError::message(format!(
+            "Subscription response from {} exceeds max_sub_bytes ({} bytes)",
+            link, max_bytes
+        ))
+    };
+    if res
+        .content_length()
+        .is_some_and(|len| len > max_bytes as u64)
+    {
+        return Err(too_large());
+    }
+
+    let mut body = Vec::new();
+    let mut stream = res.bytes_stream();
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.map_err(|e| {
+            AppError::context(
+                format!("Failed to read subscription response from {}", link),
+                e,
+            )
+        })?;
+        if body.len() + chunk.len() > max_bytes {
+            return Err(too_large());
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(body)
+}
+
+/// Cloudflare 验证页（"Just a moment..."）中的特征字符串
+const CHALLENGE_MARKERS: &[&str] = &[
+    "cf-chl",
+    "cf_chl_opt",
+    "challenge-platform",
+    "cf-browser-verification",
+    "<title>Just a moment...</title>",
+    "<title>Attention Required! | Cloudflare</title>",
+];
+
+/// 订阅请求头：User-Agent 加上订阅配置的额外请求头（如通过 Cloudflare 验证后得到的 Cookie）
+fn request_headers(sub: &Subscription) -> AppResult<HeaderMap> {
+    let user_agent = sub.user_agent.as_deref().unwrap_or(DEFAULT_USER_AGENT);
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        USER_AGENT,
+        HeaderValue::from_str(user_agent).map_err(|_| {
+            AppError::message(format!("Invalid subscription user_agent: {}", user_agent))
+        })?,
+    );
+    for (name, value) in &sub.headers {
+        let name = HeaderName::from_bytes(name.as_bytes()).map_err(|_| {
+            AppError::message(format!("Invalid subscription header name '{}'", name))
+        })?;
+        let value = HeaderValue::from_str(value).map_err(|_| {
+            AppError::message(format!("Invalid value for subscription header '{}'", name))
+        })?;
+        headers.insert(name, value);
+    }
+    Ok(headers)
+}
+
+fn is_html_content_type(headers: &HeaderMap) -> bool {
+    headers
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| {
+            value
+                .trim_start()
+                .to_ascii_lowercase()
+                .starts_with("text/html")
+        })
+}
+
+fn is_challenge_page(headers: &HeaderMap, body: &[u8]) -> bool {
+    let mitigated = headers
+        .get("cf-mitigated")
+        .is_some_and(|value| value.as_bytes().eq_ignore_ascii_case(b"challenge"));
+    let text = String::from_utf8_lossy(body);
+    mitigated || CHALLENGE_MARKERS.iter().any(|marker| text.contains(marker))
+}
+
+fn looks_like_html(body: &[u8]) -> bool {
+    let text = String::from_utf8_lossy(body);
+    let head = text
+        .trim_start()
+        .get(..15)
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    head.starts_with("<!doctype html") || head.starts_with("<html")
+}
 
-    let text = res.text().await.map_err(|e| {
+fn challenge_error(link: &str) -> AppError {
+    AppError::message(format!(
+        "Subscription {} returned an HTML challenge page, not proxy data; \
+         set headers (e.g. Cookie with cf_clearance) and a matching user_agent for this subscription",
+        link
+    ))
+}
+
+/// 下载订阅（或 proxy provider）内容，返回 subscription-userinfo 与解码后的文本。
+/// 服务端返回 HTML（常见于 Cloudflare 验证页）时直接报错，不再当作 YAML 解析
+async fn fetch_body(
+    client: &reqwest::Client,
+    link: &str,
+    headers: &HeaderMap,
+    max_bytes: usize,
+) -> AppResult<(Option<SubscriptionUserInfo>, String)> {
+    let res = client
+        .get(link)
+        .timeout(std::time::Duration::from_secs(30))
+        .headers(headers.clone())
+        .send()
+        .await
+        .map_err(|e| AppError::context(format!("Failed to fetch subscription from {}", link), e))?;
+
+    let response_headers = res.headers().clone();
+    if let Err(e) = res.error_for_status_ref() {
+        // 验证页通常以 403/503 返回，读取少量正文用于识别
+        let body = read_body_capped(res, link, max_bytes)
+            .await
+            .unwrap_or_default();
+        if is_challenge_page(&response_headers, &body) {
+            return Err(challenge_error(link));
+        }
+        return Err(AppError::context(
+            format!("Subscription server returned HTTP error for {}", link),
+            e,
+        ));
+    }
+
+    let userinfo = response_headers
+        .get("subscription-userinfo")
+        .and_then(|value| value.to_str().ok())
+        .and_then(parse_subscription_userinfo);
+
+    let body = read_body_capped(res, link, max_bytes).await?;
+    if is_challenge_page(&response_headers, &body) {
+        return Err(challenge_error(link));
+    }
+    if is_html_content_type(&response_headers) && looks_like_html(&body) {
+        return Err(AppError::message(format!(
+            "Subscription {} returned an HTML page, not proxy data",
+            link
+        )));
+    }
+    let text = decode_subscription_body(&body).map_err(|e| {
         AppError::context(
-            format!("Failed to read subscription response from {}", link),
+            format!("Failed to decode subscription response from {}", link),
             e,
         )
     })?;
 
-    let parse_result = parse_clash_proxies(&text).map_err(|e| {
+    Ok((userinfo, text.to_string()))
+}
+
+fn max_body_bytes(parse_options: &ParseOptions) -> usize {
+    parse_options
+        .max_body_bytes
+        .unwrap_or(DEFAULT_MAX_SUB_BYTES)
+}
+
+async fn fetch_and_parse(
+    client: &reqwest::Client,
+    link: &str,
+    headers: &HeaderMap,
+    parse_options: &ParseOptions,
+) -> AppResult<(Option<SubscriptionUserInfo>, ParseResult)> {
+    let (userinfo, text) = fetch_body(client, link, headers, max_body_bytes(parse_options)).await?;
+    let parse_result = parse_clash_proxies_with(&text, parse_options).map_err(|e| {
         AppError::context(
             format!("Failed to parse subscription content from {}", link),
             e,
         )
     })?;
+    Ok((userinfo, parse_result))
+}
+
+/// 获取 proxy-providers 引用的各个 http 提供者并把节点合并进主订阅结果。
+/// 单个提供者失败只记录错误；提供者内部再嵌套的 proxy-providers 不再展开
+async fn merge_proxy_providers(
+    result: &mut ParseResult,
+    client: &reqwest::Client,
+    headers: &HeaderMap,
+    parse_options: &ParseOptions,
+) {
+    let providers = std::mem::take(&mut result.providers);
+    let fetches = providers.iter().map(|provider| async move {
+        info!(provider = %provider.name, url = %provider.url, "Fetching proxy provider");
+        (
+            provider,
+            fetch_and_parse(client, &provider.url, headers, parse_options).await,
+        )
+    });
+
+    for (provider, fetched) in futures::future::join_all(fetches).await {
+        match fetched {
+            Ok((_, provider_result)) => {
+                if !provider_result.providers.is_empty() {
+                    warn!(provider = %provider.name, "Nested proxy-providers are not expanded");
+                }
+                result.total_count += provider_result.total_count;
+                result.nodes.extend(provider_result.nodes);
+                result.errors.extend(provider_result.errors);
+                for (reason, count) in provider_result.dropped {
+                    *result.dropped.entry(reason).or_default() += count;
+                }
+            }
+            Err(e) => result.errors.push(format!(
+                "Proxy provider '{}' ({}): {}",
+                provider.name, provider.url, e
+            )),
+        }
+    }
+}
+
+/// 依次按地区、include、exclude 筛选节点，被丢弃的数量计入 dropped
+pub(crate) fn apply_node_filter(
+    nodes: &mut Vec<(String, serde_json::Value)>,
+    filter: &NodeFilter,
+    dropped: &mut BTreeMap<String, usize>,
+) -> AppResult<()> {
+    let compile = |pattern: &Option<String>| {
+        pattern
+            .as_deref()
+            .map(regex::Regex::new)
+            .transpose()
+            .map_err(|e| AppError::message(format!("Invalid node filter pattern: {}", e)))
+    };
+    let include = compile(&filter.include)?;
+    let exclude = compile(&filter.exclude)?;
+
+    if !filter.regions.is_empty() {
+        let before = nodes.len();
+        nodes.retain(|(name, _)| {
+            detect_region(name).is_some_and(|region| filter.regions.iter().any(|r| r == region))
+        });
+        if nodes.len() < before {
+            dropped.insert(REGION_DROP_REASON.to_string(), before - nodes.len());
+        }
+    }
+
+    let before = nodes.len();
+    nodes.retain(|(name, _)| {
+        include.as_ref().is_none_or(|re| re.is_match(name))
+            && !exclude.as_ref().is_some_and(|re| re.is_match(name))
+    });
+    if nodes.len() < before {
+        dropped.insert(NAME_FILTER_DROP_REASON.to_string(), before - nodes.len());
+    }
+    Ok(())
+}
+
+fn hash_content(text: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    text.hash(&mut hasher);
+    hasher.finish()
+}
+
+pub async fn fetch_sub(
+    sub: &Subscription,
+    client: &reqwest::Client,
+    parse_options: &ParseOptions,
+    filter: &NodeFilter,
+) -> AppResult<FetchResult> {
+    fetch_sub_with(sub, client, parse_options, filter, None).await
+}
+
+/// 与 fetch_sub 相同；`previous` 是该订阅上次的结果，正文哈希相同时直接复用其节点，
+/// 只更新获取时间和流量信息
+pub async fn fetch_sub_with(
+    sub: &Subscription,
+    client: &reqwest::Client,
+    parse_options: &ParseOptions,
+    filter: &NodeFilter,
+    previous: Option<&FetchResult>,
+) -> AppResult<FetchResult> {
+    let link = sub.url.as_str();
+    let name_transformer =
+        NameTransformer::new(&parse_options.name_transforms).map_err(AppError::message)?;
+    let headers = request_headers(sub)?;
+    let (userinfo, text) =
+        fetch_body(client, link, &headers, max_body_bytes(parse_options)).await?;
+    let content_hash = hash_content(&text);
+    if let Some(previous) = previous.filter(|previous| previous.content_hash == Some(content_hash))
+    {
+        info!(url = %link, "Subscription content unchanged, reusing parsed nodes");
+        return Ok(FetchResult {
+            userinfo,
+            fetched_at: Instant::now(),
+            ..previous.clone()
+        });
+    }
+
+    let (parse_result, content_hash) = match sub.format {
+        SubscriptionFormat::Clash => {
+            let mut parse_result = parse_clash_proxies_with(&text, parse_options).map_err(|e| {
+                AppError::context(
+                    format!("Failed to parse subscription content from {}", link),
+                    e,
+                )
+            })?;
+            // 提供者的内容不在哈希范围内
+            let content_hash = parse_result.providers.is_empty().then_some(content_hash);
+            merge_proxy_providers(&mut parse_result, client, &headers, parse_options).await;
+            (parse_result, content_hash)
+        }
+        SubscriptionFormat::SingboxJson => {
+            let parse_result = parse_singbox_outbounds(&text).map_err(|e| {
+                AppError::context(
+                    format!("Failed to parse subscription content from {}", link),
+                    e,
+                )
+            })?;
+            (parse_result, Some(content_hash))
+        }
+    };
 
     let total_count = parse_result.total_count;
-    let node_names: Vec<String> = parse_result.nodes.iter().map(|(n, _)| n.clone()).collect();
-    let outbounds: Vec<serde_json::Value> =
-        parse_result.nodes.into_iter().map(|(_, o)| o).collect();
+    let mut nodes = parse_result.nodes;
+    let mut dropped = parse_result.dropped;
+    apply_node_filter(&mut nodes, filter, &mut dropped)?;
+
+    if !name_transformer.is_empty() {
+        for (name, outbound) in &mut nodes {
+            *name = name_transformer.apply(name);
+            outbound["tag"] = serde_json::Value::String(name.clone());
+        }
+    }
+
+    // 按最快选取的结果取决于当时的延迟，不能按正文哈希复用
+    let content_hash = content_hash.filter(|_| sub.limit_by == LimitBy::First);
+    if let Some(limit) = sub.limit.filter(|limit| nodes.len() > *limit) {
+        if sub.limit_by == LimitBy::Fastest {
+            // 稳定排序：延迟相同或没有记录的节点保持订阅中的顺序
+            nodes.sort_by_key(|(_, outbound)| {
+                node_identity(outbound)
+                    .and_then(|identity| parse_options.latencies.get(&identity).copied())
+                    .unwrap_or(u64::MAX)
+            });
+        }
+        info!(
+            url = %link,
+            available = nodes.len(),
+            limit,
+            limit_by = ?sub.limit_by,
+            "Subscription truncated to node limit"
+        );
+        nodes.truncate(limit);
+    }
+
+    let node_names: Vec<String> = nodes.iter().map(|(n, _)| n.clone()).collect();
+    let outbounds: Vec<serde_json::Value> = nodes.into_iter().map(|(_, o)| o).collect();
 
     // 解析错误将由调用方统一处理，此处不再打印
 
@@ -52,12 +598,50 @@ pub async fn fetch_sub(link: &str, client: &reqwest::Client) -> AppResult<FetchR
         outbounds,
         parse_errors: parse_result.errors,
         total_count,
+        dropped,
+        userinfo,
+        fetched_at: Instant::now(),
+        content_hash,
     })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::models::NameTransform;
+    use crate::services::node_parser::parse_clash_proxies;
+    use std::collections::HashMap;
+
+    #[test]
+    fn dedupe_subscriptions_compares_normalized_urls() {
+        let sub = |url: &str| Subscription {
+            url: url.to_string(),
+            ..Default::default()
+        };
+        let mut subs = vec![
+            sub("https://Sub.Example.com/api?token=a"),
+            sub("https://b.example.com/sub"),
+            sub(" https://sub.example.com/api?token=a "),
+            sub("https://sub.example.com/api?token=B"),
+        ];
+
+        assert_eq!(
+            duplicate_sub_urls(&subs),
+            vec![" https://sub.example.com/api?token=a "]
+        );
+        let removed = dedupe_subscriptions(&mut subs);
+
+        assert_eq!(removed, vec![" https://sub.example.com/api?token=a "]);
+        let urls: Vec<_> = subs.iter().map(|sub| sub.url.as_str()).collect();
+        assert_eq!(
+            urls,
+            vec![
+                "https://Sub.Example.com/api?token=a",
+                "https://b.example.com/sub",
+                "https://sub.example.com/api?token=B",
+            ]
+        );
+    }
 
     #[tokio::test]
     async fn fetch_sub_rejects_http_error_status() {
@@ -74,15 +658,523 @@ mod tests {
         });
 
         let client = reqwest::Client::new();
-        let err = fetch_sub(&format!("http://{addr}/sub"), &client)
-            .await
-            .unwrap_err();
+        let err = fetch_sub(
+            &format!("http://{addr}/sub").into(),
+            &client,
+            &ParseOptions::default(),
+            &NodeFilter::default(),
+        )
+        .await
+        .unwrap_err();
         let message = err.to_string();
 
         assert!(message.contains("Subscription server returned HTTP error"));
         assert!(message.contains("500"));
     }
 
+    #[tokio::test]
+    async fn fetch_sub_reports_cloudflare_challenge_page() {
+        use axum::{
+            http::{header, HeaderMap as RequestHeaders, StatusCode},
+            routing::get,
+            Router,
+        };
+
+        const CHALLENGE: &str = "<!DOCTYPE html><html><head><title>Just a moment...</title>\
+            <script src=\"/cdn-cgi/challenge-platform/h/b/orchestrate/chl_page/v1\"></script>\
+            </head></html>";
+        let app = Router::new()
+            .route(
+                "/challenge",
+                get(|| async {
+                    (
+                        StatusCode::FORBIDDEN,
+                        [(header::CONTENT_TYPE, "text/html; charset=UTF-8")],
+                        CHALLENGE,
+                    )
+                }),
+            )
+            .route(
+                "/login",
+                get(|| async {
+                    (
+                        [(header::CONTENT_TYPE, "text/html")],
+                        "<html><body>login</body></html>",
+                    )
+                }),
+            )
+            .route(
+                "/cookie",
+                get(|headers: RequestHeaders| async move {
+                    match headers.get(header::COOKIE) {
+                        Some(cookie) if cookie == "cf_clearance=ok" => (
+                            StatusCode::OK,
+                            "proxies:\n  - {name: a, type: ss, server: a.example.com, port: 1, cipher: aes-128-gcm, password: p}\n",
+                        ),
+                        _ => (StatusCode::SERVICE_UNAVAILABLE, CHALLENGE),
+                    }
+                }),
+            );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        let client = reqwest::Client::new();
+        let fetch = |sub: Subscription| {
+            let client = client.clone();
+            async move {
+                fetch_sub(
+                    &sub,
+                    &client,
+                    &ParseOptions::default(),
+                    &NodeFilter::default(),
+                )
+                .await
+            }
+        };
+
+        let err = fetch(format!("http://{addr}/challenge").into())
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("HTML challenge page"));
+
+        let err = fetch(format!("http://{addr}/login").into())
+            .await
+            .unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("returned an HTML page, not proxy data"));
+
+        let result = fetch(Subscription {
+            url: format!("http://{addr}/cookie"),
+            headers: BTreeMap::from([("Cookie".to_string(), "cf_clearance=ok".to_string())]),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        assert_eq!(result.node_names, vec!["a"]);
+    }
+
+    #[tokio::test]
+    async fn fetch_sub_aborts_when_body_exceeds_limit() {
+        use axum::{routing::get, Router};
+
+        let app = Router::new().route("/sub", get(|| async { "proxies: []\n".repeat(100) }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+
+        let options = ParseOptions {
+            max_body_bytes: Some(64),
+            ..Default::default()
+        };
+        let err = fetch_sub(
+            &format!("http://{addr}/sub").into(),
+            &reqwest::Client::new(),
+            &options,
+            &NodeFilter::default(),
+        )
+        .await
+        .unwrap_err();
+
+        assert!(err.to_string().contains("exceeds max_sub_bytes (64 bytes)"));
+    }
+
+    #[tokio::test]
+    async fn fetch_sub_truncates_to_subscription_limit() {
+        use axum::{routing::get, Router};
+
+        let app = Router::new().route(
+            "/sub",
+            get(|| async {
+                (1..=3)
+                    .map(|i| {
+                        format!(
+                            "  - {{name: node-{i}, type: ss, server: s{i}.example.com, port: 8388, cipher: aes-128-gcm, password: p}}\n"
+                        )
+                    })
+                    .fold("proxies:\n".to_string(), |yaml, line| yaml + &line)
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+
+        let sub = Subscription {
+            url: format!("http://{addr}/sub"),
+            limit: Some(2),
+            ..Default::default()
+        };
+        let result = fetch_sub(
+            &sub,
+            &reqwest::Client::new(),
+            &ParseOptions::default(),
+            &sub.filter,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(result.node_names, vec!["node-1", "node-2"]);
+        assert_eq!(result.outbounds.len(), 2);
+        assert_eq!(result.total_count, 3);
+    }
+
+    #[tokio::test]
+    async fn fetch_sub_keeps_fastest_nodes_when_limited_by_latency() {
+        use axum::{routing::get, Router};
+
+        let app = Router::new().route(
+            "/sub",
+            get(|| async {
+                (1..=4)
+                    .map(|i| {
+                        format!(
+                            "  - {{name: node-{i}, type: ss, server: s{i}.example.com, port: 8388, cipher: aes-128-gcm, password: p}}\n"
+                        )
+                    })
+                    .fold("proxies:\n".to_string(), |yaml, line| yaml + &line)
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+
+        let sub = Subscription {
+            url: format!("http://{addr}/sub"),
+            limit: Some(2),
+            limit_by: LimitBy::Fastest,
+            ..Default::default()
+        };
+        // node-1 没有延迟记录，排在已测节点之后
+        let options = ParseOptions {
+            latencies: HashMap::from([
+                ("shadowsocks://s2.example.com:8388".to_string(), 300),
+                ("shadowsocks://s3.example.com:8388".to_string(), 80),
+                ("shadowsocks://s4.example.com:8388".to_string(), 120),
+            ]),
+            ..Default::default()
+        };
+        let result = fetch_sub(&sub, &reqwest::Client::new(), &options, &sub.filter)
+            .await
+            .unwrap();
+
+        assert_eq!(result.node_names, vec!["node-3", "node-4"]);
+        assert_eq!(result.total_count, 4);
+        assert_eq!(result.content_hash, None);
+    }
+
+    #[tokio::test]
+    async fn fetch_sub_merges_http_proxy_providers() {
+        use axum::{extract::State, http::StatusCode, routing::get, Router};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = Router::new()
+            .route(
+                "/sub",
+                get(|State(addr): State<std::net::SocketAddr>| async move {
+                    format!(
+                        "proxies:\n  - {{name: inline, type: ss, server: i.example.com, port: 8388, cipher: aes-128-gcm, password: p}}\nproxy-providers:\n  good:\n    type: http\n    url: http://{addr}/provider\n  broken:\n    type: http\n    url: http://{addr}/missing\n  local:\n    type: file\n    path: ./local.yaml\n"
+                    )
+                }),
+            )
+            .route(
+                "/provider",
+                get(|| async {
+                    "proxies:\n  - {name: from-provider, type: ss, server: p.example.com, port: 8388, cipher: aes-128-gcm, password: p}\n"
+                }),
+            )
+            .route("/missing", get(|| async { StatusCode::NOT_FOUND }))
+            .with_state(addr);
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+
+        let result = fetch_sub(
+            &format!("http://{addr}/sub").into(),
+            &reqwest::Client::new(),
+            &ParseOptions::default(),
+            &NodeFilter::default(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(result.node_names, vec!["inline", "from-provider"]);
+        assert_eq!(result.total_count, 2);
+        assert_eq!(result.parse_errors.len(), 2);
+        assert!(result.parse_errors.iter().any(|e| e.contains("'broken'")));
+        assert!(result.parse_errors.iter().any(|e| e.contains("'local'")));
+    }
+
+    #[tokio::test]
+    async fn fetch_sub_with_reuses_previous_result_when_content_is_unchanged() {
+        use axum::{routing::get, Router};
+
+        let app = Router::new().route(
+            "/sub",
+            get(|| async {
+                "proxies:\n  - {name: node-1, type: ss, server: s.example.com, port: 8388, cipher: aes-128-gcm, password: p}\n"
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        let sub: Subscription = format!("http://{addr}/sub").into();
+        let client = reqwest::Client::new();
+        let options = ParseOptions::default();
+
+        let first = fetch_sub(&sub, &client, &options, &sub.filter)
+            .await
+            .unwrap();
+        assert!(first.content_hash.is_some());
+
+        // 正文未变时不重新解析，返回上次的结果
+        let marked = FetchResult {
+            node_names: vec!["cached".to_string()],
+            ..first.clone()
+        };
+        let reused = fetch_sub_with(&sub, &client, &options, &sub.filter, Some(&marked))
+            .await
+            .unwrap();
+        assert_eq!(reused.node_names, vec!["cached"]);
+        assert!(reused.fetched_at > first.fetched_at);
+
+        let stale = FetchResult {
+            content_hash: first.content_hash.map(|hash| hash ^ 1),
+            ..marked
+        };
+        let reparsed = fetch_sub_with(&sub, &client, &options, &sub.filter, Some(&stale))
+            .await
+            .unwrap();
+        assert_eq!(reparsed.node_names, vec!["node-1"]);
+        assert_eq!(reparsed.content_hash, first.content_hash);
+    }
+
+    #[tokio::test]
+    async fn fetch_sub_applies_name_transforms_to_names_and_tags() {
+        use axum::{routing::get, Router};
+
+        let app = Router::new().route(
+            "/sub",
+            get(|| async {
+                "proxies:\n  - {name: \"🇯🇵  Tokyo 01 \", type: ss, server: jp.example.com, port: 8388, cipher: aes-128-gcm, password: p}\n"
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+
+        let options = ParseOptions {
+            name_transforms: vec![NameTransform::StripEmoji, NameTransform::Trim],
+            ..Default::default()
+        };
+        let result = fetch_sub(
+            &format!("http://{addr}/sub").into(),
+            &reqwest::Client::new(),
+            &options,
+            &NodeFilter::default(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(result.node_names, vec!["Tokyo 01"]);
+        assert_eq!(result.outbounds[0]["tag"], "Tokyo 01");
+    }
+
+    #[tokio::test]
+    async fn fetch_sub_keeps_only_selected_regions() {
+        use axum::{routing::get, Router};
+
+        let app = Router::new().route(
+            "/sub",
+            get(|| async {
+                "proxies:\n  - {name: HK 01, type: ss, server: hk.example.com, port: 8388, cipher: aes-128-gcm, password: p}\n  - {name: US 01, type: ss, server: us.example.com, port: 8388, cipher: aes-128-gcm, password: p}\n  - {name: 日本 02, type: ss, server: jp.example.com, port: 8388, cipher: aes-128-gcm, password: p}\n"
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+
+        let sub = Subscription {
+            url: format!("http://{addr}/sub"),
+            filter: NodeFilter {
+                regions: vec!["HK".to_string(), "JP".to_string()],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let result = fetch_sub(
+            &sub,
+            &reqwest::Client::new(),
+            &ParseOptions::default(),
+            &sub.filter,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(result.node_names, vec!["HK 01", "日本 02"]);
+        assert_eq!(result.dropped.get(REGION_DROP_REASON), Some(&1));
+    }
+
+    #[test]
+    fn apply_node_filter_applies_include_and_exclude_patterns() {
+        let node = |name: &str| (name.to_string(), serde_json::json!({"tag": name}));
+        let mut nodes = vec![
+            node("Japan 01"),
+            node("Japan 02 IPLC"),
+            node("Japan 03 0.1x"),
+            node("US 01"),
+        ];
+        let filter = NodeFilter {
+            include: Some("^Japan".to_string()),
+            exclude: Some(r"0\.1x".to_string()),
+            ..Default::default()
+        };
+        let mut dropped = BTreeMap::new();
+
+        apply_node_filter(&mut nodes, &filter, &mut dropped).unwrap();
+
+        let names: Vec<_> = nodes.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, vec!["Japan 01", "Japan 02 IPLC"]);
+        assert_eq!(dropped.get(NAME_FILTER_DROP_REASON), Some(&2));
+    }
+
+    #[tokio::test]
+    async fn fetch_sub_parses_singbox_json_outbounds() {
+        use axum::{routing::get, Router};
+
+        let app = Router::new().route(
+            "/nodes.json",
+            get(|| async {
+                r#"[
+                    {"type": "direct", "tag": "direct"},
+                    {"type": "hysteria2", "tag": "🇯🇵 Tokyo", "server": "jp.example.com", "server_port": 443, "password": "p"},
+                    {"type": "vless", "tag": "missing-server", "server_port": 443}
+                ]"#
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+
+        let sub = Subscription {
+            url: format!("http://{addr}/nodes.json"),
+            format: SubscriptionFormat::SingboxJson,
+            ..Default::default()
+        };
+        let options = ParseOptions {
+            name_transforms: vec![NameTransform::StripEmoji, NameTransform::Trim],
+            ..Default::default()
+        };
+        let result = fetch_sub(&sub, &reqwest::Client::new(), &options, &sub.filter)
+            .await
+            .unwrap();
+
+        assert_eq!(result.total_count, 3);
+        assert_eq!(result.node_names, vec!["Tokyo"]);
+        assert_eq!(result.outbounds[0]["tag"], "Tokyo");
+        assert_eq!(result.outbounds[0]["password"], "p");
+        assert_eq!(result.dropped.get("non-proxy type 'direct'"), Some(&1));
+        assert_eq!(result.parse_errors.len(), 1);
+    }
+
+    #[test]
+    fn parse_subscription_userinfo_reads_quota_and_expiry() {
+        let info = parse_subscription_userinfo(
+            "upload=1024; download=2048;total=107374182400; expire=1735689600",
+        )
+        .unwrap();
+
+        assert_eq!(info.upload, Some(1024));
+        assert_eq!(info.download, Some(2048));
+        assert_eq!(info.total, Some(107_374_182_400));
+        assert_eq!(info.expire, Some(1_735_689_600));
+        assert_eq!(info.expire_at.as_deref(), Some("2025-01-01 00:00:00 UTC"));
+
+        let never = parse_subscription_userinfo("upload=0; download=0; total=0; expire=0").unwrap();
+        assert_eq!(never.expire, None);
+        let overflow = parse_subscription_userinfo("total=1; expire=18446744073709551615").unwrap();
+        assert_eq!(overflow.expire, None);
+        assert_eq!(overflow.expire_at, None);
+        assert!(parse_subscription_userinfo("garbage").is_none());
+    }
+
+    #[tokio::test]
+    async fn client_with_identity_reports_missing_and_invalid_files() {
+        let dir = std::env::temp_dir().join(format!("miao-mtls-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let cert = dir.join("client.crt");
+        let key = dir.join("client.key");
+        std::fs::write(&cert, "not a certificate").unwrap();
+        std::fs::write(&key, "not a key").unwrap();
+
+        let missing = client_with_identity("/nonexistent/client.crt", key.to_str().unwrap())
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(missing.contains("Failed to read client certificate /nonexistent/client.crt"));
+
+        let invalid = client_with_identity(cert.to_str().unwrap(), key.to_str().unwrap())
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(invalid.contains(&format!(
+            "Client certificate {} contains no PEM certificate",
+            cert.display()
+        )));
+
+        std::fs::write(
+            &cert,
+            "-----BEGIN CERTIFICATE-----\nMIIB\n-----END CERTIFICATE-----\n",
+        )
+        .unwrap();
+        let bad_key = client_with_identity(cert.to_str().unwrap(), key.to_str().unwrap())
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(bad_key.contains(&format!(
+            "Client key {} contains no PEM private key",
+            key.display()
+        )));
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn decode_subscription_body_strips_utf8_bom() {
+        let body = b"\xEF\xBB\xBFproxies:\n  - name: bom-node\n    type: ss\n    server: ss.example.com\n    port: 8388\n    cipher: aes-128-gcm\n    password: pass-ss\n";
+
+        let text = decode_subscription_body(body).unwrap();
+        let result = parse_clash_proxies(text).unwrap();
+
+        assert!(text.starts_with("proxies:"));
+        assert_eq!(result.nodes.len(), 1);
+        assert_eq!(result.nodes[0].0, "bom-node");
+    }
+
+    #[test]
+    fn decode_subscription_body_rejects_invalid_utf8() {
+        let err = decode_subscription_body(b"proxies: \xFF\xFE").unwrap_err();
+
+        assert!(err.to_string().contains("not valid UTF-8"));
+        assert!(err.to_string().contains("offset 9"));
+    }
+
     #[test]
     fn parse_clash_proxies_extracts_supported_nodes() {
         let yaml = r#"
@@ -0,0 +1,228 @@
+use std::process::Stdio;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+use tokio::io::AsyncReadExt;
+use tokio::time::Duration;
+use tracing::info;
+
+use crate::error::{AppError, AppResult};
+use crate::models::{SingBoxChannel, TestRunResult};
+use crate::services::singbox::SingBoxPaths;
+
+/// 同时进行的试运行各自使用独立的配置文件，互不覆盖
+static TEST_RUN_SEQ: AtomicUsize = AtomicUsize::new(0);
+const TEST_RUN_DURATION: Duration = Duration::from_secs(3);
+const MAX_CAPTURED_OUTPUT: usize = 64 * 1024;
+
+/// 将候选配置改写为沙盒配置：去掉 TUN 等入站，只保留回环 mixed 入站，并关闭 clash_api 避免端口冲突
+fn sandbox_config(candidate: &serde_json::Value, mixed_port: u16) -> serde_json::Value {
+    let mut config = candidate.clone();
+    config["inbounds"] = serde_json::json!([{
+        "type": "mixed",
+        "tag": "testrun-in",
+        "listen": "127.0.0.1",
+        "listen_port": mixed_port
+    }]);
+    if let Some(obj) = config.as_object_mut() {
+        obj.remove("experimental");
+    }
+    config
+}
+
+fn free_loopback_port() -> AppResult<u16> {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")
+        .map_err(|e| AppError::context("Failed to reserve a loopback port for test run", e))?;
+    Ok(listener
+        .local_addr()
+        .map_err(|e| AppError::context("Failed to read reserved loopback port", e))?
+        .port())
+}
+
+/// 在后台持续读取子进程输出，只保留最后 MAX_CAPTURED_OUTPUT 字节，
+/// 避免管道写满后 sing-box 阻塞在输出上
+fn capture_output<R>(reader: Option<R>) -> tokio::task::JoinHandle<Vec<u8>>
+where
+    R: tokio::io::AsyncRead + Unpin + Send + 'static,
+{
+    tokio::spawn(async move {
+        let mut captured = Vec::new();
+        let Some(mut reader) = reader else {
+            return captured;
+        };
+        let mut chunk = [0u8; 8192];
+        while let Ok(read) = reader.read(&mut chunk).await {
+            if read == 0 {
+                break;
+            }
+            captured.extend_from_slice(&chunk[..read]);
+            if captured.len() > MAX_CAPTURED_OUTPUT {
+                captured.drain(..captured.len() - MAX_CAPTURED_OUTPUT);
+            }
+        }
+        captured
+    })
+}
+
+fn tail_output(stdout: &[u8], stderr: &[u8]) -> String {
+    let mut output = String::from_utf8_lossy(stdout).into_owned();
+    output.push_str(&String::from_utf8_lossy(stderr));
+    if output.len() > MAX_CAPTURED_OUTPUT {
+        let mut start = output.len() - MAX_CAPTURED_OUTPUT;
+        while !output.is_char_boundary(start) {
+            start += 1;
+        }
+        output.drain(..start);
+    }
+    output
+}
+
+fn test_run_config_filename() -> String {
+    format!(
+        "testrun-config-{}-{}.json",
+        std::process::id(),
+        TEST_RUN_SEQ.fetch_add(1, Ordering::Relaxed)
+    )
+}
+
+/// 用候选配置启动一个独立的 sing-box 进程，观察数秒内是否保持运行，然后结束它
+pub async fn test_run_config(
+    candidate: &serde_json::Value,
+    paths: &SingBoxPaths,
+    channel: SingBoxChannel,
+) -> AppResult<TestRunResult> {
+    let config_path = paths.config_dir.join(test_run_config_filename());
+    let config = sandbox_config(candidate, free_loopback_port()?);
+    tokio::fs::write(&config_path, serde_json::to_string(&config)?)
+        .await
+        .map_err(|e| AppError::context("Failed to write test run config", e))?;
+
+    info!(config = ?config_path, "Starting sing-box test run");
+    let started = Instant::now();
+    let spawn_result = tokio::process::Command::new(paths.binary_path(channel))
+        .current_dir(&paths.data_dir)
+        .arg("run")
+        .arg("-c")
+        .arg(&config_path)
+        .arg("-D")
+        .arg(&paths.data_dir)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn();
+    let mut child = match spawn_result {
+        Ok(child) => child,
+        Err(e) => {
+            let _ = tokio::fs::remove_file(&config_path).await;
+            return Err(AppError::context(
+                "Failed to spawn sing-box for test run",
+                e,
+            ));
+        }
+    };
+
+    let stdout = capture_output(child.stdout.take());
+    let stderr = capture_output(child.stderr.take());
+    let exit_status = match tokio::time::timeout(TEST_RUN_DURATION, child.wait()).await {
+        Ok(status) => {
+            Some(status.map_err(|e| AppError::context("Failed to wait for test run", e))?)
+        }
+        Err(_) => {
+            let _ = child.start_kill();
+            let _ = child.wait().await;
+            None
+        }
+    };
+    let duration_ms = started.elapsed().as_millis() as u64;
+
+    let stdout_buf = stdout.await.unwrap_or_default();
+    let stderr_buf = stderr.await.unwrap_or_default();
+    let _ = tokio::fs::remove_file(&config_path).await;
+
+    Ok(TestRunResult {
+        success: exit_status.is_none(),
+        exit_code: exit_status.and_then(|status| status.code()),
+        duration_ms,
+        output: tail_output(&stdout_buf, &stderr_buf),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{
+        sandbox_config, tail_output, test_run_config, test_run_config_filename, MAX_CAPTURED_OUTPUT,
+    };
+    use crate::models::{Config, SingBoxChannel};
+    use crate::services::singbox::SingBoxPaths;
+
+    #[test]
+    fn sandbox_config_replaces_inbounds_and_drops_clash_api() {
+        let candidate = json!({
+            "experimental": {"clash_api": {"external_controller": "127.0.0.1:6262"}},
+            "inbounds": [{"type": "tun", "tag": "tun-in"}],
+            "outbounds": [{"type": "direct", "tag": "direct"}]
+        });
+
+        let sandbox = sandbox_config(&candidate, 18080);
+
+        assert_eq!(
+            sandbox["inbounds"],
+            json!([{"type": "mixed", "tag": "testrun-in", "listen": "127.0.0.1", "listen_port": 18080}])
+        );
+        assert!(sandbox.get("experimental").is_none());
+        assert_eq!(sandbox["outbounds"], candidate["outbounds"]);
+    }
+
+    #[test]
+    fn tail_output_keeps_the_end_of_long_logs() {
+        let stdout = "a".repeat(MAX_CAPTURED_OUTPUT);
+        let output = tail_output(stdout.as_bytes(), b"FATAL tail");
+
+        assert_eq!(output.len(), MAX_CAPTURED_OUTPUT);
+        assert!(output.ends_with("FATAL tail"));
+    }
+
+    #[tokio::test]
+    async fn test_run_drains_output_so_noisy_sing_box_can_exit() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("miao-testrun-noisy-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let dir_str = dir.to_string_lossy().into_owned();
+        let paths = SingBoxPaths::from_config(&Config {
+            sing_box_config_dir: Some(dir_str.clone()),
+            sing_box_data_dir: Some(dir_str.clone()),
+            sing_box_binary_dir: Some(dir_str),
+            ..Default::default()
+        });
+        let binary = paths.binary_path(SingBoxChannel::Stable);
+        // 输出超过管道缓冲区后才退出；不读取输出时进程会一直阻塞，被误判为运行正常
+        std::fs::write(
+            &binary,
+            "#!/bin/sh\nhead -c 300000 /dev/zero | tr '\\0' x\necho 'FATAL start service' >&2\nexit 1\n",
+        )
+        .unwrap();
+        std::fs::set_permissions(&binary, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let result = test_run_config(&json!({}), &paths, SingBoxChannel::Stable)
+            .await
+            .unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+
+        assert!(!result.success);
+        assert_eq!(result.exit_code, Some(1));
+        assert!(result.output.ends_with("FATAL start service\n"));
+        assert!(result.output.len() <= MAX_CAPTURED_OUTPUT);
+    }
+
+    #[test]
+    fn test_run_config_filename_is_unique_per_run() {
+        let first = test_run_config_filename();
+        let second = test_run_config_filename();
+
+        assert_ne!(first, second);
+        assert!(first.starts_with(&format!("testrun-config-{}-", std::process::id())));
+        assert!(first.ends_with(".json"));
+    }
+}
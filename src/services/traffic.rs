@@ -0,0 +1,141 @@
+use std::collections::{BTreeMap, HashMap};
+use std::time::Instant;
+
+use tokio::time::Duration;
+
+use crate::error::{AppError, AppResult};
+use crate::models::NodeTraffic;
+use crate::services::config::clash_api_url;
+use crate::state::AppState;
+
+/// 节点 tag -> (上传字节, 下载字节)
+type NodeTotals = BTreeMap<String, (u64, u64)>;
+
+/// 按节点累计的流量。Clash API 只报告仍在进行的连接，这里记住每个连接上次看到的字节数，
+/// 每次查询只累加增量；两次查询之间开始并结束的连接无法计入
+#[derive(Default)]
+pub struct TrafficTracker {
+    /// 连接 id -> (节点 tag, 已计入的上传字节, 已计入的下载字节)
+    connections: HashMap<String, (String, u64, u64)>,
+    totals: NodeTotals,
+    last_sample: Option<(Instant, NodeTotals)>,
+}
+
+impl TrafficTracker {
+    /// 记录一次 `/connections` 的结果，返回按总流量从高到低排列的各节点流量
+    pub fn record(&mut self, connections: &serde_json::Value, now: Instant) -> Vec<NodeTraffic> {
+        let mut seen = HashMap::new();
+        for connection in connections["connections"].as_array().into_iter().flatten() {
+            // chains 从最后一跳开始排列，第一个即实际承载流量的节点
+            let (Some(id), Some(tag)) =
+                (connection["id"].as_str(), connection["chains"][0].as_str())
+            else {
+                continue;
+            };
+            let upload = connection["upload"].as_u64().unwrap_or_default();
+            let download = connection["download"].as_u64().unwrap_or_default();
+            let (counted_up, counted_down) = self
+                .connections
+                .get(id)
+                .map(|(_, up, down)| (*up, *down))
+                .unwrap_or_default();
+            let total = self.totals.entry(tag.to_string()).or_default();
+            total.0 += upload.saturating_sub(counted_up);
+            total.1 += download.saturating_sub(counted_down);
+            seen.insert(id.to_string(), (tag.to_string(), upload, download));
+        }
+        self.connections = seen;
+
+        let previous = self.last_sample.replace((now, self.totals.clone()));
+        let rate = |current: u64, before: u64, elapsed: f64| {
+            (current.saturating_sub(before) as f64 / elapsed) as u64
+        };
+        let mut traffic: Vec<NodeTraffic> = self
+            .totals
+            .iter()
+            .map(|(tag, (upload, download))| {
+                let rates = previous
+                    .as_ref()
+                    .map(|(at, totals)| (now.duration_since(*at).as_secs_f64(), totals))
+                    .filter(|(elapsed, _)| *elapsed > 0.0)
+                    .map(|(elapsed, totals)| {
+                        let (up_before, down_before) = totals.get(tag).copied().unwrap_or_default();
+                        (
+                            rate(*upload, up_before, elapsed),
+                            rate(*download, down_before, elapsed),
+                        )
+                    });
+                NodeTraffic {
+                    tag: tag.clone(),
+                    upload_bytes: *upload,
+                    download_bytes: *download,
+                    upload_rate: rates.map(|(up, _)| up),
+                    download_rate: rates.map(|(_, down)| down),
+                }
+            })
+            .collect();
+        traffic.sort_by_key(|node| std::cmp::Reverse(node.upload_bytes + node.download_bytes));
+        traffic
+    }
+}
+
+/// 通过 Clash API 查询当前连接并更新各节点的累计流量
+pub async fn node_traffic(state: &AppState) -> AppResult<Vec<NodeTraffic>> {
+    let connections: serde_json::Value = state
+        .http_client
+        .get(clash_api_url("/connections"))
+        .timeout(Duration::from_secs(5))
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| AppError::context("Failed to query Clash API connections", e))?
+        .json()
+        .await
+        .map_err(|e| AppError::context("Failed to parse Clash API connections", e))?;
+
+    Ok(state
+        .node_traffic
+        .lock()
+        .await
+        .record(&connections, Instant::now()))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use std::time::{Duration, Instant};
+
+    use super::TrafficTracker;
+
+    #[test]
+    fn tracker_accumulates_per_node_bytes_and_rates_across_samples() {
+        let mut tracker = TrafficTracker::default();
+        let started = Instant::now();
+
+        let first = tracker.record(
+            &json!({"connections": [
+                {"id": "a", "upload": 100, "download": 1000, "chains": ["HK 01", "proxy"]},
+                {"id": "b", "upload": 10, "download": 20, "chains": ["direct"]}
+            ]}),
+            started,
+        );
+        assert_eq!(first[0].tag, "HK 01");
+        assert_eq!(first[0].download_bytes, 1000);
+        assert_eq!(first[0].download_rate, None);
+
+        // a 继续传输，b 已关闭，c 是新连接
+        let second = tracker.record(
+            &json!({"connections": [
+                {"id": "a", "upload": 300, "download": 5000, "chains": ["HK 01", "proxy"]},
+                {"id": "c", "upload": 50, "download": 2000, "chains": ["HK 01", "Streaming", "proxy"]}
+            ]}),
+            started + Duration::from_secs(2),
+        );
+        let hk = &second[0];
+        assert_eq!((hk.upload_bytes, hk.download_bytes), (350, 7000));
+        assert_eq!((hk.upload_rate, hk.download_rate), (Some(125), Some(3000)));
+        let direct = &second[1];
+        assert_eq!((direct.upload_bytes, direct.download_bytes), (10, 20));
+        assert_eq!(direct.download_rate, Some(0));
+    }
+}
@@ -14,7 +14,7 @@ use tracing::{error, info, warn};
 
 use crate::error::{AppError, AppResult};
 use crate::models::{GitHubAsset, GitHubRelease, VersionInfo};
-use crate::services::singbox::{get_sing_box_home, stop_sing_internal};
+use crate::services::singbox::{record_exit, stop_sing_internal};
 use crate::state::{AppState, VersionCache};
 use crate::VERSION;
 
@@ -247,8 +247,9 @@ async fn sing_box_is_running(state: &Arc<AppState>) -> bool {
 
     match &mut *lock {
         Some(proc) => match proc.child.try_wait() {
-            Ok(Some(_)) => {
+            Ok(Some(exit_status)) => {
                 *lock = None;
+                record_exit(state, exit_status).await;
                 false
             }
             Ok(None) => true,
@@ -489,16 +490,20 @@ pub async fn upgrade_binary(state: &Arc<AppState>) -> AppResult<String> {
     );
 
     let new_version = release.tag_name.clone();
-    let sing_box_home = get_sing_box_home();
+    let paths = state.sing_box_paths.clone();
     tokio::spawn(async move {
         sleep(Duration::from_millis(500)).await;
 
-        let files_to_remove = ["sing-box", "chinaip.srs", "chinasite.srs"];
-        for file in &files_to_remove {
-            let path = sing_box_home.join(file);
+        let files_to_remove = [
+            paths.binary_dir.join("sing-box"),
+            paths.binary_dir.join("sing-box-latest"),
+            paths.data_dir.join("chinaip.srs"),
+            paths.data_dir.join("chinasite.srs"),
+        ];
+        for path in &files_to_remove {
             if path.exists() {
                 info!("Removing old file: {:?}", path);
-                let _ = fs::remove_file(&path);
+                let _ = fs::remove_file(path);
             }
         }
 
@@ -530,7 +535,7 @@ pub async fn upgrade_binary(state: &Arc<AppState>) -> AppResult<String> {
     Ok(new_version)
 }
 
-fn current_version() -> String {
+pub(crate) fn current_version() -> String {
     format!("v{}", VERSION)
 }
 
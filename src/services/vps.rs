@@ -1,5 +1,4 @@
 use std::io::Read;
-use std::path::Path;
 use std::process::Stdio;
 
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
@@ -8,12 +7,13 @@ use tracing::{info, warn};
 
 use crate::error::{AppError, AppResult};
 use crate::models::{Config, Hysteria2, Hysteria2Obfs, Tls};
-use crate::services::config::save_config_to;
 use crate::services::node_parser::parse_node_json;
 use crate::validation::Validator;
 
 const HYSTERIA_PORT: u16 = 543;
 const HYSTERIA_OBFS_TYPE: &str = "gecko";
+/// hysteria2 服务端默认协商的 ALPN
+const HYSTERIA_ALPN: &str = "h3";
 const SSH_CONNECT_TIMEOUT_SECS: &str = "10";
 const SSH_PROBE_TIMEOUT: Duration = Duration::from_secs(30);
 const SSH_PROVISION_TIMEOUT: Duration = Duration::from_secs(300);
@@ -41,14 +41,16 @@ pub fn has_manual_node_for_vps(config: &Config) -> bool {
     })
 }
 
-pub async fn ensure_vps_hysteria_node(config: &mut Config, config_path: &Path) -> AppResult<bool> {
+/// 配置了 vps_ip 且还没有对应的手动节点时部署 Hysteria2 并把节点加入 `config`；
+/// 返回新增的节点，由调用方写入配置文件
+pub async fn ensure_vps_hysteria_node(config: &mut Config) -> AppResult<Option<String>> {
     let Some(vps_ip) = config
         .vps_ip
         .as_deref()
         .map(str::trim)
         .filter(|v| !v.is_empty())
     else {
-        return Ok(false);
+        return Ok(None);
     };
     let vps_ip = vps_ip.to_string();
 
@@ -61,7 +63,7 @@ pub async fn ensure_vps_hysteria_node(config: &mut Config, config_path: &Path) -
 
     if has_manual_node_for_vps(config) {
         info!(vps_ip = %vps_ip, "Manual node for vps_ip already exists, skipping VPS provisioning");
-        return Ok(false);
+        return Ok(None);
     }
 
     let fallback_obfs_password = random_password()?;
@@ -93,15 +95,12 @@ pub async fn ensure_vps_hysteria_node(config: &mut Config, config_path: &Path) -
         }
     };
 
-    config.nodes.push(build_hysteria_node_json(
-        &vps_ip,
-        &credentials.password,
-        &credentials.obfs_password,
-    )?);
-    save_config_to(config_path, config).await?;
-    info!(vps_ip = %vps_ip, port = HYSTERIA_PORT, config_path = ?config_path, "Added provisioned VPS Hysteria2 node to config");
+    let node =
+        build_hysteria_node_json(&vps_ip, &credentials.password, &credentials.obfs_password)?;
+    config.nodes.push(node.clone());
+    info!(vps_ip = %vps_ip, port = HYSTERIA_PORT, "Added provisioned VPS Hysteria2 node to config");
 
-    Ok(true)
+    Ok(Some(node))
 }
 
 fn build_hysteria_node_json(
@@ -123,9 +122,11 @@ fn build_hysteria_node_json(
         }),
         tls: Tls {
             enabled: true,
-            server_name: None,
             insecure: true,
+            alpn: vec![HYSTERIA_ALPN.to_string()],
+            ..Default::default()
         },
+        ignore_bandwidth: false,
     };
 
     serde_json::to_string(&node).map_err(AppError::from)
@@ -521,7 +522,7 @@ systemctl is-active --quiet hysteria-server.service
 mod tests {
     use super::{
         build_hysteria_node_json, has_manual_node_for_vps, parse_probe_credentials,
-        remote_hysteria_probe_script, vps_node_tag, HYSTERIA_PORT,
+        remote_hysteria_probe_script, vps_node_tag, HYSTERIA_ALPN, HYSTERIA_PORT,
     };
     use crate::models::Config;
 
@@ -536,6 +537,7 @@ mod tests {
             custom_rules: vec![],
             route_mode: Default::default(),
             vps_ip: Some("203.0.113.10".to_string()),
+            ..Default::default()
         };
 
         assert!(has_manual_node_for_vps(&config));
@@ -550,6 +552,7 @@ mod tests {
             custom_rules: vec![],
             route_mode: Default::default(),
             vps_ip: Some("203.0.113.10".to_string()),
+            ..Default::default()
         };
 
         assert!(!has_manual_node_for_vps(&config));
@@ -569,6 +572,8 @@ mod tests {
         assert_eq!(value["obfs"]["password"], "obfs-secret");
         assert_eq!(value["tls"]["enabled"], true);
         assert_eq!(value["tls"]["insecure"], true);
+        assert_eq!(value["tls"]["alpn"], serde_json::json!([HYSTERIA_ALPN]));
+        assert!(value["tls"].get("utls").is_none());
     }
 
     #[test]
@@ -3,25 +3,54 @@ use std::collections::HashMap;
 use std::path::PathBuf;
 use std::sync::atomic::AtomicBool;
 use std::sync::Arc;
-use std::time::Instant;
-use tokio::sync::{Mutex, RwLock};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::{Mutex, RwLock, Semaphore};
 
-use crate::models::{Config, GitHubRelease, RouteMode, SubStatus};
+use crate::models::{Config, GitHubRelease, LastExit, NodeHealth, RouteMode, SubStatus};
+use crate::services::bandwidth::NodeBandwidth;
+use crate::services::singbox::{LogBuffer, SingBoxPaths};
+use crate::services::subscription::FetchResult;
+use crate::services::traffic::TrafficTracker;
 
 /// 应用状态容器 - 包含所有运行时状态
 /// 通过依赖注入传递，避免全局静态变量
 pub struct AppState {
     pub config: RwLock<Config>, // 使用 RwLock 支持并发读
+    /// 配置文件中的内容，不含 MIAO_* 环境变量覆盖；面板修改配置时在它的基础上修改并写回
+    pub file_config: RwLock<Config>,
     pub route_mode_override: RwLock<Option<RouteMode>>,
     pub config_path: PathBuf,
+    /// 启动时由配置确定，运行期间不变
+    pub sing_box_paths: SingBoxPaths,
     pub config_update: Mutex<()>,
     pub sing_process: Mutex<Option<SingBoxProcess>>,
+    /// sing-box 最近的输出，进程退出后仍保留，供诊断信息使用
+    pub sing_box_logs: LogBuffer,
+    /// sing-box 最近一次意外退出的信息，下次成功启动时清空
+    pub last_exit: Mutex<Option<LastExit>>,
     pub sub_status: Mutex<HashMap<String, SubStatus>>,
+    /// 每个订阅最近一次成功获取的结果，定时刷新时未到期的订阅直接复用
+    pub sub_cache: Mutex<HashMap<String, FetchResult>>,
+    /// 最近一次测速得到的各地区中位延迟（毫秒），用于排列地区分组
+    pub region_latency: Mutex<HashMap<String, u64>>,
+    /// 已测得的 hysteria2 节点带宽，按节点 tag 索引
+    pub node_bandwidth: Mutex<HashMap<String, NodeBandwidth>>,
+    /// 各节点的延迟测试历史，按节点 tag 索引
+    pub node_health: Mutex<HashMap<String, NodeHealth>>,
+    /// 各节点的累计流量，由 /api/nodes/traffic 查询时更新
+    pub node_traffic: Mutex<TrafficTracker>,
+    pub bandwidth_probing: AtomicBool,
     pub config_warning: Mutex<Option<String>>,
+    /// 最近一次成功生成配置的总耗时，含全部订阅获取
+    pub last_gen_duration: Mutex<Option<Duration>>,
     pub initializing: AtomicBool,
     pub http_client: reqwest::Client,
     pub version_cache: ArcSwap<VersionCache>, // 使用 ArcSwap 实现无锁读取
     pub upgrading: AtomicBool,                // 防止并发升级
+    /// 自定义首页（index_path）的缓存，文件修改时间变化时重新读取
+    pub index_cache: Mutex<Option<IndexCache>>,
+    /// 限制同时处理的 API 请求数，容量在启动时由 max_concurrent_requests 确定
+    pub request_limit: Semaphore,
 }
 
 impl AppState {
@@ -31,19 +60,43 @@ impl AppState {
         Self::with_config_path(config, PathBuf::from("config.yaml"))
     }
 
+    #[cfg(test)]
     pub fn with_config_path(config: Config, config_path: PathBuf) -> Result<Self, reqwest::Error> {
+        Self::with_file_config(config.clone(), config, config_path)
+    }
+
+    /// `config` 是应用环境变量覆盖后实际生效的配置，`file_config` 是配置文件中的内容
+    pub fn with_file_config(
+        config: Config,
+        file_config: Config,
+        config_path: PathBuf,
+    ) -> Result<Self, reqwest::Error> {
         let http_client = reqwest::Client::builder()
             .timeout(std::time::Duration::from_secs(30))
             .build()?;
 
+        let sing_box_paths = SingBoxPaths::from_config(&config);
+        let request_limit = Semaphore::new(config.max_concurrent_requests());
+
         Ok(Self {
             config: RwLock::new(config),
+            file_config: RwLock::new(file_config),
             route_mode_override: RwLock::new(None),
             config_path,
+            sing_box_paths,
             config_update: Mutex::new(()),
             sing_process: Mutex::new(None),
+            sing_box_logs: LogBuffer::default(),
+            last_exit: Mutex::new(None),
             sub_status: Mutex::new(HashMap::new()),
+            sub_cache: Mutex::new(HashMap::new()),
+            region_latency: Mutex::new(HashMap::new()),
+            node_bandwidth: Mutex::new(HashMap::new()),
+            node_health: Mutex::new(HashMap::new()),
+            node_traffic: Mutex::new(TrafficTracker::default()),
+            bandwidth_probing: AtomicBool::new(false),
             config_warning: Mutex::new(None),
+            last_gen_duration: Mutex::new(None),
             initializing: AtomicBool::new(true),
             http_client,
             version_cache: ArcSwap::new(Arc::new(VersionCache {
@@ -51,6 +104,8 @@ impl AppState {
                 fetched_at: None,
             })),
             upgrading: AtomicBool::new(false),
+            index_cache: Mutex::new(None),
+            request_limit,
         })
     }
 }
@@ -60,6 +115,13 @@ pub struct SingBoxProcess {
     pub started_at: Instant,
 }
 
+/// 自定义首页缓存
+pub struct IndexCache {
+    pub path: PathBuf,
+    pub modified: SystemTime,
+    pub html: String,
+}
+
 /// 版本信息缓存
 #[derive(Clone)]
 pub struct VersionCache {
@@ -75,11 +137,12 @@ mod tests {
     fn app_state_new_creates_valid_instance() {
         let config = Config {
             port: Some(8080),
-            subs: vec!["https://example.com/sub".to_string()],
+            subs: vec!["https://example.com/sub".into()],
             nodes: vec![],
             custom_rules: vec![],
             route_mode: Default::default(),
             vps_ip: None,
+            ..Default::default()
         };
 
         let state = AppState::new(config.clone()).unwrap();
@@ -107,6 +170,7 @@ mod tests {
             custom_rules: vec![],
             route_mode: Default::default(),
             vps_ip: None,
+            ..Default::default()
         };
 
         let state = AppState::new(config).unwrap();
@@ -0,0 +1,400 @@
+use std::collections::{BTreeMap, HashMap};
+
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+use serde::Deserialize;
+use tokio::sync::mpsc;
+use tokio::time::{Duration, Instant};
+
+use crate::error::{AppError, AppResult};
+use crate::models::{NodeHealth, DEFAULT_PORT};
+
+/// 自动重新测速的间隔
+const LATENCY_REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+const EVENT_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(200);
+const SELECT_GROUP: &str = "proxy";
+
+#[derive(Debug, PartialEq, Eq)]
+struct TuiArgs {
+    base_url: String,
+    token: Option<String>,
+}
+
+/// `miao tui [--url http://127.0.0.1:6161] [--token xxx]`，token 也可以用 MIAO_ADMIN_TOKEN 提供
+fn parse_tui_args(args: &[String], env_token: Option<String>) -> AppResult<TuiArgs> {
+    let mut parsed = TuiArgs {
+        base_url: format!("http://127.0.0.1:{}", DEFAULT_PORT),
+        token: env_token,
+    };
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let value = match arg.as_str() {
+            "--url" | "--token" => iter
+                .next()
+                .ok_or_else(|| AppError::message(format!("{} requires a value", arg)))?,
+            _ => return Err(AppError::message(format!("Unknown tui argument: {}", arg))),
+        };
+        if arg == "--url" {
+            parsed.base_url = value.trim_end_matches('/').to_string();
+        } else {
+            parsed.token = Some(value.clone());
+        }
+    }
+    Ok(parsed)
+}
+
+#[derive(Deserialize)]
+struct ApiEnvelope<T> {
+    success: bool,
+    message: String,
+    data: Option<T>,
+}
+
+#[derive(Deserialize)]
+struct LatencyData {
+    nodes: BTreeMap<String, Option<u64>>,
+}
+
+/// 运行中的 miao 面板 API 的客户端
+#[derive(Clone)]
+struct PanelClient {
+    http: reqwest::Client,
+    base_url: String,
+    token: Option<String>,
+}
+
+impl PanelClient {
+    async fn call<T: serde::de::DeserializeOwned>(
+        &self,
+        request: reqwest::RequestBuilder,
+    ) -> AppResult<Option<T>> {
+        let request = match &self.token {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        };
+        let envelope: ApiEnvelope<T> = request.send().await?.json().await?;
+        if !envelope.success {
+            return Err(AppError::message(envelope.message));
+        }
+        Ok(envelope.data)
+    }
+
+    async fn latencies(&self) -> AppResult<BTreeMap<String, Option<u64>>> {
+        let url = format!("{}/api/nodes/latency", self.base_url);
+        let data: Option<LatencyData> = self.call(self.http.get(url)).await?;
+        Ok(data.map(|data| data.nodes).unwrap_or_default())
+    }
+
+    async fn health(&self) -> AppResult<Vec<NodeHealth>> {
+        let url = format!("{}/api/nodes/health", self.base_url);
+        Ok(self.call(self.http.get(url)).await?.unwrap_or_default())
+    }
+
+    async fn select(&self, name: &str) -> AppResult<()> {
+        let url = format!("{}/api/nodes/select", self.base_url);
+        let body = serde_json::json!({"group": SELECT_GROUP, "name": name});
+        self.call::<serde_json::Value>(self.http.post(url).json(&body))
+            .await?;
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct NodeRow {
+    tag: String,
+    latency_ms: Option<u64>,
+    consecutive_failures: u32,
+    selected: bool,
+}
+
+/// 合并延迟测试结果与健康状态，按 tag 排序
+fn node_rows(latencies: &BTreeMap<String, Option<u64>>, health: &[NodeHealth]) -> Vec<NodeRow> {
+    let health: HashMap<&str, &NodeHealth> = health
+        .iter()
+        .map(|node| (node.tag.as_str(), node))
+        .collect();
+    latencies
+        .iter()
+        .map(|(tag, latency)| {
+            let node = health.get(tag.as_str());
+            NodeRow {
+                tag: tag.clone(),
+                latency_ms: *latency,
+                consecutive_failures: node.map_or(0, |node| node.consecutive_failures),
+                selected: node.is_some_and(|node| node.selected),
+            }
+        })
+        .collect()
+}
+
+fn row_line(row: &NodeRow) -> Line<'static> {
+    let marker = if row.selected { "● " } else { "  " };
+    let (latency, color) = match row.latency_ms {
+        Some(ms) if ms < 200 => (format!("{ms:>5} ms"), Color::Green),
+        Some(ms) => (format!("{ms:>5} ms"), Color::Yellow),
+        None => ("timeout".to_string(), Color::Red),
+    };
+    let mut spans = vec![
+        Span::raw(marker),
+        Span::styled(format!("{latency:>8}"), Style::default().fg(color)),
+        Span::raw("  "),
+        Span::raw(row.tag.clone()),
+    ];
+    if row.consecutive_failures > 1 {
+        spans.push(Span::styled(
+            format!("  ({} failures)", row.consecutive_failures),
+            Style::default().fg(Color::DarkGray),
+        ));
+    }
+    Line::from(spans)
+}
+
+enum Update {
+    Nodes(AppResult<Vec<NodeRow>>),
+    Selected(AppResult<String>),
+}
+
+struct App {
+    rows: Vec<NodeRow>,
+    list: ListState,
+    status: String,
+    loading: bool,
+}
+
+impl App {
+    fn apply(&mut self, update: Update) {
+        match update {
+            Update::Nodes(Ok(rows)) => {
+                let keep = self.current().map(|row| row.tag.clone());
+                self.rows = rows;
+                let index = keep
+                    .and_then(|tag| self.rows.iter().position(|row| row.tag == tag))
+                    .or_else(|| self.rows.iter().position(|row| row.selected))
+                    .or(if self.rows.is_empty() { None } else { Some(0) });
+                self.list.select(index);
+                self.loading = false;
+                self.status = format!("{} nodes", self.rows.len());
+            }
+            Update::Nodes(Err(e)) => {
+                self.loading = false;
+                self.status = format!("Failed to load nodes: {}", e);
+            }
+            Update::Selected(Ok(name)) => {
+                for row in &mut self.rows {
+                    row.selected = row.tag == name;
+                }
+                self.status = format!("Switched to {}", name);
+            }
+            Update::Selected(Err(e)) => self.status = format!("Failed to switch: {}", e),
+        }
+    }
+
+    fn current(&self) -> Option<&NodeRow> {
+        self.list.selected().and_then(|index| self.rows.get(index))
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [list_area, status_area] =
+            Layout::vertical([Constraint::Min(1), Constraint::Length(1)]).areas(frame.area());
+        let items: Vec<ListItem> = self
+            .rows
+            .iter()
+            .map(|row| ListItem::new(row_line(row)))
+            .collect();
+        let list = List::new(items)
+            .block(
+                Block::bordered()
+                    .title(" miao nodes — ↑/↓ move · Enter select · r re-test · q quit "),
+            )
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(list, list_area, &mut self.list);
+        let status = if self.loading {
+            format!("{} · testing latency…", self.status)
+        } else {
+            self.status.clone()
+        };
+        frame.render_widget(Paragraph::new(status), status_area);
+    }
+}
+
+fn spawn_refresh(client: &PanelClient, updates: &mpsc::UnboundedSender<Update>) {
+    let client = client.clone();
+    let updates = updates.clone();
+    tokio::spawn(async move {
+        let result = async {
+            let latencies = client.latencies().await?;
+            let health = client.health().await?;
+            Ok(node_rows(&latencies, &health))
+        }
+        .await;
+        let _ = updates.send(Update::Nodes(result));
+    });
+}
+
+fn spawn_select(client: &PanelClient, updates: &mpsc::UnboundedSender<Update>, name: String) {
+    let client = client.clone();
+    let updates = updates.clone();
+    tokio::spawn(async move {
+        let result = client.select(&name).await.map(|_| name);
+        let _ = updates.send(Update::Selected(result));
+    });
+}
+
+/// crossterm 的事件读取是阻塞的，放到单独的线程里转发按键
+fn spawn_key_reader(keys: mpsc::UnboundedSender<KeyCode>) {
+    std::thread::spawn(move || loop {
+        match event::poll(EVENT_POLL_INTERVAL) {
+            Ok(true) => match event::read() {
+                Ok(Event::Key(key)) if key.kind == KeyEventKind::Press => {
+                    if keys.send(key.code).is_err() {
+                        return;
+                    }
+                }
+                Ok(_) => {}
+                Err(_) => return,
+            },
+            Ok(false) if keys.is_closed() => return,
+            Ok(false) => {}
+            Err(_) => return,
+        }
+    });
+}
+
+async fn run_app(terminal: &mut DefaultTerminal, client: PanelClient) -> AppResult<()> {
+    let (updates_tx, mut updates) = mpsc::unbounded_channel();
+    let (keys_tx, mut keys) = mpsc::unbounded_channel();
+    spawn_key_reader(keys_tx);
+
+    let mut app = App {
+        rows: Vec::new(),
+        list: ListState::default(),
+        status: format!("Connecting to {}", client.base_url),
+        loading: true,
+    };
+    spawn_refresh(&client, &updates_tx);
+    let mut next_refresh = Instant::now() + LATENCY_REFRESH_INTERVAL;
+
+    loop {
+        terminal.draw(|frame| app.draw(frame))?;
+        tokio::select! {
+            Some(key) = keys.recv() => match key {
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                KeyCode::Up | KeyCode::Char('k') => app.list.select_previous(),
+                KeyCode::Down | KeyCode::Char('j') => app.list.select_next(),
+                KeyCode::Enter => {
+                    if let Some(tag) = app.current().map(|row| row.tag.clone()) {
+                        app.status = format!("Switching to {}…", tag);
+                        spawn_select(&client, &updates_tx, tag);
+                    }
+                }
+                KeyCode::Char('r') if !app.loading => {
+                    app.loading = true;
+                    spawn_refresh(&client, &updates_tx);
+                    next_refresh = Instant::now() + LATENCY_REFRESH_INTERVAL;
+                }
+                _ => {}
+            },
+            Some(update) = updates.recv() => app.apply(update),
+            _ = tokio::time::sleep_until(next_refresh) => {
+                if !app.loading {
+                    app.loading = true;
+                    spawn_refresh(&client, &updates_tx);
+                }
+                next_refresh = Instant::now() + LATENCY_REFRESH_INTERVAL;
+            }
+        }
+    }
+}
+
+/// `miao tui`：连接运行中的 miao，列出节点与延迟，用方向键选择并切换节点
+pub async fn run(args: &[String]) -> AppResult<()> {
+    let args = parse_tui_args(args, std::env::var("MIAO_ADMIN_TOKEN").ok())?;
+    let client = PanelClient {
+        http: reqwest::Client::builder()
+            .timeout(std::time::Duration::from_secs(30))
+            .build()?,
+        base_url: args.base_url,
+        token: args.token,
+    };
+
+    let mut terminal = ratatui::init();
+    let result = run_app(&mut terminal, client).await;
+    ratatui::restore();
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::{node_rows, parse_tui_args, NodeRow, TuiArgs};
+    use crate::models::NodeHealth;
+
+    fn args(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    #[test]
+    fn parse_tui_args_defaults_to_local_panel() {
+        assert_eq!(
+            parse_tui_args(&[], Some("env-token".to_string())).unwrap(),
+            TuiArgs {
+                base_url: "http://127.0.0.1:6161".to_string(),
+                token: Some("env-token".to_string()),
+            }
+        );
+        assert_eq!(
+            parse_tui_args(
+                &args(&["--url", "http://10.0.0.2:8080/", "--token", "t"]),
+                None
+            )
+            .unwrap(),
+            TuiArgs {
+                base_url: "http://10.0.0.2:8080".to_string(),
+                token: Some("t".to_string()),
+            }
+        );
+        assert!(parse_tui_args(&args(&["--url"]), None).is_err());
+        assert!(parse_tui_args(&args(&["--verbose"]), None).is_err());
+    }
+
+    #[test]
+    fn node_rows_merges_latency_and_health() {
+        let latencies =
+            BTreeMap::from([("JP 01".to_string(), Some(80)), ("US 01".to_string(), None)]);
+        let health = vec![
+            NodeHealth {
+                tag: "JP 01".to_string(),
+                selected: true,
+                ..Default::default()
+            },
+            NodeHealth {
+                tag: "US 01".to_string(),
+                consecutive_failures: 3,
+                ..Default::default()
+            },
+        ];
+
+        assert_eq!(
+            node_rows(&latencies, &health),
+            vec![
+                NodeRow {
+                    tag: "JP 01".to_string(),
+                    latency_ms: Some(80),
+                    consecutive_failures: 0,
+                    selected: true,
+                },
+                NodeRow {
+                    tag: "US 01".to_string(),
+                    latency_ms: None,
+                    consecutive_failures: 3,
+                    selected: false,
+                },
+            ]
+        );
+    }
+}
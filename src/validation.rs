@@ -1,4 +1,5 @@
 use regex::Regex;
+use std::collections::BTreeMap;
 use std::sync::LazyLock;
 
 static VALID_TAG_REGEX: LazyLock<Regex> =
@@ -28,7 +29,21 @@ static VALID_SS_CIPHERS: &[&str] = &[
 ];
 
 static VALID_VMESS_CIPHERS: &[&str] = &["auto", "none", "zero", "aes-128-gcm", "chacha20-poly1305"];
-static VALID_TRANSPORT_TYPES: &[&str] = &["tcp", "ws", "http", "h2", "grpc"];
+
+/// sing-box route sniff 动作支持的嗅探器
+static VALID_SNIFF_PROTOCOLS: &[&str] = &[
+    "http",
+    "tls",
+    "quic",
+    "stun",
+    "dns",
+    "bittorrent",
+    "dtls",
+    "ssh",
+    "rdp",
+    "ntp",
+];
+static VALID_TRANSPORT_TYPES: &[&str] = &["tcp", "ws", "http", "h2", "grpc", "quic"];
 static VALID_CLIENT_FINGERPRINTS: &[&str] = &[
     "chrome",
     "firefox",
@@ -41,13 +56,28 @@ static VALID_CLIENT_FINGERPRINTS: &[&str] = &[
     "random",
     "randomized",
 ];
+/// 按从低到高排列，tls_version_range 用下标比较版本
+static VALID_TLS_VERSIONS: &[&str] = &["1.0", "1.1", "1.2", "1.3"];
 static VALID_PACKET_ENCODINGS: &[&str] = &["packetaddr", "xudp"];
 static VALID_VLESS_FLOWS: &[&str] = &["xtls-rprx-vision"];
 static VALID_TUIC_CONGESTION_CONTROLS: &[&str] = &["cubic", "new_reno", "bbr"];
 static VALID_TUIC_UDP_RELAY_MODES: &[&str] = &["native", "quic"];
 static VALID_HYSTERIA2_OBFS_TYPES: &[&str] = &["salamander", "gecko"];
-
-use crate::models::NodeRequest;
+static VALID_SING_LOG_LEVELS: &[&str] =
+    &["trace", "debug", "info", "warn", "error", "fatal", "panic"];
+static VALID_DOMAIN_STRATEGIES: &[&str] = &["prefer_ipv4", "prefer_ipv6", "ipv4_only", "ipv6_only"];
+
+const MIN_TUN_MTU: u32 = 576;
+const MAX_TUN_MTU: u32 = 65535;
+
+use crate::models::{
+    Config, DnsServer, DnsServerType, LimitBy, NodeFilter, NodeGroup, NodeRequest, RuleSetRule,
+    SingLogConfig, TunMtu, MAX_PROBE_BYTES,
+};
+use crate::services::name_transform::NameTransformer;
+use crate::services::node_groups::{DEFAULT_GROUP_TAG, GROUPS_SELECTOR_TAG};
+use crate::services::region::known_regions;
+use crate::services::subscription::duplicate_sub_urls;
 
 pub struct Validator;
 
@@ -58,7 +88,324 @@ fn non_empty(value: &Option<String>) -> Option<&str> {
         .filter(|value| !value.is_empty())
 }
 
+/// 解析逗号分隔的端口列表，返回单个端口和 sing-box 格式的端口范围（"3478:3480"）
+pub fn parse_port_spec(spec: &str) -> Result<(Vec<u16>, Vec<String>), String> {
+    let parse_port = |port: &str| match port.trim().parse::<u16>() {
+        Ok(port) if port > 0 => Ok(port),
+        _ => Err(format!("无效的端口: {}", port.trim())),
+    };
+
+    let mut ports = Vec::new();
+    let mut ranges = Vec::new();
+    for part in spec.split(',').map(str::trim) {
+        if part.is_empty() {
+            return Err(format!("无效的端口列表: {}", spec));
+        }
+        match part.split_once('-') {
+            Some((start, end)) => {
+                let (start, end) = (parse_port(start)?, parse_port(end)?);
+                if start > end {
+                    return Err(format!("端口范围起点大于终点: {}", part));
+                }
+                ranges.push(format!("{}:{}", start, end));
+            }
+            None => ports.push(parse_port(part)?),
+        }
+    }
+    Ok((ports, ranges))
+}
+
+/// 拆分 host 或 host:port 形式的地址，IPv6 需写成 [addr]:port 才能带端口
+pub fn split_host_port(address: &str) -> Result<(&str, Option<u16>), String> {
+    let parse_port = |port: &str| match port.parse::<u16>() {
+        Ok(port) if port > 0 => Ok(Some(port)),
+        _ => Err(format!("无效的端口: {}", port)),
+    };
+
+    if let Some(rest) = address.strip_prefix('[') {
+        let (host, rest) = rest
+            .split_once(']')
+            .ok_or_else(|| format!("无效的地址: {}", address))?;
+        return match rest {
+            "" => Ok((host, None)),
+            _ => match rest.strip_prefix(':') {
+                Some(port) => Ok((host, parse_port(port)?)),
+                None => Err(format!("无效的地址: {}", address)),
+            },
+        };
+    }
+    if address.parse::<std::net::IpAddr>().is_ok() {
+        return Ok((address, None));
+    }
+    match address.rsplit_once(':') {
+        Some((host, port)) => Ok((host, parse_port(port)?)),
+        None => Ok((address, None)),
+    }
+}
+
 impl Validator {
+    /// TUN 接口的 MTU 需在以太网最小值与 IP 包上限之间
+    pub fn tun_mtu(mtu: u32) -> Result<(), String> {
+        if !(MIN_TUN_MTU..=MAX_TUN_MTU).contains(&mtu) {
+            return Err(format!(
+                "tun_mtu 必须在 {} 到 {} 之间",
+                MIN_TUN_MTU, MAX_TUN_MTU
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn rule_sets(rule_sets: &[RuleSetRule]) -> Result<(), String> {
+        let mut seen = std::collections::HashSet::new();
+        for rule_set in rule_sets {
+            let tag = rule_set.tag.trim();
+            if tag.is_empty() {
+                return Err("规则集 tag 不能为空".to_string());
+            }
+            if !seen.insert(tag) {
+                return Err(format!("规则集 tag 重复: {}", tag));
+            }
+            match url::Url::parse(&rule_set.url) {
+                Ok(url) if matches!(url.scheme(), "http" | "https") => {}
+                _ => return Err(format!("规则集 {} 的 URL 无效: {}", tag, rule_set.url)),
+            }
+        }
+        Ok(())
+    }
+
+    pub fn sniff_protocols(protocols: &[String]) -> Result<(), String> {
+        for protocol in protocols {
+            if !VALID_SNIFF_PROTOCOLS.contains(&protocol.as_str()) {
+                return Err(format!(
+                    "不支持的嗅探协议: {}（可选: {}）",
+                    protocol,
+                    VALID_SNIFF_PROTOCOLS.join(", ")
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn regions(regions: &[String]) -> Result<(), String> {
+        for region in regions {
+            if !known_regions().any(|known| known == region) {
+                return Err(format!(
+                    "不支持的地区: {}（可选: {}）",
+                    region,
+                    known_regions().collect::<Vec<_>>().join(", ")
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn node_filter(filter: &NodeFilter) -> Result<(), String> {
+        Self::regions(&filter.regions)?;
+        for pattern in filter.include.iter().chain(filter.exclude.iter()) {
+            regex::Regex::new(pattern)
+                .map_err(|e| format!("节点筛选正则无效: {}（{}）", pattern, e))?;
+        }
+        Ok(())
+    }
+
+    /// 订阅请求头的名称与取值必须是合法的 HTTP 头
+    pub fn sub_headers(headers: &BTreeMap<String, String>) -> Result<(), String> {
+        for (name, value) in headers {
+            reqwest::header::HeaderName::from_bytes(name.as_bytes())
+                .map_err(|_| format!("订阅请求头名称无效: {}", name))?;
+            reqwest::header::HeaderValue::from_str(value)
+                .map_err(|_| format!("订阅请求头 {} 的值无效", name))?;
+        }
+        Ok(())
+    }
+
+    /// 启动时校验配置文件中的运行参数
+    pub fn config(config: &Config) -> Result<(), String> {
+        Self::sing_box_args(&config.sing_box_args)?;
+        for sub in &config.subs {
+            if sub.limit_by == LimitBy::Fastest && sub.limit.is_none() {
+                return Err(format!("订阅 {} 设置了 limit_by 但没有设置 limit", sub.url));
+            }
+            Self::node_filter(&sub.filter)?;
+            Self::sub_headers(&sub.headers)?;
+        }
+        Self::tls_version_range(
+            config.tls_min_version.as_deref(),
+            config.tls_max_version.as_deref(),
+        )?;
+        let mut output_paths = std::collections::HashSet::new();
+        for output in &config.outputs {
+            if output.path.trim().is_empty() {
+                return Err("outputs 的 path 不能为空".to_string());
+            }
+            if !output_paths.insert(output.path.as_str()) {
+                return Err(format!("outputs 的 path 重复: {}", output.path));
+            }
+            Self::node_filter(&output.filter)?;
+        }
+        if config.strict_subs {
+            if let Some(url) = duplicate_sub_urls(&config.subs).first() {
+                return Err(format!("订阅 URL 重复: {}", url));
+            }
+        }
+        if let Some(ref filter) = config.node_filter {
+            Self::node_filter(filter)?;
+        }
+        for rule in &config.domain_rules {
+            if rule.domain.is_empty() && rule.domain_suffix.is_empty() {
+                return Err(format!(
+                    "domain_rules 中指向 {} 的规则至少需要 domain 或 domain_suffix",
+                    rule.outbound
+                ));
+            }
+            if rule
+                .domain
+                .iter()
+                .chain(&rule.domain_suffix)
+                .any(|domain| domain.trim().is_empty())
+            {
+                return Err("domain_rules 中的域名不能为空".to_string());
+            }
+        }
+        Self::node_groups(&config.groups)?;
+        if config.fail_fast && config.direct_fallback {
+            return Err("fail_fast 与 direct_fallback 不能同时开启".to_string());
+        }
+        if !config.domain_rules.is_empty() && !config.sniff {
+            return Err("domain_rules 依赖域名嗅探，不能与 sniff: false 同时使用".to_string());
+        }
+        for rule in &config.port_rules {
+            parse_port_spec(&rule.ports)?;
+        }
+        Self::rule_sets(&config.rule_sets)?;
+        for mirror in &config.rule_set_mirrors {
+            match url::Url::parse(mirror) {
+                Ok(url) if matches!(url.scheme(), "http" | "https") => {}
+                _ => return Err(format!("规则集镜像 URL 无效: {}", mirror)),
+            }
+        }
+        Self::sniff_protocols(&config.sniff_protocols)?;
+        if let Some(ref strategy) = config.outbound_domain_strategy {
+            Self::domain_strategy(strategy)?;
+        }
+        NameTransformer::new(&config.name_transforms)?;
+        if let Some(TunMtu::Fixed(mtu)) = config.tun_mtu {
+            Self::tun_mtu(mtu)?;
+        }
+        if config
+            .admin_token
+            .as_deref()
+            .is_some_and(|token| token.trim().is_empty())
+        {
+            return Err("admin_token 不能为空".to_string());
+        }
+        if let Some(ref probe) = config.bandwidth_probe {
+            if !(1..=MAX_PROBE_BYTES).contains(&probe.test_bytes) {
+                return Err(format!(
+                    "bandwidth_probe.test_bytes 必须在 1 到 {} 之间",
+                    MAX_PROBE_BYTES
+                ));
+            }
+        }
+        if config.max_concurrent_requests == Some(0) {
+            return Err("max_concurrent_requests 必须大于 0".to_string());
+        }
+        if config.failover_min_nodes == Some(0) {
+            return Err("failover_min_nodes 必须大于 0".to_string());
+        }
+        if let Some(ref tuning) = config.hysteria2 {
+            if tuning.up_mbps == Some(0) || tuning.down_mbps == Some(0) {
+                return Err("hysteria2.up_mbps/down_mbps 必须大于 0".to_string());
+            }
+            if tuning.ignore_bandwidth && (tuning.up_mbps.is_some() || tuning.down_mbps.is_some()) {
+                return Err(
+                    "hysteria2.ignore_bandwidth 不能与 up_mbps/down_mbps 同时设置".to_string(),
+                );
+            }
+            if tuning.ignore_bandwidth && config.bandwidth_probe.is_some() {
+                return Err(
+                    "hysteria2.ignore_bandwidth 不能与 bandwidth_probe 同时使用".to_string()
+                );
+            }
+        }
+        if config.bandwidth_probe.is_some() {
+            if let Some(node) = config
+                .nodes
+                .iter()
+                .filter_map(|node| serde_json::from_str::<serde_json::Value>(node).ok())
+                .find(|node| node["ignore_bandwidth"] == true)
+            {
+                return Err(format!(
+                    "节点 {} 设置了 ignore_bandwidth，不能与 bandwidth_probe 同时使用",
+                    node["tag"].as_str().unwrap_or_default()
+                ));
+            }
+        }
+        if config.max_sub_bytes == Some(0) {
+            return Err("max_sub_bytes 必须大于 0".to_string());
+        }
+        if let Some(ref dns) = config.dns {
+            for server in dns.remote.iter().chain(dns.local.iter()) {
+                Self::dns_server(server)?;
+            }
+            if dns
+                .final_server
+                .as_deref()
+                .is_some_and(|tag| tag.trim().is_empty())
+            {
+                return Err("dns.final 不能为空".to_string());
+            }
+        }
+        if let Some(ref sing_log) = config.sing_log {
+            Self::sing_log(sing_log)?;
+        }
+        Ok(())
+    }
+
+    pub fn node_groups(groups: &[NodeGroup]) -> Result<(), String> {
+        let mut names = std::collections::HashSet::new();
+        for group in groups {
+            let name = group.name.trim();
+            if name.is_empty() {
+                return Err("groups 的 name 不能为空".to_string());
+            }
+            if name == DEFAULT_GROUP_TAG || name == GROUPS_SELECTOR_TAG {
+                return Err(format!("分组名 {} 为保留名称", name));
+            }
+            if !names.insert(name) {
+                return Err(format!("分组名重复: {}", name));
+            }
+            if group.patterns.is_empty() {
+                return Err(format!("分组 {} 至少需要一个 patterns", name));
+            }
+            for pattern in &group.patterns {
+                Regex::new(pattern)
+                    .map_err(|e| format!("分组 {} 的正则无效: {}（{}）", name, pattern, e))?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn sing_log(sing_log: &SingLogConfig) -> Result<(), String> {
+        if let Some(ref level) = sing_log.level {
+            if !VALID_SING_LOG_LEVELS.contains(&level.as_str()) {
+                return Err(format!(
+                    "无效的 sing-box 日志级别: {}，可选值: {}",
+                    level,
+                    VALID_SING_LOG_LEVELS.join(", ")
+                ));
+            }
+        }
+        if sing_log
+            .output
+            .as_deref()
+            .is_some_and(|output| output.trim().is_empty())
+        {
+            return Err("sing_log.output 不能为空".to_string());
+        }
+        Ok(())
+    }
+
     pub fn validate_node_request(req: &NodeRequest) -> Result<(), String> {
         Self::node_tag(&req.tag)?;
         Self::server_address(&req.server)?;
@@ -76,6 +423,9 @@ impl Validator {
         if let Some(ref sni) = req.sni {
             Self::sni(sni)?;
         }
+        if let Some(strategy) = non_empty(&req.domain_strategy) {
+            Self::domain_strategy(strategy)?;
+        }
         if let Some(ref cipher) = req.cipher {
             if !cipher.trim().is_empty() {
                 match node_type {
@@ -90,6 +440,21 @@ impl Validator {
             if !matches!(node_type, "vmess" | "vless" | "trojan") {
                 return Err(format!("{} 节点不支持传输层配置", node_type));
             }
+            if transport_type == "quic" {
+                let tls_enabled =
+                    node_type == "trojan" || req.tls_enabled.unwrap_or(node_type != "vmess");
+                if !tls_enabled {
+                    return Err("QUIC 传输层需要开启 TLS".to_string());
+                }
+                if non_empty(&req.reality_public_key).is_some() {
+                    return Err("QUIC 传输层不能与 Reality 同时使用".to_string());
+                }
+                if non_empty(&req.transport_path).is_some()
+                    || non_empty(&req.transport_host).is_some()
+                {
+                    return Err("QUIC 传输层不支持 path 与 host".to_string());
+                }
+            }
         }
         if let Some(path) = non_empty(&req.transport_path) {
             Self::transport_path(path)?;
@@ -106,6 +471,10 @@ impl Validator {
         if let Some(fingerprint) = non_empty(&req.client_fingerprint) {
             Self::client_fingerprint(fingerprint)?;
         }
+        Self::tls_version_range(
+            non_empty(&req.tls_min_version),
+            non_empty(&req.tls_max_version),
+        )?;
         if non_empty(&req.reality_public_key).is_some()
             || non_empty(&req.reality_short_id).is_some()
         {
@@ -361,6 +730,24 @@ impl Validator {
         Ok(())
     }
 
+    /// sing-box 接受的 TLS 版本为 "1.0" 到 "1.3"，min 不能高于 max
+    pub fn tls_version_range(min: Option<&str>, max: Option<&str>) -> Result<(), String> {
+        let rank = |version: &str| {
+            VALID_TLS_VERSIONS
+                .iter()
+                .position(|valid| *valid == version)
+                .ok_or_else(|| format!("不支持的 TLS 版本: {}（可选 1.0、1.1、1.2、1.3）", version))
+        };
+        let min_rank = min.map(rank).transpose()?;
+        let max_rank = max.map(rank).transpose()?;
+        if let (Some(min_rank), Some(max_rank)) = (min_rank, max_rank) {
+            if min_rank > max_rank {
+                return Err("TLS 最低版本不能高于最高版本".to_string());
+            }
+        }
+        Ok(())
+    }
+
     pub fn client_fingerprint(fingerprint: &str) -> Result<(), String> {
         let normalized = fingerprint.to_ascii_lowercase();
         if !VALID_CLIENT_FINGERPRINTS.contains(&normalized.as_str()) {
@@ -410,6 +797,63 @@ impl Validator {
         Ok(())
     }
 
+    /// 额外的 sing-box 启动参数不能再指定配置文件或工作目录，这两者由 Miao 管理
+    pub fn sing_box_args(args: &[String]) -> Result<(), String> {
+        for arg in args {
+            let flag = arg.split('=').next().unwrap_or(arg);
+            if matches!(flag, "-c" | "--config" | "-C" | "--config-directory") {
+                return Err(format!(
+                    "sing_box_args 不能包含 {}，配置文件由 Miao 管理",
+                    flag
+                ));
+            }
+            if matches!(flag, "-D" | "--directory") {
+                return Err(format!(
+                    "sing_box_args 不能包含 {}，请使用 sing_box_data_dir 设置工作目录",
+                    flag
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn domain_strategy(strategy: &str) -> Result<(), String> {
+        if !VALID_DOMAIN_STRATEGIES.contains(&strategy) {
+            return Err(format!(
+                "无效的域名解析策略: {}，可选值: {}",
+                strategy,
+                VALID_DOMAIN_STRATEGIES.join(", ")
+            ));
+        }
+        Ok(())
+    }
+
+    /// DoH 需要完整的 https:// URL，udp/tls/quic 只接受 host 或 host:port
+    pub fn dns_server(server: &DnsServer) -> Result<(), String> {
+        let address = server.address.trim();
+        if address.is_empty() {
+            return Err("DNS 服务器地址不能为空".to_string());
+        }
+
+        if server.server_type == DnsServerType::Https {
+            let parsed = url::Url::parse(address)
+                .map_err(|_| format!("DoH 服务器必须是完整的 https:// URL: {}", address))?;
+            if parsed.scheme() != "https" || parsed.host_str().is_none() {
+                return Err(format!("DoH 服务器必须是完整的 https:// URL: {}", address));
+            }
+            return Ok(());
+        }
+
+        if address.contains("://") {
+            return Err(format!(
+                "udp/tls/quic 类型的 DNS 服务器只接受 host 或 host:port，不能使用 URL: {}",
+                address
+            ));
+        }
+        let (host, _) = split_host_port(address)?;
+        Self::server_address(host)
+    }
+
     pub fn sni(sni: &str) -> Result<(), String> {
         if sni.is_empty() {
             return Ok(());
@@ -427,6 +871,74 @@ impl Validator {
 mod tests {
     use super::*;
 
+    #[test]
+    fn parse_port_spec_accepts_ports_and_ranges() {
+        assert_eq!(
+            parse_port_spec("443, 3478-3480").unwrap(),
+            (vec![443], vec!["3478:3480".to_string()])
+        );
+        assert!(parse_port_spec("3480-3478").is_err());
+        assert!(parse_port_spec("0").is_err());
+        assert!(parse_port_spec("80,").is_err());
+        assert!(parse_port_spec("70000").is_err());
+    }
+
+    #[test]
+    fn test_domain_strategy() {
+        assert!(Validator::domain_strategy("ipv4_only").is_ok());
+        assert!(Validator::domain_strategy("prefer_ipv6").is_ok());
+        assert!(Validator::domain_strategy("ipv4").is_err());
+        assert!(Validator::domain_strategy("").is_err());
+    }
+
+    #[test]
+    fn test_dns_server_address_forms() {
+        let server = |server_type, address: &str| DnsServer {
+            server_type,
+            address: address.to_string(),
+        };
+
+        assert!(
+            Validator::dns_server(&server(DnsServerType::Https, "https://1.1.1.1/dns-query"))
+                .is_ok()
+        );
+        assert!(Validator::dns_server(&server(DnsServerType::Tls, "dns.google:853")).is_ok());
+        assert!(
+            Validator::dns_server(&server(DnsServerType::Quic, "[2606:4700::1111]:853")).is_ok()
+        );
+        assert!(Validator::dns_server(&server(DnsServerType::Udp, "223.5.5.5")).is_ok());
+
+        assert!(Validator::dns_server(&server(DnsServerType::Https, "1.1.1.1")).is_err());
+        assert!(
+            Validator::dns_server(&server(DnsServerType::Https, "http://1.1.1.1/dns-query"))
+                .is_err()
+        );
+        assert!(Validator::dns_server(&server(DnsServerType::Tls, "https://dns.google")).is_err());
+        assert!(Validator::dns_server(&server(DnsServerType::Udp, "1.1.1.1:0")).is_err());
+        assert!(Validator::dns_server(&server(DnsServerType::Udp, "")).is_err());
+    }
+
+    #[test]
+    fn test_sing_box_args_reject_config_overrides() {
+        let ok = vec!["--disable-color".to_string()];
+        assert!(Validator::sing_box_args(&ok).is_ok());
+
+        for arg in ["-c", "--config", "--config=/tmp/other.json", "-C"] {
+            let err = Validator::sing_box_args(&[arg.to_string()]).unwrap_err();
+            assert!(err.contains("sing_box_args"), "{arg}: {err}");
+        }
+        for arg in [
+            "-D",
+            "--directory",
+            "-D=/var/lib/sb",
+            "--directory=/var/lib/sb",
+        ] {
+            let err = Validator::sing_box_args(&[arg.to_string(), "/var/lib/sb".to_string()])
+                .unwrap_err();
+            assert!(err.contains("sing_box_data_dir"), "{arg}: {err}");
+        }
+    }
+
     #[test]
     fn test_valid_subscription_urls() {
         assert!(Validator::subscription_url("https://example.com/sub").is_ok());
@@ -615,6 +1127,15 @@ mod tests {
         assert!(Validator::client_fingerprint("unknown").is_err());
     }
 
+    #[test]
+    fn test_tls_version_range() {
+        assert!(Validator::tls_version_range(Some("1.2"), Some("1.3")).is_ok());
+        assert!(Validator::tls_version_range(None, Some("1.2")).is_ok());
+        assert!(Validator::tls_version_range(Some("1.3"), None).is_ok());
+        assert!(Validator::tls_version_range(Some("1.3"), Some("1.2")).is_err());
+        assert!(Validator::tls_version_range(Some("TLSv1.3"), None).is_err());
+    }
+
     #[test]
     fn test_sni_validation() {
         // Empty SNI is valid (optional)